@@ -1,45 +1,36 @@
-use crate::config::AliyunConfig;
+use crate::config::{
+    AliyunConfig, AliyunFetchAllConfig, AliyunRetryConfig, AliyunTasksCacheConfig,
+};
 use crate::error::{AppError, AppResult};
+use crate::http_client::request_error;
 use anyhow::Context;
-use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+use tracing::{Span, debug, warn};
 use utoipa::ToSchema;
 
+use super::credentials::{CredentialProvider, Credentials, SharedCredentials};
+use super::object_path::url_host;
+use super::refresh_budget::RefreshBudget;
 use super::signature::{AliyunSignInput, AliyunSigner};
+pub use crate::api::aliyun::{
+    BpsDataPoint, CdnDomain, CdnDomainDetail, CdnDomainLogsResponse, CdnDomainSource, CdnLogFile,
+    CdnProduct, DescribeCdnDomainLogsPayload, DescribeRefreshQuotaResponse,
+    DescribeRefreshTaskByIdResponse, DescribeRefreshTasksPayload, DescribeRefreshTasksResponse,
+    DescribeUserDomainsPayload, DescribeUserDomainsResponse, DomainBpsData, DomainHitRateData,
+    DomainsContainer, DownloadCdnDomainLogPayload, HitRateDataPoint, InvokeAliyunResult,
+    RefreshAndWaitResult, RefreshTask, RefreshTaskObjectType, RefreshTaskStatus, TasksContainer,
+};
+use crate::metrics::record_aliyun_call;
 
-/// CDN API endpoint
-const CDN_ENDPOINT: &str = "https://cdn.aliyuncs.com";
-const CDN_HOST: &str = "cdn.aliyuncs.com";
-
-#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
-pub struct TasksContainer {
-    #[serde(rename = "CDNTask")]
-    pub cdn_tasks: Vec<RefreshTask>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
-pub struct RefreshTask {
-    #[serde(rename = "TaskId")]
-    pub task_id: String,
-
-    #[serde(rename = "ObjectPath")]
-    pub object_path: String,
-
-    #[serde(rename = "ObjectType")]
-    pub object_type: String,
-
-    #[serde(rename = "Status")]
-    pub status: String,
-
-    #[serde(rename = "Process")]
-    pub process: String,
-
-    #[serde(rename = "CreationTime")]
-    pub creation_time: String,
-
-    #[serde(rename = "Description", skip_serializing_if = "Option::is_none")]
-    pub description: Option<String>,
-}
+/// Task id reported for refreshes that were only prepared, never sent
+pub const DRY_RUN_TASK_ID: &str = "dry-run";
 
 /// Request parameters for RefreshObjectCaches API
 ///
@@ -56,6 +47,35 @@ pub struct RefreshObjectCachesRequest {
     /// Whether to directly delete CDN cache nodes (default false)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub force: Option<bool>,
+
+    /// Product to refresh on; `None` picks it by each path's domain, see
+    /// [`AliyunCdnClient::route`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub product: Option<CdnProduct>,
+}
+
+impl RefreshObjectCachesRequest {
+    /// Split into requests of at most as many paths as Aliyun accepts per call, in order
+    ///
+    /// A request within the limit is returned as is.
+    pub fn split(&self) -> Vec<RefreshObjectCachesRequest> {
+        let limit = super::max_object_paths(self.object_type.as_deref().unwrap_or("File"));
+        let paths = self
+            .object_path
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .collect::<Vec<_>>();
+        if paths.len() <= limit {
+            return vec![self.clone()];
+        }
+        paths
+            .chunks(limit)
+            .map(|chunk| RefreshObjectCachesRequest {
+                object_path: chunk.join("\n"),
+                ..self.clone()
+            })
+            .collect()
+    }
 }
 
 /// Form parameters for RefreshObjectCaches API
@@ -82,24 +102,683 @@ pub struct RefreshObjectCachesResponse {
     pub refresh_task_id: String,
 }
 
+impl RefreshObjectCachesResponse {
+    /// Add the tasks of another call, keeping the first request id
+    pub fn append(&mut self, other: RefreshObjectCachesResponse) {
+        self.refresh_task_id.push(',');
+        self.refresh_task_id.push_str(&other.refresh_task_id);
+    }
+}
+
+/// Request parameters for PushObjectCache API, preloading URLs onto edge nodes
+///
+/// Reference: https://help.aliyun.com/zh/cdn/developer-reference/api-cdn-2018-05-10-pushobjectcache
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PushObjectCachesRequest {
+    /// URLs to preload (separated by newlines, max 100 per request)
+    pub object_path: String,
+
+    /// `domestic` (default) or `overseas`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub area: Option<String>,
+
+    /// Also preload onto L2 nodes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub l2_preload: Option<bool>,
+}
+
+/// Form parameters for PushObjectCache API
+#[derive(Debug, Clone, Serialize)]
+struct PushObjectCachesFormParams {
+    #[serde(rename = "ObjectPath")]
+    object_path: String,
+
+    #[serde(rename = "Area", skip_serializing_if = "Option::is_none")]
+    area: Option<String>,
+
+    #[serde(rename = "L2Preload", skip_serializing_if = "Option::is_none")]
+    l2_preload: Option<bool>,
+}
+
+/// Response from PushObjectCache API
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PushObjectCachesResponse {
+    #[serde(rename = "RequestId")]
+    pub request_id: String,
+
+    #[serde(rename = "PushTaskId")]
+    pub push_task_id: String,
+}
+
+impl PushObjectCachesResponse {
+    /// Add the task of another call, keeping the first request id
+    pub fn append(&mut self, other: PushObjectCachesResponse) {
+        self.push_task_id.push(',');
+        self.push_task_id.push_str(&other.push_task_id);
+    }
+}
+
+/// CDN OpenAPI version used by the typed wrappers
+const CDN_API_VERSION: &str = "2018-05-10";
+
+/// DCDN OpenAPI version used by the typed wrappers
+const DCDN_API_VERSION: &str = "2018-01-15";
+
+impl CdnProduct {
+    /// OpenAPI version of the product's actions
+    fn version(self) -> &'static str {
+        match self {
+            CdnProduct::Cdn => CDN_API_VERSION,
+            CdnProduct::Dcdn => DCDN_API_VERSION,
+        }
+    }
+
+    /// Reference: https://help.aliyun.com/zh/edge-security-acceleration/dcdn/developer-reference/api-dcdn-2018-01-15-refreshdcdnobjectcaches
+    fn refresh_action(self) -> &'static str {
+        match self {
+            CdnProduct::Cdn => "RefreshObjectCaches",
+            CdnProduct::Dcdn => "RefreshDcdnObjectCaches",
+        }
+    }
+
+    /// Both answer with the same task list
+    fn describe_task_action(self) -> &'static str {
+        match self {
+            CdnProduct::Cdn => "DescribeRefreshTaskById",
+            CdnProduct::Dcdn => "DescribeDcdnRefreshTaskById",
+        }
+    }
+}
+
+/// Most task ids DescribeRefreshTaskById accepts in one call
+pub const MAX_DESCRIBE_TASK_IDS: usize = 10;
+
+/// Most URLs PushObjectCache accepts in one call
+pub const MAX_PUSH_PATHS: usize = 100;
+
+/// Largest `PageSize` DescribeRefreshTasks accepts
+pub const MAX_REFRESH_TASKS_PAGE_SIZE: u32 = 100;
+
+/// Largest `PageSize` DescribeCdnDomainLogs accepts
+pub const MAX_DOMAIN_LOGS_PAGE_SIZE: u32 = 1000;
+
+/// Largest `PageSize` DescribeUserDomains accepts
+pub const MAX_USER_DOMAINS_PAGE_SIZE: u32 = 500;
+
+/// How long a looked-up domain status is trusted, domains rarely go on- or offline
+const DOMAIN_STATUS_TTL: Duration = Duration::from_secs(300);
+
+/// Bound on streaming one log file, which can be far larger than an API answer
+const LOG_DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Response from DescribeCdnDomainLogs API, one detail per requested domain
+#[derive(Debug, Deserialize)]
+struct DomainLogsResponse {
+    #[serde(rename = "RequestId")]
+    request_id: String,
+    #[serde(rename = "DomainLogDetails", default)]
+    details: DomainLogDetails,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct DomainLogDetails {
+    #[serde(rename = "DomainLogDetail", default)]
+    details: Vec<DomainLogDetail>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct DomainLogDetail {
+    #[serde(rename = "DomainName")]
+    domain_name: String,
+    #[serde(rename = "PageInfos", default)]
+    page_infos: DomainLogPageInfos,
+    #[serde(rename = "LogInfos", default)]
+    log_infos: DomainLogInfos,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct DomainLogPageInfos {
+    #[serde(rename = "PageIndex", default)]
+    page_index: u64,
+    #[serde(rename = "PageSize", default)]
+    page_size: u64,
+    #[serde(rename = "Total", default)]
+    total: u64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct DomainLogInfos {
+    #[serde(rename = "LogInfoDetail", default)]
+    files: Vec<DomainLogFile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DomainLogFile {
+    #[serde(rename = "LogName")]
+    log_name: String,
+    /// Signed OSS URL without a scheme
+    #[serde(rename = "LogPath")]
+    log_path: String,
+    #[serde(rename = "LogSize", default)]
+    log_size: u64,
+    #[serde(rename = "StartTime", default)]
+    start_time: String,
+    #[serde(rename = "EndTime", default)]
+    end_time: String,
+}
+
+/// Response from DescribeCdnDomainDetail API
+#[derive(Debug, Deserialize)]
+struct DomainDetailResponse {
+    #[serde(rename = "RequestId")]
+    request_id: String,
+    #[serde(rename = "GetDomainDetailModel")]
+    detail: DomainDetailModel,
+}
+
+#[derive(Debug, Deserialize)]
+struct DomainDetailModel {
+    #[serde(rename = "DomainName")]
+    domain_name: String,
+    #[serde(rename = "Cname", default)]
+    cname: String,
+    #[serde(rename = "DomainStatus", default)]
+    domain_status: String,
+    #[serde(rename = "SslProtocol", default)]
+    ssl_protocol: String,
+    #[serde(rename = "ServerCertificateStatus", default)]
+    server_certificate_status: Option<String>,
+    #[serde(rename = "CdnType", default)]
+    cdn_type: String,
+    #[serde(rename = "Scope", default)]
+    scope: Option<String>,
+    #[serde(rename = "SourceModels", default)]
+    sources: DomainSourceModels,
+    #[serde(rename = "GmtCreated", default)]
+    gmt_created: String,
+    #[serde(rename = "GmtModified", default)]
+    gmt_modified: String,
+    #[serde(rename = "Description", default)]
+    description: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct DomainSourceModels {
+    #[serde(rename = "SourceModel", default)]
+    sources: Vec<DomainSourceModel>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DomainSourceModel {
+    #[serde(rename = "Content")]
+    content: String,
+    #[serde(rename = "Type", default)]
+    source_type: String,
+    #[serde(rename = "Port", default)]
+    port: u16,
+    #[serde(rename = "Priority", default)]
+    priority: Option<String>,
+    #[serde(rename = "Weight", default)]
+    weight: Option<String>,
+    #[serde(rename = "Enabled", default)]
+    enabled: Option<String>,
+}
+
+impl From<DomainDetailResponse> for CdnDomainDetail {
+    fn from(response: DomainDetailResponse) -> Self {
+        let detail = response.detail;
+        CdnDomainDetail {
+            request_id: response.request_id,
+            domain_name: detail.domain_name,
+            cname: detail.cname,
+            domain_status: detail.domain_status,
+            ssl_protocol: detail.ssl_protocol,
+            server_certificate_status: detail.server_certificate_status,
+            cdn_type: detail.cdn_type,
+            scope: detail.scope,
+            sources: detail
+                .sources
+                .sources
+                .into_iter()
+                .map(|source| CdnDomainSource {
+                    content: source.content,
+                    source_type: source.source_type,
+                    port: source.port,
+                    priority: source.priority,
+                    weight: source.weight,
+                    enabled: source.enabled,
+                })
+                .collect(),
+            gmt_created: detail.gmt_created,
+            gmt_modified: detail.gmt_modified,
+            // Aliyun sends an empty description rather than none
+            description: detail
+                .description
+                .filter(|description| !description.is_empty()),
+        }
+    }
+}
+
+/// Response from DescribeDomainBpsData and DescribeDomainHitRateData
+///
+/// Only the name of the series differs between the two; values come as strings.
+#[derive(Debug, Deserialize)]
+struct DomainStatsResponse {
+    #[serde(rename = "RequestId")]
+    request_id: String,
+    #[serde(rename = "DomainName", default)]
+    domain_name: String,
+    #[serde(rename = "StartTime", default)]
+    start_time: String,
+    #[serde(rename = "EndTime", default)]
+    end_time: String,
+    #[serde(rename = "DataInterval", default, deserialize_with = "stats_number")]
+    interval: f64,
+    #[serde(alias = "BpsDataPerInterval", alias = "HitRateInterval", default)]
+    series: DomainStatsSeries,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct DomainStatsSeries {
+    #[serde(rename = "DataModule", default)]
+    points: Vec<DomainStatsPoint>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DomainStatsPoint {
+    #[serde(rename = "TimeStamp")]
+    time_stamp: String,
+    #[serde(rename = "Value", default, deserialize_with = "stats_number")]
+    value: f64,
+    #[serde(
+        rename = "HttpsValue",
+        default,
+        deserialize_with = "optional_stats_number"
+    )]
+    https_value: Option<f64>,
+}
+
+/// A statistic sent as a number, a numeric string, or an empty string for no traffic
+fn stats_number<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    Ok(optional_stats_number(deserializer)?.unwrap_or_default())
+}
+
+fn optional_stats_number<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<f64>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Value {
+        Number(f64),
+        Text(String),
+    }
+    match Option::<Value>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Value::Number(value)) => Ok(Some(value)),
+        Some(Value::Text(text)) if text.trim().is_empty() => Ok(None),
+        Some(Value::Text(text)) => text
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+    }
+}
+
+/// One OpenAPI call before signing
+struct CallParts<'a> {
+    product: CdnProduct,
+    action: &'a str,
+    version: &'a str,
+    method: &'a reqwest::Method,
+    /// Resource path of ROA-style APIs, unencoded (see [`fill_path`]); `/` for RPC-style ones
+    path: String,
+    query_params: BTreeMap<String, String>,
+    /// URL-encoded form body; without one the call has an empty body and no content type
+    form_body: Option<String>,
+}
+
+/// A signed call, ready to send
+#[derive(Debug)]
+struct PreparedCall {
+    url: String,
+    headers: reqwest::header::HeaderMap,
+    body: Option<String>,
+}
+
 /// Aliyun CDN API client
 pub struct AliyunCdnClient {
     signer: AliyunSigner,
+    /// What `signer` signs with
+    credentials: SharedCredentials,
+    /// Fetches `credentials` again when a call finds them expired
+    credential_provider: Option<Arc<CredentialProvider>>,
     client: reqwest::Client,
+    /// Endpoint URL without trailing slash, e.g. `https://cdn.aliyuncs.com`
+    endpoint: String,
+    /// Host part of `endpoint`, signed as the `host` header
+    host: String,
+    /// DCDN endpoint and host, like `endpoint` and `host`
+    dcdn_endpoint: String,
+    dcdn_host: String,
+    /// Lowercase hosts refreshed through DCDN
+    dcdn_domains: HashSet<String>,
+    /// Overrides the shared client's total timeout for each API call
+    timeout: Option<Duration>,
+    /// `host:port` of the proxy in use, named when it refuses the connection
+    proxy_host: Option<String>,
+    retry: AliyunRetryConfig,
+    /// URLs that may still be refreshed before `aliyun.refresh_budget` runs out
+    refresh_budget: RefreshBudget,
+    /// Lowercase domain -> whether it was online, and when that was looked up
+    domain_status: Mutex<HashMap<String, (bool, Instant)>>,
+    tasks_cache_config: AliyunTasksCacheConfig,
+    /// JSON of a DescribeRefreshTasks query -> Aliyun's answer, and when it came
+    tasks_cache: Mutex<HashMap<String, (DescribeRefreshTasksResponse, Instant)>>,
+}
+
+/// Whether a cached answer was served
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheStatus {
+    Hit,
+    Miss,
+}
+
+impl CacheStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CacheStatus::Hit => "hit",
+            CacheStatus::Miss => "miss",
+        }
+    }
+}
+
+impl std::fmt::Debug for AliyunCdnClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // The signer holds secrets, keep them out of logs
+        f.debug_struct("AliyunCdnClient")
+            .field("endpoint", &self.endpoint)
+            .field("dcdn_endpoint", &self.dcdn_endpoint)
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
 }
 
 impl AliyunCdnClient {
-    /// Create a new Aliyun CDN client
+    /// Create a new Aliyun CDN client signing with the configured AccessKey
     pub fn new(config: &AliyunConfig, client: reqwest::Client) -> Self {
-        let signer = AliyunSigner::new(
-            config.access_key_id.clone(),
-            config.access_key_secret.clone(),
-        );
+        let credentials = Arc::new(RwLock::new(Credentials::from_config(config)));
+
+        let (endpoint, mut host) = endpoint_and_host(&config.endpoint);
+        if let Some(configured) = &config.host {
+            host.clone_from(configured);
+        }
+        let (dcdn_endpoint, dcdn_host) = endpoint_and_host(&config.dcdn.endpoint);
+
+        Self {
+            signer: AliyunSigner::from_credentials(credentials.clone()),
+            credentials,
+            credential_provider: None,
+            client,
+            endpoint,
+            host,
+            dcdn_endpoint,
+            dcdn_host,
+            dcdn_domains: config
+                .dcdn
+                .domains
+                .iter()
+                .map(|domain| domain.to_ascii_lowercase())
+                .collect(),
+            timeout: None,
+            proxy_host: None,
+            retry: config.retry.clone(),
+            refresh_budget: RefreshBudget::new(&config.refresh_budget),
+            domain_status: Mutex::new(HashMap::new()),
+            tasks_cache_config: config.tasks_cache.clone(),
+            tasks_cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sign with `credentials`, which the STS refresh task may rotate at any time
+    pub fn with_credentials(mut self, credentials: SharedCredentials) -> Self {
+        self.signer = AliyunSigner::from_credentials(credentials.clone());
+        self.credentials = credentials;
+        self
+    }
+
+    /// Fetch the credentials from `provider` before a call when they have expired
+    pub fn with_credential_provider(mut self, provider: Arc<CredentialProvider>) -> Self {
+        self.credential_provider = Some(provider);
+        self
+    }
+
+    /// Bound every API call to `timeout` instead of the shared client's default
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Name `proxy_host` in errors when the proxy refuses the connection
+    pub fn with_proxy_host(mut self, proxy_host: Option<String>) -> Self {
+        self.proxy_host = proxy_host;
+        self
+    }
+
+    /// Local budget every refresh is counted against
+    pub fn refresh_budget(&self) -> &RefreshBudget {
+        &self.refresh_budget
+    }
 
-        Self { signer, client }
+    /// Call any CDN OpenAPI action and parse its JSON response
+    ///
+    /// Handles signing, sending, metrics and Aliyun error bodies. Throttled and server-side
+    /// failures are retried as `aliyun.retry` says, each attempt signed anew. Errors whose
+    /// `Code` ends in `NotFound` become [`AppError::NotFound`].
+    pub async fn call<T: DeserializeOwned>(
+        &self,
+        action: &str,
+        version: &str,
+        method: reqwest::Method,
+        query_params: BTreeMap<String, String>,
+        form_body: Option<String>,
+    ) -> AppResult<T> {
+        self.call_parts(CallParts {
+            product: CdnProduct::Cdn,
+            action,
+            version,
+            method: &method,
+            path: "/".to_string(),
+            query_params,
+            form_body,
+        })
+        .await
     }
 
-    /// Call RefreshObjectCaches API
+    /// Call an ROA-style action, whose resource is named by `path` rather than parameters
+    ///
+    /// `path` is unencoded, e.g. a template filled by [`fill_path`]; it's signed and sent
+    /// with each segment percent-encoded. Otherwise like [`Self::call`].
+    pub async fn call_path<T: DeserializeOwned>(
+        &self,
+        action: &str,
+        version: &str,
+        method: reqwest::Method,
+        path: &str,
+        query_params: BTreeMap<String, String>,
+        form_body: Option<String>,
+    ) -> AppResult<T> {
+        self.call_parts(CallParts {
+            product: CdnProduct::Cdn,
+            action,
+            version,
+            method: &method,
+            path: path.to_string(),
+            query_params,
+            form_body,
+        })
+        .await
+    }
+
+    /// Call any CDN OpenAPI action once and hand back Aliyun's answer, error or not
+    ///
+    /// Unlike [`Self::call`], nothing is retried and error bodies are returned as they are.
+    pub async fn invoke(
+        &self,
+        action: &str,
+        version: &str,
+        method: reqwest::Method,
+        query_params: BTreeMap<String, String>,
+        form_params: Option<BTreeMap<String, String>>,
+    ) -> AppResult<InvokeAliyunResult> {
+        if let Some(provider) = &self.credential_provider {
+            provider
+                .refresh_if_expired(&self.client, &self.credentials)
+                .await
+                .context("Failed to fetch expired Aliyun credentials")?;
+        }
+        let form_body = form_params
+            .map(serde_urlencoded::to_string)
+            .transpose()
+            .context("Failed to encode form parameters")?;
+        let prepared = self.prepare(
+            CallParts {
+                product: CdnProduct::Cdn,
+                action,
+                version,
+                method: &method,
+                path: "/".to_string(),
+                query_params,
+                form_body,
+            },
+            None,
+        )?;
+        let mut request = self
+            .client
+            .request(method, &prepared.url)
+            .headers(prepared.headers);
+        if let Some(body) = prepared.body {
+            request = request.body(body);
+        }
+        let (status, body) = self.send(action, request).await?;
+        let aliyun_request_id = aliyun_request_id(&body);
+        let body = serde_json::from_str(&body)
+            .with_context(|| format!("Failed to parse {action} response"))?;
+        Ok(InvokeAliyunResult {
+            status: status.as_u16(),
+            body,
+            aliyun_request_id,
+        })
+    }
+
+    /// Send `parts` to its product's endpoint, as [`Self::call`] does
+    async fn call_parts<T: DeserializeOwned>(&self, parts: CallParts<'_>) -> AppResult<T> {
+        let action = parts.action;
+        if let Some(provider) = &self.credential_provider {
+            provider
+                .refresh_if_expired(&self.client, &self.credentials)
+                .await
+                .context("Failed to fetch expired Aliyun credentials")?;
+        }
+        let mut attempt = 1;
+        let body = loop {
+            let prepared = self.prepare(
+                CallParts {
+                    path: parts.path.clone(),
+                    query_params: parts.query_params.clone(),
+                    form_body: parts.form_body.clone(),
+                    ..parts
+                },
+                None,
+            )?;
+
+            let mut request = self
+                .client
+                .request(parts.method.clone(), &prepared.url)
+                .headers(prepared.headers);
+            if let Some(body) = prepared.body {
+                request = request.body(body);
+            }
+            let (status, body) = self.send(action, request).await?;
+            if status.is_success() {
+                break body;
+            }
+
+            let code = aliyun_error_code(status, &body);
+            if attempt >= self.retry.max_attempts.get() || !is_retryable(status, &code) {
+                return Err(aliyun_error(status, &body));
+            }
+            let delay = retry_delay(self.retry.base_delay_ms, attempt);
+            warn!(
+                action,
+                code,
+                attempt,
+                retry_in_ms = delay.as_millis() as u64,
+                "Aliyun call failed, retrying"
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        };
+
+        let result = serde_json::from_str(&body)
+            .with_context(|| format!("Failed to parse {action} response"))?;
+        Ok(result)
+    }
+
+    /// Product serving `url`: DCDN when its host is listed in `aliyun.dcdn.domains`
+    pub fn product_for(&self, url: &str) -> CdnProduct {
+        if self
+            .dcdn_domains
+            .contains(&url_host(url).to_ascii_lowercase())
+        {
+            CdnProduct::Dcdn
+        } else {
+            CdnProduct::Cdn
+        }
+    }
+
+    /// Split `request` into one request per product, each with `product` set
+    ///
+    /// A request naming its product is kept whole; otherwise paths are grouped by
+    /// [`Self::product_for`], in order of first appearance.
+    pub fn route(&self, request: &RefreshObjectCachesRequest) -> Vec<RefreshObjectCachesRequest> {
+        if request.product.is_some() {
+            return vec![request.clone()];
+        }
+        let mut routed: Vec<RefreshObjectCachesRequest> = Vec::new();
+        for path in request.object_path.lines() {
+            if path.trim().is_empty() {
+                continue;
+            }
+            let product = self.product_for(path.trim());
+            match routed
+                .iter_mut()
+                .find(|routed| routed.product == Some(product))
+            {
+                Some(routed) => {
+                    routed.object_path.push('\n');
+                    routed.object_path.push_str(path);
+                }
+                None => routed.push(RefreshObjectCachesRequest {
+                    object_path: path.to_string(),
+                    product: Some(product),
+                    ..request.clone()
+                }),
+            }
+        }
+        if routed.is_empty() {
+            routed.push(RefreshObjectCachesRequest {
+                product: Some(CdnProduct::Cdn),
+                ..request.clone()
+            });
+        }
+        routed
+    }
+
+    /// Call RefreshObjectCaches API, or RefreshDcdnObjectCaches for DCDN paths
+    ///
+    /// A request spanning both products makes one call each, and their task ids are joined
+    /// with commas like Aliyun's own. Its URLs are first counted against the refresh budget,
+    /// failing with [`AppError::RateLimited`] while it has no room for them.
     ///
     /// # Arguments
     /// * `request` - Request parameters
@@ -110,72 +789,1773 @@ impl AliyunCdnClient {
         &self,
         request: &RefreshObjectCachesRequest,
     ) -> AppResult<RefreshObjectCachesResponse> {
-        // RefreshObjectCaches is a POST request with parameters in an HTML form body.
-        // Reference: https://help.aliyun.com/zh/cdn/developer-reference/api-cdn-2018-05-10-refreshobjectcaches
-        let form_params = RefreshObjectCachesFormParams {
-            object_path: request.object_path.clone(),
-            object_type: request.object_type.clone(),
-            force: request.force,
+        // Malformed paths would never be sent, so they mustn't use up the budget either
+        super::validate_object_paths(
+            &request.object_path,
+            request.object_type.as_deref().unwrap_or("File"),
+        )?;
+        self.refresh_budget
+            .take(request.object_path.lines().count())?;
+        let mut merged: Option<RefreshObjectCachesResponse> = None;
+        for request in self.route(request) {
+            let response = self.call_parts(refresh_parts(&request)?).await?;
+            match &mut merged {
+                Some(merged) => merged.append(response),
+                None => merged = Some(response),
+            }
+        }
+        Ok(merged.expect("route returns at least one request"))
+    }
+
+    /// Encode and sign the calls of [`Self::refresh_object_caches`], but never send them
+    ///
+    /// Returns a response carrying [`DRY_RUN_TASK_ID`] so callers can treat both alike.
+    pub fn refresh_object_caches_dry_run(
+        &self,
+        request: &RefreshObjectCachesRequest,
+    ) -> AppResult<RefreshObjectCachesResponse> {
+        for request in self.route(request) {
+            self.prepare(refresh_parts(&request)?, None)?;
+        }
+        Ok(RefreshObjectCachesResponse {
+            request_id: DRY_RUN_TASK_ID.to_string(),
+            refresh_task_id: DRY_RUN_TASK_ID.to_string(),
+        })
+    }
+
+    /// Submit `request` with [`Self::refresh_object_caches`], then wait for its task with
+    /// [`Self::wait_for_refresh_task`]
+    ///
+    /// See [`Self::ensure_waitable`] for the refreshes that can be waited on.
+    pub async fn refresh_object_caches_and_wait(
+        &self,
+        request: &RefreshObjectCachesRequest,
+        timeout: Duration,
+        poll_interval: Duration,
+    ) -> AppResult<RefreshAndWaitResult> {
+        self.ensure_waitable(request)?;
+        let response = self.refresh_object_caches(request).await?;
+        let mut result = self
+            .wait_for_refresh_task(&response.refresh_task_id, timeout, poll_interval)
+            .await?;
+        result.aliyun_request_id = Some(response.request_id);
+        Ok(result)
+    }
+
+    /// Fail with [`AppError::BadRequest`] unless `request` is sent as a single CDN call
+    ///
+    /// DescribeRefreshTasks lists neither DCDN tasks nor the several tasks of a split refresh.
+    pub fn ensure_waitable(&self, request: &RefreshObjectCachesRequest) -> AppResult<()> {
+        let object_type = request.object_type.as_deref().unwrap_or("File");
+        super::validate_object_paths(&request.object_path, object_type)?;
+        let routed = self.route(request);
+        if routed.len() != 1 || routed[0].product != Some(CdnProduct::Cdn) {
+            return Err(AppError::BadRequest(anyhow::anyhow!(
+                "Only refreshes of CDN domains can be waited on, DCDN tasks aren't listed by DescribeRefreshTasks"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Poll DescribeRefreshTasks for `task_id` every `poll_interval` until Aliyun reports it
+    /// `Complete` or `Failed`, or `timeout` has passed
+    ///
+    /// The first look is immediate. A task that isn't listed yet counts as still running.
+    /// Running out of time isn't an error: the result is marked `timed_out` and carries the
+    /// last status seen. `aliyun_request_id` is left for the caller that submitted the task.
+    pub async fn wait_for_refresh_task(
+        &self,
+        task_id: &str,
+        timeout: Duration,
+        poll_interval: Duration,
+    ) -> AppResult<RefreshAndWaitResult> {
+        let started = Instant::now();
+        let deadline = started + timeout;
+        let payload = DescribeRefreshTasksPayload {
+            task_id: Some(task_id.to_string()),
+            ..Default::default()
         };
+        let mut status = None;
+        loop {
+            let response = self.describe_refresh_tasks(&payload).await?;
+            status = response
+                .tasks
+                .cdn_tasks
+                .iter()
+                .find(|task| task.task_id == task_id)
+                .map(|task| task.status)
+                .or(status);
+            let finished = status.is_some_and(RefreshTaskStatus::is_terminal);
+            let now = Instant::now();
+            if finished || now >= deadline {
+                return Ok(RefreshAndWaitResult {
+                    task_id: task_id.to_string(),
+                    status,
+                    elapsed_seconds: now.duration_since(started).as_secs_f64(),
+                    timed_out: !finished,
+                    aliyun_request_id: None,
+                });
+            }
+            // The last look is right at the deadline
+            tokio::time::sleep(poll_interval.min(deadline - now)).await;
+        }
+    }
 
-        let form_body = serde_urlencoded::to_string(&form_params)
-            .context("Failed to encode form parameters")?;
+    /// Call PushObjectCache API
+    ///
+    /// # Arguments
+    /// * `request` - Request parameters
+    ///
+    /// # Returns
+    /// Response containing the preload task ID
+    pub async fn push_object_caches(
+        &self,
+        request: &PushObjectCachesRequest,
+    ) -> AppResult<PushObjectCachesResponse> {
+        self.call_parts(push_parts(request)?).await
+    }
 
-        // Sign the request (ACS3-HMAC-SHA256). For this API, the form body must be included
-        // in the body hash, so keep the canonical query empty.
-        let signed = self
-            .signer
-            .sign_request(AliyunSignInput {
-                method: "POST",
-                host: CDN_HOST,
-                canonical_uri: "/",
-                action: "RefreshObjectCaches",
-                version: "2018-05-10",
-                query_params: BTreeMap::new(),
-                body: form_body.as_bytes(),
-                content_type: Some("application/x-www-form-urlencoded"),
-                extra_headers: BTreeMap::new(),
+    /// Preload newline-separated `object_path` in as many PushObjectCache calls as it takes
+    ///
+    /// The task ids of all calls are joined with commas; the first failed call fails the whole
+    /// preload.
+    pub async fn preload_object_caches(
+        &self,
+        object_path: &str,
+        area: Option<String>,
+    ) -> AppResult<PushObjectCachesResponse> {
+        let paths = object_path.lines().collect::<Vec<_>>();
+        let mut merged: Option<PushObjectCachesResponse> = None;
+        for chunk in paths.chunks(MAX_PUSH_PATHS) {
+            let response = self
+                .push_object_caches(&PushObjectCachesRequest {
+                    object_path: chunk.join("\n"),
+                    area: area.clone(),
+                    l2_preload: None,
+                })
+                .await?;
+            match &mut merged {
+                Some(merged) => merged.append(response),
+                None => merged = Some(response),
+            }
+        }
+        merged.ok_or_else(|| AppError::BadRequest(anyhow::anyhow!("object_path is empty")))
+    }
+
+    /// Call DescribeRefreshTaskById API
+    ///
+    /// `task_ids` is a single id or up to 10 comma-separated ones. Fails with
+    /// [`AppError::NotFound`] when none of them exist.
+    pub async fn describe_refresh_task_by_id(
+        &self,
+        task_ids: &str,
+    ) -> AppResult<DescribeRefreshTaskByIdResponse> {
+        self.describe_refresh_task_by_id_on(CdnProduct::Cdn, task_ids)
+            .await
+    }
+
+    /// Call DescribeRefreshTaskById, or DescribeDcdnRefreshTaskById for tasks created on DCDN
+    pub async fn describe_refresh_task_by_id_on(
+        &self,
+        product: CdnProduct,
+        task_ids: &str,
+    ) -> AppResult<DescribeRefreshTaskByIdResponse> {
+        // DescribeRefreshTaskById is a GET request with parameters in the query string.
+        // Reference: https://help.aliyun.com/zh/cdn/developer-reference/api-cdn-2018-05-10-describerefreshtaskbyid
+        let result: DescribeRefreshTaskByIdResponse = self
+            .call_parts(CallParts {
+                product,
+                action: product.describe_task_action(),
+                version: product.version(),
+                method: &reqwest::Method::GET,
+                path: "/".to_string(),
+                query_params: BTreeMap::from([("TaskId".to_string(), task_ids.to_string())]),
+                form_body: None,
             })
-            .context("Failed to sign Aliyun request")?;
+            .await?;
+        if result.tasks.is_empty() {
+            return Err(AppError::NotFound(anyhow::anyhow!(
+                "Refresh task {} not found",
+                task_ids
+            )));
+        }
 
-        let query_string = signed.query_string;
-        let headers = signed.headers;
+        Ok(result)
+    }
 
-        let url = if query_string.is_empty() {
-            format!("{}/", CDN_ENDPOINT)
-        } else {
-            format!("{}/?{}", CDN_ENDPOINT, query_string)
+    /// Call DescribeRefreshQuota API for the remaining refresh and preload quota of today
+    pub async fn describe_refresh_quota(&self) -> AppResult<DescribeRefreshQuotaResponse> {
+        // DescribeRefreshQuota is a GET request without parameters.
+        // Reference: https://help.aliyun.com/zh/cdn/developer-reference/api-cdn-2018-05-10-describerefreshquota
+        self.call(
+            "DescribeRefreshQuota",
+            CDN_API_VERSION,
+            reqwest::Method::GET,
+            BTreeMap::new(),
+            None,
+        )
+        .await
+    }
+
+    /// Call DescribeUserDomains for one page of CDN domains matching `payload`
+    pub async fn describe_user_domains(
+        &self,
+        payload: &DescribeUserDomainsPayload,
+    ) -> AppResult<DescribeUserDomainsResponse> {
+        // Reference: https://help.aliyun.com/zh/cdn/developer-reference/api-cdn-2018-05-10-describeuserdomains
+        let params = [
+            ("DomainName", payload.domain_name.clone()),
+            ("DomainStatus", payload.domain_status.clone()),
+            ("PageNumber", payload.page_number.map(|n| n.to_string())),
+            ("PageSize", payload.page_size.map(|n| n.to_string())),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value?)))
+        .collect();
+        self.call(
+            "DescribeUserDomains",
+            CDN_API_VERSION,
+            reqwest::Method::GET,
+            params,
+            None,
+        )
+        .await
+    }
+
+    /// Call DescribeCdnDomainDetail for the configuration of `domain_name`
+    ///
+    /// An unknown domain (`InvalidDomain.NotFound`) is [`AppError::NotFound`].
+    pub async fn describe_cdn_domain_detail(
+        &self,
+        domain_name: &str,
+    ) -> AppResult<CdnDomainDetail> {
+        // Reference: https://help.aliyun.com/zh/cdn/developer-reference/api-cdn-2018-05-10-describecdndomaindetail
+        let response: DomainDetailResponse = self
+            .call(
+                "DescribeCdnDomainDetail",
+                CDN_API_VERSION,
+                reqwest::Method::GET,
+                BTreeMap::from([("DomainName".to_string(), domain_name.to_string())]),
+                None,
+            )
+            .await?;
+        Ok(response.into())
+    }
+
+    /// Call DescribeDomainBpsData for the bandwidth of `domain_name` between `start` and `end`
+    pub async fn describe_domain_bps_data(
+        &self,
+        domain_name: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        interval: Option<u32>,
+    ) -> AppResult<DomainBpsData> {
+        // Reference: https://help.aliyun.com/zh/cdn/developer-reference/api-cdn-2018-05-10-describedomainbpsdata
+        let response = self
+            .domain_stats("DescribeDomainBpsData", domain_name, start, end, interval)
+            .await?;
+        Ok(DomainBpsData {
+            request_id: response.request_id,
+            domain_name: response.domain_name,
+            start_time: response.start_time,
+            end_time: response.end_time,
+            interval: response.interval as u64,
+            data: response
+                .series
+                .points
+                .into_iter()
+                .map(|point| BpsDataPoint {
+                    time_stamp: point.time_stamp,
+                    bps: point.value,
+                    https_bps: point.https_value,
+                })
+                .collect(),
+        })
+    }
+
+    /// Call DescribeDomainHitRateData for the byte hit rate of `domain_name` between `start` and
+    /// `end`
+    pub async fn describe_domain_hit_rate_data(
+        &self,
+        domain_name: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        interval: Option<u32>,
+    ) -> AppResult<DomainHitRateData> {
+        // Reference: https://help.aliyun.com/zh/cdn/developer-reference/api-cdn-2018-05-10-describedomainhitratedata
+        let response = self
+            .domain_stats(
+                "DescribeDomainHitRateData",
+                domain_name,
+                start,
+                end,
+                interval,
+            )
+            .await?;
+        Ok(DomainHitRateData {
+            request_id: response.request_id,
+            domain_name: response.domain_name,
+            start_time: response.start_time,
+            end_time: response.end_time,
+            interval: response.interval as u64,
+            data: response
+                .series
+                .points
+                .into_iter()
+                .map(|point| HitRateDataPoint {
+                    time_stamp: point.time_stamp,
+                    hit_rate: point.value,
+                    https_hit_rate: point.https_value,
+                })
+                .collect(),
+        })
+    }
+
+    /// Call a statistics action taking a domain, a window in UTC and an interval
+    async fn domain_stats(
+        &self,
+        action: &str,
+        domain_name: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        interval: Option<u32>,
+    ) -> AppResult<DomainStatsResponse> {
+        // Aliyun takes UTC without fractional seconds, e.g. `2026-10-16T00:00:00Z`
+        let format = "%Y-%m-%dT%H:%M:%SZ";
+        let mut params = BTreeMap::from([
+            ("DomainName".to_string(), domain_name.to_string()),
+            ("StartTime".to_string(), start.format(format).to_string()),
+            ("EndTime".to_string(), end.format(format).to_string()),
+        ]);
+        if let Some(interval) = interval {
+            params.insert("Interval".to_string(), interval.to_string());
+        }
+        self.call(action, CDN_API_VERSION, reqwest::Method::GET, params, None)
+            .await
+    }
+
+    /// Whether `domain` is onboarded to CDN and online, looked up at most every 5 minutes
+    pub async fn is_domain_online(&self, domain: &str) -> AppResult<bool> {
+        let domain = domain.to_ascii_lowercase();
+        if let Some((online, checked)) = self
+            .domain_status
+            .lock()
+            .expect("domain status lock poisoned")
+            .get(&domain)
+            && checked.elapsed() < DOMAIN_STATUS_TTL
+        {
+            return Ok(*online);
+        }
+
+        // DomainName is a fuzzy match, so look for the exact name among the results
+        let response = self
+            .describe_user_domains(&DescribeUserDomainsPayload {
+                domain_name: Some(domain.clone()),
+                page_size: Some(MAX_USER_DOMAINS_PAGE_SIZE),
+                ..DescribeUserDomainsPayload::default()
+            })
+            .await?;
+        let online =
+            response.domains.page_data.iter().any(|listed| {
+                listed.domain_name.eq_ignore_ascii_case(&domain) && listed.is_online()
+            });
+        self.domain_status
+            .lock()
+            .expect("domain status lock poisoned")
+            .insert(domain, (online, Instant::now()));
+        Ok(online)
+    }
+
+    /// Call DescribeRefreshTasks for one page of tasks matching `payload`
+    ///
+    /// `fetch_all` is ignored here, see [`Self::describe_refresh_tasks_all`].
+    pub async fn describe_refresh_tasks(
+        &self,
+        payload: &DescribeRefreshTasksPayload,
+    ) -> AppResult<DescribeRefreshTasksResponse> {
+        // Reference: https://help.aliyun.com/zh/cdn/developer-reference/api-cdn-2018-05-10-describerefreshtasks
+        let params = [
+            ("DomainName", payload.domain_name.clone()),
+            ("ObjectPath", payload.object_path.clone()),
+            ("TaskId", payload.task_id.clone()),
+            (
+                "ObjectType",
+                payload
+                    .object_type
+                    .map(|object_type| object_type.as_str().to_string()),
+            ),
+            (
+                "Status",
+                payload.status.map(|status| status.as_str().to_string()),
+            ),
+            ("StartTime", payload.start_time.clone()),
+            ("EndTime", payload.end_time.clone()),
+            ("PageNumber", payload.page_number.map(|n| n.to_string())),
+            ("PageSize", payload.page_size.map(|n| n.to_string())),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value?)))
+        .collect();
+        self.call(
+            "DescribeRefreshTasks",
+            CDN_API_VERSION,
+            reqwest::Method::GET,
+            params,
+            None,
+        )
+        .await
+    }
+
+    /// List refresh tasks like [`Self::describe_refresh_tasks`], or with `payload.fetch_all`
+    /// like [`Self::describe_refresh_tasks_all`], through `aliyun.tasks_cache`
+    ///
+    /// Only successful answers are cached. The status is `None` with the cache disabled.
+    pub async fn describe_refresh_tasks_cached(
+        &self,
+        payload: &DescribeRefreshTasksPayload,
+        fetch_all: &AliyunFetchAllConfig,
+    ) -> AppResult<(DescribeRefreshTasksResponse, Option<CacheStatus>)> {
+        let list = async {
+            if payload.fetch_all {
+                self.describe_refresh_tasks_all(
+                    payload,
+                    fetch_all.max_pages.get(),
+                    Duration::from_millis(fetch_all.page_delay_ms),
+                )
+                .await
+            } else {
+                self.describe_refresh_tasks(payload).await
+            }
         };
+        let config = &self.tasks_cache_config;
+        if !config.enabled || config.max_entries == 0 {
+            return Ok((list.await?, None));
+        }
+
+        let key = serde_json::to_string(payload).context("Failed to encode refresh task query")?;
+        let ttl = Duration::from_secs(config.ttl_secs);
+        if let Some((response, cached)) = self
+            .tasks_cache
+            .lock()
+            .expect("tasks cache lock poisoned")
+            .get(&key)
+            && cached.elapsed() < ttl
+        {
+            return Ok((response.clone(), Some(CacheStatus::Hit)));
+        }
+
+        let response = list.await?;
+        let mut cache = self.tasks_cache.lock().expect("tasks cache lock poisoned");
+        cache.retain(|_, (_, cached)| cached.elapsed() < ttl);
+        if cache.len() >= config.max_entries
+            && let Some(oldest) = cache
+                .iter()
+                .min_by_key(|(_, (_, cached))| *cached)
+                .map(|(key, _)| key.clone())
+        {
+            cache.remove(&oldest);
+        }
+        cache.insert(key, (response.clone(), Instant::now()));
+        Ok((response, Some(CacheStatus::Miss)))
+    }
 
-        // Send request
+    /// Follow DescribeRefreshTasks pages of [`MAX_REFRESH_TASKS_PAGE_SIZE`] (unless `payload`
+    /// sets a smaller size) until `TotalCount` tasks are fetched, waiting `page_delay` between
+    /// calls
+    ///
+    /// Stops after `max_pages` or `payload.max_results` tasks, setting `warning` on the combined
+    /// response. Totals that shift between calls can't make it loop: a short, empty or repeated
+    /// page ends the listing.
+    pub async fn describe_refresh_tasks_all(
+        &self,
+        payload: &DescribeRefreshTasksPayload,
+        max_pages: u32,
+        page_delay: Duration,
+    ) -> AppResult<DescribeRefreshTasksResponse> {
+        let page_size = payload.page_size.unwrap_or(MAX_REFRESH_TASKS_PAGE_SIZE);
+        let mut page_payload = DescribeRefreshTasksPayload {
+            page_number: Some(1),
+            page_size: Some(page_size),
+            fetch_all: false,
+            max_results: None,
+            ..payload.clone()
+        };
+        let max_results = payload.max_results.map_or(usize::MAX, |max| max as usize);
+        let mut combined = self.describe_refresh_tasks(&page_payload).await?;
+        let mut seen = combined
+            .tasks
+            .cdn_tasks
+            .iter()
+            .map(|task| task.task_id.clone())
+            .collect::<HashSet<_>>();
+        let mut last_page_len = combined.tasks.cdn_tasks.len();
+        let mut pages = 1;
+        loop {
+            let fetched = combined.tasks.cdn_tasks.len();
+            if fetched as u64 >= combined.total_count || last_page_len < page_size as usize {
+                break;
+            }
+            if fetched >= max_results {
+                combined.warning = Some(format!(
+                    "Stopped at max_results with {max_results} of {} tasks",
+                    combined.total_count
+                ));
+                break;
+            }
+            if pages >= max_pages {
+                combined.warning = Some(format!(
+                    "Stopped after {pages} pages with {fetched} of {} tasks",
+                    combined.total_count
+                ));
+                break;
+            }
+
+            tokio::time::sleep(page_delay).await;
+            pages += 1;
+            page_payload.page_number = Some(pages);
+            let page = self.describe_refresh_tasks(&page_payload).await?;
+            last_page_len = page.tasks.cdn_tasks.len();
+            let new_tasks = page
+                .tasks
+                .cdn_tasks
+                .into_iter()
+                .filter(|task| seen.insert(task.task_id.clone()))
+                .collect::<Vec<_>>();
+            // Tasks finishing between calls can shrink the listing; don't spin on empty pages,
+            // nor on pages Aliyun already sent
+            if new_tasks.is_empty() {
+                if last_page_len > 0 {
+                    combined.warning = Some(format!(
+                        "Page {pages} repeated earlier tasks, stopped with {fetched} of {} tasks",
+                        page.total_count
+                    ));
+                }
+                break;
+            }
+            combined.total_count = page.total_count;
+            combined.tasks.cdn_tasks.extend(new_tasks);
+        }
+        if combined.tasks.cdn_tasks.len() > max_results {
+            combined.tasks.cdn_tasks.truncate(max_results);
+            combined.warning.get_or_insert_with(|| {
+                format!(
+                    "Stopped at max_results with {max_results} of {} tasks",
+                    combined.total_count
+                )
+            });
+        }
+        combined.page_number = 1;
+        combined.page_size = combined.tasks.cdn_tasks.len() as u64;
+        Ok(combined)
+    }
+
+    /// Call DescribeCdnDomainLogs for one page of `payload.domain_name`'s access log files
+    pub async fn describe_cdn_domain_logs(
+        &self,
+        payload: &DescribeCdnDomainLogsPayload,
+    ) -> AppResult<CdnDomainLogsResponse> {
+        // Reference: https://help.aliyun.com/zh/cdn/developer-reference/api-cdn-2018-05-10-describecdndomainlogs
+        let params = [
+            ("DomainName", Some(payload.domain_name.clone())),
+            ("StartTime", payload.start_time.clone()),
+            ("EndTime", payload.end_time.clone()),
+            ("PageNumber", payload.page_number.map(|n| n.to_string())),
+            ("PageSize", payload.page_size.map(|n| n.to_string())),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value?)))
+        .collect();
+        let response: DomainLogsResponse = self
+            .call(
+                "DescribeCdnDomainLogs",
+                CDN_API_VERSION,
+                reqwest::Method::GET,
+                params,
+                None,
+            )
+            .await?;
+
+        // An unknown domain or empty window may come without any detail
+        let detail = response
+            .details
+            .details
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| DomainLogDetail {
+                domain_name: payload.domain_name.clone(),
+                ..DomainLogDetail::default()
+            });
+        Ok(CdnDomainLogsResponse {
+            request_id: response.request_id,
+            domain_name: detail.domain_name,
+            page_number: detail.page_infos.page_index,
+            page_size: detail.page_infos.page_size,
+            total_count: detail.page_infos.total,
+            logs: detail
+                .log_infos
+                .files
+                .into_iter()
+                .map(|file| CdnLogFile {
+                    download_url: download_url(&file.log_path),
+                    name: file.log_name,
+                    size: file.log_size,
+                    start_time: file.start_time,
+                    end_time: file.end_time,
+                })
+                .collect(),
+        })
+    }
+
+    /// Start downloading a log file listed by [`Self::describe_cdn_domain_logs`]
+    ///
+    /// The body is left unread so it can be streamed.
+    pub async fn open_domain_log(&self, log: &CdnLogFile) -> AppResult<reqwest::Response> {
         let response = self
             .client
-            .post(&url)
-            .headers(headers)
-            .body(form_body)
+            .get(&log.download_url)
+            .timeout(LOG_DOWNLOAD_TIMEOUT)
             .send()
             .await
-            .context("Failed to send RefreshObjectCaches request")?;
+            .map_err(|err| {
+                let what = format!("Failed to download log {}", log.name);
+                request_error(err, &what, self.proxy_host.as_deref())
+            })?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(AppError::InternalError(anyhow::anyhow!(
+                "Downloading log {} failed with status {}",
+                log.name,
+                status
+            )));
+        }
+        Ok(response)
+    }
+
+    /// Send a signed call and read its body, recording the outcome under `action`
+    async fn send(
+        &self,
+        action: &str,
+        mut request: reqwest::RequestBuilder,
+    ) -> AppResult<(reqwest::StatusCode, String)> {
+        if let Some(timeout) = self.timeout {
+            request = request.timeout(timeout);
+        }
+
+        let started = Instant::now();
+        let response = request.send().await.map_err(|err| {
+            record_aliyun_call(action, "network_error", started.elapsed());
+            let what = format!("Failed to send {action} request");
+            request_error(err, &what, self.proxy_host.as_deref())
+        })?;
 
-        // Parse response
         let status = response.status();
+        let header_request_id = response
+            .headers()
+            .get("x-acs-request-id")
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
         let body = response
             .text()
             .await
             .context("Failed to read response body")?;
 
-        if !status.is_success() {
-            return Err(AppError::InternalError(anyhow::anyhow!(
-                "Aliyun API error (status {}): {}",
-                status,
-                body
-            )));
+        // Support tickets need it, so it goes on the span of the request being served
+        if let Some(request_id) = aliyun_request_id(&body).or(header_request_id) {
+            Span::current().record("aliyun_request_id", request_id.as_str());
+            debug!(
+                action,
+                status = status.as_u16(),
+                aliyun_request_id = request_id,
+                "Aliyun answered"
+            );
         }
 
-        // Parse JSON response
-        let result: RefreshObjectCachesResponse =
-            serde_json::from_str(&body).context("Failed to parse RefreshObjectCaches response")?;
+        let result = if status.is_success() {
+            "ok".to_string()
+        } else {
+            aliyun_error_code(status, &body)
+        };
+        record_aliyun_call(action, &result, started.elapsed());
 
-        Ok(result)
+        Ok((status, body))
+    }
+
+    /// Sign a call (ACS3-HMAC-SHA256), with a fixed date and nonce when `stamp` is given
+    fn prepare(
+        &self,
+        parts: CallParts<'_>,
+        stamp: Option<(&str, &str)>,
+    ) -> AppResult<PreparedCall> {
+        let (endpoint, host) = match parts.product {
+            CdnProduct::Cdn => (&self.endpoint, &self.host),
+            CdnProduct::Dcdn => (&self.dcdn_endpoint, &self.dcdn_host),
+        };
+        let body = parts.form_body.as_deref().unwrap_or_default();
+        let input = AliyunSignInput {
+            method: parts.method.as_str(),
+            host,
+            canonical_uri: &parts.path,
+            action: parts.action,
+            version: parts.version,
+            query_params: parts.query_params.into(),
+            body: body.as_bytes(),
+            content_type: parts
+                .form_body
+                .as_ref()
+                .map(|_| "application/x-www-form-urlencoded"),
+            extra_headers: BTreeMap::new(),
+        };
+        let signed = match stamp {
+            Some((date, nonce)) => self.signer.sign_request_at(input, date, nonce),
+            None => self.signer.sign_request(input),
+        }
+        .context("Failed to sign Aliyun request")?;
+
+        let url = if signed.query_string.is_empty() {
+            format!("{endpoint}{}", signed.canonical_uri)
+        } else {
+            format!("{endpoint}{}?{}", signed.canonical_uri, signed.query_string)
+        };
+
+        Ok(PreparedCall {
+            url,
+            headers: signed.headers,
+            body: parts.form_body,
+        })
+    }
+}
+
+/// Fill the `{name}` segments of an ROA-style path template such as `/v2/rules/{id}`
+///
+/// Values are left unencoded, the signer encodes each segment; a value holding `/` would
+/// change which resource the path names, so it's refused.
+pub fn fill_path(template: &str, params: &BTreeMap<&str, &str>) -> AppResult<String> {
+    template
+        .split('/')
+        .map(|segment| {
+            let Some(name) = segment
+                .strip_prefix('{')
+                .and_then(|rest| rest.strip_suffix('}'))
+            else {
+                return Ok(segment);
+            };
+            match params.get(name) {
+                Some(value) if value.contains('/') => Err(AppError::BadRequest(anyhow::anyhow!(
+                    "path parameter {name} must not contain '/', got '{value}'"
+                ))),
+                Some(value) => Ok(*value),
+                None => Err(AppError::InternalError(anyhow::anyhow!(
+                    "path parameter {name} of {template} is missing"
+                ))),
+            }
+        })
+        .collect::<AppResult<Vec<_>>>()
+        .map(|segments| segments.join("/"))
+}
+
+/// `endpoint` without trailing slash, and its host part signed as the `host` header
+///
+/// The host keeps a non-default port, e.g. `localhost:8080`, as the `host` header carries it.
+fn endpoint_and_host(endpoint: &str) -> (String, String) {
+    let endpoint = endpoint.trim_end_matches('/').to_string();
+    let host = endpoint
+        .split_once("://")
+        .map_or(endpoint.as_str(), |(_, rest)| rest)
+        .split('/')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    (endpoint, host)
+}
+
+/// Log paths come without a scheme, e.g. `cdnlog.cn-hangzhou.oss.aliyun-inc.com/...`
+fn download_url(log_path: &str) -> String {
+    if log_path.starts_with("http://") || log_path.starts_with("https://") {
+        log_path.to_string()
+    } else {
+        format!("https://{log_path}")
+    }
+}
+
+/// RefreshObjectCaches is a POST request with parameters in an HTML form body
+///
+/// The form body is included in the body hash, so the canonical query stays empty.
+/// RefreshDcdnObjectCaches takes the same parameters. `request.product` defaults to CDN.
+/// Paths are validated first, failing with [`AppError::BadRequest`] before anything is sent.
+/// Reference: https://help.aliyun.com/zh/cdn/developer-reference/api-cdn-2018-05-10-refreshobjectcaches
+fn refresh_parts(request: &RefreshObjectCachesRequest) -> AppResult<CallParts<'static>> {
+    // Aliyun's own rejection of a malformed path is far less helpful
+    super::validate_object_paths(
+        &request.object_path,
+        request.object_type.as_deref().unwrap_or("File"),
+    )?;
+    let product = request.product.unwrap_or_default();
+    let form_params = RefreshObjectCachesFormParams {
+        object_path: request.object_path.clone(),
+        object_type: request.object_type.clone(),
+        force: request.force,
+    };
+    let form_body =
+        serde_urlencoded::to_string(&form_params).context("Failed to encode form parameters")?;
+
+    Ok(CallParts {
+        product,
+        action: product.refresh_action(),
+        version: product.version(),
+        method: &reqwest::Method::POST,
+        path: "/".to_string(),
+        query_params: BTreeMap::new(),
+        form_body: Some(form_body),
+    })
+}
+
+/// PushObjectCache is a POST request with parameters in an HTML form body, like
+/// RefreshObjectCaches
+///
+/// Reference: https://help.aliyun.com/zh/cdn/developer-reference/api-cdn-2018-05-10-pushobjectcache
+fn push_parts(request: &PushObjectCachesRequest) -> AppResult<CallParts<'static>> {
+    let form_params = PushObjectCachesFormParams {
+        object_path: request.object_path.clone(),
+        area: request.area.clone(),
+        l2_preload: request.l2_preload,
+    };
+    let form_body =
+        serde_urlencoded::to_string(&form_params).context("Failed to encode form parameters")?;
+
+    Ok(CallParts {
+        product: CdnProduct::Cdn,
+        action: "PushObjectCache",
+        version: CDN_API_VERSION,
+        method: &reqwest::Method::POST,
+        path: "/".to_string(),
+        query_params: BTreeMap::new(),
+        form_body: Some(form_body),
+    })
+}
+
+/// Whether a failed call may succeed when sent again: Aliyun throttled it or failed itself
+fn is_retryable(status: reqwest::StatusCode, code: &str) -> bool {
+    status.is_server_error()
+        || code.starts_with("Throttling")
+        || matches!(code, "ServiceUnavailable" | "InternalError" | "ServiceBusy")
+}
+
+/// Wait before retry `attempt` (from 1): `base_ms` doubled per earlier retry, plus up to half
+/// of that as jitter so throttled callers don't retry in lockstep
+fn retry_delay(base_ms: u64, attempt: u32) -> Duration {
+    let backoff = base_ms.saturating_mul(1 << (attempt - 1).min(16));
+    let jitter = rand::thread_rng().gen_range(0..=backoff / 2);
+    Duration::from_millis(backoff + jitter)
+}
+
+/// Error body Aliyun answers a rejected OpenAPI call with
+///
+/// Reference: https://help.aliyun.com/zh/openapi/common-error-codes
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AliyunApiError {
+    /// HTTP status Aliyun answered with
+    #[serde(skip)]
+    pub status: u16,
+
+    #[serde(rename = "RequestId", default)]
+    pub request_id: String,
+
+    /// e.g. `InvalidObjectPath.Malformed` or `QuotaExceeded.Refresh`
+    #[serde(rename = "Code")]
+    pub code: String,
+
+    #[serde(rename = "Message", default)]
+    pub message: String,
+
+    /// Link to Aliyun's diagnosis of the request
+    #[serde(rename = "Recommend", default, skip_serializing_if = "Option::is_none")]
+    pub recommend: Option<String>,
+}
+
+impl AliyunApiError {
+    /// Parse a non-2xx answer, `None` when the body isn't Aliyun's error JSON
+    pub fn parse(status: reqwest::StatusCode, body: &str) -> Option<Self> {
+        let mut err = serde_json::from_str::<Self>(body).ok()?;
+        err.status = status.as_u16();
+        Some(err)
+    }
+
+    /// Status to answer the caller with
+    ///
+    /// Throttling and exhausted quota are `429`, other rejected parameters `400`. Failures on
+    /// Aliyun's side and rejected credentials are not the caller's to fix, so they are `502`.
+    pub fn status_code(&self) -> reqwest::StatusCode {
+        const CREDENTIAL_CODES: &[&str] = &[
+            "SignatureDoesNotMatch",
+            "IncompleteSignature",
+            "SignatureNonceUsed",
+            "InvalidAccessKeyId",
+            "InvalidSecurityToken",
+            "InvalidTimeStamp",
+        ];
+        if self.code.starts_with("Throttling") || self.code.starts_with("QuotaExceeded") {
+            reqwest::StatusCode::TOO_MANY_REQUESTS
+        } else if self.status == 400
+            && !CREDENTIAL_CODES
+                .iter()
+                .any(|code| self.code.starts_with(code))
+        {
+            reqwest::StatusCode::BAD_REQUEST
+        } else {
+            reqwest::StatusCode::BAD_GATEWAY
+        }
+    }
+}
+
+impl std::fmt::Display for AliyunApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.code)?;
+        if !self.message.is_empty() {
+            write!(f, ": {}", self.message)?;
+        }
+        write!(f, " (status {}, request {})", self.status, self.request_id)
+    }
+}
+
+/// Turn a non-2xx Aliyun answer into an error carrying Aliyun's error, logging the raw body
+fn aliyun_error(status: reqwest::StatusCode, body: &str) -> AppError {
+    warn!(status = status.as_u16(), body, "Aliyun API error");
+    match AliyunApiError::parse(status, body) {
+        Some(err) if err.code.ends_with("NotFound") => {
+            AppError::NotFound(anyhow::anyhow!("Aliyun API error: {}", err))
+        }
+        Some(err) => AppError::Aliyun(err),
+        None => AppError::InternalError(anyhow::anyhow!(
+            "Aliyun API error (status {}): {}",
+            status,
+            body
+        )),
+    }
+}
+
+/// Extract the `RequestId` Aliyun puts in every answer, successful or not
+fn aliyun_request_id(body: &str) -> Option<String> {
+    serde_json::from_str::<serde_json::Value>(body)
+        .ok()?
+        .get("RequestId")?
+        .as_str()
+        .map(str::to_string)
+}
+
+/// Extract the `Code` field of an Aliyun error body, falling back to the HTTP status
+fn aliyun_error_code(status: reqwest::StatusCode, body: &str) -> String {
+    serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|value| value.get("Code")?.as_str().map(str::to_string))
+        .unwrap_or_else(|| status.as_u16().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{body_string_contains, header, method, path, query_param},
+    };
+
+    use super::*;
+    use crate::{aliyun::signature::QueryParams, test_support::test_settings};
+
+    const DATE: &str = "2026-10-16T02:00:00Z";
+    const NONCE: &str = "3156853299f313e23d1673dc12e1703d";
+
+    fn client() -> AliyunCdnClient {
+        AliyunCdnClient::new(&test_settings().aliyun, reqwest::Client::new())
+    }
+
+    #[test]
+    fn test_domain_detail_parses_a_captured_response() {
+        let body = r#"{"RequestId":"18CF38AA-1275-451D-A12B-4EC0BF1C5E30","GetDomainDetailModel":{"GmtModified":"2026-09-02T07:23:14Z","SslProtocol":"on","DomainName":"static.prts.wiki","Description":"","ResourceGroupId":"rg-acfmyuji4b6r4ry","ServerCertificateStatus":"on","Cname":"static.prts.wiki.w.kunlunsl.com","Scope":"domestic","CdnType":"web","DomainStatus":"online","GmtCreated":"2020-05-11T08:04:26Z","SourceModels":{"SourceModel":[{"Type":"oss","Priority":"20","Weight":"10","Enabled":"online","Content":"prts-static.oss-cn-shanghai.aliyuncs.com","Port":443}]}}}"#;
+        let detail =
+            CdnDomainDetail::from(serde_json::from_str::<DomainDetailResponse>(body).unwrap());
+
+        assert_eq!(detail.request_id, "18CF38AA-1275-451D-A12B-4EC0BF1C5E30");
+        assert_eq!(detail.domain_name, "static.prts.wiki");
+        assert_eq!(detail.cname, "static.prts.wiki.w.kunlunsl.com");
+        assert_eq!(detail.domain_status, "online");
+        assert_eq!(detail.ssl_protocol, "on");
+        assert_eq!(detail.server_certificate_status.as_deref(), Some("on"));
+        assert_eq!(detail.gmt_created, "2020-05-11T08:04:26Z");
+        assert_eq!(detail.description, None);
+        assert_eq!(detail.sources.len(), 1);
+        assert_eq!(
+            detail.sources[0].content,
+            "prts-static.oss-cn-shanghai.aliyuncs.com"
+        );
+        assert_eq!(
+            (
+                detail.sources[0].source_type.as_str(),
+                detail.sources[0].port
+            ),
+            ("oss", 443)
+        );
+    }
+
+    #[test]
+    fn test_domain_detail_tolerates_missing_sources() {
+        let body = r#"{"RequestId":"r","GetDomainDetailModel":{"DomainName":"static.prts.wiki","DomainStatus":"configuring"}}"#;
+        let detail =
+            CdnDomainDetail::from(serde_json::from_str::<DomainDetailResponse>(body).unwrap());
+        assert_eq!(detail.domain_status, "configuring");
+        assert!(detail.sources.is_empty());
+    }
+
+    /// A client calling `server`, retrying up to 3 times without waiting long
+    fn client_for(server: &MockServer) -> AliyunCdnClient {
+        let mut config = test_settings().aliyun;
+        config.endpoint = server.uri();
+        config.retry.base_delay_ms = 1;
+        AliyunCdnClient::new(&config, reqwest::Client::new())
+    }
+
+    /// Answer `action` with `status` and `body`, `times` times
+    async fn mount(server: &MockServer, action: &str, status: u16, body: &str, times: u64) {
+        Mock::given(header("x-acs-action", action))
+            .respond_with(ResponseTemplate::new(status).set_body_string(body))
+            .up_to_n_times(times)
+            .expect(times)
+            .mount(server)
+            .await;
+    }
+
+    fn refresh_request() -> RefreshObjectCachesRequest {
+        RefreshObjectCachesRequest {
+            object_path: "https://static.prts.wiki/a.png".to_string(),
+            object_type: Some("File".to_string()),
+            force: None,
+            product: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_throttled_refresh_is_retried_until_it_succeeds() {
+        let server = MockServer::start().await;
+        mount(
+            &server,
+            "RefreshObjectCaches",
+            400,
+            r#"{"Code":"Throttling.User","Message":"Request was denied due to user flow control."}"#,
+            1,
+        )
+        .await;
+        mount(
+            &server,
+            "RefreshObjectCaches",
+            503,
+            r#"{"Code":"ServiceUnavailable"}"#,
+            1,
+        )
+        .await;
+        mount(
+            &server,
+            "RefreshObjectCaches",
+            200,
+            r#"{"RequestId":"r","RefreshTaskId":"17772470467"}"#,
+            1,
+        )
+        .await;
+
+        let response = client_for(&server)
+            .refresh_object_caches(&refresh_request())
+            .await
+            .unwrap();
+        assert_eq!(response.refresh_task_id, "17772470467");
+        server.verify().await;
+
+        // Every attempt is signed anew
+        let nonces = server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .map(|request| request.headers["x-acs-signature-nonce"].clone())
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(nonces.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_paths_are_rejected_before_calling_aliyun() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&server)
+            .await;
+        let client = client_for(&server);
+
+        let cases = [
+            (
+                "static.prts.wiki/a.png",
+                "File",
+                "not an absolute http(s) URL",
+            ),
+            (
+                "ftp://static.prts.wiki/a.png",
+                "File",
+                "not an absolute http(s) URL",
+            ),
+            (
+                "https://static.prts.wiki/images",
+                "Directory",
+                "directory paths must end with '/'",
+            ),
+        ];
+        for (object_path, object_type, reason) in cases {
+            let request = RefreshObjectCachesRequest {
+                object_path: format!("https://static.prts.wiki/ok/\n{object_path}"),
+                object_type: Some(object_type.to_string()),
+                ..refresh_request()
+            };
+            let err = client.refresh_object_caches(&request).await.unwrap_err();
+            assert!(
+                matches!(&err, AppError::BadRequest(err) if err.to_string().contains(&format!("line 2 '{object_path}': {reason}"))),
+                "{err:?}"
+            );
+            assert!(client.refresh_object_caches_dry_run(&request).is_err());
+        }
+        let oversized = RefreshObjectCachesRequest {
+            object_path: vec!["https://static.prts.wiki/a.png"; crate::aliyun::MAX_FILE_PATHS + 1]
+                .join("\n"),
+            ..refresh_request()
+        };
+        assert!(matches!(
+            client.refresh_object_caches(&oversized).await,
+            Err(AppError::BadRequest(_))
+        ));
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_signed_host_is_the_endpoint_host_or_the_configured_one() {
+        let server = MockServer::start().await;
+        let address = server.address().to_string();
+        Mock::given(header("host", address.as_str()))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"1"}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(header("host", "cdn.ap-southeast-1.aliyuncs.com"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"2"}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        // `host:port` is signed and sent as is
+        let client = client_for(&server);
+        let prepared = client
+            .prepare(refresh_parts(&refresh_request()).unwrap(), None)
+            .unwrap();
+        assert_eq!(prepared.headers["host"], address.as_str());
+        let authorization = prepared.headers["authorization"].to_str().unwrap();
+        assert!(authorization.contains(";host;"), "{authorization}");
+        let response = client
+            .refresh_object_caches(&refresh_request())
+            .await
+            .unwrap();
+        assert_eq!(response.refresh_task_id, "1");
+
+        let mut config = test_settings().aliyun;
+        config.endpoint = format!("{}/", server.uri());
+        config.host = Some("cdn.ap-southeast-1.aliyuncs.com".to_string());
+        let client = AliyunCdnClient::new(&config, reqwest::Client::new());
+        let response = client
+            .refresh_object_caches(&refresh_request())
+            .await
+            .unwrap();
+        assert_eq!(response.refresh_task_id, "2");
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_throttled_listing_is_retried_until_it_succeeds() {
+        let server = MockServer::start().await;
+        mount(
+            &server,
+            "DescribeRefreshTasks",
+            400,
+            r#"{"Code":"Throttling.User"}"#,
+            2,
+        )
+        .await;
+        mount(
+            &server,
+            "DescribeRefreshTasks",
+            200,
+            r#"{"RequestId":"r","PageNumber":1,"PageSize":20,"TotalCount":0,"Tasks":{"CDNTask":[]}}"#,
+            1,
+        )
+        .await;
+
+        let response = client_for(&server)
+            .describe_refresh_tasks(&DescribeRefreshTasksPayload::default())
+            .await
+            .unwrap();
+        assert_eq!(response.total_count, 0);
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_retries_stop_at_max_attempts() {
+        let server = MockServer::start().await;
+        mount(
+            &server,
+            "RefreshObjectCaches",
+            503,
+            r#"{"Code":"ServiceUnavailable"}"#,
+            3,
+        )
+        .await;
+
+        let err = client_for(&server)
+            .refresh_object_caches(&refresh_request())
+            .await
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("ServiceUnavailable"));
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_hung_calls_time_out_as_network_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"1"}"#)
+                    .set_delay(Duration::from_secs(2)),
+            )
+            .mount(&server)
+            .await;
+
+        let started = Instant::now();
+        let err = client_for(&server)
+            .with_timeout(Duration::from_millis(200))
+            .refresh_object_caches(&refresh_request())
+            .await
+            .unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(2));
+        let AppError::NetworkError(err) = err else {
+            panic!("expected a network error, got {err:?}");
+        };
+        let message = format!("{err:#}");
+        assert!(
+            message.starts_with("Failed to send RefreshObjectCaches request"),
+            "{message}"
+        );
+        assert!(message.contains("timed out"), "{message}");
+    }
+
+    #[tokio::test]
+    async fn test_other_errors_fail_fast() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(400).set_body_string(
+                r#"{"Code":"InvalidObjectPath.Malformed","Message":"ObjectPath is malformed."}"#,
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let err = client_for(&server)
+            .refresh_object_caches(&refresh_request())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::Aliyun(_)));
+        server.verify().await;
+    }
+
+    fn parsed(status: u16, body: &str) -> AppError {
+        aliyun_error(reqwest::StatusCode::from_u16(status).unwrap(), body)
+    }
+
+    #[test]
+    fn test_error_bodies_map_to_caller_statuses() {
+        let cases = [
+            (
+                400,
+                r#"{"RequestId":"A1","HostId":"cdn.aliyuncs.com","Code":"InvalidObjectPath.Malformed","Message":"Specified ObjectPath is malformed.","Recommend":"https://api.aliyun.com/troubleshoot?q=InvalidObjectPath.Malformed&product=Cdn"}"#,
+                400,
+            ),
+            (
+                400,
+                r#"{"RequestId":"A2","Code":"InvalidParameter","Message":"The parameter Area is invalid."}"#,
+                400,
+            ),
+            (
+                400,
+                r#"{"RequestId":"A3","Code":"QuotaExceeded.Refresh","Message":"The refresh quota is used up."}"#,
+                429,
+            ),
+            (
+                400,
+                r#"{"RequestId":"A4","Code":"Throttling.User","Message":"Request was denied due to user flow control."}"#,
+                429,
+            ),
+            (
+                400,
+                r#"{"RequestId":"A5","Code":"SignatureDoesNotMatch","Message":"Specified signature is not matched with our calculation."}"#,
+                502,
+            ),
+            (
+                503,
+                r#"{"RequestId":"A6","Code":"ServiceUnavailable","Message":"The request has failed due to a temporary failure of the server."}"#,
+                502,
+            ),
+        ];
+        for (status, body, expected) in cases {
+            let AppError::Aliyun(err) = parsed(status, body) else {
+                panic!("{body} should parse");
+            };
+            assert_eq!(err.status_code().as_u16(), expected, "{}", err.code);
+        }
+    }
+
+    #[test]
+    fn test_error_fields_are_kept() {
+        let AppError::Aliyun(err) = parsed(
+            400,
+            r#"{"RequestId":"A1","Code":"InvalidObjectPath.Malformed","Message":"Specified ObjectPath is malformed.","Recommend":"https://api.aliyun.com/troubleshoot"}"#,
+        ) else {
+            panic!("should parse");
+        };
+        assert_eq!(err.request_id, "A1");
+        assert_eq!(err.message, "Specified ObjectPath is malformed.");
+        assert_eq!(
+            err.recommend.as_deref(),
+            Some("https://api.aliyun.com/troubleshoot")
+        );
+        assert_eq!(
+            err.to_string(),
+            "InvalidObjectPath.Malformed: Specified ObjectPath is malformed. (status 400, request A1)"
+        );
+    }
+
+    #[test]
+    fn test_unparsable_and_not_found_bodies_keep_their_variants() {
+        assert!(matches!(
+            parsed(502, "<html>Bad Gateway</html>"),
+            AppError::InternalError(_)
+        ));
+        assert!(matches!(
+            parsed(
+                404,
+                r#"{"RequestId":"A7","Code":"InvalidDomain.NotFound","Message":"x"}"#
+            ),
+            AppError::NotFound(_)
+        ));
+    }
+
+    #[test]
+    fn test_retry_delay_doubles_with_jitter() {
+        for attempt in 1..=3 {
+            let backoff = 100 << (attempt - 1);
+            let delay = retry_delay(100, attempt).as_millis() as u64;
+            assert!(
+                (backoff..=backoff + backoff / 2).contains(&delay),
+                "{delay}"
+            );
+        }
+    }
+
+    fn signer() -> AliyunSigner {
+        AliyunSigner::new(
+            "test-access-key-id".to_string(),
+            "test-access-key-secret".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_refresh_wrapper_signs_like_dedicated_implementation() {
+        let request = RefreshObjectCachesRequest {
+            object_path: "https://static.prts.wiki/a+b.png".to_string(),
+            object_type: Some("File".to_string()),
+            force: Some(false),
+            product: None,
+        };
+        let prepared = client()
+            .prepare(refresh_parts(&request).unwrap(), Some((DATE, NONCE)))
+            .unwrap();
+
+        let form_body =
+            "ObjectPath=https%3A%2F%2Fstatic.prts.wiki%2Fa%2Bb.png&ObjectType=File&Force=false";
+        let expected = signer()
+            .sign_request_at(
+                AliyunSignInput {
+                    method: "POST",
+                    host: "cdn.aliyuncs.com",
+                    canonical_uri: "/",
+                    action: "RefreshObjectCaches",
+                    version: "2018-05-10",
+                    query_params: QueryParams::default(),
+                    body: form_body.as_bytes(),
+                    content_type: Some("application/x-www-form-urlencoded"),
+                    extra_headers: BTreeMap::new(),
+                },
+                DATE,
+                NONCE,
+            )
+            .unwrap();
+
+        assert_eq!(prepared.url, "https://cdn.aliyuncs.com/");
+        assert_eq!(prepared.body.as_deref(), Some(form_body));
+        assert_eq!(prepared.headers, expected.headers);
+    }
+
+    #[test]
+    fn test_each_product_signs_its_own_action_and_version() {
+        let cases = [
+            (
+                CdnProduct::Cdn,
+                "cdn.aliyuncs.com",
+                "RefreshObjectCaches",
+                "2018-05-10",
+            ),
+            (
+                CdnProduct::Dcdn,
+                "dcdn.aliyuncs.com",
+                "RefreshDcdnObjectCaches",
+                "2018-01-15",
+            ),
+        ];
+        for (product, host, action, version) in cases {
+            let request = RefreshObjectCachesRequest {
+                product: Some(product),
+                ..refresh_request()
+            };
+            let prepared = client()
+                .prepare(refresh_parts(&request).unwrap(), Some((DATE, NONCE)))
+                .unwrap();
+
+            let form_body = "ObjectPath=https%3A%2F%2Fstatic.prts.wiki%2Fa.png&ObjectType=File";
+            let expected = signer()
+                .sign_request_at(
+                    AliyunSignInput {
+                        method: "POST",
+                        host,
+                        canonical_uri: "/",
+                        action,
+                        version,
+                        query_params: QueryParams::default(),
+                        body: form_body.as_bytes(),
+                        content_type: Some("application/x-www-form-urlencoded"),
+                        extra_headers: BTreeMap::new(),
+                    },
+                    DATE,
+                    NONCE,
+                )
+                .unwrap();
+
+            assert_eq!(prepared.url, format!("https://{host}/"));
+            assert_eq!(prepared.headers["x-acs-action"], action);
+            assert_eq!(prepared.headers["x-acs-version"], version);
+            assert_eq!(prepared.headers, expected.headers);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_paths_are_refreshed_on_the_product_of_their_domain() {
+        let cdn = MockServer::start().await;
+        let dcdn = MockServer::start().await;
+        Mock::given(header("x-acs-action", "RefreshObjectCaches"))
+            .and(header("x-acs-version", "2018-05-10"))
+            .and(body_string_contains("static.prts.wiki"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r1","RefreshTaskId":"1"}"#),
+            )
+            .expect(1)
+            .mount(&cdn)
+            .await;
+        Mock::given(header("x-acs-action", "RefreshDcdnObjectCaches"))
+            .and(header("x-acs-version", "2018-01-15"))
+            .and(body_string_contains("media.prts.wiki"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r2","RefreshTaskId":"2"}"#),
+            )
+            .expect(1)
+            .mount(&dcdn)
+            .await;
+        let mut config = test_settings().aliyun;
+        config.endpoint = cdn.uri();
+        config.dcdn.endpoint = dcdn.uri();
+        config.dcdn.domains = vec!["Media.prts.wiki".to_string()];
+        let client = AliyunCdnClient::new(&config, reqwest::Client::new());
+
+        let request = RefreshObjectCachesRequest {
+            object_path: "https://media.prts.wiki/a.png\nhttps://static.prts.wiki/a.png\nhttps://media.prts.wiki:443/b.png".to_string(),
+            ..refresh_request()
+        };
+        let routed = client.route(&request);
+        assert_eq!(
+            routed
+                .iter()
+                .map(|request| (request.product, request.object_path.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    Some(CdnProduct::Dcdn),
+                    "https://media.prts.wiki/a.png\nhttps://media.prts.wiki:443/b.png"
+                ),
+                (Some(CdnProduct::Cdn), "https://static.prts.wiki/a.png"),
+            ]
+        );
+        // A product named by the caller overrides the domains
+        let forced = RefreshObjectCachesRequest {
+            product: Some(CdnProduct::Cdn),
+            ..request.clone()
+        };
+        assert_eq!(client.route(&forced).len(), 1);
+
+        let response = client.refresh_object_caches(&request).await.unwrap();
+        assert_eq!(response.request_id, "r2");
+        assert_eq!(response.refresh_task_id, "2,1");
+        cdn.verify().await;
+        dcdn.verify().await;
+    }
+
+    #[test]
+    fn test_split_keeps_each_call_within_the_limit() {
+        let paths = (0..2500)
+            .map(|index| format!("https://static.prts.wiki/{index}.png"))
+            .collect::<Vec<_>>();
+        let request = RefreshObjectCachesRequest {
+            object_path: paths.join("\n"),
+            object_type: Some("File".to_string()),
+            force: Some(true),
+            product: None,
+        };
+        let chunks = request.split();
+        assert_eq!(
+            chunks
+                .iter()
+                .map(|chunk| chunk.object_path.lines().count())
+                .collect::<Vec<_>>(),
+            [1000, 1000, 500]
+        );
+        assert_eq!(
+            chunks[1].object_path.lines().next(),
+            Some(paths[1000].as_str())
+        );
+        assert!(chunks.iter().all(|chunk| chunk.force == Some(true)));
+
+        let directories = RefreshObjectCachesRequest {
+            object_path: vec!["https://static.prts.wiki/images/"; 150].join("\n"),
+            object_type: Some("Directory".to_string()),
+            force: None,
+            product: None,
+        };
+        assert_eq!(directories.split().len(), 2);
+        let small = RefreshObjectCachesRequest {
+            object_path: "https://static.prts.wiki/a.png".to_string(),
+            ..request
+        };
+        assert_eq!(small.split().len(), 1);
+    }
+
+    #[test]
+    fn test_push_parts_encode_optional_parameters() {
+        let request = PushObjectCachesRequest {
+            object_path: "https://static.prts.wiki/a.png\nhttps://static.prts.wiki/b.png"
+                .to_string(),
+            area: Some("overseas".to_string()),
+            l2_preload: Some(true),
+        };
+        let parts = push_parts(&request).unwrap();
+        assert_eq!(parts.action, "PushObjectCache");
+        assert_eq!(
+            parts.form_body.as_deref(),
+            Some(
+                "ObjectPath=https%3A%2F%2Fstatic.prts.wiki%2Fa.png%0Ahttps%3A%2F%2Fstatic.prts.wiki%2Fb.png&Area=overseas&L2Preload=true"
+            )
+        );
+
+        let bare = push_parts(&PushObjectCachesRequest {
+            area: None,
+            l2_preload: None,
+            ..request
+        })
+        .unwrap();
+        assert!(!bare.form_body.unwrap().contains("Area"));
+    }
+
+    #[test]
+    fn test_describe_wrapper_signs_like_dedicated_implementation() {
+        let method = reqwest::Method::GET;
+        let prepared = client()
+            .prepare(
+                CallParts {
+                    product: CdnProduct::Cdn,
+                    action: "DescribeRefreshTaskById",
+                    version: CDN_API_VERSION,
+                    method: &method,
+                    path: "/".to_string(),
+                    query_params: BTreeMap::from([("TaskId".to_string(), "1,2".to_string())]),
+                    form_body: None,
+                },
+                Some((DATE, NONCE)),
+            )
+            .unwrap();
+
+        let expected = signer()
+            .sign_request_at(
+                AliyunSignInput {
+                    method: "GET",
+                    host: "cdn.aliyuncs.com",
+                    canonical_uri: "/",
+                    action: "DescribeRefreshTaskById",
+                    version: "2018-05-10",
+                    query_params: BTreeMap::from([("TaskId".to_string(), "1,2".to_string())])
+                        .into(),
+                    body: b"",
+                    content_type: None,
+                    extra_headers: BTreeMap::new(),
+                },
+                DATE,
+                NONCE,
+            )
+            .unwrap();
+
+        assert_eq!(prepared.url, "https://cdn.aliyuncs.com/?TaskId=1%2C2");
+        assert_eq!(prepared.body, None);
+        assert_eq!(prepared.headers, expected.headers);
+    }
+    #[test]
+    fn test_roa_paths_are_signed_and_sent_encoded() {
+        let method = reqwest::Method::GET;
+        let query_params = BTreeMap::from([("PageNumber".to_string(), "1".to_string())]);
+        for (path, url) in [
+            (
+                "/v2/rules/a b%/",
+                "https://cdn.aliyuncs.com/v2/rules/a%20b%25/?PageNumber=1",
+            ),
+            (
+                "/v2/rules/7",
+                "https://cdn.aliyuncs.com/v2/rules/7?PageNumber=1",
+            ),
+        ] {
+            let prepared = client()
+                .prepare(
+                    CallParts {
+                        product: CdnProduct::Cdn,
+                        action: "ListRules",
+                        version: "2024-09-10",
+                        method: &method,
+                        path: path.to_string(),
+                        query_params: query_params.clone(),
+                        form_body: None,
+                    },
+                    Some((DATE, NONCE)),
+                )
+                .unwrap();
+
+            let input = |canonical_uri| AliyunSignInput {
+                method: "GET",
+                host: "cdn.aliyuncs.com",
+                canonical_uri,
+                action: "ListRules",
+                version: "2024-09-10",
+                query_params: query_params.clone().into(),
+                body: b"",
+                content_type: None,
+                extra_headers: BTreeMap::new(),
+            };
+            let expected = signer().sign_request_at(input(path), DATE, NONCE).unwrap();
+            let root = signer().sign_request_at(input("/"), DATE, NONCE).unwrap();
+
+            assert_eq!(prepared.url, url);
+            assert_eq!(prepared.headers, expected.headers);
+            // The path is part of what is signed
+            assert_ne!(expected.signature, root.signature);
+        }
+    }
+
+    #[test]
+    fn test_fill_path_substitutes_whole_segments() {
+        let params = BTreeMap::from([("id", "rule 1"), ("site", "42")]);
+        assert_eq!(
+            fill_path("/v2/sites/{site}/rules/{id}/", &params).unwrap(),
+            "/v2/sites/42/rules/rule 1/"
+        );
+        // Braces inside a segment aren't a parameter
+        assert_eq!(fill_path("/v2/a{id}", &params).unwrap(), "/v2/a{id}");
+
+        let err = fill_path("/v2/rules/{id}", &BTreeMap::from([("id", "a/b")])).unwrap_err();
+        assert!(matches!(err, AppError::BadRequest(_)), "{err:?}");
+        let err = fill_path("/v2/rules/{id}", &BTreeMap::new()).unwrap_err();
+        assert!(matches!(err, AppError::InternalError(_)), "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn test_call_path_sends_the_path_and_query() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v2/rules/rule%201"))
+            .and(query_param("SiteId", "42"))
+            .and(header("x-acs-action", "GetRule"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"RequestId":"r"}"#))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let path = fill_path("/v2/rules/{id}", &BTreeMap::from([("id", "rule 1")])).unwrap();
+
+        let response: serde_json::Value = client_for(&server)
+            .call_path(
+                "GetRule",
+                "2024-09-10",
+                reqwest::Method::GET,
+                &path,
+                BTreeMap::from([("SiteId".to_string(), "42".to_string())]),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(response["RequestId"], "r");
+        server.verify().await;
+    }
+    #[tokio::test]
+    async fn test_tasks_cache_drops_the_oldest_answer_when_full() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(header("x-acs-action", "DescribeRefreshTasks"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"RequestId":"r","PageNumber":1,"PageSize":20,"TotalCount":0,"Tasks":{"CDNTask":[]}}"#,
+            ))
+            .expect(3)
+            .mount(&server)
+            .await;
+        let mut settings = test_settings();
+        settings.aliyun.endpoint = server.uri();
+        settings.aliyun.tasks_cache.max_entries = 1;
+        let client = AliyunCdnClient::new(&settings.aliyun, reqwest::Client::new());
+        let fetch_all = AliyunFetchAllConfig::default();
+        let query = |task_id: &str| DescribeRefreshTasksPayload {
+            task_id: Some(task_id.to_string()),
+            ..Default::default()
+        };
+
+        for (task_id, status) in [
+            ("1", CacheStatus::Miss),
+            ("1", CacheStatus::Hit),
+            ("2", CacheStatus::Miss),
+            // Evicted by the answer for 2
+            ("1", CacheStatus::Miss),
+        ] {
+            let (_, cache) = client
+                .describe_refresh_tasks_cached(&query(task_id), &fetch_all)
+                .await
+                .unwrap();
+            assert_eq!(cache, Some(status), "task {task_id}");
+        }
+        server.verify().await;
     }
 }
@@ -0,0 +1,548 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use super::signature::{AliyunSignInput, AliyunSigner};
+use crate::config::{AliyunConfig, AliyunStsConfig, CredentialSource, EcsRamRoleConfig};
+
+const STS_API_VERSION: &str = "2015-04-01";
+/// Wait before retrying a failed credential refresh
+const STS_RETRY_DELAY: Duration = Duration::from_secs(30);
+/// Credentials this close to expiring are fetched again before a call signs with them
+const EXPIRY_MARGIN: chrono::Duration = chrono::Duration::seconds(60);
+
+/// Aliyun credentials used to sign API calls
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    pub access_key_id: String,
+    pub access_key_secret: String,
+    /// STS token, sent and signed as `x-acs-security-token`
+    pub security_token: Option<String>,
+    /// When temporary credentials stop working
+    pub expiration: Option<DateTime<Utc>>,
+}
+
+impl Credentials {
+    /// Long-lived AccessKey, or static STS credentials when `security_token` is set
+    pub fn from_config(config: &AliyunConfig) -> Self {
+        Self {
+            access_key_id: config.access_key_id.clone(),
+            access_key_secret: config.access_key_secret.clone(),
+            security_token: config.security_token.clone(),
+            expiration: None,
+        }
+    }
+
+    /// The start of `access_key_id`, enough to tell keys apart in audit logs
+    pub fn key_id_prefix(&self) -> String {
+        const SHOWN: usize = 8;
+        let mut prefix = self.access_key_id.chars().take(SHOWN).collect::<String>();
+        if self.access_key_id.chars().count() > SHOWN {
+            prefix.push('…');
+        }
+        prefix
+    }
+}
+
+/// Credentials shared by every client, swapped in place when STS rotates them
+pub type SharedCredentials = Arc<RwLock<Credentials>>;
+
+/// Where credentials come from, and how to fetch them again
+///
+/// A static AccessKey never changes. An STS role or the ECS instance's RAM role hands out
+/// temporary credentials, fetched at startup, again before each expiry, and on demand when a
+/// call finds them expired (e.g. after the background refresh kept failing).
+#[derive(Debug)]
+pub struct CredentialProvider {
+    config: AliyunConfig,
+    /// Held while fetching, so concurrent calls finding expired credentials fetch them once
+    fetching: tokio::sync::Mutex<()>,
+}
+
+impl CredentialProvider {
+    pub fn new(config: &AliyunConfig) -> Self {
+        Self {
+            config: config.clone(),
+            fetching: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Whether the credentials expire and must be fetched again
+    pub fn rotates(&self) -> bool {
+        self.config.sts.is_some() || self.config.credential_source == CredentialSource::EcsRamRole
+    }
+
+    /// Name of the source in logs
+    fn source(&self) -> &'static str {
+        match (&self.config.credential_source, &self.config.sts) {
+            (CredentialSource::EcsRamRole, _) => "ecs_ram_role",
+            (CredentialSource::Static, Some(_)) => "sts",
+            (CredentialSource::Static, None) => "static",
+        }
+    }
+
+    fn refresh_before_secs(&self) -> u64 {
+        match (&self.config.credential_source, &self.config.sts) {
+            (CredentialSource::EcsRamRole, _) => self.config.ecs_ram_role.refresh_before_secs,
+            (CredentialSource::Static, Some(sts)) => sts.refresh_before_secs,
+            (CredentialSource::Static, None) => 0,
+        }
+    }
+
+    /// Fetch the current credentials from the source
+    pub async fn fetch(&self, client: &reqwest::Client) -> Result<Credentials> {
+        match (&self.config.credential_source, &self.config.sts) {
+            (CredentialSource::EcsRamRole, _) => {
+                fetch_ecs_ram_role(&self.config.ecs_ram_role, client).await
+            }
+            (CredentialSource::Static, Some(sts)) => assume_role(&self.config, sts, client).await,
+            (CredentialSource::Static, None) => Ok(Credentials::from_config(&self.config)),
+        }
+    }
+
+    /// Fetch new credentials into `credentials` if they expire within a minute
+    pub async fn refresh_if_expired(
+        &self,
+        client: &reqwest::Client,
+        credentials: &SharedCredentials,
+    ) -> Result<()> {
+        if !self.rotates() || !expiring(credentials) {
+            return Ok(());
+        }
+        let _fetching = self.fetching.lock().await;
+        // Another call may have fetched them while this one waited
+        if !expiring(credentials) {
+            return Ok(());
+        }
+        warn!(
+            source = self.source(),
+            "Aliyun credentials expired, fetching them before the call"
+        );
+        let fresh = self.fetch(client).await?;
+        *credentials.write().expect("credentials lock poisoned") = fresh;
+        Ok(())
+    }
+}
+
+/// Whether `credentials` expire within [`EXPIRY_MARGIN`]
+fn expiring(credentials: &SharedCredentials) -> bool {
+    credentials
+        .read()
+        .expect("credentials lock poisoned")
+        .expiration
+        .is_some_and(|expiration| expiration - EXPIRY_MARGIN <= Utc::now())
+}
+
+#[derive(Debug, Deserialize)]
+struct AssumeRoleResponse {
+    #[serde(rename = "Credentials")]
+    credentials: StsCredentials,
+}
+
+#[derive(Debug, Deserialize)]
+struct StsCredentials {
+    #[serde(rename = "AccessKeyId")]
+    access_key_id: String,
+    #[serde(rename = "AccessKeySecret")]
+    access_key_secret: String,
+    #[serde(rename = "SecurityToken")]
+    security_token: String,
+    /// ISO 8601 UTC, e.g. `2015-04-09T11:52:19Z`
+    #[serde(rename = "Expiration")]
+    expiration: String,
+}
+
+/// Obtain temporary credentials for the configured role
+///
+/// Uses AssumeRoleWithOIDC when an OIDC token file is configured, otherwise AssumeRole signed
+/// with the main AccessKey.
+pub async fn assume_role(
+    config: &AliyunConfig,
+    sts: &AliyunStsConfig,
+    client: &reqwest::Client,
+) -> Result<Credentials> {
+    let mut params = BTreeMap::from([
+        ("RoleArn".to_string(), sts.role_arn.clone()),
+        ("RoleSessionName".to_string(), sts.role_session_name.clone()),
+        ("DurationSeconds".to_string(), sts.duration_secs.to_string()),
+    ]);
+    let action = if let (Some(provider_arn), Some(token_file)) =
+        (&sts.oidc_provider_arn, &sts.oidc_token_file)
+    {
+        let token = tokio::fs::read_to_string(token_file)
+            .await
+            .with_context(|| format!("Failed to read OIDC token file {token_file}"))?;
+        params.insert("OIDCProviderArn".to_string(), provider_arn.clone());
+        params.insert("OIDCToken".to_string(), token.trim().to_string());
+        "AssumeRoleWithOIDC"
+    } else {
+        "AssumeRole"
+    };
+
+    let endpoint = sts.endpoint.trim_end_matches('/');
+    let host = endpoint
+        .split_once("://")
+        .map_or(endpoint, |(_, rest)| rest);
+    let signer =
+        AliyunSigner::from_credentials(Arc::new(RwLock::new(Credentials::from_config(config))));
+    let signed = signer.sign_request(AliyunSignInput {
+        method: "POST",
+        host,
+        canonical_uri: "/",
+        action,
+        version: STS_API_VERSION,
+        query_params: params.into(),
+        body: b"",
+        content_type: None,
+        extra_headers: BTreeMap::new(),
+    })?;
+    let mut headers = signed.headers;
+    // AssumeRoleWithOIDC is anonymous; the OIDC token itself proves the caller
+    if action == "AssumeRoleWithOIDC" {
+        headers.remove(reqwest::header::AUTHORIZATION);
+    }
+
+    let response = client
+        .post(format!("{}/?{}", endpoint, signed.query_string))
+        .headers(headers)
+        .send()
+        .await
+        .with_context(|| format!("Failed to send {action} request"))?;
+    let status = response.status();
+    let body = response
+        .text()
+        .await
+        .context("Failed to read response body")?;
+    if !status.is_success() {
+        bail!("Aliyun STS error (status {}): {}", status, body);
+    }
+
+    let assumed: AssumeRoleResponse = serde_json::from_str(&body)
+        .with_context(|| format!("Failed to parse {action} response"))?;
+    Ok(Credentials {
+        expiration: Some(parse_expiration(&assumed.credentials.expiration, action)?),
+        access_key_id: assumed.credentials.access_key_id,
+        access_key_secret: assumed.credentials.access_key_secret,
+        security_token: Some(assumed.credentials.security_token),
+    })
+}
+
+/// Credentials of a RAM role, as the ECS instance metadata service hands them out
+#[derive(Debug, Deserialize)]
+struct EcsRoleCredentials {
+    /// `Success`, or why there are no credentials
+    #[serde(rename = "Code")]
+    code: String,
+    #[serde(rename = "AccessKeyId", default)]
+    access_key_id: String,
+    #[serde(rename = "AccessKeySecret", default)]
+    access_key_secret: String,
+    #[serde(rename = "SecurityToken", default)]
+    security_token: String,
+    #[serde(rename = "Expiration", default)]
+    expiration: String,
+}
+
+/// Fetch the temporary credentials of the RAM role attached to this ECS instance
+///
+/// Without a configured `role_name` the metadata service is asked which role is attached.
+/// Reference: https://help.aliyun.com/zh/ecs/user-guide/attach-an-instance-ram-role-to-an-ecs-instance
+pub async fn fetch_ecs_ram_role(
+    config: &EcsRamRoleConfig,
+    client: &reqwest::Client,
+) -> Result<Credentials> {
+    let base = format!(
+        "{}/latest/meta-data/ram/security-credentials/",
+        config.metadata_endpoint.trim_end_matches('/')
+    );
+    let role_name = match &config.role_name {
+        Some(role_name) => role_name.clone(),
+        None => metadata_get(client, &base)
+            .await?
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .map(str::to_string)
+            .context("No RAM role is attached to this ECS instance")?,
+    };
+
+    let body = metadata_get(client, &format!("{base}{role_name}")).await?;
+    let role: EcsRoleCredentials = serde_json::from_str(&body)
+        .with_context(|| format!("Failed to parse credentials of RAM role {role_name}"))?;
+    if role.code != "Success" {
+        bail!(
+            "Instance metadata refused credentials of RAM role {role_name}: {}",
+            role.code
+        );
+    }
+    Ok(Credentials {
+        expiration: Some(parse_expiration(&role.expiration, "instance metadata")?),
+        access_key_id: role.access_key_id,
+        access_key_secret: role.access_key_secret,
+        security_token: Some(role.security_token),
+    })
+}
+
+/// GET `url` from the instance metadata service
+async fn metadata_get(client: &reqwest::Client, url: &str) -> Result<String> {
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .with_context(|| format!("Failed to reach the instance metadata service at {url}"))?;
+    let status = response.status();
+    let body = response
+        .text()
+        .await
+        .context("Failed to read response body")?;
+    if !status.is_success() {
+        bail!("Instance metadata error (status {}): {}", status, body);
+    }
+    Ok(body)
+}
+
+/// Parse an ISO 8601 UTC `Expiration`, e.g. `2015-04-09T11:52:19Z`, from `what`
+fn parse_expiration(expiration: &str, what: &str) -> Result<DateTime<Utc>> {
+    Ok(DateTime::parse_from_rfc3339(expiration)
+        .with_context(|| format!("Invalid Expiration in {what} response: {expiration}"))?
+        .with_timezone(&Utc))
+}
+
+/// Fetch credentials into `credentials`, returning how long until the next refresh
+pub async fn refresh_credentials(
+    provider: &CredentialProvider,
+    client: &reqwest::Client,
+    credentials: &SharedCredentials,
+) -> Result<Duration> {
+    let _fetching = provider.fetching.lock().await;
+    let fresh = provider.fetch(client).await?;
+    let expiration = fresh.expiration.unwrap_or_else(Utc::now);
+    info!(
+        source = provider.source(),
+        access_key_id = %fresh.access_key_id,
+        %expiration,
+        "Aliyun credentials refreshed"
+    );
+    *credentials.write().expect("credentials lock poisoned") = fresh;
+
+    let until_refresh =
+        expiration - Utc::now() - chrono::Duration::seconds(provider.refresh_before_secs() as i64);
+    Ok(until_refresh
+        .to_std()
+        .unwrap_or(STS_RETRY_DELAY)
+        .max(STS_RETRY_DELAY))
+}
+
+/// Refresh `credentials`, returning how long to wait before the next attempt
+///
+/// A failed refresh keeps the previous credentials and is retried shortly after.
+pub async fn try_refresh_credentials(
+    provider: &CredentialProvider,
+    client: &reqwest::Client,
+    credentials: &SharedCredentials,
+) -> Duration {
+    match refresh_credentials(provider, client, credentials).await {
+        Ok(wait) => wait,
+        Err(err) => {
+            warn!(error = ?err, source = provider.source(), "Aliyun credential refresh failed");
+            STS_RETRY_DELAY
+        }
+    }
+}
+
+/// Refresh `credentials` after `wait`, then again before every expiry until the task is aborted
+pub async fn run_credential_refresh(
+    provider: Arc<CredentialProvider>,
+    client: reqwest::Client,
+    credentials: SharedCredentials,
+    mut wait: Duration,
+) {
+    loop {
+        tokio::time::sleep(wait).await;
+        wait = try_refresh_credentials(&provider, &client, &credentials).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{header, header_exists, method, path, query_param},
+    };
+
+    use super::*;
+    use crate::test_support::test_settings;
+
+    fn sts_config(endpoint: &str) -> AliyunStsConfig {
+        toml::from_str(&format!(
+            r#"
+role_arn = "acs:ram::123:role/janus"
+endpoint = "{endpoint}"
+"#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_key_id_prefix_hides_the_rest() {
+        let credentials = |access_key_id: &str| Credentials {
+            access_key_id: access_key_id.to_string(),
+            access_key_secret: "secret".to_string(),
+            security_token: None,
+            expiration: None,
+        };
+        assert_eq!(
+            credentials("LTAI5tQ8mWkR2xYz7PqLnB3v").key_id_prefix(),
+            "LTAI5tQ8…"
+        );
+        assert_eq!(credentials("LTAI5t").key_id_prefix(), "LTAI5t");
+    }
+
+    #[tokio::test]
+    async fn test_assume_role_swaps_shared_credentials() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("x-acs-action", "AssumeRole"))
+            .and(header_exists("Authorization"))
+            .and(query_param("RoleArn", "acs:ram::123:role/janus"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"RequestId":"r","Credentials":{"AccessKeyId":"STS.id","AccessKeySecret":"sts-secret","SecurityToken":"token","Expiration":"2099-01-01T00:00:00Z"}}"#,
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut config = test_settings().aliyun;
+        config.sts = Some(sts_config(&server.uri()));
+        let credentials: SharedCredentials =
+            Arc::new(RwLock::new(Credentials::from_config(&config)));
+        let wait = refresh_credentials(
+            &CredentialProvider::new(&config),
+            &reqwest::Client::new(),
+            &credentials,
+        )
+        .await
+        .unwrap();
+
+        let current = credentials.read().unwrap().clone();
+        assert_eq!(current.access_key_id, "STS.id");
+        assert_eq!(current.security_token.as_deref(), Some("token"));
+        assert!(wait > Duration::from_secs(3600));
+    }
+
+    const ROLE_CREDENTIALS: &str = r#"{"AccessKeyId":"STS.ecs","AccessKeySecret":"ecs-secret","Expiration":"2099-01-01T00:00:00Z","SecurityToken":"ecs-token","LastUpdated":"2026-10-16T00:00:00Z","Code":"Success"}"#;
+
+    /// Settings taking credentials from the instance metadata stand-in `server`
+    fn ecs_config(server: &MockServer) -> AliyunConfig {
+        let mut config = test_settings().aliyun;
+        config.access_key_id = String::new();
+        config.access_key_secret = String::new();
+        config.credential_source = CredentialSource::EcsRamRole;
+        config.ecs_ram_role.metadata_endpoint = server.uri();
+        config
+    }
+
+    #[tokio::test]
+    async fn test_ecs_ram_role_is_discovered_and_fetched() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/latest/meta-data/ram/security-credentials/"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("janus-role\n"))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(
+                "/latest/meta-data/ram/security-credentials/janus-role",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_string(ROLE_CREDENTIALS))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let provider = CredentialProvider::new(&ecs_config(&server));
+        assert!(provider.rotates());
+        let credentials = provider.fetch(&reqwest::Client::new()).await.unwrap();
+        assert_eq!(credentials.access_key_id, "STS.ecs");
+        assert_eq!(credentials.security_token.as_deref(), Some("ecs-token"));
+        assert_eq!(
+            credentials.expiration.unwrap().to_rfc3339(),
+            "2099-01-01T00:00:00+00:00"
+        );
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_ecs_ram_role_errors_are_reported() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_string(r#"{"Code":"RoleNotAttached"}"#),
+            )
+            .mount(&server)
+            .await;
+        let mut config = ecs_config(&server);
+        config.ecs_ram_role.role_name = Some("janus-role".to_string());
+
+        let err = CredentialProvider::new(&config)
+            .fetch(&reqwest::Client::new())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("RoleNotAttached"), "{err:#}");
+    }
+
+    #[tokio::test]
+    async fn test_expired_credentials_are_fetched_again_before_a_call() {
+        let metadata = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(
+                "/latest/meta-data/ram/security-credentials/janus-role",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_string(ROLE_CREDENTIALS))
+            .expect(1)
+            .mount(&metadata)
+            .await;
+        let cdn = MockServer::start().await;
+        Mock::given(header("x-acs-security-token", "ecs-token"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"1"}"#),
+            )
+            .expect(2)
+            .mount(&cdn)
+            .await;
+
+        let mut config = ecs_config(&metadata);
+        config.ecs_ram_role.role_name = Some("janus-role".to_string());
+        config.endpoint = cdn.uri();
+        let credentials: SharedCredentials = Arc::new(RwLock::new(Credentials {
+            access_key_id: "STS.old".to_string(),
+            access_key_secret: "old".to_string(),
+            security_token: Some("old-token".to_string()),
+            expiration: Some(Utc::now() - chrono::Duration::seconds(1)),
+        }));
+        let client = crate::aliyun::AliyunCdnClient::new(&config, reqwest::Client::new())
+            .with_credentials(credentials.clone())
+            .with_credential_provider(Arc::new(CredentialProvider::new(&config)));
+
+        let request = crate::aliyun::RefreshObjectCachesRequest {
+            object_path: "https://static.prts.wiki/a.png".to_string(),
+            object_type: None,
+            force: None,
+            product: None,
+        };
+        // Only the first call finds them expired
+        client.refresh_object_caches(&request).await.unwrap();
+        client.refresh_object_caches(&request).await.unwrap();
+        assert_eq!(credentials.read().unwrap().access_key_id, "STS.ecs");
+        metadata.verify().await;
+        cdn.verify().await;
+    }
+}
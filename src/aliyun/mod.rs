@@ -1,5 +1,30 @@
 pub mod cdn;
+mod credentials;
+mod object_key;
+mod object_path;
+mod oss;
+mod refresh_budget;
 mod signature;
 
-pub use cdn::{AliyunCdnClient, RefreshObjectCachesRequest, RefreshObjectCachesResponse};
-pub use signature::{AliyunSigner, UNRESERVED};
+pub use cdn::{
+    AliyunApiError, AliyunCdnClient, BpsDataPoint, CdnDomain, CdnDomainDetail,
+    CdnDomainLogsResponse, CdnDomainSource, CdnLogFile, CdnProduct, DRY_RUN_TASK_ID,
+    DescribeCdnDomainLogsPayload, DescribeRefreshQuotaResponse, DescribeRefreshTaskByIdResponse,
+    DescribeRefreshTasksPayload, DescribeRefreshTasksResponse, DescribeUserDomainsPayload,
+    DescribeUserDomainsResponse, DomainBpsData, DomainHitRateData, DownloadCdnDomainLogPayload,
+    HitRateDataPoint, InvokeAliyunResult, PushObjectCachesRequest, PushObjectCachesResponse,
+    RefreshAndWaitResult, RefreshObjectCachesRequest, RefreshObjectCachesResponse, RefreshTask,
+    RefreshTaskObjectType, RefreshTaskStatus,
+};
+pub use credentials::{
+    CredentialProvider, Credentials, SharedCredentials, assume_role, fetch_ecs_ram_role,
+    refresh_credentials, run_credential_refresh, try_refresh_credentials,
+};
+pub use object_key::{decode_object_key, object_urls, percent_encode_path};
+pub use object_path::{
+    MAX_DIRECTORY_PATHS, MAX_FILE_PATHS, max_object_paths, normalize_directory_path,
+    parse_object_paths, url_host, validate_object_paths,
+};
+pub use oss::OssClient;
+pub use refresh_budget::{RefreshBudget, RefreshBudgetStatus, RefreshBudgetWindow};
+pub use signature::{AliyunSigner, QueryParams, UNRESERVED};
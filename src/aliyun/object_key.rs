@@ -0,0 +1,219 @@
+use percent_encoding::{AsciiSet, percent_decode_str, percent_encode};
+
+use super::signature::UNRESERVED;
+use crate::config::{BucketUrls, KeyEncoding};
+
+/// Bytes escaped in the path of a CDN URL
+///
+/// RFC 3986 `pchar` plus `/`: sub-delims, `:` and `@` stay readable, while `?`, `#`, `[` and
+/// `]` would end or break the path and `%` would be read as an escape.
+const PATH: &AsciiSet = &UNRESERVED
+    .remove(b'/')
+    .remove(b':')
+    .remove(b'@')
+    // sub-delims
+    .remove(b'!')
+    .remove(b'$')
+    .remove(b'&')
+    .remove(b'\'')
+    .remove(b'(')
+    .remove(b')')
+    .remove(b'*')
+    .remove(b'+')
+    .remove(b',')
+    .remove(b';')
+    .remove(b'=');
+
+/// Percent-encode an object key for the `{object_key}` slot of a bucket URL template
+pub fn percent_encode_path(key: &str) -> String {
+    percent_encode(key.as_bytes(), PATH).to_string()
+}
+
+/// CDN URLs of `key`, one per URL template of its bucket
+pub fn object_urls(urls: &BucketUrls, key: &str) -> Vec<String> {
+    let encoded = percent_encode_path(key);
+    urls.templates()
+        .iter()
+        .map(|template| template.replace("{object_key}", &encoded))
+        .collect()
+}
+
+/// Turn an object key from an OSS event into the key as stored in the bucket
+///
+/// Keys that don't decode to UTF-8 are kept as given.
+pub fn decode_object_key(key: &str, encoding: KeyEncoding) -> String {
+    match encoding {
+        KeyEncoding::Raw => key.to_string(),
+        KeyEncoding::Url => form_decode(key),
+        KeyEncoding::Auto if has_percent_sequences(key) => form_decode(key),
+        KeyEncoding::Auto => key.to_string(),
+        KeyEncoding::Percent => percent_decode(key),
+    }
+}
+
+/// Percent-decode, leaving `+` alone
+fn percent_decode(key: &str) -> String {
+    match percent_decode_str(key).decode_utf8() {
+        Ok(decoded) => decoded.into_owned(),
+        Err(_) => key.to_string(),
+    }
+}
+
+/// Percent-decode with `+` as a space, like OSS encodes keys in events
+fn form_decode(key: &str) -> String {
+    let spaced = key.replace('+', " ");
+    match percent_decode_str(&spaced).decode_utf8() {
+        Ok(decoded) => decoded.into_owned(),
+        Err(_) => key.to_string(),
+    }
+}
+
+/// Whether `key` has a `%` and each is followed by two hex digits
+fn has_percent_sequences(key: &str) -> bool {
+    let bytes = key.as_bytes();
+    let mut found = false;
+    for (index, _) in key.match_indices('%') {
+        let valid = bytes
+            .get(index + 1..index + 3)
+            .is_some_and(|hex| hex.iter().all(u8::is_ascii_hexdigit));
+        if !valid {
+            return false;
+        }
+        found = true;
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percent_encode_path() {
+        let cases = [
+            ("images/a.png", "images/a.png"),
+            ("images/a b.png", "images/a%20b.png"),
+            ("images/a+b.png", "images/a+b.png"),
+            ("images/a#1.png", "images/a%231.png"),
+            ("images/a?b.png", "images/a%3Fb.png"),
+            ("images/100%.png", "images/100%25.png"),
+            ("images/a%20b.png", "images/a%2520b.png"),
+            ("images/[1].png", "images/%5B1%5D.png"),
+            ("images/a(1),b=c;@d.png", "images/a(1),b=c;@d.png"),
+            (
+                "立绘/阿米娅.png",
+                "%E7%AB%8B%E7%BB%98/%E9%98%BF%E7%B1%B3%E5%A8%85.png",
+            ),
+        ];
+        for (key, encoded) in cases {
+            assert_eq!(percent_encode_path(key), encoded, "key {key:?}");
+        }
+    }
+
+    #[test]
+    fn test_raw_keys_are_kept() {
+        for key in ["a+b.png", "a%20b.png", "立绘/阿米娅.png", "100%.png"] {
+            assert_eq!(decode_object_key(key, KeyEncoding::Raw), key);
+        }
+    }
+
+    #[test]
+    fn test_url_keys_are_form_decoded() {
+        let cases = [
+            ("images/a.png", "images/a.png"),
+            ("images/a%20b.png", "images/a b.png"),
+            ("images/a+b.png", "images/a b.png"),
+            ("images/a%2Bb.png", "images/a+b.png"),
+            ("images/a%231.png", "images/a#1.png"),
+            (
+                "%E7%AB%8B%E7%BB%98/%E9%98%BF%E7%B1%B3%E5%A8%85.png",
+                "立绘/阿米娅.png",
+            ),
+            ("立绘/阿米娅.png", "立绘/阿米娅.png"),
+            // Not an escape, left alone
+            ("images/100%.png", "images/100%.png"),
+            // Not UTF-8 once decoded
+            ("images/%FF.png", "images/%FF.png"),
+        ];
+        for (key, decoded) in cases {
+            assert_eq!(
+                decode_object_key(key, KeyEncoding::Url),
+                decoded,
+                "key {key:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_auto_decodes_only_valid_sequences() {
+        let cases = [
+            ("images/a%20b.png", "images/a b.png"),
+            ("images/a%20b+c.png", "images/a b c.png"),
+            (
+                "%E7%AB%8B%E7%BB%98/%E9%98%BF%E7%B1%B3%E5%A8%85.png",
+                "立绘/阿米娅.png",
+            ),
+            // Nothing encoded, so `+` is a literal plus
+            ("images/a+b.png", "images/a+b.png"),
+            ("立绘/阿米娅 1.png", "立绘/阿米娅 1.png"),
+            ("images/a#1.png", "images/a#1.png"),
+            // A stray `%` means the key was never encoded
+            ("images/100%.png", "images/100%.png"),
+            ("images/50%off%20now.png", "images/50%off%20now.png"),
+            ("images/%2.png", "images/%2.png"),
+        ];
+        for (key, decoded) in cases {
+            assert_eq!(
+                decode_object_key(key, KeyEncoding::Auto),
+                decoded,
+                "key {key:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_percent_keys_keep_their_plus() {
+        let cases = [
+            ("images/a%20b+c.png", "images/a b+c.png"),
+            ("images/a+b.png", "images/a+b.png"),
+            ("images/a%2Bb.png", "images/a+b.png"),
+            ("images/a%231%3Fv%3D2.png", "images/a#1?v=2.png"),
+            (
+                "%E7%AB%8B%E7%BB%98/%E9%98%BF%E7%B1%B3%E5%A8%85+1.png",
+                "立绘/阿米娅+1.png",
+            ),
+            ("images/100%25.png", "images/100%.png"),
+            // Not an escape, left alone
+            ("images/100%.png", "images/100%.png"),
+            // Not UTF-8 once decoded
+            ("images/%FF.png", "images/%FF.png"),
+        ];
+        for (key, decoded) in cases {
+            assert_eq!(
+                decode_object_key(key, KeyEncoding::Percent),
+                decoded,
+                "key {key:?}"
+            );
+        }
+        assert_eq!(
+            percent_encode_path(&decode_object_key(
+                "images/a%231%3Fv+1%25.png",
+                KeyEncoding::Percent
+            )),
+            "images/a%231%3Fv+1%25.png"
+        );
+    }
+
+    #[test]
+    fn test_decoded_keys_encode_once() {
+        let key = "%E7%AB%8B%E7%BB%98/a%20b+c%23d.png";
+        assert_eq!(
+            percent_encode_path(&decode_object_key(key, KeyEncoding::Url)),
+            "%E7%AB%8B%E7%BB%98/a%20b%20c%23d.png"
+        );
+        assert_eq!(
+            percent_encode_path(&decode_object_key(key, KeyEncoding::Raw)),
+            "%25E7%25AB%258B%25E7%25BB%2598/a%2520b+c%2523d.png"
+        );
+    }
+}
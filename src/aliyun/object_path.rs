@@ -0,0 +1,243 @@
+use crate::error::{AppError, AppResult};
+
+/// Most URLs Aliyun accepts in one `File` refresh
+pub const MAX_FILE_PATHS: usize = 1000;
+/// Most paths Aliyun accepts in one `Directory` refresh
+pub const MAX_DIRECTORY_PATHS: usize = 100;
+/// Invalid entries listed in the error before the rest are summarised
+const REPORTED_ERRORS: usize = 5;
+
+/// Split a newline-separated `ObjectPath` into URLs Aliyun will accept for `object_type`
+///
+/// Lines are trimmed and blank lines dropped. Fails with a 400 naming the first few invalid
+/// lines (1-based, counting blank ones) so callers don't get Aliyun's cryptic rejection, or
+/// when there are more than one call accepts.
+pub fn validate_object_paths(object_path: &str, object_type: &str) -> AppResult<Vec<String>> {
+    let paths = parse_object_paths(object_path, object_type)?;
+    let limit = max_object_paths(object_type);
+    if paths.len() > limit {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "object_path has {} entries, Aliyun accepts at most {} per {} refresh",
+            paths.len(),
+            limit,
+            object_type
+        )));
+    }
+
+    Ok(paths)
+}
+
+/// Most paths of `object_type` Aliyun accepts in one refresh
+pub fn max_object_paths(object_type: &str) -> usize {
+    if object_type == "Directory" {
+        MAX_DIRECTORY_PATHS
+    } else {
+        MAX_FILE_PATHS
+    }
+}
+
+/// Like [`validate_object_paths`], but any number of paths, for callers splitting them
+pub fn parse_object_paths(object_path: &str, object_type: &str) -> AppResult<Vec<String>> {
+    let directory = object_type == "Directory";
+    let mut paths = Vec::new();
+    let mut errors = Vec::new();
+
+    for (index, line) in object_path.lines().enumerate() {
+        let path = line.trim();
+        if path.is_empty() {
+            continue;
+        }
+        match check_path(path, directory) {
+            Ok(()) => paths.push(path.to_string()),
+            Err(reason) => errors.push(format!("line {} '{}': {}", index + 1, path, reason)),
+        }
+    }
+
+    if !errors.is_empty() {
+        let mut msg = format!(
+            "Invalid object_path: {}",
+            errors[..errors.len().min(REPORTED_ERRORS)].join("; ")
+        );
+        if errors.len() > REPORTED_ERRORS {
+            msg.push_str(&format!(" (and {} more)", errors.len() - REPORTED_ERRORS));
+        }
+        return Err(AppError::BadRequest(anyhow::anyhow!(msg)));
+    }
+    if paths.is_empty() {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "object_path must contain at least one URL"
+        )));
+    }
+
+    Ok(paths)
+}
+
+/// A directory URL as Aliyun expects it: trimmed, without query string or fragment, ending in `/`
+pub fn normalize_directory_path(path: &str) -> String {
+    let path = path.trim();
+    let end = path.find(['?', '#']).unwrap_or(path.len());
+    let mut path = path[..end].to_string();
+    if !path.ends_with('/') {
+        path.push('/');
+    }
+    path
+}
+
+/// Host of a URL, without port
+pub fn url_host(url: &str) -> &str {
+    let host = url
+        .split_once("://")
+        .map_or(url, |(_, rest)| rest)
+        .split(['/', '?', '#'])
+        .next()
+        .unwrap_or_default();
+    host.rsplit_once(':').map_or(host, |(host, _)| host)
+}
+
+fn check_path(path: &str, directory: bool) -> Result<(), &'static str> {
+    let Some(rest) = path
+        .strip_prefix("https://")
+        .or_else(|| path.strip_prefix("http://"))
+    else {
+        return Err("not an absolute http(s) URL");
+    };
+    if rest.split('/').next().is_none_or(str::is_empty) {
+        return Err("missing domain");
+    }
+    if path.chars().any(char::is_whitespace) {
+        return Err("contains whitespace");
+    }
+    // Catches what the checks above let through, such as a bad port or host
+    if reqwest::Url::parse(path).is_err() {
+        return Err("not a valid URL");
+    }
+    if directory && !path.ends_with('/') {
+        return Err("directory paths must end with '/'");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_of(object_path: &str, object_type: &str) -> String {
+        format!(
+            "{:#}",
+            match validate_object_paths(object_path, object_type).unwrap_err() {
+                AppError::BadRequest(err) => err,
+                other => panic!("expected BadRequest, got {other:?}"),
+            }
+        )
+    }
+
+    #[test]
+    fn test_valid_paths_are_trimmed() {
+        let paths = validate_object_paths(
+            " https://static.prts.wiki/a.png\n\nhttp://media.prts.wiki/b.mp3 \n",
+            "File",
+        )
+        .unwrap();
+        assert_eq!(
+            paths,
+            [
+                "https://static.prts.wiki/a.png",
+                "http://media.prts.wiki/b.mp3"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_directories_are_normalized() {
+        assert_eq!(
+            normalize_directory_path(" https://static.prts.wiki/images?v=2#top "),
+            "https://static.prts.wiki/images/"
+        );
+        assert_eq!(
+            normalize_directory_path("https://static.prts.wiki/images/"),
+            "https://static.prts.wiki/images/"
+        );
+        assert_eq!(
+            normalize_directory_path("https://static.prts.wiki"),
+            "https://static.prts.wiki/"
+        );
+    }
+
+    #[test]
+    fn test_invalid_lines_are_reported_with_line_numbers() {
+        assert_eq!(
+            error_of(
+                "https://static.prts.wiki/a.png\n\nstatic.prts.wiki/b.png\nhttps://static.prts.wiki/c d.png\nhttps:///e.png",
+                "File"
+            ),
+            "Invalid object_path: line 3 'static.prts.wiki/b.png': not an absolute http(s) URL; \
+             line 4 'https://static.prts.wiki/c d.png': contains whitespace; \
+             line 5 'https:///e.png': missing domain"
+        );
+    }
+
+    #[test]
+    fn test_unparsable_urls_are_rejected() {
+        assert_eq!(
+            error_of(
+                "https://static.prts.wiki:99999/a.png\nhttps://static.prts.wiki/b.png\nhttp://[::1/c.png",
+                "File"
+            ),
+            "Invalid object_path: line 1 'https://static.prts.wiki:99999/a.png': not a valid URL; \
+             line 3 'http://[::1/c.png': not a valid URL"
+        );
+    }
+
+    #[test]
+    fn test_only_first_errors_are_listed() {
+        let object_path = ["ftp://x/"; 8].join("\n");
+        assert!(
+            error_of(&object_path, "File")
+                .ends_with("line 5 'ftp://x/': not an absolute http(s) URL (and 3 more)")
+        );
+    }
+
+    #[test]
+    fn test_directories_need_trailing_slash() {
+        assert_eq!(
+            error_of("https://static.prts.wiki/images", "Directory"),
+            "Invalid object_path: line 1 'https://static.prts.wiki/images': directory paths must end with '/'"
+        );
+        assert!(validate_object_paths("https://static.prts.wiki/images/", "Directory").is_ok());
+    }
+
+    #[test]
+    fn test_per_type_limits() {
+        let files = vec!["https://static.prts.wiki/a.png"; MAX_FILE_PATHS + 1].join("\n");
+        assert!(error_of(&files, "File").contains("at most 1000 per File refresh"));
+
+        let directories =
+            vec!["https://static.prts.wiki/images/"; MAX_DIRECTORY_PATHS + 1].join("\n");
+        assert!(error_of(&directories, "Directory").contains("at most 100 per Directory refresh"));
+        assert!(
+            validate_object_paths(
+                &vec!["https://static.prts.wiki/images/"; MAX_DIRECTORY_PATHS].join("\n"),
+                "Directory"
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_parsing_has_no_limit() {
+        let files = vec!["https://static.prts.wiki/a.png"; MAX_FILE_PATHS + 1].join("\n");
+        assert_eq!(
+            parse_object_paths(&files, "File").unwrap().len(),
+            MAX_FILE_PATHS + 1
+        );
+        assert!(parse_object_paths("static.prts.wiki/a.png", "File").is_err());
+    }
+
+    #[test]
+    fn test_blank_object_path_is_rejected() {
+        assert_eq!(
+            error_of(" \n\n", "File"),
+            "object_path must contain at least one URL"
+        );
+    }
+}
@@ -0,0 +1,537 @@
+//! Minimal OSS client, just what OSS event handling needs
+//!
+//! OSS doesn't take the OpenAPI signature, so requests are signed with its V4 signature
+//! (OSS4-HMAC-SHA256).
+//! Docs: https://help.aliyun.com/zh/oss/developer-reference/recommend-to-use-signature-version-4
+
+use std::{
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use percent_encoding::percent_encode;
+use reqwest::header::{AUTHORIZATION, HeaderMap, HeaderName, HeaderValue};
+
+use super::credentials::{CredentialProvider, Credentials, SharedCredentials};
+use super::signature::{UNRESERVED, hex_encode_lower, sha256_hex};
+use crate::{
+    config::AliyunConfig,
+    error::{AppError, AppResult},
+    http_client::request_error,
+    metrics::record_aliyun_call,
+};
+
+/// OSS only signs the payload on request, a HEAD has none anyway
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+/// Most keys OSS returns in one ListObjectsV2 page
+const MAX_KEYS_PER_PAGE: usize = 1000;
+
+/// Client for the OSS buckets behind the CDN
+pub struct OssClient {
+    credentials: SharedCredentials,
+    credential_provider: Option<Arc<CredentialProvider>>,
+    client: reqwest::Client,
+    /// URL template with `{bucket}` and `{region}` placeholders
+    endpoint: String,
+    /// Overrides the shared client's total timeout for each request
+    timeout: Option<Duration>,
+    /// `host:port` of the proxy in use, named when it refuses the connection
+    proxy_host: Option<String>,
+}
+
+impl std::fmt::Debug for OssClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // The credentials are secrets, keep them out of logs
+        f.debug_struct("OssClient")
+            .field("endpoint", &self.endpoint)
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+impl OssClient {
+    pub fn new(config: &AliyunConfig, client: reqwest::Client) -> Self {
+        Self {
+            credentials: Arc::new(RwLock::new(Credentials::from_config(config))),
+            credential_provider: None,
+            client,
+            endpoint: config.oss_endpoint.trim_end_matches('/').to_string(),
+            timeout: None,
+            proxy_host: None,
+        }
+    }
+
+    /// Sign with `credentials`, which the STS refresh task may rotate at any time
+    pub fn with_credentials(mut self, credentials: SharedCredentials) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    /// Fetch the credentials from `provider` before a request when they have expired
+    pub fn with_credential_provider(mut self, provider: Arc<CredentialProvider>) -> Self {
+        self.credential_provider = Some(provider);
+        self
+    }
+
+    /// Bound every request to `timeout` instead of the shared client's default
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Name `proxy_host` in errors when the proxy refuses the connection
+    pub fn with_proxy_host(mut self, proxy_host: Option<String>) -> Self {
+        self.proxy_host = proxy_host;
+        self
+    }
+
+    /// Whether `key` exists in `bucket`, located in `region` (e.g. `cn-shanghai`)
+    ///
+    /// Only a `404` answers `false`; any other failure, including a denied HeadObject, is an
+    /// error.
+    pub async fn head_object(&self, region: &str, bucket: &str, key: &str) -> AppResult<bool> {
+        let credentials = self.current_credentials().await?;
+        let path = encode_key(key);
+        let url = format!("{}/{}", self.bucket_endpoint(region, bucket), path);
+        let headers = sign_v4(
+            &credentials,
+            "HEAD",
+            &format!("/{bucket}/{path}"),
+            "",
+            region,
+            Utc::now(),
+        )?;
+
+        let mut request = self.client.head(&url).headers(headers);
+        if let Some(timeout) = self.timeout {
+            request = request.timeout(timeout);
+        }
+        let started = Instant::now();
+        let response = request.send().await.map_err(|err| {
+            record_aliyun_call("HeadObject", "network_error", started.elapsed());
+            request_error(
+                err,
+                "Failed to send HeadObject request",
+                self.proxy_host.as_deref(),
+            )
+        })?;
+        let status = response.status();
+        let result = if status.is_success() {
+            "ok"
+        } else {
+            status.as_str()
+        };
+        record_aliyun_call("HeadObject", result, started.elapsed());
+
+        match status {
+            status if status.is_success() => Ok(true),
+            reqwest::StatusCode::NOT_FOUND => Ok(false),
+            // A HEAD answer has no body to explain itself, only the request id
+            status => Err(AppError::InternalError(anyhow::anyhow!(
+                "OSS HeadObject of {}/{} answered {} (request {})",
+                bucket,
+                key,
+                status,
+                response
+                    .headers()
+                    .get("x-oss-request-id")
+                    .and_then(|value| value.to_str().ok())
+                    .unwrap_or("unknown")
+            ))),
+        }
+    }
+
+    /// Keys of the objects under `prefix` in `bucket`, following every page of the listing
+    ///
+    /// Answers `None` as soon as the listing holds more than `max` objects, so a huge
+    /// directory isn't listed in full.
+    pub async fn list_objects_v2(
+        &self,
+        region: &str,
+        bucket: &str,
+        prefix: &str,
+        max: usize,
+    ) -> AppResult<Option<Vec<String>>> {
+        let mut keys = Vec::new();
+        let mut continuation_token = None;
+        loop {
+            // One more than the cap tells an exact fit apart from an overflow
+            let max_keys = (max + 1 - keys.len()).min(MAX_KEYS_PER_PAGE);
+            let body = self
+                .list_page(
+                    region,
+                    bucket,
+                    prefix,
+                    continuation_token.as_deref(),
+                    max_keys,
+                )
+                .await?;
+            keys.extend(xml_elements(&body, "Key").map(xml_unescape));
+            if keys.len() > max {
+                return Ok(None);
+            }
+            continuation_token = xml_elements(&body, "NextContinuationToken")
+                .next()
+                .map(xml_unescape);
+            let truncated = xml_elements(&body, "IsTruncated").next() == Some("true");
+            if !truncated || continuation_token.is_none() {
+                return Ok(Some(keys));
+            }
+        }
+    }
+
+    /// One page of a ListObjectsV2 listing, as the raw XML answer
+    async fn list_page(
+        &self,
+        region: &str,
+        bucket: &str,
+        prefix: &str,
+        continuation_token: Option<&str>,
+        max_keys: usize,
+    ) -> AppResult<String> {
+        let credentials = self.current_credentials().await?;
+        let mut query = vec![
+            ("list-type", "2".to_string()),
+            ("max-keys", max_keys.to_string()),
+            ("prefix", prefix.to_string()),
+        ];
+        if let Some(token) = continuation_token {
+            query.push(("continuation-token", token.to_string()));
+        }
+        query.sort();
+        let query = query
+            .iter()
+            .map(|(name, value)| format!("{name}={}", percent_encode(value.as_bytes(), UNRESERVED)))
+            .collect::<Vec<_>>()
+            .join("&");
+        let url = format!("{}/?{query}", self.bucket_endpoint(region, bucket));
+        let headers = sign_v4(
+            &credentials,
+            "GET",
+            &format!("/{bucket}/"),
+            &query,
+            region,
+            Utc::now(),
+        )?;
+
+        let mut request = self.client.get(&url).headers(headers);
+        if let Some(timeout) = self.timeout {
+            request = request.timeout(timeout);
+        }
+        let started = Instant::now();
+        let response = request.send().await.map_err(|err| {
+            record_aliyun_call("ListObjectsV2", "network_error", started.elapsed());
+            request_error(
+                err,
+                "Failed to send ListObjectsV2 request",
+                self.proxy_host.as_deref(),
+            )
+        })?;
+        let status = response.status();
+        let result = if status.is_success() {
+            "ok"
+        } else {
+            status.as_str()
+        };
+        record_aliyun_call("ListObjectsV2", result, started.elapsed());
+        let body = response
+            .text()
+            .await
+            .context("Failed to read ListObjectsV2 response")?;
+        if !status.is_success() {
+            return Err(AppError::InternalError(anyhow::anyhow!(
+                "OSS ListObjectsV2 of {}/{} answered {}: {} (request {})",
+                bucket,
+                prefix,
+                status,
+                xml_elements(&body, "Code")
+                    .next()
+                    .unwrap_or("unknown error"),
+                xml_elements(&body, "RequestId").next().unwrap_or("unknown")
+            )));
+        }
+        Ok(body)
+    }
+
+    /// The credentials to sign with, fetched first when they have expired
+    async fn current_credentials(&self) -> AppResult<Credentials> {
+        if let Some(provider) = &self.credential_provider {
+            provider
+                .refresh_if_expired(&self.client, &self.credentials)
+                .await
+                .context("Failed to fetch expired Aliyun credentials")?;
+        }
+        Ok(self
+            .credentials
+            .read()
+            .expect("credentials lock poisoned")
+            .clone())
+    }
+
+    fn bucket_endpoint(&self, region: &str, bucket: &str) -> String {
+        self.endpoint
+            .replace("{bucket}", bucket)
+            .replace("{region}", region)
+    }
+}
+
+/// Texts of every `<tag>` element in an OSS XML answer
+///
+/// OSS answers are flat enough that scanning for the tags is all the parsing they need.
+fn xml_elements<'a>(body: &'a str, tag: &str) -> impl Iterator<Item = &'a str> {
+    let open = format!("<{tag}>");
+    let close = format!("</{tag}>");
+    let mut rest = body;
+    std::iter::from_fn(move || {
+        let start = rest.find(&open)? + open.len();
+        let end = start + rest[start..].find(&close)?;
+        let text = &rest[start..end];
+        rest = &rest[end + close.len()..];
+        Some(text)
+    })
+}
+
+/// Undo the XML escaping of an element's text
+fn xml_unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Percent-encode an object key for the URL path, keeping its `/` separators
+fn encode_key(key: &str) -> String {
+    key.split('/')
+        .map(|segment| percent_encode(segment.as_bytes(), UNRESERVED).to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn hmac_sha256(key: &[u8], message: &str) -> Vec<u8> {
+    use hmac::{Hmac, Mac};
+
+    let mut mac = Hmac::<sha2::Sha256>::new_from_slice(key).expect("HMAC can take key of any size");
+    mac.update(message.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Headers signing a bodiless request for `canonical_uri` (`/{bucket}/{encoded key}`) at `now`
+///
+/// `canonical_query` is the request's query, sorted and encoded. No additional headers are
+/// signed, so only `x-oss-*` ones are.
+fn sign_v4(
+    credentials: &Credentials,
+    method: &str,
+    canonical_uri: &str,
+    canonical_query: &str,
+    region: &str,
+    now: DateTime<Utc>,
+) -> AppResult<HeaderMap> {
+    let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+    let scope = format!("{date}/{region}/oss/aliyun_v4_request");
+
+    let mut signed = vec![
+        ("x-oss-content-sha256", UNSIGNED_PAYLOAD.to_string()),
+        ("x-oss-date", timestamp.clone()),
+    ];
+    if let Some(token) = &credentials.security_token {
+        signed.push(("x-oss-security-token", token.clone()));
+    }
+    signed.sort();
+    let canonical_headers = signed
+        .iter()
+        .map(|(name, value)| format!("{name}:{}\n", value.trim()))
+        .collect::<String>();
+    // Method, URI, query, headers, additional header names (none) and payload hash
+    let canonical_request = format!(
+        "{method}\n{canonical_uri}\n{canonical_query}\n{canonical_headers}\n\n{UNSIGNED_PAYLOAD}"
+    );
+    let string_to_sign = format!(
+        "OSS4-HMAC-SHA256\n{timestamp}\n{scope}\n{}",
+        sha256_hex(canonical_request.as_bytes())
+    );
+
+    let mut key = hmac_sha256(
+        format!("aliyun_v4{}", credentials.access_key_secret).as_bytes(),
+        &date,
+    );
+    for part in [region, "oss", "aliyun_v4_request"] {
+        key = hmac_sha256(&key, part);
+    }
+    let signature = hex_encode_lower(&hmac_sha256(&key, &string_to_sign));
+
+    let mut headers = HeaderMap::new();
+    for (name, value) in signed {
+        headers.insert(
+            HeaderName::from_static(name),
+            HeaderValue::from_str(&value).context("invalid OSS header value")?,
+        );
+    }
+    headers.insert(
+        AUTHORIZATION,
+        HeaderValue::from_str(&format!(
+            "OSS4-HMAC-SHA256 Credential={}/{scope},Signature={signature}",
+            credentials.access_key_id
+        ))
+        .context("invalid authorization header value")?,
+    );
+    Ok(headers)
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{header, header_regex, method, path, query_param, query_param_is_missing},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_sign_v4_scopes_the_credential() {
+        let credentials = Credentials {
+            access_key_id: "LTAI".to_string(),
+            access_key_secret: "secret".to_string(),
+            security_token: Some("token".to_string()),
+            expiration: None,
+        };
+        let now = DateTime::parse_from_rfc3339("2026-10-16T02:03:04Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let headers = sign_v4(
+            &credentials,
+            "HEAD",
+            "/prts-static/a.png",
+            "",
+            "cn-shanghai",
+            now,
+        )
+        .unwrap();
+
+        assert_eq!(headers["x-oss-date"], "20261016T020304Z");
+        assert_eq!(headers["x-oss-security-token"], "token");
+        let authorization = headers[AUTHORIZATION].to_str().unwrap();
+        let signature = authorization
+            .strip_prefix(
+                "OSS4-HMAC-SHA256 Credential=LTAI/20261016/cn-shanghai/oss/aliyun_v4_request,Signature=",
+            )
+            .unwrap();
+        assert_eq!(signature.len(), 64);
+        // The key is part of what is signed
+        let other = sign_v4(
+            &credentials,
+            "HEAD",
+            "/prts-static/b.png",
+            "",
+            "cn-shanghai",
+            now,
+        )
+        .unwrap();
+        assert_ne!(headers[AUTHORIZATION], other[AUTHORIZATION]);
+    }
+
+    #[tokio::test]
+    async fn test_head_object_tells_missing_objects_apart() {
+        let server = MockServer::start().await;
+        Mock::given(method("HEAD"))
+            .and(path("/images/%E5%B9%B2%E5%91%98.png"))
+            .and(header("x-oss-content-sha256", "UNSIGNED-PAYLOAD"))
+            .and(header_regex(
+                "authorization",
+                "^OSS4-HMAC-SHA256 Credential=LTAI/",
+            ))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+        Mock::given(method("HEAD"))
+            .and(path("/gone.png"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+        Mock::given(method("HEAD"))
+            .and(path("/denied.png"))
+            .respond_with(ResponseTemplate::new(403).insert_header("x-oss-request-id", "R1"))
+            .mount(&server)
+            .await;
+        let config = AliyunConfig {
+            access_key_id: "LTAI".to_string(),
+            access_key_secret: "secret".to_string(),
+            oss_endpoint: server.uri(),
+            ..AliyunConfig::default()
+        };
+        let client = OssClient::new(&config, reqwest::Client::new());
+
+        assert!(
+            client
+                .head_object("cn-shanghai", "prts-static", "images/干员.png")
+                .await
+                .unwrap()
+        );
+        assert!(
+            !client
+                .head_object("cn-shanghai", "prts-static", "gone.png")
+                .await
+                .unwrap()
+        );
+        let err = client
+            .head_object("cn-shanghai", "prts-static", "denied.png")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("403"), "{err}");
+        assert!(err.to_string().contains("request R1"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_list_objects_v2_follows_pages_up_to_the_cap() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/"))
+            .and(query_param("list-type", "2"))
+            .and(query_param("prefix", "images/"))
+            .and(query_param_is_missing("continuation-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                "<ListBucketResult><IsTruncated>true</IsTruncated>\
+                 <NextContinuationToken>page&amp;2</NextContinuationToken>\
+                 <Contents><Key>images/a.png</Key></Contents></ListBucketResult>",
+            ))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/"))
+            .and(query_param("continuation-token", "page&2"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                "<ListBucketResult><IsTruncated>false</IsTruncated>\
+                 <Contents><Key>images/b.png</Key></Contents></ListBucketResult>",
+            ))
+            .mount(&server)
+            .await;
+        let config = AliyunConfig {
+            access_key_id: "LTAI".to_string(),
+            access_key_secret: "secret".to_string(),
+            oss_endpoint: server.uri(),
+            ..AliyunConfig::default()
+        };
+        let client = OssClient::new(&config, reqwest::Client::new());
+
+        let keys = client
+            .list_objects_v2("cn-shanghai", "prts-static", "images/", 2)
+            .await
+            .unwrap();
+        assert_eq!(
+            keys,
+            Some(vec!["images/a.png".to_string(), "images/b.png".to_string()])
+        );
+        assert_eq!(
+            client
+                .list_objects_v2("cn-shanghai", "prts-static", "images/", 1)
+                .await
+                .unwrap(),
+            None
+        );
+    }
+}
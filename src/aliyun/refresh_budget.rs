@@ -0,0 +1,260 @@
+//! Local budget of URLs submitted for CDN refresh
+//!
+//! Aliyun counts every refreshed URL against a daily quota and rejects further calls once it is
+//! used up, so an OSS sync emitting thousands of events would spend the day on failing calls.
+//! Token buckets per minute and per day stop submissions here first. Unlike the route limits in
+//! `rate_limit`, they have to report how much is used, so they don't go through `governor`.
+
+use std::{
+    num::NonZeroU32,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    config::AliyunRefreshBudgetConfig,
+    error::{AppError, AppResult},
+    metrics::record_refresh_budget,
+};
+
+/// Consumption of one window of the refresh budget
+#[derive(Debug, Clone, PartialEq, ToSchema, Serialize)]
+pub struct RefreshBudgetWindow {
+    /// URLs the window allows
+    pub limit: u32,
+    /// URLs counted against it that haven't refilled yet
+    pub used: u32,
+}
+
+/// Consumption of the refresh budget, by window
+#[derive(Debug, Clone, PartialEq, ToSchema, Serialize)]
+pub struct RefreshBudgetStatus {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub minute: Option<RefreshBudgetWindow>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub day: Option<RefreshBudgetWindow>,
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    window: &'static str,
+    config_key: &'static str,
+    limit: u32,
+    /// Tokens regained per second
+    refill_rate: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(
+        window: &'static str,
+        config_key: &'static str,
+        limit: NonZeroU32,
+        period: Duration,
+    ) -> Self {
+        Self {
+            window,
+            config_key,
+            limit: limit.get(),
+            refill_rate: f64::from(limit.get()) / period.as_secs_f64(),
+            tokens: f64::from(limit.get()),
+            updated: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_rate).min(f64::from(self.limit));
+        self.updated = now;
+    }
+
+    /// Time until `urls` tokens are available
+    fn wait_for(&self, urls: u32) -> Duration {
+        let missing = f64::from(urls) - self.tokens;
+        if missing <= 0.0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(missing / self.refill_rate)
+    }
+
+    fn window(&self) -> RefreshBudgetWindow {
+        RefreshBudgetWindow {
+            limit: self.limit,
+            used: self.limit - self.tokens.floor() as u32,
+        }
+    }
+}
+
+/// Buckets of `aliyun.refresh_budget`, shared by every refresh the CDN client sends
+#[derive(Debug)]
+pub struct RefreshBudget {
+    /// Both windows change together, so one lock covers them
+    buckets: Mutex<Vec<TokenBucket>>,
+}
+
+impl RefreshBudget {
+    pub fn new(config: &AliyunRefreshBudgetConfig) -> Self {
+        let mut buckets = Vec::new();
+        if let Some(limit) = config.urls_per_minute {
+            buckets.push(TokenBucket::new(
+                "minute",
+                "urls_per_minute",
+                limit,
+                Duration::from_secs(60),
+            ));
+        }
+        if let Some(limit) = config.urls_per_day {
+            buckets.push(TokenBucket::new(
+                "day",
+                "urls_per_day",
+                limit,
+                Duration::from_secs(24 * 60 * 60),
+            ));
+        }
+        Self {
+            buckets: Mutex::new(buckets),
+        }
+    }
+
+    /// Count `urls` against every window, or take nothing and fail with the seconds until all
+    /// of them have room
+    ///
+    /// More URLs than a window allows can never fit, which is a bad request instead.
+    pub fn take(&self, urls: usize) -> AppResult<()> {
+        self.take_at(urls, Instant::now())
+    }
+
+    fn take_at(&self, urls: usize, now: Instant) -> AppResult<()> {
+        let mut buckets = self.buckets.lock().expect("refresh budget lock poisoned");
+        if buckets.is_empty() {
+            return Ok(());
+        }
+        let urls = u32::try_from(urls).unwrap_or(u32::MAX);
+        if let Some(bucket) = buckets.iter().find(|bucket| urls > bucket.limit) {
+            return Err(AppError::BadRequest(anyhow::anyhow!(
+                "Refresh of {} URLs exceeds aliyun.refresh_budget.{} ({})",
+                urls,
+                bucket.config_key,
+                bucket.limit
+            )));
+        }
+
+        let mut wait = Duration::ZERO;
+        for bucket in buckets.iter_mut() {
+            bucket.refill(now);
+            wait = wait.max(bucket.wait_for(urls));
+        }
+        if !wait.is_zero() {
+            return Err(AppError::RateLimited(
+                wait.as_secs_f64().ceil().max(1.0) as u64
+            ));
+        }
+        for bucket in buckets.iter_mut() {
+            bucket.tokens -= f64::from(urls);
+        }
+        Ok(())
+    }
+
+    /// Current consumption, or `None` when no window is limited
+    pub fn status(&self) -> Option<RefreshBudgetStatus> {
+        self.status_at(Instant::now())
+    }
+
+    fn status_at(&self, now: Instant) -> Option<RefreshBudgetStatus> {
+        let mut buckets = self.buckets.lock().expect("refresh budget lock poisoned");
+        if buckets.is_empty() {
+            return None;
+        }
+        let mut status = RefreshBudgetStatus {
+            minute: None,
+            day: None,
+        };
+        for bucket in buckets.iter_mut() {
+            bucket.refill(now);
+            let window = bucket.window();
+            match bucket.window {
+                "minute" => status.minute = Some(window),
+                _ => status.day = Some(window),
+            }
+        }
+        Some(status)
+    }
+
+    /// Publish the current consumption as gauges, right before a scrape
+    pub fn record_metrics(&self) {
+        let Some(status) = self.status() else {
+            return;
+        };
+        for (window, consumption) in [("minute", status.minute), ("day", status.day)] {
+            if let Some(consumption) = consumption {
+                record_refresh_budget(window, consumption.used, consumption.limit);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn budget(per_minute: Option<u32>, per_day: Option<u32>) -> RefreshBudget {
+        RefreshBudget::new(&AliyunRefreshBudgetConfig {
+            urls_per_minute: per_minute.and_then(NonZeroU32::new),
+            urls_per_day: per_day.and_then(NonZeroU32::new),
+        })
+    }
+
+    #[test]
+    fn test_exhausted_budget_reports_when_it_refills() {
+        let budget = budget(Some(60), Some(100));
+        let start = Instant::now();
+        budget.take_at(50, start).unwrap();
+        assert!(matches!(
+            budget.take_at(20, start),
+            Err(AppError::RateLimited(10))
+        ));
+        // A rejected call takes nothing
+        assert_eq!(
+            budget.status_at(start).unwrap().minute,
+            Some(RefreshBudgetWindow {
+                limit: 60,
+                used: 50
+            })
+        );
+
+        let later = start + Duration::from_secs(10);
+        budget.take_at(20, later).unwrap();
+        assert_eq!(budget.status_at(later).unwrap().day.unwrap().used, 70);
+
+        // The day window refills far slower than the minute one
+        let Err(AppError::RateLimited(wait)) = budget.take_at(40, later + Duration::from_secs(60))
+        else {
+            panic!("the day budget should be exhausted");
+        };
+        assert!((8500..8640).contains(&wait), "{wait}");
+    }
+
+    #[test]
+    fn test_requests_larger_than_a_window_are_rejected() {
+        let budget = budget(Some(10), None);
+        let Err(AppError::BadRequest(err)) = budget.take(11) else {
+            panic!("11 URLs can never fit a budget of 10");
+        };
+        assert_eq!(
+            err.to_string(),
+            "Refresh of 11 URLs exceeds aliyun.refresh_budget.urls_per_minute (10)"
+        );
+        assert_eq!(budget.status().unwrap().day, None);
+    }
+
+    #[test]
+    fn test_no_limits_leave_refreshes_unbudgeted() {
+        let budget = budget(None, None);
+        budget.take(usize::MAX).unwrap();
+        assert_eq!(budget.status(), None);
+    }
+}
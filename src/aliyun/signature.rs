@@ -3,7 +3,12 @@ use chrono::Utc;
 use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, percent_encode};
 use rand::RngCore;
 use sha2::{Digest, Sha256};
-use std::collections::BTreeMap;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+};
+
+use super::credentials::{Credentials, SharedCredentials};
 
 /// RFC 3986 unreserved characters: ALPHA / DIGIT / "-" / "_" / "." / "~"
 /// These characters should NOT be percent-encoded.
@@ -16,9 +21,30 @@ pub const UNRESERVED: &AsciiSet = &NON_ALPHANUMERIC
 /// Aliyun OpenAPI V3 signature generator (ACS3-HMAC-SHA256)
 ///
 /// Docs: https://help.aliyun.com/zh/sdk/product-overview/v3-request-structure-and-signature
+///
+/// Credentials are read on every signature, so rotating them in the shared cell takes effect
+/// without rebuilding the signer.
 pub struct AliyunSigner {
-    access_key_id: String,
-    access_key_secret: String,
+    credentials: SharedCredentials,
+}
+
+/// Request parameters sent in the query string, in any order; a key may repeat
+///
+/// Lists go out either as numbered keys (`TaskId.1`, `TaskId.2`) or as one key given
+/// several times.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueryParams(pub Vec<(String, String)>);
+
+impl From<BTreeMap<String, String>> for QueryParams {
+    fn from(params: BTreeMap<String, String>) -> Self {
+        Self(params.into_iter().collect())
+    }
+}
+
+impl From<Vec<(String, String)>> for QueryParams {
+    fn from(params: Vec<(String, String)>) -> Self {
+        Self(params)
+    }
 }
 
 pub struct AliyunSignInput<'a> {
@@ -27,7 +53,7 @@ pub struct AliyunSignInput<'a> {
     pub canonical_uri: &'a str,
     pub action: &'a str,
     pub version: &'a str,
-    pub query_params: BTreeMap<String, String>,
+    pub query_params: QueryParams,
     pub body: &'a [u8],
     pub content_type: Option<&'a str>,
     /// Any extra request headers. If the name is `x-acs-*`, `host`, or `content-type`, it will be included in the signature.
@@ -35,17 +61,32 @@ pub struct AliyunSignInput<'a> {
 }
 
 pub struct AliyunSignedRequest {
+    /// RFC3986-encoded request path the request was signed for, `/` for RPC-style APIs.
+    pub canonical_uri: String,
     /// RFC3986-encoded canonical query string.
     pub query_string: String,
     pub headers: reqwest::header::HeaderMap,
+    /// `x-acs-date` the request was signed with.
+    pub date: String,
+    /// `x-acs-signature-nonce` the request was signed with.
+    pub nonce: String,
+    /// Hex HMAC-SHA256 signature carried in the `Authorization` header.
+    pub signature: String,
 }
 
 impl AliyunSigner {
     pub fn new(access_key_id: String, access_key_secret: String) -> Self {
-        Self {
+        Self::from_credentials(Arc::new(RwLock::new(Credentials {
             access_key_id,
             access_key_secret,
-        }
+            security_token: None,
+            expiration: None,
+        })))
+    }
+
+    /// Sign with credentials that may be rotated behind the signer's back
+    pub fn from_credentials(credentials: SharedCredentials) -> Self {
+        Self { credentials }
     }
 
     /// Generate a random nonce (hex string) for request.
@@ -60,23 +101,30 @@ impl AliyunSigner {
         Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string()
     }
 
-    fn build_canonical_query_string(params: &BTreeMap<String, String>) -> String {
-        params
+    /// Encode each key and value, sorted by key then value as ACS3 requires
+    fn build_canonical_query_string(params: &QueryParams) -> String {
+        let mut encoded = params
+            .0
             .iter()
             .map(|(k, v)| {
-                format!(
-                    "{}={}",
-                    percent_encode(k.as_bytes(), UNRESERVED),
-                    percent_encode(v.as_bytes(), UNRESERVED)
+                (
+                    percent_encode(k.as_bytes(), UNRESERVED).to_string(),
+                    percent_encode(v.as_bytes(), UNRESERVED).to_string(),
                 )
             })
+            .collect::<Vec<_>>();
+        encoded.sort();
+        encoded
+            .iter()
+            .map(|(k, v)| format!("{k}={v}"))
             .collect::<Vec<_>>()
             .join("&")
     }
 
     /// Canonicalize a request path (CanonicalURI).
     ///
-    /// For RPC-style APIs this is typically just `/`.
+    /// For RPC-style APIs this is typically just `/`; ROA-style APIs put resources in the path,
+    /// whose segments are encoded one by one.
     fn canonicalize_uri(path: &str) -> String {
         if path.is_empty() {
             return "/".to_string();
@@ -103,13 +151,31 @@ impl AliyunSigner {
         out
     }
 
+    /// Sign `input` with the current time and a random nonce.
     pub fn sign_request(&self, input: AliyunSignInput<'_>) -> Result<AliyunSignedRequest> {
+        self.sign_request_at(input, &Self::get_timestamp(), &Self::generate_nonce())
+    }
+
+    /// Sign `input` with a given `x-acs-date` (ISO 8601 UTC) and `x-acs-signature-nonce`.
+    ///
+    /// Deterministic, so signatures can be checked against known vectors.
+    pub fn sign_request_at(
+        &self,
+        input: AliyunSignInput<'_>,
+        date: &str,
+        nonce: &str,
+    ) -> Result<AliyunSignedRequest> {
+        let credentials = self
+            .credentials
+            .read()
+            .expect("credentials lock poisoned")
+            .clone();
         let host = input.host.trim();
         let action = input.action.trim();
         let version = input.version.trim();
 
-        let x_acs_date = Self::get_timestamp();
-        let x_acs_signature_nonce = Self::generate_nonce();
+        let x_acs_date = date.to_string();
+        let x_acs_signature_nonce = nonce.to_string();
         let x_acs_content_sha256 = sha256_hex(input.body);
 
         // Canonical query
@@ -143,6 +209,9 @@ impl AliyunSigner {
         if let Some(ct) = input.content_type {
             signing_headers.insert("content-type".to_string(), ct.trim().to_string());
         }
+        if let Some(token) = &credentials.security_token {
+            signing_headers.insert("x-acs-security-token".to_string(), token.clone());
+        }
 
         let canonical_headers = signing_headers
             .iter()
@@ -166,11 +235,11 @@ impl AliyunSigner {
 
         let hashed_canonical_request = sha256_hex(canonical_request.as_bytes());
         let string_to_sign = format!("ACS3-HMAC-SHA256\n{}", hashed_canonical_request);
-        let signature = hmac_sha256_hex(&self.access_key_secret, &string_to_sign);
+        let signature = hmac_sha256_hex(&credentials.access_key_secret, &string_to_sign);
 
         let authorization = format!(
             "ACS3-HMAC-SHA256 Credential={},SignedHeaders={},Signature={}",
-            self.access_key_id, signed_headers, signature
+            credentials.access_key_id, signed_headers, signature
         );
 
         let mut headers = reqwest::header::HeaderMap::new();
@@ -214,6 +283,14 @@ impl AliyunSigner {
                 ct.parse().context("invalid content-type header value")?,
             );
         }
+        if let Some(token) = &credentials.security_token {
+            headers.insert(
+                reqwest::header::HeaderName::from_static("x-acs-security-token"),
+                token
+                    .parse()
+                    .context("invalid x-acs-security-token header value")?,
+            );
+        }
         headers.insert(
             reqwest::header::AUTHORIZATION,
             authorization
@@ -222,13 +299,17 @@ impl AliyunSigner {
         );
 
         Ok(AliyunSignedRequest {
+            canonical_uri,
             query_string: canonical_query,
             headers,
+            date: x_acs_date,
+            nonce: x_acs_signature_nonce,
+            signature,
         })
     }
 }
 
-fn sha256_hex(input: &[u8]) -> String {
+pub(super) fn sha256_hex(input: &[u8]) -> String {
     let mut hasher = Sha256::new();
     hasher.update(input);
     hex_encode_lower(&hasher.finalize())
@@ -245,7 +326,7 @@ fn hmac_sha256_hex(secret: &str, message: &str) -> String {
     hex_encode_lower(&result)
 }
 
-fn hex_encode_lower(input: &[u8]) -> String {
+pub(super) fn hex_encode_lower(input: &[u8]) -> String {
     let mut out = String::with_capacity(input.len() * 2);
     for b in input {
         use std::fmt::Write;
@@ -275,76 +356,112 @@ mod tests {
         );
         query_params.insert("RegionId".to_string(), "cn-shanghai".to_string());
 
-        // Keep deterministic verification without injecting timestamp/nonce into `sign_request`.
-        let method = "POST";
-        let host = "ecs.cn-shanghai.aliyuncs.com";
-        let canonical_uri = "/";
-        let action = "RunInstances";
-        let version = "2014-05-26";
-        let x_acs_date = "2023-10-26T10:22:32Z";
-        let x_acs_signature_nonce = "3156853299f313e23d1673dc12e1703d";
-        let x_acs_content_sha256 =
-            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
-
-        let canonical_query = AliyunSigner::build_canonical_query_string(&query_params);
+        let signed = signer
+            .sign_request_at(
+                AliyunSignInput {
+                    method: "POST",
+                    host: "ecs.cn-shanghai.aliyuncs.com",
+                    canonical_uri: "/",
+                    action: "RunInstances",
+                    version: "2014-05-26",
+                    query_params: query_params.into(),
+                    body: b"",
+                    content_type: None,
+                    extra_headers: BTreeMap::new(),
+                },
+                "2023-10-26T10:22:32Z",
+                "3156853299f313e23d1673dc12e1703d",
+            )
+            .unwrap();
+
         assert_eq!(
-            canonical_query,
+            signed.query_string,
             "ImageId=win2019_1809_x64_dtc_zh-cn_40G_alibase_20230811.vhd&RegionId=cn-shanghai"
         );
-
-        let mut signing_headers: BTreeMap<String, String> = BTreeMap::new();
-        signing_headers.insert("host".to_string(), host.to_string());
-        signing_headers.insert("x-acs-action".to_string(), action.to_string());
-        signing_headers.insert(
-            "x-acs-content-sha256".to_string(),
-            x_acs_content_sha256.to_string(),
-        );
-        signing_headers.insert("x-acs-date".to_string(), x_acs_date.to_string());
-        signing_headers.insert(
-            "x-acs-signature-nonce".to_string(),
-            x_acs_signature_nonce.to_string(),
-        );
-        signing_headers.insert("x-acs-version".to_string(), version.to_string());
-
-        let canonical_headers = signing_headers
-            .iter()
-            .map(|(k, v)| format!("{}:{}\n", k, v))
-            .collect::<String>();
-        let signed_headers = signing_headers
-            .keys()
-            .cloned()
-            .collect::<Vec<_>>()
-            .join(";");
-
+        assert_eq!(signed.date, "2023-10-26T10:22:32Z");
+        assert_eq!(signed.nonce, "3156853299f313e23d1673dc12e1703d");
         assert_eq!(
-            signed_headers,
-            "host;x-acs-action;x-acs-content-sha256;x-acs-date;x-acs-signature-nonce;x-acs-version"
+            signed.headers["x-acs-content-sha256"],
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
         );
-
-        let canonical_request = format!(
-            "{}\n{}\n{}\n{}\n{}\n{}",
-            method,
-            AliyunSigner::canonicalize_uri(canonical_uri),
-            canonical_query,
-            canonical_headers,
-            signed_headers,
-            x_acs_content_sha256
-        );
-
-        let hashed_canonical_request = sha256_hex(canonical_request.as_bytes());
         assert_eq!(
-            hashed_canonical_request,
-            "7ea06492da5221eba5297e897ce16e55f964061054b7695beedaac1145b1e259"
+            signed.signature,
+            "06563a9e1b43f5dfe96b81484da74bceab24a1d853912eee15083a6f0f3283c0"
         );
-
-        let string_to_sign = format!("ACS3-HMAC-SHA256\n{}", hashed_canonical_request);
-        let signature = hmac_sha256_hex(&signer.access_key_secret, &string_to_sign);
         assert_eq!(
-            signature,
-            "06563a9e1b43f5dfe96b81484da74bceab24a1d853912eee15083a6f0f3283c0"
+            signed.headers[reqwest::header::AUTHORIZATION],
+            "ACS3-HMAC-SHA256 Credential=YourAccessKeyId,\
+             SignedHeaders=host;x-acs-action;x-acs-content-sha256;x-acs-date;x-acs-signature-nonce;x-acs-version,\
+             Signature=06563a9e1b43f5dfe96b81484da74bceab24a1d853912eee15083a6f0f3283c0"
         );
     }
 
+    #[test]
+    fn test_sign_request_uses_fresh_date_and_nonce() {
+        let signer = AliyunSigner::new("id".to_string(), "secret".to_string());
+        let input = || AliyunSignInput {
+            method: "GET",
+            host: "cdn.aliyuncs.com",
+            canonical_uri: "/",
+            action: "DescribeRefreshTaskById",
+            version: "2018-05-10",
+            query_params: QueryParams::default(),
+            body: b"",
+            content_type: None,
+            extra_headers: BTreeMap::new(),
+        };
+
+        let first = signer.sign_request(input()).unwrap();
+        let second = signer.sign_request(input()).unwrap();
+        assert_eq!(first.nonce.len(), 32);
+        assert_ne!(first.nonce, second.nonce);
+        assert_ne!(first.signature, second.signature);
+        assert_eq!(first.headers["x-acs-date"], first.date.as_str());
+    }
+
+    #[test]
+    fn test_security_token_is_signed_and_sent() {
+        let credentials = Arc::new(RwLock::new(Credentials {
+            access_key_id: "STS.id".to_string(),
+            access_key_secret: "secret".to_string(),
+            security_token: Some("sts-token".to_string()),
+            expiration: None,
+        }));
+        let signer = AliyunSigner::from_credentials(credentials.clone());
+        let input = || AliyunSignInput {
+            method: "POST",
+            host: "cdn.aliyuncs.com",
+            canonical_uri: "/",
+            action: "RefreshObjectCaches",
+            version: "2018-05-10",
+            query_params: QueryParams::default(),
+            body: b"",
+            content_type: None,
+            extra_headers: BTreeMap::new(),
+        };
+
+        let signed = signer.sign_request(input()).unwrap();
+        assert_eq!(signed.headers["x-acs-security-token"], "sts-token");
+        let authorization = signed.headers[reqwest::header::AUTHORIZATION]
+            .to_str()
+            .unwrap();
+        assert!(authorization.starts_with("ACS3-HMAC-SHA256 Credential=STS.id,"));
+        assert!(authorization.contains(
+            "SignedHeaders=host;x-acs-action;x-acs-content-sha256;x-acs-date;\
+             x-acs-security-token;x-acs-signature-nonce;x-acs-version,"
+        ));
+
+        // Rotated credentials are picked up by the same signer
+        *credentials.write().unwrap() = Credentials {
+            access_key_id: "STS.next".to_string(),
+            access_key_secret: "next".to_string(),
+            security_token: Some("next-token".to_string()),
+            expiration: None,
+        };
+        let signed = signer.sign_request(input()).unwrap();
+        assert_eq!(signed.headers["x-acs-security-token"], "next-token");
+    }
+
     #[test]
     fn test_canonicalize_uri_with_unreserved_chars() {
         // Test that unreserved characters (-, _, ., ~) are NOT percent-encoded
@@ -378,6 +495,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_canonicalize_uri_for_roa_paths() {
+        // A trailing slash names a different resource, so it's kept
+        assert_eq!(AliyunSigner::canonicalize_uri("/v2/rules/"), "/v2/rules/");
+        assert_eq!(AliyunSigner::canonicalize_uri(""), "/");
+
+        // Segments are encoded on their own; `%` too, so values are passed unencoded
+        assert_eq!(
+            AliyunSigner::canonicalize_uri("/v2/rules/a+b:c%20"),
+            "/v2/rules/a%2Bb%3Ac%2520"
+        );
+        assert_eq!(
+            AliyunSigner::canonicalize_uri("/v2/规则"),
+            "/v2/%E8%A7%84%E5%88%99"
+        );
+    }
+
     #[test]
     fn test_build_canonical_query_string_with_unreserved_chars() {
         // Test that unreserved characters (-, _, ., ~) are NOT percent-encoded
@@ -386,20 +520,76 @@ mod tests {
         params.insert("key.2".to_string(), "value.2".to_string());
         params.insert("key~3".to_string(), "value~3".to_string());
 
-        let result = AliyunSigner::build_canonical_query_string(&params);
+        let result = AliyunSigner::build_canonical_query_string(&params.into());
         // BTreeMap orders keys alphabetically
         assert_eq!(result, "key-1=value_1&key.2=value.2&key~3=value~3");
 
         // Test that special characters ARE encoded
         let mut params2 = BTreeMap::new();
         params2.insert("key with space".to_string(), "value with space".to_string());
-        let result2 = AliyunSigner::build_canonical_query_string(&params2);
+        let result2 = AliyunSigner::build_canonical_query_string(&params2.into());
         assert_eq!(result2, "key%20with%20space=value%20with%20space");
 
         // Test mixed case
         let mut params3 = BTreeMap::new();
         params3.insert("valid-_~.key".to_string(), "needs encoding!".to_string());
-        let result3 = AliyunSigner::build_canonical_query_string(&params3);
+        let result3 = AliyunSigner::build_canonical_query_string(&params3.into());
         assert_eq!(result3, "valid-_~.key=needs%20encoding%21");
     }
+
+    #[test]
+    fn test_repeated_query_keys_are_sorted_by_key_then_value() {
+        let params = |pairs: &[(&str, &str)]| {
+            QueryParams::from(
+                pairs
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect::<Vec<_>>(),
+            )
+        };
+        let signer = AliyunSigner::new("id".to_string(), "secret".to_string());
+        let sign = |query_params| {
+            signer
+                .sign_request_at(
+                    AliyunSignInput {
+                        method: "GET",
+                        host: "cdn.aliyuncs.com",
+                        canonical_uri: "/",
+                        action: "DescribeRefreshTaskById",
+                        version: "2018-05-10",
+                        query_params,
+                        body: b"",
+                        content_type: None,
+                        extra_headers: BTreeMap::new(),
+                    },
+                    "2026-10-16T02:00:00Z",
+                    "3156853299f313e23d1673dc12e1703d",
+                )
+                .unwrap()
+        };
+
+        let signed = sign(params(&[
+            ("TaskId", "2"),
+            ("Status", "Complete"),
+            ("TaskId", "1"),
+            ("TaskId.1", "b c"),
+        ]));
+        assert_eq!(
+            signed.query_string,
+            "Status=Complete&TaskId=1&TaskId=2&TaskId.1=b%20c"
+        );
+        assert_eq!(
+            format!("https://cdn.aliyuncs.com/?{}", signed.query_string),
+            "https://cdn.aliyuncs.com/?Status=Complete&TaskId=1&TaskId=2&TaskId.1=b%20c"
+        );
+
+        // The order parameters are given in doesn't change the signature
+        let reordered = sign(params(&[
+            ("TaskId.1", "b c"),
+            ("TaskId", "1"),
+            ("TaskId", "2"),
+            ("Status", "Complete"),
+        ]));
+        assert_eq!(reordered.signature, signed.signature);
+    }
 }
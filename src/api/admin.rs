@@ -0,0 +1,26 @@
+//! Models of the `/api/admin` routes
+
+use serde::{Deserialize, Serialize};
+
+/// Payload for toggling read-only mode
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SetReadOnlyPayload {
+    pub enabled: bool,
+    /// Operator message returned by blocked endpoints while engaged
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+/// Current read-only mode state
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ReadOnlyStatus {
+    pub enabled: bool,
+    /// Operator message returned by blocked endpoints
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// When the mode was last changed (RFC3339)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub changed_at: Option<String>,
+}
@@ -0,0 +1,1252 @@
+//! Models of the `/api/aliyun` routes
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// OSS bucket information in event data
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct OssBucket {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arn: Option<String>,
+    #[serde(rename = "ownerIdentity", skip_serializing_if = "Option::is_none")]
+    pub owner_identity: Option<String>,
+}
+
+/// OSS object information in event data
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct OssObject {
+    pub key: String,
+    #[serde(rename = "eTag", skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(rename = "deltaSize", skip_serializing_if = "Option::is_none")]
+    pub delta_size: Option<i64>,
+}
+
+/// OSS-specific data in event
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct OssData {
+    pub bucket: OssBucket,
+    pub object: OssObject,
+    #[serde(rename = "ossSchemaVersion", skip_serializing_if = "Option::is_none")]
+    pub oss_schema_version: Option<String>,
+}
+
+/// Complete event data structure from OSS
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct OssEventData {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    #[serde(rename = "eventVersion", skip_serializing_if = "Option::is_none")]
+    pub event_version: Option<String>,
+    #[serde(rename = "eventSource", skip_serializing_if = "Option::is_none")]
+    pub event_source: Option<String>,
+    #[serde(rename = "eventName", skip_serializing_if = "Option::is_none")]
+    pub event_name: Option<String>,
+    #[serde(rename = "eventTime", skip_serializing_if = "Option::is_none")]
+    pub event_time: Option<String>,
+    pub oss: OssData,
+}
+
+/// EventBridge OSS event payload
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct OssEventPayload {
+    pub id: String,
+    pub source: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub specversion: Option<String>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub event_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub datacontenttype: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time: Option<String>,
+    pub data: OssEventData,
+}
+
+/// Response for OSS event handler
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct OssEventResponse {
+    pub message: String,
+    /// First of `task_ids`, kept for clients predating buckets with several CDN domains
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task_id: Option<String>,
+    /// Aliyun refresh task ids, one or more per mapped CDN domain
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub task_ids: Vec<String>,
+    /// URLs that would have been refreshed, one per line (dry-run only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub object_path: Option<String>,
+    /// Refresh type that would have been used (dry-run only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub object_type: Option<String>,
+    /// Queued refresh, see `GET /api/aliyun/jobs/{id}`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub job_id: Option<u64>,
+    /// Dead-lettered event, see `GET /api/aliyun/events/dlq`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dead_letter_id: Option<u64>,
+    /// Every URL was refreshed within `aliyun.url_dedup_window_secs`; `task_ids` are of that
+    /// refresh
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub deduplicated: bool,
+}
+
+impl OssEventResponse {
+    /// An event that was acknowledged without refreshing anything
+    pub fn skipped(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            ..Self::default()
+        }
+    }
+
+    /// With the ids of the refresh that already covered the event
+    #[must_use]
+    pub fn with_task_ids(mut self, task_ids: Vec<String>) -> Self {
+        self.task_id = task_ids.first().cloned();
+        self.task_ids = task_ids;
+        self
+    }
+}
+
+/// EventBridge delivers one event, or an array of them when batching is enabled
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(untagged)]
+pub enum OssEventsPayload {
+    Batch(Vec<OssEventPayload>),
+    Single(Box<OssEventPayload>),
+}
+
+/// What happened to one delivered event
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum OssEventStatus {
+    Refreshed,
+    /// Left to the background refresh worker
+    Queued,
+    /// Filtered out or a duplicate of a recent refresh
+    Skipped,
+    /// Held back while read-only mode is engaged
+    Deferred,
+    /// Could never be processed as delivered, parked for `POST /api/aliyun/events/dlq/{id}/replay`
+    DeadLettered,
+    /// Left for EventBridge to redeliver
+    Failed,
+}
+
+/// Outcome of one event in a batch delivery
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct OssEventResult {
+    /// Event `id`, when the event had one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub status: OssEventStatus,
+    pub message: String,
+    /// First of `task_ids`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub task_ids: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub job_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dead_letter_id: Option<u64>,
+}
+
+/// Response for a batch delivery, listing each event in order
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct OssBatchEventResponse {
+    pub message: String,
+    pub results: Vec<OssEventResult>,
+}
+
+/// Response matching the shape of the delivery
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(untagged)]
+pub enum OssEventsResponse {
+    Batch(OssBatchEventResponse),
+    Single(OssEventResponse),
+}
+
+/// Aliyun product a refresh is sent to
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum CdnProduct {
+    /// Classic CDN, `cdn.aliyuncs.com`
+    #[default]
+    Cdn,
+    /// Dynamic Route for CDN (DCDN), `dcdn.aliyuncs.com`
+    Dcdn,
+}
+
+/// Payload for a manual CDN refresh
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RefreshObjectCachesPayload {
+    /// URLs to refresh, one per line
+    pub object_path: String,
+    /// `File` (default) or `Directory`
+    #[serde(default)]
+    pub object_type: Option<String>,
+    /// Delete cached copies instead of marking them expired
+    #[serde(default)]
+    pub force: Option<bool>,
+    /// Validate and sign the call but don't send it, so no quota is used
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Send every path to this product; by default each goes to DCDN when its domain is
+    /// listed in `aliyun.dcdn.domains`, else to CDN
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub product: Option<CdnProduct>,
+}
+
+/// Payload for refreshing whole CDN directories
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RefreshDirectoryPayload {
+    /// Directory URLs, at most 100; a missing trailing `/` is added and query strings dropped
+    pub directories: Vec<String>,
+    /// Delete cached copies instead of marking them expired
+    #[serde(default)]
+    pub force: Option<bool>,
+    /// Validate and sign the call but don't send it, so no quota is used
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Send every directory to this product; by default each goes to DCDN when its domain is
+    /// listed in `aliyun.dcdn.domains`, else to CDN
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub product: Option<CdnProduct>,
+}
+
+/// Result of a manual CDN refresh
+///
+/// Paths beyond what Aliyun accepts in one call (1000 files or 100 directories) are refreshed
+/// in several calls, one task id each.
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RefreshObjectCachesResult {
+    /// Aliyun task id of the first call that succeeded, or `dry-run`
+    pub task_id: String,
+    /// Aliyun task ids of every call that succeeded, in order
+    #[serde(default)]
+    pub task_ids: Vec<String>,
+    pub object_type: String,
+    pub object_paths: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dry_run: bool,
+    /// Calls Aliyun rejected while others succeeded; retry just their paths
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failed_chunks: Vec<FailedRefreshChunk>,
+    /// Aliyun's `RequestId` of the call that returned `task_id`; absent for dry runs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aliyun_request_id: Option<String>,
+}
+
+/// One call of a split refresh that failed
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct FailedRefreshChunk {
+    /// Position of the call among all calls, from 0
+    pub chunk: usize,
+    pub object_paths: Vec<String>,
+    pub error: String,
+}
+
+/// Payload for a CDN refresh that waits for Aliyun to finish it
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RefreshAndWaitPayload {
+    /// URLs to refresh, one per line, few enough for a single call
+    pub object_path: String,
+    /// `File` (default) or `Directory`
+    #[serde(default)]
+    pub object_type: Option<String>,
+    /// Delete cached copies instead of marking them expired
+    #[serde(default)]
+    pub force: Option<bool>,
+    /// Longest wait for the task to finish, at most 600
+    #[serde(default = "default_wait_timeout_seconds")]
+    pub timeout_seconds: u64,
+    /// Pause between two looks at the task, at least 1
+    #[serde(default = "default_wait_poll_interval_seconds")]
+    pub poll_interval_seconds: u64,
+}
+
+fn default_wait_timeout_seconds() -> u64 {
+    60
+}
+
+fn default_wait_poll_interval_seconds() -> u64 {
+    5
+}
+
+/// Outcome of a CDN refresh that was waited on
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RefreshAndWaitResult {
+    /// Aliyun refresh task id
+    pub task_id: String,
+    /// Last status Aliyun reported; absent if the task wasn't listed yet
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<RefreshTaskStatus>,
+    /// Seconds spent waiting for the task
+    pub elapsed_seconds: f64,
+    /// The task didn't finish within `timeout_seconds`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub timed_out: bool,
+    /// Aliyun's `RequestId` of the refresh call
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aliyun_request_id: Option<String>,
+}
+
+/// Payload for preloading URLs onto CDN edge nodes
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PushObjectCachesPayload {
+    /// URLs to preload, one per line, at most 100
+    pub object_path: String,
+    /// `domestic` (default) or `overseas`
+    #[serde(default)]
+    pub area: Option<String>,
+    /// Also preload onto L2 nodes
+    #[serde(default)]
+    pub l2_preload: Option<bool>,
+}
+
+/// Result of a CDN preload
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PushObjectCachesResult {
+    /// Aliyun preload task id
+    pub task_id: String,
+    pub object_paths: Vec<String>,
+    /// Aliyun's `RequestId` of the preload call
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aliyun_request_id: Option<String>,
+}
+
+/// Payload for refreshing URLs and preloading them again right away
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RefreshAndPreloadPayload {
+    /// URLs to refresh and preload, one per line, at most 100
+    pub object_path: String,
+    /// Preload area, `domestic` (default) or `overseas`
+    #[serde(default)]
+    pub area: Option<String>,
+}
+
+/// Outcome of a refresh followed by a preload of the same URLs
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RefreshAndPreloadResult {
+    /// Aliyun refresh task ids, comma-separated when the URLs span products
+    pub refresh_task_id: String,
+    /// Aliyun preload task id; absent when the preload failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preload_task_id: Option<String>,
+    /// Why the preload failed after the refresh succeeded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preload_error: Option<String>,
+    pub object_paths: Vec<String>,
+}
+
+/// Any CDN OpenAPI call, for actions without a dedicated endpoint
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RawAliyunCallPayload {
+    /// OpenAPI action, e.g. `DescribeDomainsBySource`
+    pub action: String,
+    /// API version, defaults to the CDN API `2018-05-10`
+    #[serde(default = "default_raw_version")]
+    pub version: String,
+    /// `GET` or `POST` (default)
+    #[serde(default = "default_raw_method")]
+    pub method: String,
+    /// Request parameters, sent in the query string
+    #[serde(default)]
+    pub params: BTreeMap<String, String>,
+}
+
+fn default_raw_version() -> String {
+    "2018-05-10".to_string()
+}
+
+fn default_raw_method() -> String {
+    "POST".to_string()
+}
+
+/// Allowlisted CDN OpenAPI call, for actions without a dedicated endpoint
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct InvokeAliyunPayload {
+    /// OpenAPI action, one of `aliyun.invoke_allowed_actions`
+    pub action: String,
+    /// API version, defaults to the CDN API `2018-05-10`
+    #[serde(default = "default_raw_version")]
+    pub version: String,
+    /// `GET` or `POST` (default)
+    #[serde(default = "default_raw_method")]
+    pub method: String,
+    /// Parameters sent in the query string
+    #[serde(default)]
+    pub query_params: BTreeMap<String, String>,
+    /// Parameters sent form-encoded in the body
+    #[serde(default)]
+    pub body: Option<BTreeMap<String, String>>,
+}
+
+/// Aliyun's answer to an allowlisted call, whatever its status
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct InvokeAliyunResult {
+    /// HTTP status Aliyun answered with
+    pub status: u16,
+    /// Aliyun's JSON response, an error body included
+    pub body: serde_json::Value,
+    /// Aliyun's `RequestId` of the call
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aliyun_request_id: Option<String>,
+}
+
+/// Response from DescribeRefreshTaskById API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DescribeRefreshTaskByIdResponse {
+    #[serde(rename = "RequestId")]
+    pub request_id: String,
+
+    #[serde(rename = "TotalCount", default)]
+    pub total_count: u64,
+
+    #[serde(rename = "Tasks", default)]
+    pub tasks: Vec<RefreshTask>,
+}
+
+/// Response from DescribeRefreshQuota API: today's refresh and preload quota
+///
+/// Aliyun sends the counts as strings; they are served as numbers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DescribeRefreshQuotaResponse {
+    #[serde(rename = "RequestId")]
+    pub request_id: String,
+
+    /// URLs that may be refreshed per day
+    #[serde(rename = "UrlQuota", deserialize_with = "quota_count")]
+    pub url_quota: u64,
+
+    #[serde(rename = "UrlRemain", deserialize_with = "quota_count")]
+    pub url_remain: u64,
+
+    /// Directories that may be refreshed per day
+    #[serde(rename = "DirQuota", deserialize_with = "quota_count")]
+    pub dir_quota: u64,
+
+    #[serde(rename = "DirRemain", deserialize_with = "quota_count")]
+    pub dir_remain: u64,
+
+    /// URLs that may be preloaded per day
+    #[serde(rename = "PreloadQuota", deserialize_with = "quota_count")]
+    pub preload_quota: u64,
+
+    #[serde(rename = "PreloadRemain", deserialize_with = "quota_count")]
+    pub preload_remain: u64,
+}
+
+/// A count sent either as a number or as a numeric string
+fn quota_count<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Count {
+        Number(u64),
+        Text(String),
+    }
+    match Count::deserialize(deserializer)? {
+        Count::Number(count) => Ok(count),
+        Count::Text(text) => text.trim().parse().map_err(serde::de::Error::custom),
+    }
+}
+
+/// Kind of task to list with DescribeRefreshTasks
+///
+/// Aliyun's lowercase names; `File` and `Directory` are also accepted as in refresh payloads.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum RefreshTaskObjectType {
+    #[serde(rename = "file", alias = "File")]
+    File,
+    #[serde(rename = "directory", alias = "Directory")]
+    Directory,
+    #[serde(rename = "preload")]
+    Preload,
+    /// Refreshes that ignored URL parameters
+    IgnoreParams,
+    /// Refreshes of URLs with query strings
+    ExQuery,
+}
+
+impl RefreshTaskObjectType {
+    /// The `ObjectType` value Aliyun expects
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RefreshTaskObjectType::File => "file",
+            RefreshTaskObjectType::Directory => "directory",
+            RefreshTaskObjectType::Preload => "preload",
+            RefreshTaskObjectType::IgnoreParams => "IgnoreParams",
+            RefreshTaskObjectType::ExQuery => "ExQuery",
+        }
+    }
+}
+
+/// Status Aliyun reports for a refresh or preload task
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum RefreshTaskStatus {
+    Complete,
+    Refreshing,
+    Failed,
+    /// Accepted but not started yet
+    Pending,
+    /// A status this version doesn't know; never terminal
+    #[serde(other)]
+    Unknown,
+}
+
+impl RefreshTaskStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RefreshTaskStatus::Complete => "Complete",
+            RefreshTaskStatus::Refreshing => "Refreshing",
+            RefreshTaskStatus::Failed => "Failed",
+            RefreshTaskStatus::Pending => "Pending",
+            RefreshTaskStatus::Unknown => "Unknown",
+        }
+    }
+
+    /// The statuses this version knows, i.e. all but `Unknown`
+    const KNOWN: [RefreshTaskStatus; 4] = [
+        RefreshTaskStatus::Complete,
+        RefreshTaskStatus::Refreshing,
+        RefreshTaskStatus::Failed,
+        RefreshTaskStatus::Pending,
+    ];
+    const KNOWN_NAMES: [&str; 4] = ["Complete", "Refreshing", "Failed", "Pending"];
+
+    /// The status named `name` in any case, or `Unknown`
+    pub fn from_name(name: &str) -> Self {
+        Self::KNOWN
+            .into_iter()
+            .find(|status| status.as_str().eq_ignore_ascii_case(name.trim()))
+            .unwrap_or(RefreshTaskStatus::Unknown)
+    }
+
+    /// Whether Aliyun no longer changes a task with this status
+    pub fn is_terminal(self) -> bool {
+        matches!(
+            self,
+            RefreshTaskStatus::Complete | RefreshTaskStatus::Failed
+        )
+    }
+}
+
+/// A status filter, which callers may write in any case
+pub fn task_status_filter<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<RefreshTaskStatus>, D::Error> {
+    Ok(
+        Option::<String>::deserialize(deserializer)?
+            .map(|name| RefreshTaskStatus::from_name(&name)),
+    )
+}
+
+/// A status filter sent on to Aliyun, which must name a status exactly as Aliyun does
+fn known_task_status<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<RefreshTaskStatus>, D::Error> {
+    let Some(name) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    RefreshTaskStatus::KNOWN
+        .into_iter()
+        .find(|status| status.as_str() == name)
+        .map(Some)
+        .ok_or_else(|| serde::de::Error::unknown_variant(&name, &RefreshTaskStatus::KNOWN_NAMES))
+}
+
+/// Filters for listing refresh tasks with DescribeRefreshTasks
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DescribeRefreshTasksPayload {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub object_path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub object_type: Option<RefreshTaskObjectType>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "known_task_status"
+    )]
+    pub status: Option<RefreshTaskStatus>,
+    /// RFC 3339, e.g. `2026-10-16T08:00:00+08:00`; sent to Aliyun in UTC
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<String>,
+    /// RFC 3339, after `start_time` and at most 3 days later
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_time: Option<String>,
+    /// 1-based page, ignored with `fetch_all`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_number: Option<u32>,
+    /// 1 to 100, Aliyun's default 20 if omitted and 100 with `fetch_all`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_size: Option<u32>,
+    /// Follow every page up to `aliyun.fetch_all.max_pages` and return the tasks combined
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub fetch_all: bool,
+    /// With `fetch_all`, stop once this many tasks are fetched
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_results: Option<u32>,
+}
+
+/// Response from DescribeRefreshTasks API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DescribeRefreshTasksResponse {
+    #[serde(rename = "RequestId")]
+    pub request_id: String,
+
+    #[serde(rename = "PageNumber", default)]
+    pub page_number: u64,
+
+    #[serde(rename = "PageSize", default)]
+    pub page_size: u64,
+
+    /// Matching tasks on Aliyun, even when fewer were returned
+    #[serde(rename = "TotalCount", default)]
+    pub total_count: u64,
+
+    #[serde(rename = "Tasks", default)]
+    pub tasks: TasksContainer,
+
+    /// Set when `fetch_all` stopped at the page cap before every task was fetched
+    #[serde(rename = "Warning", default, skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TasksContainer {
+    #[serde(rename = "CDNTask", default)]
+    pub cdn_tasks: Vec<RefreshTask>,
+}
+
+/// One refresh task reported by DescribeRefreshTaskById or DescribeRefreshTasks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RefreshTask {
+    #[serde(rename = "TaskId")]
+    pub task_id: String,
+
+    #[serde(rename = "ObjectPath")]
+    pub object_path: String,
+
+    #[serde(rename = "ObjectType")]
+    pub object_type: String,
+
+    #[serde(rename = "Status")]
+    pub status: RefreshTaskStatus,
+
+    #[serde(rename = "Process")]
+    pub process: String,
+
+    #[serde(rename = "CreationTime")]
+    pub creation_time: String,
+
+    #[serde(rename = "Description", skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// Filters of the CDN domains to list with DescribeUserDomains
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct DescribeUserDomainsPayload {
+    /// Domains containing this name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain_name: Option<String>,
+    /// `online`, `offline`, `configuring`, `configure_failed`, `checking` or `check_failed`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain_status: Option<String>,
+    /// 1-based page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_number: Option<u32>,
+    /// 1 to 500, Aliyun's default 20 if omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_size: Option<u32>,
+}
+
+/// Response from DescribeUserDomains API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DescribeUserDomainsResponse {
+    #[serde(rename = "RequestId")]
+    pub request_id: String,
+
+    #[serde(rename = "PageNumber", default)]
+    pub page_number: u64,
+
+    #[serde(rename = "PageSize", default)]
+    pub page_size: u64,
+
+    /// Matching domains on Aliyun, even when fewer were returned
+    #[serde(rename = "TotalCount", default)]
+    pub total_count: u64,
+
+    #[serde(rename = "Domains", default)]
+    pub domains: DomainsContainer,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DomainsContainer {
+    #[serde(rename = "PageData", default)]
+    pub page_data: Vec<CdnDomain>,
+}
+
+/// One CDN domain listed by DescribeUserDomains
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CdnDomain {
+    #[serde(rename = "DomainName")]
+    pub domain_name: String,
+
+    /// CNAME the domain should point to
+    #[serde(rename = "Cname", default)]
+    pub cname: String,
+
+    /// `web`, `download` or `video`
+    #[serde(rename = "CdnType", default)]
+    pub cdn_type: String,
+
+    /// `online` once the domain serves traffic
+    #[serde(rename = "DomainStatus")]
+    pub domain_status: String,
+
+    /// `on` when HTTPS is enabled
+    #[serde(rename = "SslProtocol", default)]
+    pub ssl_protocol: String,
+
+    #[serde(rename = "GmtCreated", default)]
+    pub gmt_created: String,
+
+    #[serde(rename = "GmtModified", default)]
+    pub gmt_modified: String,
+
+    #[serde(
+        rename = "Description",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub description: Option<String>,
+}
+
+impl CdnDomain {
+    /// Whether the domain serves traffic, so its URLs can be refreshed
+    pub fn is_online(&self) -> bool {
+        self.domain_status == "online"
+    }
+}
+
+/// Configuration of one CDN domain, from DescribeCdnDomainDetail
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CdnDomainDetail {
+    pub request_id: String,
+    pub domain_name: String,
+    /// CNAME the domain should point to
+    pub cname: String,
+    /// `online` once the domain serves traffic
+    pub domain_status: String,
+    /// `on` when HTTPS is enabled
+    pub ssl_protocol: String,
+    /// `on` when an HTTPS certificate is deployed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_certificate_status: Option<String>,
+    /// `web`, `download` or `video`
+    pub cdn_type: String,
+    /// `domestic`, `overseas` or `global`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    /// Origins the domain pulls from
+    pub sources: Vec<CdnDomainSource>,
+    pub gmt_created: String,
+    pub gmt_modified: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// An origin of a CDN domain
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CdnDomainSource {
+    /// Address of the origin, e.g. an OSS bucket domain
+    pub content: String,
+    /// `ipaddr`, `domain` or `oss`
+    #[serde(rename = "type")]
+    pub source_type: String,
+    pub port: u16,
+    /// Lower is preferred; `20` is the primary origin, `30` a backup
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<String>,
+    /// `online` while the origin is used
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<String>,
+}
+
+/// Domain and window of a CDN statistics time series
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct DomainStatsQuery {
+    /// Accelerated domain, e.g. `static.prts.wiki`
+    pub domain_name: String,
+    /// RFC 3339, e.g. `2026-10-16T08:00:00+08:00`
+    pub start_time: String,
+    /// RFC 3339, after `start_time` and at most 90 days later
+    pub end_time: String,
+    /// Seconds per data point: `300`, `3600` or `86400`; Aliyun picks one by the window if omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval: Option<u32>,
+}
+
+/// Bandwidth of a CDN domain over time, from DescribeDomainBpsData
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "openapi", schema(example = json!({
+    "request_id": "B955107D-E658-4E77-B913-E0AC3D31693E",
+    "domain_name": "static.prts.wiki",
+    "start_time": "2026-10-16T00:00:00Z",
+    "end_time": "2026-10-16T00:10:00Z",
+    "interval": 300,
+    "data": [
+        {"time_stamp": "2026-10-16T00:00:00Z", "bps": 11288111.0, "https_bps": 10221048.0},
+        {"time_stamp": "2026-10-16T00:05:00Z", "bps": 10892000.0, "https_bps": 9873224.0}
+    ]
+})))]
+pub struct DomainBpsData {
+    pub request_id: String,
+    pub domain_name: String,
+    pub start_time: String,
+    pub end_time: String,
+    /// Seconds per data point
+    pub interval: u64,
+    pub data: Vec<BpsDataPoint>,
+}
+
+/// Bandwidth of one interval, in bits per second
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct BpsDataPoint {
+    /// Start of the interval
+    pub time_stamp: String,
+    pub bps: f64,
+    /// Share of `bps` served over HTTPS
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub https_bps: Option<f64>,
+}
+
+/// Byte hit rate of a CDN domain over time, from DescribeDomainHitRateData
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "openapi", schema(example = json!({
+    "request_id": "7F7B7A53-2B4B-4D08-9D37-E0D9A4B3F1C5",
+    "domain_name": "static.prts.wiki",
+    "start_time": "2026-10-16T00:00:00Z",
+    "end_time": "2026-10-16T00:10:00Z",
+    "interval": 300,
+    "data": [
+        {"time_stamp": "2026-10-16T00:00:00Z", "hit_rate": 98.21, "https_hit_rate": 98.37},
+        {"time_stamp": "2026-10-16T00:05:00Z", "hit_rate": 71.4, "https_hit_rate": 70.95}
+    ]
+})))]
+pub struct DomainHitRateData {
+    pub request_id: String,
+    pub domain_name: String,
+    pub start_time: String,
+    pub end_time: String,
+    /// Seconds per data point
+    pub interval: u64,
+    pub data: Vec<HitRateDataPoint>,
+}
+
+/// Byte hit rate of one interval, in percent
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct HitRateDataPoint {
+    /// Start of the interval
+    pub time_stamp: String,
+    pub hit_rate: f64,
+    /// Hit rate of the requests served over HTTPS
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub https_hit_rate: Option<f64>,
+}
+
+/// Window of CDN access log files to list with DescribeCdnDomainLogs
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DescribeCdnDomainLogsPayload {
+    /// Accelerated domain, e.g. `static.prts.wiki`
+    pub domain_name: String,
+    /// ISO 8601 UTC, e.g. `2026-10-16T00:00:00Z`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_time: Option<String>,
+    /// 1-based page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_number: Option<u32>,
+    /// 1 to 1000, Aliyun's default 300 if omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_size: Option<u32>,
+}
+
+/// One page of a domain's CDN access log files
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CdnDomainLogsResponse {
+    pub request_id: String,
+    pub domain_name: String,
+    pub page_number: u64,
+    pub page_size: u64,
+    /// Log files in the window, even when fewer were returned
+    pub total_count: u64,
+    pub logs: Vec<CdnLogFile>,
+}
+
+/// A CDN access log file, covering one hour by default
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CdnLogFile {
+    /// e.g. `static.prts.wiki_2026_10_16_0000_0100.gz`
+    pub name: String,
+    /// Size of the gzip file in bytes
+    pub size: u64,
+    pub start_time: String,
+    pub end_time: String,
+    /// Signed OSS URL, valid for a limited time
+    pub download_url: String,
+}
+
+/// A log file to stream through Janus, looked up in the given window
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DownloadCdnDomainLogPayload {
+    pub domain_name: String,
+    /// `name` of a file listed by `POST /api/aliyun/domainLogs`
+    pub log_name: String,
+    /// Window to look the file up in, as listed; Aliyun's default if omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_time: Option<String>,
+}
+
+/// Progress of a queued CDN refresh
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum RefreshJobStatus {
+    /// Waiting for the worker, or for its next retry
+    Pending,
+    Running,
+    Succeeded,
+    /// Every attempt failed, see `error`
+    Failed,
+}
+
+/// A queued CDN refresh and its outcome
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RefreshJob {
+    pub id: u64,
+    pub status: RefreshJobStatus,
+    pub object_path: String,
+    pub object_type: String,
+    /// Calls made to Aliyun so far
+    pub attempts: u32,
+    /// Aliyun refresh task id once succeeded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task_id: Option<String>,
+    /// Error of the last failed attempt
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// RFC 3339
+    pub created_at: String,
+    /// RFC 3339
+    pub updated_at: String,
+}
+
+/// A refresh task Aliyun accepted, with its last known status there
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RefreshLogEntry {
+    pub task_id: String,
+    /// Product the task was created on
+    #[serde(default)]
+    pub product: CdnProduct,
+    /// What requested the refresh: `manual` or `oss_event`
+    pub source: String,
+    pub object_paths: Vec<String>,
+    pub object_type: String,
+    /// Absent until first checked
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<RefreshTaskStatus>,
+    /// Aliyun's progress, e.g. `100%`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub process: Option<String>,
+    /// Aliyun's reason for a failure
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// RFC 3339
+    pub created_at: String,
+    /// RFC 3339
+    pub updated_at: String,
+}
+
+/// Whether a dead-lettered event still waits for a replay
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum DeadLetterStatus {
+    Pending,
+    /// A replay processed it
+    Resolved,
+}
+
+/// An OSS event that could not be processed, kept so it can be replayed
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DeadLetter {
+    pub id: u64,
+    pub status: DeadLetterStatus,
+    /// The event as delivered; a body that wasn't JSON is kept as a string
+    pub event: serde_json::Value,
+    /// Why it could not be processed, updated by failed replays
+    pub error: String,
+    /// Tries before it was parked: 1 for an event rejected on delivery, or the refresh
+    /// attempts of a queued job
+    #[serde(default)]
+    pub attempts: u32,
+    /// Replays attempted so far
+    pub replays: u32,
+    /// Outcome of the replay that resolved it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<String>,
+    /// RFC 3339
+    pub created_at: String,
+    /// RFC 3339
+    pub updated_at: String,
+}
+
+/// One page of dead-lettered events, newest first
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DeadLetterPage {
+    /// Dead letters matching the filter across all pages
+    pub total: usize,
+    pub page_number: usize,
+    pub page_size: usize,
+    pub items: Vec<DeadLetter>,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// `value` parses as `T` and serializes back unchanged
+    fn round_trip<T: Serialize + serde::de::DeserializeOwned>(value: serde_json::Value) -> T {
+        let parsed: T = serde_json::from_value(value.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), value);
+        parsed
+    }
+
+    #[test]
+    fn test_refresh_payload_and_result_wire_format() {
+        let payload: RefreshObjectCachesPayload = round_trip(json!({
+            "object_path": "https://static.prts.wiki/a.png",
+            "object_type": "Directory",
+            "force": true,
+            "dry_run": true
+        }));
+        assert_eq!(payload.object_type.as_deref(), Some("Directory"));
+
+        // Everything but the paths may be left out
+        let minimal: RefreshObjectCachesPayload =
+            serde_json::from_str(r#"{"object_path":"https://static.prts.wiki/a.png"}"#).unwrap();
+        assert!(!minimal.dry_run);
+
+        round_trip::<RefreshObjectCachesResult>(json!({
+            "task_id": "17772470467",
+            "task_ids": ["17772470467", "17772470468"],
+            "object_type": "File",
+            "object_paths": ["https://static.prts.wiki/a.png", "https://static.prts.wiki/b.png"],
+            "failed_chunks": [{
+                "chunk": 2,
+                "object_paths": ["https://static.prts.wiki/c.png"],
+                "error": "Aliyun API error (status 400)"
+            }]
+        }));
+    }
+
+    #[test]
+    fn test_oss_event_payload_wire_format() {
+        let single = json!({
+            "id": "event-1",
+            "source": "acs.oss",
+            "type": "oss:ObjectCreated:PutObject",
+            "data": {
+                "region": "cn-shanghai",
+                "eventName": "ObjectCreated:PutObject",
+                "oss": {
+                    "bucket": { "name": "prts-static" },
+                    "object": { "key": "a.png", "eTag": "abc", "deltaSize": 12 }
+                }
+            }
+        });
+        let OssEventsPayload::Single(event) = round_trip(single.clone()) else {
+            panic!("a lone event is not a batch");
+        };
+        assert_eq!(event.data.oss.object.etag.as_deref(), Some("abc"));
+
+        let OssEventsPayload::Batch(events) = round_trip(json!([single])) else {
+            panic!("an array is a batch");
+        };
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    fn test_oss_event_responses_wire_format() {
+        round_trip::<OssEventsResponse>(json!({
+            "message": "CDN refresh queued for a.png in bucket prts-static",
+            "job_id": 1
+        }));
+        let OssEventsResponse::Batch(batch) = round_trip(json!({
+            "message": "Processed 2 events",
+            "results": [
+                {
+                    "id": "event-1",
+                    "status": "refreshed",
+                    "message": "ok",
+                    "task_id": "1",
+                    "task_ids": ["1", "2"]
+                },
+                { "status": "skipped", "message": "filtered" }
+            ]
+        })) else {
+            panic!("results make a batch response");
+        };
+        assert_eq!(batch.results[1].status, OssEventStatus::Skipped);
+    }
+
+    #[test]
+    fn test_refresh_job_wire_format() {
+        let job: RefreshJob = round_trip(json!({
+            "id": 1,
+            "status": "failed",
+            "object_path": "https://static.prts.wiki/a.png",
+            "object_type": "File",
+            "attempts": 3,
+            "error": "Aliyun API error (status 503)",
+            "created_at": "2026-01-01T00:00:00+00:00",
+            "updated_at": "2026-01-01T00:00:03+00:00"
+        }));
+        assert_eq!(job.status, RefreshJobStatus::Failed);
+    }
+
+    #[test]
+    fn test_describe_refresh_tasks_filters_wire_format() {
+        let payload: DescribeRefreshTasksPayload = serde_json::from_value(json!({
+            "object_type": "Directory",
+            "status": "Refreshing"
+        }))
+        .unwrap();
+        assert_eq!(payload.object_type, Some(RefreshTaskObjectType::Directory));
+        assert_eq!(payload.object_type.unwrap().as_str(), "directory");
+        assert_eq!(payload.status, Some(RefreshTaskStatus::Refreshing));
+        assert!(!RefreshTaskStatus::Refreshing.is_terminal());
+        assert!(
+            serde_json::from_value::<DescribeRefreshTasksPayload>(json!({"status": "Blocked"}))
+                .is_err()
+        );
+
+        // Statuses Aliyun adds later don't break parsing its responses
+        let status: RefreshTaskStatus = serde_json::from_value(json!("Blocked")).unwrap();
+        assert_eq!(status, RefreshTaskStatus::Unknown);
+        assert!(!status.is_terminal());
+
+        round_trip::<DescribeRefreshTasksPayload>(json!({
+            "object_type": "file",
+            "status": "Failed"
+        }));
+        assert!(
+            serde_json::from_value::<DescribeRefreshTasksPayload>(json!({"object_type": "blob"}))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_refresh_quota_response_parses_string_counts() {
+        let body = r#"{"UrlRemain":"1996","RequestId":"42E0554B-80F4-4921-AED6-ACFB22CAAAD0","DirRemain":"100","PreloadRemain":"497","BlockQuota":"100","DirQuota":"100","UrlQuota":"2000","BlockRemain":"100","PreloadQuota":"500","RegexQuota":"20","RegexRemain":"20","IgnoreParamsQuota":"10","IgnoreParamsRemain":"10","PreloadEdgeQuota":"20","PreloadEdgeRemain":"20"}"#;
+        let quota: DescribeRefreshQuotaResponse = serde_json::from_str(body).unwrap();
+
+        assert_eq!(quota.request_id, "42E0554B-80F4-4921-AED6-ACFB22CAAAD0");
+        assert_eq!((quota.url_quota, quota.url_remain), (2000, 1996));
+        assert_eq!((quota.dir_quota, quota.dir_remain), (100, 100));
+        assert_eq!((quota.preload_quota, quota.preload_remain), (500, 497));
+        assert_eq!(
+            serde_json::to_value(&quota).unwrap()["UrlRemain"],
+            serde_json::json!(1996)
+        );
+        assert!(
+            serde_json::from_str::<DescribeRefreshQuotaResponse>(
+                &body.replace(r#""UrlQuota":"2000""#, r#""UrlQuota":"many""#)
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_raw_call_defaults() {
+        let call: RawAliyunCallPayload =
+            serde_json::from_str(r#"{"action":"DescribeDomainsBySource"}"#).unwrap();
+        assert_eq!(call.version, "2018-05-10");
+        assert_eq!(call.method, "POST");
+        assert!(call.params.is_empty());
+    }
+}
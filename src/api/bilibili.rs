@@ -0,0 +1,138 @@
+//! Models of the `/api/bilibili` routes
+
+use serde::{Deserialize, Serialize};
+
+/// Response for createDynamic endpoint
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "openapi", schema(example = json!({
+    "code": 0,
+    "data": {"doc_id": 0, "dynamic_id": 1_021_451_253_404_745_734_u64, "create_result": 0, "errmsg": ""}
+})))]
+pub struct DynamicResponse {
+    pub code: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub msg: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exception: Option<serde_json::Value>,
+}
+
+/// Request body for deleteDynamic endpoint
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DeleteDynamicPayload {
+    /// ID of the dynamic to remove, as a string since it exceeds JSON's safe integer range
+    #[cfg_attr(feature = "openapi", schema(example = "1021451253404745734"))]
+    pub dynamic_id: String,
+    /// Configured Bilibili account that posted it, `bilibili.default_account` if omitted
+    #[serde(default)]
+    pub account: Option<String>,
+}
+
+/// Progress of a dynamic scheduled with `publish_at`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduledDynamicStatus {
+    /// Waiting for `publish_at`, or for read-only mode to be released
+    Pending,
+    Publishing,
+    Published,
+    /// Uploading or posting failed, see `error`; never retried
+    Failed,
+    Cancelled,
+}
+
+/// A dynamic held back until its `publish_at`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ScheduledDynamic {
+    pub id: u64,
+    pub status: ScheduledDynamicStatus,
+    /// Configured Bilibili account it is posted as
+    pub account: String,
+    /// RFC 3339
+    pub publish_at: String,
+    /// Images uploaded to Bilibili at publish time
+    pub image_count: usize,
+    /// ID of the posted dynamic, as a string since it exceeds JSON's safe integer range
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dynamic_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// RFC 3339
+    pub created_at: String,
+    /// RFC 3339
+    pub updated_at: String,
+}
+
+/// One segment of a dynamic's text (`dyn_req.content.contents[]`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ContentItem {
+    pub raw_text: String,
+    /// Segment type, `1` for plain text
+    #[serde(rename = "type")]
+    pub kind: i32,
+    /// Referenced object (e.g. the user ID of a mention); sent as `""` when absent
+    #[serde(default, serialize_with = "serialize_biz_id")]
+    pub biz_id: Option<String>,
+}
+
+/// `type` of a plain text content item
+pub const TEXT_CONTENT_TYPE: i32 = 1;
+
+impl ContentItem {
+    /// A plain text segment
+    pub fn text(raw_text: impl Into<String>) -> Self {
+        Self {
+            raw_text: raw_text.into(),
+            kind: TEXT_CONTENT_TYPE,
+            biz_id: None,
+        }
+    }
+}
+
+fn serialize_biz_id<S: serde::Serializer>(
+    biz_id: &Option<String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(biz_id.as_deref().unwrap_or(""))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_content_item_wire_format() {
+        let items: Vec<ContentItem> = serde_json::from_str(
+            r#"[{"type":1,"raw_text":"hi","biz_id":""},{"type":1,"raw_text":"x"}]"#,
+        )
+        .unwrap();
+        assert_eq!(items[1], ContentItem::text("x"));
+        // A missing biz_id is still sent as "", as Bilibili expects
+        assert_eq!(
+            serde_json::to_value(&items[1]).unwrap(),
+            json!({"type": 1, "raw_text": "x", "biz_id": ""})
+        );
+    }
+
+    #[test]
+    fn test_dynamic_payloads_wire_format() {
+        let response = json!({
+            "code": 0,
+            "data": {"doc_id": 0, "dynamic_id": 1021451253404745734_u64, "create_result": 0, "errmsg": ""}
+        });
+        let parsed: DynamicResponse = serde_json::from_value(response.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), response);
+
+        let delete: DeleteDynamicPayload =
+            serde_json::from_str(r#"{"dynamic_id":"1021451253404745734"}"#).unwrap();
+        assert_eq!(delete.account, None);
+    }
+}
@@ -0,0 +1,53 @@
+//! Request and response models of the HTTP API
+//!
+//! Only needs serde, so services calling Janus can depend on the crate with
+//! `default-features = false`. The `openapi` feature adds the `ToSchema` derives the server
+//! documents them with.
+
+pub mod admin;
+pub mod aliyun;
+pub mod bilibili;
+
+use serde::{Deserialize, Serialize};
+
+pub use admin::{ReadOnlyStatus, SetReadOnlyPayload};
+pub use aliyun::{
+    BpsDataPoint, CdnDomain, CdnDomainDetail, CdnDomainLogsResponse, CdnDomainSource, CdnLogFile,
+    CdnProduct, DeadLetter, DeadLetterPage, DeadLetterStatus, DescribeCdnDomainLogsPayload,
+    DescribeRefreshQuotaResponse, DescribeRefreshTaskByIdResponse, DescribeRefreshTasksPayload,
+    DescribeRefreshTasksResponse, DescribeUserDomainsPayload, DescribeUserDomainsResponse,
+    DomainBpsData, DomainHitRateData, DomainStatsQuery, DomainsContainer,
+    DownloadCdnDomainLogPayload, HitRateDataPoint, InvokeAliyunPayload, InvokeAliyunResult,
+    OssBatchEventResponse, OssBucket, OssData, OssEventData, OssEventPayload, OssEventResponse,
+    OssEventResult, OssEventStatus, OssEventsPayload, OssEventsResponse, OssObject,
+    PushObjectCachesPayload, PushObjectCachesResult, RawAliyunCallPayload,
+    RefreshAndPreloadPayload, RefreshAndPreloadResult, RefreshAndWaitPayload, RefreshAndWaitResult,
+    RefreshDirectoryPayload, RefreshJob, RefreshJobStatus, RefreshLogEntry,
+    RefreshObjectCachesPayload, RefreshObjectCachesResult, RefreshTask, RefreshTaskObjectType,
+    RefreshTaskStatus,
+};
+pub use bilibili::{
+    ContentItem, DeleteDynamicPayload, DynamicResponse, ScheduledDynamic, ScheduledDynamicStatus,
+    TEXT_CONTENT_TYPE,
+};
+
+/// JSON body of every error response
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "openapi", schema(example = json!({"code": 1, "msg": "Bad request: dynamic_id must be a numeric string"})))]
+pub struct ErrorBody {
+    /// Always `1`
+    pub code: i32,
+    /// What went wrong, for client errors, timeouts and unavailable features
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub msg: Option<String>,
+    /// `READ_ONLY` while read-only mode is engaged
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Bilibili's response, or Aliyun's error, when it rejected the call
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exception: Option<serde_json::Value>,
+    /// `RequestId` of the call Aliyun rejected, to quote in support tickets
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aliyun_request_id: Option<String>,
+}
@@ -1,18 +1,31 @@
 use anyhow::Result;
 use clap::Parser;
-use std::path::Path;
+use std::{net::SocketAddr, path::Path, time::Duration};
 use tokio::net::TcpListener;
-use tracing::info;
+use tracing::{error, info, warn};
 
 use crate::{
+    aliyun::{run_credential_refresh, try_refresh_credentials},
     auth::generate_token,
+    bilibili::run_session_checks,
     config::AppSettings,
+    config_check::{Severity, check_settings, format_issues},
+    metrics::Metrics,
+    refresh_jobs::{self, run_refresh_worker},
+    refresh_log::run_refresh_reconciler,
+    reload::reload_on_sighup,
     routes::build_router,
+    scheduled_dynamics::run_dynamic_scheduler,
     shutdown::shutdown_signal,
     state::init_state,
+    tls::ServerTls,
     tracing::{init_sentry, init_tracing},
+    webhooks::run_webhook_dispatcher,
 };
 
+/// Longest shutdown waits for queued webhook deliveries
+const WEBHOOK_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 #[command(propagate_version = true)]
@@ -41,19 +54,151 @@ pub enum Commands {
         #[arg(short, long)]
         bucket_name: String,
     },
+    /// Compare recorded response examples against the examples in the OpenAPI spec
+    SyncExamples {
+        #[arg(short, long, default_value = "config.toml")]
+        config: String,
+        /// JSON file exported from `GET /api/admin/examples`
+        #[arg(short, long)]
+        input: String,
+    },
+    /// Load and check the configuration without starting anything
+    CheckConfig {
+        #[arg(short, long, default_value = "config.toml")]
+        config: String,
+    },
     /// Show version information
     Version,
 }
 
-async fn start(config: &AppSettings) -> Result<()> {
+async fn start(config: &AppSettings, config_path: &Path) -> Result<()> {
+    // Refuse to start on a config that would only fail once requests arrive
+    let issues = check_settings(config);
+    for issue in &issues {
+        match issue.severity {
+            Severity::Error => error!(path = %issue.path, "{}", issue.message),
+            Severity::Warning => warn!(path = %issue.path, "{}", issue.message),
+        }
+    }
+    let errors = issues
+        .iter()
+        .filter(|issue| issue.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        anyhow::bail!("{errors} configuration error(s), run `janus check-config` for details");
+    }
+
+    // A broken certificate must fail startup, not the first handshake
+    let tls = match &config.server.tls {
+        Some(tls) => Some(ServerTls::load(tls).await?),
+        None => None,
+    };
+
     // // Build router
     let listener = TcpListener::bind(config.server.full_url()).await?;
-    info!("Server is running on {}", config.server.full_url());
-    let state = init_state(config).await;
-    let router = build_router(state);
-    axum::serve(listener, router)
-        .with_graceful_shutdown(shutdown_signal())
-        .await?;
+    info!(
+        tls = tls.is_some(),
+        "Server is running on {}",
+        config.server.full_url()
+    );
+    if config.server.read_only {
+        warn!(
+            message = config.server.read_only_message.as_deref(),
+            "Starting in read-only mode: Bilibili posting and CDN purges are disabled"
+        );
+    }
+    let mut buckets: Vec<_> = config.aliyun.bucket_url_map.iter().collect();
+    buckets.sort_by_key(|(bucket, _)| bucket.as_str());
+    for (bucket, urls) in buckets {
+        info!(bucket, urls = ?urls.templates(), "Bucket mapped to CDN URLs");
+    }
+    let metrics = config.metrics.as_ref().map(Metrics::init);
+    let state = init_state(config, metrics).await;
+
+    // Cookie checks run in the background so a failing first check never blocks startup
+    let mut session_checks = Vec::new();
+    if config.bilibili.session_check_interval_secs > 0 {
+        let interval = Duration::from_secs(config.bilibili.session_check_interval_secs);
+        for (account, client) in &state.bilibili_clients {
+            session_checks.push(tokio::spawn(run_session_checks(
+                account.clone(),
+                client.clone(),
+                interval,
+            )));
+        }
+    }
+
+    // The first refresh is awaited so requests never go out with the bootstrap keys
+    let credential_refresh = if state.aliyun_credential_provider.rotates() {
+        let wait = try_refresh_credentials(
+            &state.aliyun_credential_provider,
+            &state.aliyun_http_client,
+            &state.aliyun_credentials,
+        )
+        .await;
+        Some(tokio::spawn(run_credential_refresh(
+            state.aliyun_credential_provider.clone(),
+            state.aliyun_http_client.clone(),
+            state.aliyun_credentials.clone(),
+            wait,
+        )))
+    } else {
+        None
+    };
+
+    let refresh_worker = tokio::spawn(run_refresh_worker(state.clone()));
+    let refresh_reconciler = tokio::spawn(run_refresh_reconciler(state.clone()));
+    let dynamic_scheduler = tokio::spawn(run_dynamic_scheduler(
+        state.clone(),
+        Duration::from_secs(config.bilibili.schedule.tick_secs.max(1)),
+    ));
+    let webhook_dispatcher = tokio::spawn(run_webhook_dispatcher(
+        state.webhooks.clone(),
+        state.http_client.clone(),
+    ));
+    let reload = tokio::spawn(reload_on_sighup(
+        config_path.to_path_buf(),
+        config.clone(),
+        state.clone(),
+        tls.clone(),
+    ));
+
+    let router = build_router(state.clone());
+    match tls {
+        Some(tls) => tls.serve(listener, router, shutdown_signal()).await?,
+        // Peer addresses let unauthenticated routes be rate limited per client IP
+        None => {
+            axum::serve(
+                listener,
+                router.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(shutdown_signal())
+            .await?
+        }
+    }
+
+    for session_check in session_checks {
+        session_check.abort();
+    }
+    if let Some(credential_refresh) = credential_refresh {
+        credential_refresh.abort();
+    }
+    reload.abort();
+    // Let the worker finish the jobs it claimed; the ones still pending would otherwise be
+    // lost with the process
+    state.refresh_jobs.stop();
+    let _ = refresh_worker.await;
+    refresh_jobs::flush(&state).await;
+    refresh_reconciler.abort();
+    dynamic_scheduler.abort();
+    // Deliver the notifications of the final flush, without hanging on a dead endpoint
+    state.webhooks.close();
+    if tokio::time::timeout(WEBHOOK_DRAIN_TIMEOUT, webhook_dispatcher)
+        .await
+        .is_err()
+    {
+        warn!("Webhook deliveries still queued at shutdown were dropped");
+    }
 
     info!("Web server has gracefully shutdown");
     Ok(())
@@ -63,15 +208,19 @@ pub async fn run() -> Result<()> {
     let cli = Commands::parse();
     match cli {
         Commands::Server { config } => {
-            let config = AppSettings::new(Path::new(&config))?;
+            let path = Path::new(&config);
+            let config = AppSettings::new(path)?;
 
             init_tracing(&config.logger);
             let _sentry_guard = &config.sentry.as_ref().map(init_sentry);
-            start(&config).await?;
+            start(&config, path).await?;
             Ok(())
         }
         Commands::GenerateJwt { config, subject } => {
             let config = AppSettings::new(Path::new(&config))?;
+            if config.server.read_only {
+                anyhow::bail!("Refusing to issue tokens while read-only mode is configured");
+            }
 
             let token = generate_token(subject.clone(), &config.jwt.private_key)?;
 
@@ -86,25 +235,39 @@ pub async fn run() -> Result<()> {
             bucket_name,
         } => {
             let config = AppSettings::new(Path::new(&config))?;
+            if !config.aliyun.is_configured() {
+                anyhow::bail!("Aliyun integration not configured, set [aliyun] credentials");
+            }
 
-            let url_template = config
+            let urls = config
                 .aliyun
                 .bucket_url_map
                 .get(&bucket_name)
                 .ok_or_else(|| anyhow::anyhow!("Unsupported bucket: {}", bucket_name))?;
 
-            use percent_encoding::percent_encode;
-            let encoded_object_key =
-                percent_encode(object_key.as_bytes(), crate::routes::URI).to_string();
-            let object_url = url_template.replace("{object_key}", &encoded_object_key);
+            let object_urls = crate::aliyun::object_urls(urls, &object_key);
 
-            let http_client = reqwest::Client::new();
-            let client = crate::aliyun::AliyunCdnClient::new(&config.aliyun, http_client);
+            let aliyun_proxy = config.http_client.aliyun_proxy();
+            let http_client =
+                crate::http_client::build_http_client(&config.http_client, &aliyun_proxy);
+            let mut client =
+                crate::aliyun::AliyunCdnClient::new(&config.aliyun, http_client.clone())
+                    .with_timeout(std::time::Duration::from_secs(
+                        config.http_client.aliyun_timeout_secs,
+                    ))
+                    .with_proxy_host(aliyun_proxy.proxy_host());
+            let provider = crate::aliyun::CredentialProvider::new(&config.aliyun);
+            if provider.rotates() {
+                let credentials = provider.fetch(&http_client).await?;
+                client = client
+                    .with_credentials(std::sync::Arc::new(std::sync::RwLock::new(credentials)));
+            }
 
             let request = crate::aliyun::RefreshObjectCachesRequest {
-                object_path: object_url.clone(),
+                object_path: object_urls.join("\n"),
                 object_type: Some("File".to_string()),
                 force: Some(false),
+                product: None,
             };
 
             let response = client.refresh_object_caches(&request).await?;
@@ -117,6 +280,50 @@ pub async fn run() -> Result<()> {
 
             Ok(())
         }
+        Commands::SyncExamples { config, input } => {
+            let config = AppSettings::new(Path::new(&config))?;
+            let recorded: Vec<crate::examples::RecordedExample> =
+                serde_json::from_str(&std::fs::read_to_string(&input)?)?;
+
+            let state = init_state(&config, None).await;
+            let spec = serde_json::to_value(crate::routes::openapi_spec(&state))?;
+            let drifts = crate::examples::compare_with_spec(&spec, &recorded);
+
+            if drifts.is_empty() {
+                println!(
+                    "All {} recorded examples match the documented examples",
+                    recorded.len()
+                );
+                return Ok(());
+            }
+            for drift in &drifts {
+                println!("{drift}");
+            }
+            anyhow::bail!("{} example drift(s) found", drifts.len())
+        }
+        Commands::CheckConfig { config } => {
+            let settings = match AppSettings::new(Path::new(&config)) {
+                Ok(settings) => settings,
+                Err(err) => {
+                    println!("error: {err}");
+                    anyhow::bail!("{config} could not be loaded");
+                }
+            };
+            let issues = check_settings(&settings);
+            if issues.is_empty() {
+                println!("{config} is valid");
+                return Ok(());
+            }
+            print!("{}", format_issues(&issues));
+            let errors = issues
+                .iter()
+                .filter(|issue| issue.severity == Severity::Error)
+                .count();
+            if errors > 0 {
+                anyhow::bail!("{errors} configuration error(s) found");
+            }
+            Ok(())
+        }
         Commands::Version => {
             println!(
                 "{} ({})",
@@ -72,7 +72,7 @@ fn extract_token_from_header(auth_header: &str) -> Option<&str> {
 /// JWT authentication middleware
 pub async fn jwt_auth_middleware(
     State(state): State<AppState>,
-    request: Request,
+    mut request: Request,
     next: Next,
 ) -> AppResult<Response> {
     // Extract Authorization header
@@ -93,10 +93,12 @@ pub async fn jwt_auth_middleware(
     })?;
 
     // Verify token
-    verify_token(token, &state.jwt_config.public_key).map_err(|err| {
+    let claims = verify_token(token, &state.jwt_config.load().public_key).map_err(|err| {
         AppError::Unauthorized(anyhow::anyhow!("JWT verification failed: {}", err))
     })?;
 
-    // Token is valid, proceed with request
+    // Token is valid, proceed with request; later layers key on the subject
+    tracing::Span::current().record("subject", claims.sub.as_str());
+    request.extensions_mut().insert(claims);
     Ok(next.run(request).await)
 }
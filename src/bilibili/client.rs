@@ -0,0 +1,681 @@
+use anyhow::Context;
+use arc_swap::ArcSwap;
+use rand::Rng;
+use reqwest::multipart::{Form, Part};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tracing::info;
+
+use super::SessionStatusCell;
+pub use crate::api::bilibili::{ContentItem, TEXT_CONTENT_TYPE};
+use crate::config::BilibiliAccount;
+use crate::error::{AppError, AppResult};
+use crate::metrics::record_bilibili_upload;
+
+/// Bilibili upload response
+#[derive(Debug, Deserialize)]
+struct BilibiliUploadResponse {
+    code: i32,
+    data: Option<BilibiliUploadData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BilibiliUploadData {
+    image_url: String,
+    image_width: f64,
+    image_height: f64,
+}
+
+/// Bilibili create dynamic response
+#[derive(Debug, Deserialize, Serialize)]
+struct BilibiliCreateResponse {
+    code: i32,
+    data: Option<CreateResult>,
+}
+
+/// `data` of a successful create dynamic response
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateResult {
+    #[serde(default)]
+    pub doc_id: Option<u64>,
+    #[serde(default)]
+    pub dynamic_id: Option<u64>,
+    #[serde(default)]
+    pub create_result: Option<i32>,
+    #[serde(default)]
+    pub errmsg: Option<String>,
+}
+
+/// Bilibili nav response
+#[derive(Debug, Deserialize)]
+struct BilibiliNavResponse {
+    code: i32,
+    data: Option<NavInfo>,
+}
+
+/// Login state reported by `x/web-interface/nav`
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct NavInfo {
+    #[serde(rename = "isLogin", default)]
+    pub is_login: bool,
+    #[serde(default)]
+    pub mid: Option<u64>,
+    #[serde(default)]
+    pub uname: Option<String>,
+}
+
+/// An image stored on Bilibili's BFS, ready to be attached to a dynamic
+#[derive(Debug, Clone)]
+pub struct UploadedImage {
+    pub image_url: String,
+    pub image_width: f64,
+    pub image_height: f64,
+    /// Size of the uploaded file in KiB
+    pub size_kb: f64,
+}
+
+/// Picture info for dynamic request
+#[derive(Debug, Serialize)]
+pub struct PicInfo {
+    pub img_src: String,
+    pub img_width: f64,
+    pub img_height: f64,
+    pub img_size: f64,
+}
+
+impl From<UploadedImage> for PicInfo {
+    fn from(image: UploadedImage) -> Self {
+        Self {
+            img_src: image.image_url,
+            img_width: image.image_width,
+            img_height: image.image_height,
+            img_size: image.size_kb,
+        }
+    }
+}
+
+/// Topic (话题) attached to a dynamic
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Topic {
+    pub id: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// Optional parts of a create dynamic request
+#[derive(Debug, Clone, Default)]
+pub struct DynamicOptions {
+    pub topic: Option<Topic>,
+    /// Post as a forward of this dynamic instead of an original post
+    pub forward_dynamic_id: Option<String>,
+}
+
+/// Build the `dyn_req` body for Bilibili's create dynamic API
+fn build_dyn_req(
+    contents: Vec<ContentItem>,
+    pics: Option<Vec<PicInfo>>,
+    options: DynamicOptions,
+    upload_id: &str,
+) -> AppResult<serde_json::Value> {
+    let scene = match (&options.forward_dynamic_id, &pics) {
+        (Some(_), Some(_)) => {
+            return Err(AppError::BadRequest(anyhow::anyhow!(
+                "A forwarded dynamic cannot carry images"
+            )));
+        }
+        (Some(_), None) => 4,
+        (None, Some(_)) => 2,
+        (None, None) => 1,
+    };
+
+    let mut dyn_req_content = serde_json::json!({
+        "dyn_req": {
+            "content": {
+                "contents": contents
+            },
+            "scene": scene,
+            "attach_card": null,
+            "upload_id": upload_id,
+            "meta": {
+                "app_meta": {
+                    "from": "create.dynamic.web",
+                    "mobi_app": "web"
+                }
+            }
+        }
+    });
+
+    // Add pics field if provided
+    if let Some(pics) = pics {
+        dyn_req_content["dyn_req"]["pics"] =
+            serde_json::to_value(pics).context("Failed to serialize pics")?;
+    }
+    if let Some(topic) = options.topic {
+        dyn_req_content["dyn_req"]["topic"] =
+            serde_json::to_value(topic).context("Failed to serialize topic")?;
+    }
+    // Forwards reference the original dynamic next to `dyn_req`
+    if let Some(dynamic_id) = options.forward_dynamic_id {
+        dyn_req_content["web_repost_src"] = serde_json::json!({ "dyn_id_str": dynamic_id });
+    }
+
+    Ok(dyn_req_content)
+}
+
+/// Generate random nonce
+fn get_nonce() -> i32 {
+    rand::thread_rng().gen_range(1000..9999)
+}
+
+/// Get unix timestamp in seconds
+fn get_unix_seconds() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time should be after UNIX epoch")
+        .as_secs_f64()
+}
+
+/// Bilibili web API client authenticated with the configured cookies
+#[derive(Debug, Clone)]
+pub struct BilibiliClient {
+    client: reqwest::Client,
+    base_url: String,
+    /// Cookies, swapped in place when the config is reloaded
+    account: Arc<ArcSwap<BilibiliAccount>>,
+    session: SessionStatusCell,
+    /// Overrides the shared client's total timeout for image uploads
+    upload_timeout: Option<Duration>,
+    /// `host:port` of the proxy in use, named when it refuses the connection
+    proxy_host: Option<String>,
+}
+
+impl BilibiliClient {
+    /// Create a client for one account against `api_base_url`
+    pub fn new(api_base_url: &str, account: &BilibiliAccount, client: reqwest::Client) -> Self {
+        Self {
+            client,
+            base_url: api_base_url.trim_end_matches('/').to_string(),
+            account: Arc::new(ArcSwap::from_pointee(account.clone())),
+            session: SessionStatusCell::default(),
+            upload_timeout: None,
+            proxy_host: None,
+        }
+    }
+
+    /// Give image uploads `timeout` instead of the shared client's default
+    pub fn with_upload_timeout(mut self, timeout: Duration) -> Self {
+        self.upload_timeout = Some(timeout);
+        self
+    }
+
+    /// Name `proxy_host` in errors when the proxy refuses the connection
+    pub fn with_proxy_host(mut self, proxy_host: Option<String>) -> Self {
+        self.proxy_host = proxy_host;
+        self
+    }
+
+    fn request_error(&self, err: reqwest::Error, what: &str) -> AppError {
+        crate::http_client::request_error(err, what, self.proxy_host.as_deref())
+    }
+
+    /// Use new cookies for every later request, including through clones of this client
+    pub fn set_account(&self, account: BilibiliAccount) {
+        self.account.store(Arc::new(account));
+    }
+
+    /// Cookies the client currently authenticates with
+    pub fn account(&self) -> Arc<BilibiliAccount> {
+        self.account.load_full()
+    }
+
+    /// Latest result of the background cookie check for this account
+    pub fn session(&self) -> &SessionStatusCell {
+        &self.session
+    }
+
+    /// Browser-like headers carrying the SESSDATA cookie
+    fn headers(&self) -> reqwest::header::HeaderMap {
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert("Accept", "*/*".parse().unwrap());
+        headers.insert(
+            "User-Agent",
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/121.0.0.0 Safari/537.36"
+                .parse()
+                .unwrap(),
+        );
+        headers.insert(
+            "Sec-Ch-Ua",
+            "\"Not A(Brand\";v=\"99\", \"Google Chrome\";v=\"121\", \"Chromium\";v=\"121\""
+                .parse()
+                .unwrap(),
+        );
+        headers.insert("Sec-Ch-Ua-Mobile", "?0".parse().unwrap());
+        headers.insert("Sec-Ch-Ua-Platform", "\"Windows\"".parse().unwrap());
+        headers.insert("Sec-Fetch-Dest", "empty".parse().unwrap());
+        headers.insert("Sec-Fetch-Mode", "cors".parse().unwrap());
+        headers.insert("Sec-Fetch-Site", "same-site".parse().unwrap());
+        headers.insert(
+            "Cookie",
+            format!("SESSDATA={}; l=v", self.account.load().sessdata)
+                .parse()
+                .unwrap(),
+        );
+        headers
+    }
+
+    /// Upload a single image to Bilibili
+    pub async fn upload_image(
+        &self,
+        bytes: Vec<u8>,
+        file_name: String,
+        mime: String,
+    ) -> AppResult<UploadedImage> {
+        let started = Instant::now();
+        let result = self.upload_image_inner(bytes, file_name, mime).await;
+        record_bilibili_upload(result.is_ok(), started.elapsed());
+        result
+    }
+
+    async fn upload_image_inner(
+        &self,
+        bytes: Vec<u8>,
+        file_name: String,
+        mime: String,
+    ) -> AppResult<UploadedImage> {
+        let size_kb = bytes.len() as f64 / 1024.0;
+
+        let file_part = Part::bytes(bytes)
+            .file_name(file_name)
+            .mime_str(&mime)
+            .map_err(|e| {
+                AppError::InternalError(anyhow::Error::new(e).context("Failed to create file part"))
+            })?;
+
+        let form = Form::new()
+            .part("file_up", file_part)
+            .text("biz", "draw")
+            .text("category", "daily")
+            .text("csrf", self.account.load().bili_jct.clone());
+
+        let mut request = self
+            .client
+            .post(format!("{}/x/dynamic/feed/draw/upload_bfs", self.base_url))
+            .headers(self.headers())
+            .multipart(form);
+        if let Some(timeout) = self.upload_timeout {
+            request = request.timeout(timeout);
+        }
+        let resp = request
+            .send()
+            .await
+            .map_err(|err| self.request_error(err, "Upload request failed"))?;
+
+        let resp_text = resp.text().await.context("Failed to read response")?;
+
+        let upload_resp: BilibiliUploadResponse =
+            serde_json::from_str(&resp_text).context("Failed to parse upload response")?;
+
+        if upload_resp.code != 0 {
+            return Err(AppError::InternalError(anyhow::anyhow!(
+                "Bilibili file upload failed, response: {}",
+                resp_text
+            )));
+        }
+
+        let data = upload_resp.data.ok_or_else(|| {
+            AppError::InternalError(anyhow::anyhow!("Upload response missing data"))
+        })?;
+
+        Ok(UploadedImage {
+            image_url: data.image_url,
+            image_width: data.image_width,
+            image_height: data.image_height,
+            size_kb,
+        })
+    }
+
+    /// Create a dynamic: text-only (scene 1), with images (scene 2) or as a forward (scene 4)
+    ///
+    /// Bilibili sometimes returns `code=0` with `data=null`, which is still a success and
+    /// comes back as `None`.
+    pub async fn create_dynamic(
+        &self,
+        contents: Vec<ContentItem>,
+        pics: Option<Vec<PicInfo>>,
+        options: DynamicOptions,
+    ) -> AppResult<Option<CreateResult>> {
+        let upload_id = format!("{}_{}", get_unix_seconds(), get_nonce());
+        let dyn_req_content = build_dyn_req(contents, pics, options, &upload_id)?;
+
+        let mut headers = self.headers();
+        headers.insert("Content-Type", "application/json".parse().unwrap());
+
+        let url = format!(
+            "{}/x/dynamic/feed/create/dyn?platform=web&csrf={}",
+            self.base_url,
+            self.account.load().bili_jct
+        );
+
+        let resp = self
+            .client
+            .post(&url)
+            .headers(headers)
+            .body(dyn_req_content.to_string())
+            .send()
+            .await
+            .map_err(|err| self.request_error(err, "Create dynamic request failed"))?;
+
+        let body = resp.text().await.context("Read response failed")?;
+
+        info!(
+            response_body = %body,
+            "Create dynamic response received"
+        );
+
+        let r: BilibiliCreateResponse =
+            serde_json::from_str(&body).context("Parse create dynamic response failed")?;
+
+        if r.code != 0 {
+            return Err(AppError::InternalError(anyhow::anyhow!(
+                "Bilibili API returned code {}",
+                r.code
+            )));
+        }
+
+        Ok(r.data)
+    }
+
+    /// Remove a previously posted dynamic
+    ///
+    /// Returns Bilibili's `data` on success.
+    pub async fn remove_dynamic(&self, dynamic_id: &str) -> AppResult<serde_json::Value> {
+        let mut headers = self.headers();
+        headers.insert("Content-Type", "application/json".parse().unwrap());
+
+        let url = format!(
+            "{}/x/dynamic/feed/operate/remove?platform=web&csrf={}",
+            self.base_url,
+            self.account.load().bili_jct
+        );
+
+        let resp = self
+            .client
+            .post(&url)
+            .headers(headers)
+            .body(serde_json::json!({ "dyn_id_str": dynamic_id }).to_string())
+            .send()
+            .await
+            .map_err(|err| self.request_error(err, "Remove dynamic request failed"))?;
+
+        let body = resp.text().await.context("Read response failed")?;
+
+        info!(
+            dynamic_id,
+            response_body = %body,
+            "Remove dynamic response received"
+        );
+
+        let mut r: serde_json::Value =
+            serde_json::from_str(&body).context("Parse remove dynamic response failed")?;
+
+        match r.get("code").and_then(serde_json::Value::as_i64) {
+            Some(0) => Ok(r["data"].take()),
+            Some(_) => Err(AppError::BilibiliRejected(r)),
+            None => Err(AppError::InternalError(anyhow::anyhow!(
+                "Remove dynamic response missing code: {}",
+                body
+            ))),
+        }
+    }
+
+    /// Fetch the login state of the configured cookie from `x/web-interface/nav`
+    pub async fn nav(&self) -> AppResult<NavInfo> {
+        let resp = self
+            .client
+            .get(format!("{}/x/web-interface/nav", self.base_url))
+            .headers(self.headers())
+            .send()
+            .await
+            .map_err(|err| self.request_error(err, "Nav request failed"))?;
+
+        let body = resp.text().await.context("Read response failed")?;
+        let r: BilibiliNavResponse =
+            serde_json::from_str(&body).context("Parse nav response failed")?;
+
+        // `-101` means "not logged in" and still carries `isLogin: false`
+        match (r.code, r.data) {
+            (0 | -101, Some(data)) => Ok(data),
+            (-101, None) => Ok(NavInfo::default()),
+            (code, _) => Err(AppError::InternalError(anyhow::anyhow!(
+                "Bilibili nav returned code {}",
+                code
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{body_string_contains, header, method, path, query_param},
+    };
+
+    use super::*;
+    use crate::test_support::test_bilibili_client;
+
+    fn client_for(server: &MockServer) -> BilibiliClient {
+        test_bilibili_client(&format!("{}/", server.uri()))
+    }
+
+    #[test]
+    fn test_headers_carry_sessdata_cookie() {
+        let client = test_bilibili_client("https://api.bilibili.com");
+        let headers = client.headers();
+
+        assert_eq!(headers["Cookie"], "SESSDATA=test-sessdata; l=v");
+        assert_eq!(headers["Sec-Fetch-Site"], "same-site");
+        assert!(
+            headers["User-Agent"]
+                .to_str()
+                .unwrap()
+                .starts_with("Mozilla/5.0")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_upload_image_sends_csrf_form_field() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/x/dynamic/feed/draw/upload_bfs"))
+            .and(header("Cookie", "SESSDATA=test-sessdata; l=v"))
+            .and(body_string_contains("test-bili-jct"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"code":0,"data":{"image_url":"https://i0.hdslb.com/a.png","image_width":10,"image_height":20}}"#,
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let image = client_for(&server)
+            .upload_image(vec![0; 2048], "a.png".to_string(), "image/png".to_string())
+            .await
+            .unwrap();
+
+        assert_eq!(image.image_url, "https://i0.hdslb.com/a.png");
+        assert_eq!(image.size_kb, 2.0);
+    }
+
+    #[tokio::test]
+    async fn test_slow_upload_times_out_as_network_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/x/dynamic/feed/draw/upload_bfs"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"code":0,"data":null}"#)
+                    .set_delay(Duration::from_millis(500)),
+            )
+            .mount(&server)
+            .await;
+
+        let err = client_for(&server)
+            .with_upload_timeout(Duration::from_millis(50))
+            .upload_image(vec![0; 16], "a.png".to_string(), "image/png".to_string())
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, AppError::NetworkError(_)), "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn test_create_dynamic_sends_csrf_query_param() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/x/dynamic/feed/create/dyn"))
+            .and(query_param("platform", "web"))
+            .and(query_param("csrf", "test-bili-jct"))
+            .and(header("Cookie", "SESSDATA=test-sessdata; l=v"))
+            .and(header("Content-Type", "application/json"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"code":0,"data":{"dynamic_id":42}}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let result = client_for(&server)
+            .create_dynamic(
+                vec![ContentItem::text("hi")],
+                None,
+                DynamicOptions::default(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(result.dynamic_id, Some(42));
+        let request = &server.received_requests().await.unwrap()[0];
+        let sent: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(sent["dyn_req"]["scene"], 1);
+        assert!(sent["dyn_req"].get("pics").is_none());
+    }
+
+    fn expected_dyn_req(scene: u8) -> serde_json::Value {
+        serde_json::json!({
+            "dyn_req": {
+                "content": {"contents": [{"type": 1, "raw_text": "hi", "biz_id": ""}]},
+                "scene": scene,
+                "attach_card": null,
+                "upload_id": "1700000000_1234",
+                "meta": {"app_meta": {"from": "create.dynamic.web", "mobi_app": "web"}}
+            }
+        })
+    }
+
+    fn dyn_req(
+        pics: Option<Vec<PicInfo>>,
+        options: DynamicOptions,
+    ) -> AppResult<serde_json::Value> {
+        build_dyn_req(
+            vec![ContentItem::text("hi")],
+            pics,
+            options,
+            "1700000000_1234",
+        )
+    }
+
+    fn topic() -> Option<Topic> {
+        Some(Topic {
+            id: 1_234,
+            name: Some("明日方舟".to_string()),
+        })
+    }
+
+    #[test]
+    fn test_dyn_req_text_only() {
+        let sent = dyn_req(None, DynamicOptions::default()).unwrap();
+        assert_eq!(sent, expected_dyn_req(1));
+    }
+
+    #[test]
+    fn test_dyn_req_with_pics() {
+        let pics = vec![PicInfo {
+            img_src: "https://i0.hdslb.com/a.png".to_string(),
+            img_width: 10.0,
+            img_height: 20.0,
+            img_size: 1.5,
+        }];
+        let sent = dyn_req(Some(pics), DynamicOptions::default()).unwrap();
+
+        let mut expected = expected_dyn_req(2);
+        expected["dyn_req"]["pics"] = serde_json::json!([{
+            "img_src": "https://i0.hdslb.com/a.png",
+            "img_width": 10.0,
+            "img_height": 20.0,
+            "img_size": 1.5
+        }]);
+        assert_eq!(sent, expected);
+    }
+
+    #[test]
+    fn test_dyn_req_with_topic() {
+        let sent = dyn_req(
+            None,
+            DynamicOptions {
+                topic: topic(),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut expected = expected_dyn_req(1);
+        expected["dyn_req"]["topic"] = serde_json::json!({"id": 1234, "name": "明日方舟"});
+        assert_eq!(sent, expected);
+
+        let sent = dyn_req(
+            None,
+            DynamicOptions {
+                topic: Some(Topic {
+                    id: 1_234,
+                    name: None,
+                }),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut expected = expected_dyn_req(1);
+        expected["dyn_req"]["topic"] = serde_json::json!({"id": 1234});
+        assert_eq!(sent, expected);
+    }
+
+    #[test]
+    fn test_dyn_req_forward() {
+        let forward = || DynamicOptions {
+            forward_dynamic_id: Some("1021451253404745734".to_string()),
+            ..Default::default()
+        };
+
+        let sent = dyn_req(None, forward()).unwrap();
+        let mut expected = expected_dyn_req(4);
+        expected["web_repost_src"] = serde_json::json!({"dyn_id_str": "1021451253404745734"});
+        assert_eq!(sent, expected);
+
+        let sent = dyn_req(
+            None,
+            DynamicOptions {
+                topic: topic(),
+                ..forward()
+            },
+        )
+        .unwrap();
+        expected["dyn_req"]["topic"] = serde_json::json!({"id": 1234, "name": "明日方舟"});
+        assert_eq!(sent, expected);
+
+        let err = dyn_req(Some(Vec::new()), forward()).unwrap_err();
+        assert_eq!(err.status_code(), reqwest::StatusCode::BAD_REQUEST);
+    }
+}
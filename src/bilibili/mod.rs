@@ -0,0 +1,14 @@
+mod client;
+mod publish;
+mod session;
+mod validate;
+
+pub use client::{
+    BilibiliClient, ContentItem, CreateResult, DynamicOptions, NavInfo, PicInfo, TEXT_CONTENT_TYPE,
+    Topic, UploadedImage,
+};
+pub use publish::{DynamicDraft, UploadFile, publish_dynamic, upload_images};
+pub use session::{
+    BilibiliSessionStatus, SessionState, SessionStatusCell, check_session, run_session_checks,
+};
+pub use validate::{ImageInfo, inspect_image};
@@ -0,0 +1,159 @@
+use futures::{StreamExt, TryFutureExt, TryStreamExt, stream};
+use tracing::info;
+
+use super::{ContentItem, CreateResult, DynamicOptions, PicInfo};
+use crate::error::AppResult;
+use crate::state::AppState;
+use crate::webhooks::WebhookEvent;
+
+/// An image of a dynamic, read and checked but not yet uploaded
+pub struct UploadFile {
+    pub data: Vec<u8>,
+    pub file_name: String,
+    pub content_type: String,
+    /// Dimensions read from the image header
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Everything needed to post a dynamic
+pub struct DynamicDraft {
+    pub contents: Vec<ContentItem>,
+    pub options: DynamicOptions,
+    pub files: Vec<UploadFile>,
+}
+
+/// Upload the draft's images, post it as `account` (the default if `None`) and announce the
+/// outcome to the webhooks
+pub async fn publish_dynamic(
+    state: &AppState,
+    account: Option<&str>,
+    draft: DynamicDraft,
+) -> AppResult<Option<CreateResult>> {
+    let posted = async {
+        let client = state.bilibili_client(account)?;
+        // If files are present, upload them first (scene 2), otherwise post text only (scene 1)
+        let pics = if draft.files.is_empty() {
+            None
+        } else {
+            info!(file_count = draft.files.len(), "Uploading files");
+            let pics = upload_images(
+                draft.files,
+                state.bilibili_config.upload_concurrency,
+                |file| {
+                    let (width, height) = (file.width, file.height);
+                    client
+                        .upload_image(file.data, file.file_name, file.content_type)
+                        // Dimensions are known locally, so don't depend on Bilibili echoing them
+                        .map_ok(move |image| PicInfo {
+                            img_width: f64::from(width),
+                            img_height: f64::from(height),
+                            ..PicInfo::from(image)
+                        })
+                },
+            )
+            .await?;
+            Some(pics)
+        };
+        client
+            .create_dynamic(draft.contents, pics, draft.options)
+            .await
+    }
+    .await;
+    state.webhooks.notify(WebhookEvent::dynamic(
+        account.unwrap_or(&state.bilibili_default_account),
+        posted
+            .as_ref()
+            .map(|data| data.as_ref().and_then(|data| data.dynamic_id)),
+    ));
+    posted
+}
+
+/// Upload images with at most `concurrency` requests in flight
+///
+/// Results come back in the original file order regardless of completion order. The first
+/// failure drops (and thereby cancels) every upload still in flight.
+pub async fn upload_images<F, Fut>(
+    files: Vec<UploadFile>,
+    concurrency: usize,
+    upload: F,
+) -> AppResult<Vec<PicInfo>>
+where
+    F: Fn(UploadFile) -> Fut,
+    Fut: Future<Output = AppResult<PicInfo>>,
+{
+    let mut uploaded = stream::iter(files.into_iter().enumerate())
+        .map(|(index, file)| upload(file).map_ok(move |pic| (index, pic)))
+        .buffer_unordered(concurrency.max(1))
+        .try_collect::<Vec<_>>()
+        .await?;
+    uploaded.sort_by_key(|(index, _)| *index);
+
+    Ok(uploaded.into_iter().map(|(_, pic)| pic).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::error::AppError;
+
+    fn fake_file(latency_ms: usize) -> UploadFile {
+        UploadFile {
+            data: Vec::new(),
+            file_name: latency_ms.to_string(),
+            content_type: "image/png".to_string(),
+            width: 1,
+            height: 1,
+        }
+    }
+
+    /// Stand-in for Bilibili: each upload takes `file_name` milliseconds
+    async fn fake_upload(file: UploadFile) -> AppResult<PicInfo> {
+        let latency: u64 = file.file_name.parse().unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(latency)).await;
+        if latency == 13 {
+            return Err(AppError::InternalError(anyhow::anyhow!("upload failed")));
+        }
+        Ok(PicInfo {
+            img_src: file.file_name,
+            img_width: 1.0,
+            img_height: 1.0,
+            img_size: 0.0,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_uploads_keep_file_order() {
+        let latencies = [120, 20, 80, 10, 60, 100];
+        let files = latencies
+            .iter()
+            .map(|latency| fake_file(*latency))
+            .collect();
+
+        let started = Instant::now();
+        let pics = upload_images(files, 3, fake_upload).await.unwrap();
+        let elapsed = started.elapsed();
+
+        let order = pics
+            .iter()
+            .map(|pic| pic.img_src.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(order, ["120", "20", "80", "10", "60", "100"]);
+        // Sequential uploads would take 390ms; three lanes finish in roughly 200ms
+        assert!(elapsed.as_millis() >= 120, "{elapsed:?}");
+        assert!(elapsed.as_millis() < 330, "{elapsed:?}");
+    }
+
+    #[tokio::test]
+    async fn test_failed_upload_cancels_remaining() {
+        let files = vec![fake_file(500), fake_file(13), fake_file(500)];
+
+        let started = Instant::now();
+        let result = upload_images(files, 3, fake_upload).await;
+
+        assert!(result.is_err());
+        assert!(started.elapsed().as_millis() < 400);
+    }
+}
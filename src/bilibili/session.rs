@@ -0,0 +1,170 @@
+use std::{sync::Arc, time::Duration};
+
+use arc_swap::ArcSwap;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+use super::BilibiliClient;
+
+/// Outcome of the last cookie check
+#[derive(ToSchema, Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionState {
+    /// No check has completed yet
+    #[default]
+    Unknown,
+    Valid,
+    /// Bilibili reports the cookie as logged out; posting will fail
+    Invalid,
+    /// Bilibili could not be asked, the previous state may still hold
+    CheckFailed,
+}
+
+/// Login state of the configured SESSDATA cookie
+#[derive(ToSchema, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct BilibiliSessionStatus {
+    pub state: SessionState,
+    /// Account the cookie belongs to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mid: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uname: Option<String>,
+    /// When the last check finished (RFC3339)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checked_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Shared, lock-free view of the latest [`BilibiliSessionStatus`]
+pub type SessionStatusCell = Arc<ArcSwap<BilibiliSessionStatus>>;
+
+/// Ask Bilibili whether the cookie is still logged in and record the answer
+///
+/// Warns and reports to Sentry once when the session turns invalid.
+pub async fn check_session(account: &str, client: &BilibiliClient) {
+    let status = client.session();
+    let previous = status.load().state;
+    let checked_at = Some(Utc::now().to_rfc3339());
+
+    let next = match client.nav().await {
+        Ok(nav) if nav.is_login => BilibiliSessionStatus {
+            state: SessionState::Valid,
+            mid: nav.mid,
+            uname: nav.uname,
+            checked_at,
+            error: None,
+        },
+        Ok(_) => BilibiliSessionStatus {
+            state: SessionState::Invalid,
+            checked_at,
+            ..Default::default()
+        },
+        Err(err) => BilibiliSessionStatus {
+            state: SessionState::CheckFailed,
+            checked_at,
+            error: Some(err.to_string()),
+            ..Default::default()
+        },
+    };
+
+    match next.state {
+        SessionState::Invalid if previous != SessionState::Invalid => {
+            warn!(
+                account,
+                "Bilibili session is no longer valid, SESSDATA needs to be renewed"
+            );
+            sentry::capture_message(
+                &format!(
+                    "Bilibili session of account '{account}' is no longer valid, SESSDATA needs to be renewed"
+                ),
+                sentry::Level::Warning,
+            );
+        }
+        SessionState::CheckFailed => {
+            warn!(
+                account,
+                error = next.error.as_deref(),
+                "Bilibili session check failed"
+            );
+        }
+        SessionState::Valid if previous != SessionState::Valid => {
+            info!(account, mid = next.mid, "Bilibili session is valid");
+        }
+        _ => {}
+    }
+
+    status.store(Arc::new(next));
+}
+
+/// Check the session every `interval` until the task is aborted
+///
+/// The first check runs immediately; its failure only degrades the reported status.
+pub async fn run_session_checks(account: String, client: BilibiliClient, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        check_session(&account, &client).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{header, method, path},
+    };
+
+    use super::*;
+    use crate::test_support::test_bilibili_client;
+
+    async fn check_against(response: ResponseTemplate) -> BilibiliSessionStatus {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/x/web-interface/nav"))
+            .and(header("Cookie", "SESSDATA=test-sessdata; l=v"))
+            .respond_with(response)
+            .mount(&server)
+            .await;
+
+        let client = test_bilibili_client(&server.uri());
+        check_session("default", &client).await;
+        client.session().load().as_ref().clone()
+    }
+
+    #[tokio::test]
+    async fn test_logged_in_session_is_valid() {
+        let status = check_against(ResponseTemplate::new(200).set_body_string(
+            r#"{"code":0,"data":{"isLogin":true,"mid":161775300,"uname":"PRTS"}}"#,
+        ))
+        .await;
+
+        assert_eq!(status.state, SessionState::Valid);
+        assert_eq!(status.mid, Some(161_775_300));
+        assert_eq!(status.uname.as_deref(), Some("PRTS"));
+        assert!(status.checked_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_expired_session_is_invalid() {
+        let status =
+            check_against(ResponseTemplate::new(200).set_body_string(
+                r#"{"code":-101,"message":"账号未登录","data":{"isLogin":false}}"#,
+            ))
+            .await;
+
+        assert_eq!(status.state, SessionState::Invalid);
+        assert_eq!(status.mid, None);
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_bilibili_degrades_to_check_failed() {
+        let status = check_against(ResponseTemplate::new(502).set_body_string("bad gateway")).await;
+
+        assert_eq!(status.state, SessionState::CheckFailed);
+        assert!(status.error.unwrap().contains("Parse nav response failed"));
+    }
+}
@@ -0,0 +1,90 @@
+use std::io::Cursor;
+
+use anyhow::{Context, bail};
+use image::{ImageFormat, ImageReader};
+
+/// Format and size of an image accepted for upload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageInfo {
+    /// MIME type sniffed from the magic bytes
+    pub mime: &'static str,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Check that `data` is a JPEG, PNG, GIF or WebP image and read its dimensions
+///
+/// The format comes from the magic bytes rather than the declared content type, and only the
+/// header is decoded.
+pub fn inspect_image(data: &[u8]) -> anyhow::Result<ImageInfo> {
+    if data.is_empty() {
+        bail!("file is empty");
+    }
+
+    let format = image::guess_format(data).ok();
+    let mime = match format {
+        Some(ImageFormat::Jpeg) => "image/jpeg",
+        Some(ImageFormat::Png) => "image/png",
+        Some(ImageFormat::Gif) => "image/gif",
+        Some(ImageFormat::WebP) => "image/webp",
+        _ => bail!("not a JPEG, PNG, GIF or WebP image"),
+    };
+
+    let (width, height) = ImageReader::with_format(Cursor::new(data), format.unwrap())
+        .into_dimensions()
+        .context("image header could not be decoded")?;
+    if width == 0 || height == 0 {
+        bail!("image has no pixels ({width}x{height})");
+    }
+
+    Ok(ImageInfo {
+        mime,
+        width,
+        height,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(format: ImageFormat) -> Vec<u8> {
+        let mut data = Cursor::new(Vec::new());
+        image::RgbImage::new(3, 2)
+            .write_to(&mut data, format)
+            .unwrap();
+        data.into_inner()
+    }
+
+    #[test]
+    fn test_inspect_supported_formats() {
+        for (format, mime) in [
+            (ImageFormat::Jpeg, "image/jpeg"),
+            (ImageFormat::Png, "image/png"),
+            (ImageFormat::Gif, "image/gif"),
+            (ImageFormat::WebP, "image/webp"),
+        ] {
+            let info = inspect_image(&encode(format)).unwrap();
+            assert_eq!(
+                info,
+                ImageInfo {
+                    mime,
+                    width: 3,
+                    height: 2
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_inspect_rejects_other_files() {
+        let zip = b"PK\x03\x04\x14\x00\x00\x00\x08\x00";
+        assert_eq!(
+            inspect_image(zip).unwrap_err().to_string(),
+            "not a JPEG, PNG, GIF or WebP image"
+        );
+        assert_eq!(inspect_image(b"").unwrap_err().to_string(), "file is empty");
+        // Right magic bytes, truncated header
+        assert!(inspect_image(b"\x89PNG\r\n\x1a\n").is_err());
+    }
+}
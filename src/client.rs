@@ -0,0 +1,345 @@
+//! Typed HTTP client for services calling Janus, behind the `client` feature
+
+use reqwest::{
+    Method, RequestBuilder, Response,
+    multipart::{Form, Part},
+};
+use serde::de::DeserializeOwned;
+use thiserror::Error;
+
+use crate::api::{
+    ContentItem, DescribeRefreshTaskByIdResponse, DynamicResponse, ErrorBody,
+    RefreshAndWaitPayload, RefreshAndWaitResult, RefreshDirectoryPayload,
+    RefreshObjectCachesPayload, RefreshObjectCachesResult, RefreshTask,
+};
+
+#[derive(Error, Debug)]
+pub enum ClientError {
+    /// Janus could not be reached or its answer could not be read
+    #[error("Request to Janus failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    /// Janus answered with an error status
+    #[error("Janus answered {status}: {}", body.msg.as_deref().unwrap_or("no message"))]
+    Api { status: u16, body: ErrorBody },
+}
+
+/// An image attached to a dynamic
+#[derive(Debug, Clone)]
+pub struct DynamicImage {
+    pub file_name: String,
+    /// Sniffed by Janus when left empty
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+/// Fields of the createDynamic multipart form
+#[derive(Debug, Clone, Default)]
+pub struct CreateDynamic {
+    /// JSON array of `ContentItem`, or plain text posted as a single text item
+    pub msg: String,
+    pub images: Vec<DynamicImage>,
+    pub topic_id: Option<String>,
+    /// Requires `topic_id`
+    pub topic_name: Option<String>,
+    pub forward_dynamic_id: Option<String>,
+    /// Configured Bilibili account to post as, the server's default if omitted
+    pub account: Option<String>,
+}
+
+impl CreateDynamic {
+    /// A dynamic made of `contents`
+    pub fn new(contents: &[ContentItem]) -> Self {
+        Self {
+            msg: serde_json::to_string(contents).expect("content items serialize"),
+            ..Self::default()
+        }
+    }
+
+    /// A plain text dynamic
+    pub fn text(text: impl Into<String>) -> Self {
+        Self::new(&[ContentItem::text(text)])
+    }
+
+    fn into_form(self) -> Result<Form, ClientError> {
+        let mut form = Form::new().text("msg", self.msg);
+        for (name, value) in [
+            ("topic_id", self.topic_id),
+            ("topic_name", self.topic_name),
+            ("forward_dynamic_id", self.forward_dynamic_id),
+            ("account", self.account),
+        ] {
+            if let Some(value) = value {
+                form = form.text(name, value);
+            }
+        }
+        for image in self.images {
+            let mut part = Part::bytes(image.data).file_name(image.file_name);
+            if !image.content_type.is_empty() {
+                part = part.mime_str(&image.content_type)?;
+            }
+            form = form.part("files", part);
+        }
+        Ok(form)
+    }
+}
+
+/// Client for the JWT-protected Janus routes
+#[derive(Debug, Clone)]
+pub struct JanusClient {
+    http: reqwest::Client,
+    /// Server root without the `/api` prefix, e.g. `https://janus.prts.wiki`
+    base_url: String,
+    token: String,
+}
+
+impl JanusClient {
+    pub fn new(base_url: impl Into<String>, token: impl Into<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            token: token.into(),
+        }
+    }
+
+    /// Send requests through `http`, e.g. one with custom timeouts
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.http
+            .request(method, format!("{}/api{path}", self.base_url))
+            .bearer_auth(&self.token)
+    }
+
+    /// `POST /api/aliyun/refreshObjectCaches`
+    pub async fn refresh_object_caches(
+        &self,
+        payload: &RefreshObjectCachesPayload,
+    ) -> Result<RefreshObjectCachesResult, ClientError> {
+        let response = self
+            .request(Method::POST, "/aliyun/refreshObjectCaches")
+            .json(payload)
+            .send()
+            .await?;
+        decode(response).await
+    }
+
+    /// `POST /api/aliyun/refreshObjectCaches:wait`
+    ///
+    /// A wait that ran out of time is still `Ok`, with `timed_out` set.
+    pub async fn refresh_object_caches_and_wait(
+        &self,
+        payload: &RefreshAndWaitPayload,
+    ) -> Result<RefreshAndWaitResult, ClientError> {
+        let response = self
+            .request(Method::POST, "/aliyun/refreshObjectCaches:wait")
+            .json(payload)
+            .send()
+            .await?;
+        decode(response).await
+    }
+
+    /// `POST /api/aliyun/refreshDirectory`
+    pub async fn refresh_directory(
+        &self,
+        payload: &RefreshDirectoryPayload,
+    ) -> Result<RefreshObjectCachesResult, ClientError> {
+        let response = self
+            .request(Method::POST, "/aliyun/refreshDirectory")
+            .json(payload)
+            .send()
+            .await?;
+        decode(response).await
+    }
+
+    /// `GET /api/aliyun/refreshTask/{task_id}`, with up to 10 ids
+    pub async fn describe_refresh_tasks(
+        &self,
+        task_ids: &[&str],
+    ) -> Result<DescribeRefreshTaskByIdResponse, ClientError> {
+        let path = format!("/aliyun/refreshTask/{}", task_ids.join(","));
+        let response = self.request(Method::GET, &path).send().await?;
+        decode(response).await
+    }
+
+    /// `GET /api/aliyun/refreshTasks/{task_id}`
+    pub async fn get_refresh_task(&self, task_id: &str) -> Result<RefreshTask, ClientError> {
+        let path = format!("/aliyun/refreshTasks/{task_id}");
+        let response = self.request(Method::GET, &path).send().await?;
+        decode(response).await
+    }
+
+    /// `POST /api/bilibili/createDynamic`
+    pub async fn create_dynamic(
+        &self,
+        dynamic: CreateDynamic,
+    ) -> Result<DynamicResponse, ClientError> {
+        let response = self
+            .request(Method::POST, "/bilibili/createDynamic")
+            .multipart(dynamic.into_form()?)
+            .send()
+            .await?;
+        decode(response).await
+    }
+}
+
+/// Parse a success body as `T`, or an error status as [`ErrorBody`]
+async fn decode<T: DeserializeOwned>(response: Response) -> Result<T, ClientError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response.json().await?);
+    }
+    let text = response.text().await?;
+    // Errors not produced by Janus itself (e.g. a proxy's) keep their text as the message
+    let body = serde_json::from_str(&text).unwrap_or_else(|_| ErrorBody {
+        code: 1,
+        msg: Some(text).filter(|text| !text.is_empty()),
+        error: None,
+        exception: None,
+        aliyun_request_id: None,
+    });
+    Err(ClientError::Api {
+        status: status.as_u16(),
+        body,
+    })
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use std::net::SocketAddr;
+
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{body_string_contains, header, method, path},
+    };
+
+    use super::*;
+    use crate::{
+        config::AppSettings,
+        routes::build_router,
+        test_support::{state_from, test_settings, test_token},
+    };
+
+    /// Serve the real router on a local port, returning its base URL
+    async fn serve(settings: &AppSettings) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let router = build_router(state_from(settings));
+        tokio::spawn(async move {
+            axum::serve(
+                listener,
+                router.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await
+            .unwrap();
+        });
+        format!("http://{addr}")
+    }
+
+    #[tokio::test]
+    async fn test_refresh_and_describe_round_trip() {
+        let aliyun = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("x-acs-action", "RefreshObjectCaches"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"17772470467"}"#),
+            )
+            .expect(1)
+            .mount(&aliyun)
+            .await;
+        Mock::given(header("x-acs-action", "DescribeRefreshTaskById"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"RequestId":"r","TotalCount":1,"Tasks":[{"TaskId":"17772470467","ObjectPath":"https://static.prts.wiki/a.png","ObjectType":"file","Status":"Complete","Process":"100%","CreationTime":"2026-01-01T00:00:00Z"}]}"#,
+            ))
+            .expect(1)
+            .mount(&aliyun)
+            .await;
+        let mut settings = test_settings();
+        settings.aliyun.endpoint = aliyun.uri();
+        let client = JanusClient::new(serve(&settings).await, test_token());
+
+        let result = client
+            .refresh_object_caches(&RefreshObjectCachesPayload {
+                object_path: "https://static.prts.wiki/a.png".to_string(),
+                object_type: None,
+                force: None,
+                dry_run: false,
+                product: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(result.task_id, "17772470467");
+        assert_eq!(result.object_type, "File");
+        assert_eq!(result.object_paths, ["https://static.prts.wiki/a.png"]);
+
+        let tasks = client
+            .describe_refresh_tasks(&[&result.task_id])
+            .await
+            .unwrap();
+        assert_eq!(
+            tasks.tasks[0].status,
+            crate::api::RefreshTaskStatus::Complete
+        );
+        aliyun.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_create_dynamic_sends_the_form() {
+        let bilibili = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/x/dynamic/feed/create/dyn"))
+            .and(body_string_contains(r#""raw_text":"hi""#))
+            .and(body_string_contains(r#""id":42"#))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"code":0,"data":{"doc_id":0,"dynamic_id":1021451253404745734,"create_result":0,"errmsg":""}}"#,
+            ))
+            .expect(1)
+            .mount(&bilibili)
+            .await;
+        let mut settings = test_settings();
+        settings.bilibili.api_base_url = bilibili.uri();
+        let client = JanusClient::new(serve(&settings).await, test_token());
+
+        let response = client
+            .create_dynamic(CreateDynamic {
+                topic_id: Some("42".to_string()),
+                ..CreateDynamic::text("hi")
+            })
+            .await
+            .unwrap();
+        assert_eq!(response.code, 0);
+        assert_eq!(
+            response.data.unwrap()["dynamic_id"],
+            1021451253404745734_u64
+        );
+        bilibili.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_error_status_carries_the_error_body() {
+        let client = JanusClient::new(serve(&test_settings()).await, test_token());
+
+        let err = client
+            .describe_refresh_tasks(&["not-a-number"])
+            .await
+            .unwrap_err();
+        let ClientError::Api { status, body } = err else {
+            panic!("expected an API error, got {err:?}");
+        };
+        assert_eq!(status, 400);
+        assert_eq!(
+            body.msg.as_deref(),
+            Some("Task id 'not-a-number' is not numeric")
+        );
+
+        let unauthorized = JanusClient::new(serve(&test_settings()).await, "bogus")
+            .describe_refresh_tasks(&["1"])
+            .await
+            .unwrap_err();
+        assert!(matches!(unauthorized, ClientError::Api { status: 401, .. }));
+    }
+}
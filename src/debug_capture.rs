@@ -0,0 +1,524 @@
+//! Capture of recent requests and responses, for "works from curl, fails from the pipeline" bugs
+//!
+//! Intended for debugging sessions only. When `[debug.capture]` is absent the middleware is
+//! never layered, so there is no per-request cost. Credentials are redacted before storing and
+//! uploaded files are replaced by their size.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use axum::{
+    body::{Body, HttpBody},
+    extract::{Request, State},
+    http::{HeaderMap, header},
+    middleware::Next,
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::{
+    config::CaptureConfig,
+    error::{AppError, AppResult},
+};
+
+const REDACTED: &str = "<redacted>";
+
+/// Header and field names whose values are never stored, matched case-insensitively as substrings
+const SENSITIVE_NAMES: &[&str] = &[
+    "authorization",
+    "cookie",
+    "sessdata",
+    "bili_jct",
+    "access_key",
+    "secret",
+    "token",
+    "signature",
+    "password",
+];
+
+/// A request and the response it got
+#[derive(ToSchema, Serialize, Deserialize, Debug, Clone)]
+pub struct CapturedExchange {
+    pub method: String,
+    /// Path and query
+    pub path: String,
+    /// Request headers, credentials redacted
+    pub request_headers: BTreeMap<String, String>,
+    /// Request body, redacted and cut to `max_body_bytes`
+    pub request_body: String,
+    pub status: u16,
+    pub latency_ms: u64,
+    /// Response body, cut to `max_body_bytes`; streamed bodies are only described
+    pub response_body: String,
+    /// RFC 3339
+    pub captured_at: String,
+}
+
+/// The most recent exchanges on the configured routes
+#[derive(Debug)]
+pub struct RequestCapture {
+    config: CaptureConfig,
+    /// Request bodies over it are refused, as the handlers would
+    max_request_bytes: usize,
+    exchanges: Mutex<VecDeque<CapturedExchange>>,
+}
+
+impl RequestCapture {
+    pub fn new(config: CaptureConfig, max_request_bytes: usize) -> Self {
+        Self {
+            exchanges: Mutex::new(VecDeque::with_capacity(config.capacity)),
+            config,
+            max_request_bytes,
+        }
+    }
+
+    fn wants(&self, path: &str) -> bool {
+        self.config.capacity > 0
+            && self
+                .config
+                .route_prefixes
+                .iter()
+                .any(|prefix| path.starts_with(prefix.as_str()))
+    }
+
+    fn record(&self, exchange: CapturedExchange) {
+        let mut exchanges = self.exchanges.lock().expect("capture lock poisoned");
+        while exchanges.len() >= self.config.capacity {
+            exchanges.pop_front();
+        }
+        exchanges.push_back(exchange);
+    }
+
+    /// Captured exchanges, newest first, optionally only under `path` or with `status`
+    pub fn exchanges(&self, path: Option<&str>, status: Option<u16>) -> Vec<CapturedExchange> {
+        let exchanges = self.exchanges.lock().expect("capture lock poisoned");
+        exchanges
+            .iter()
+            .rev()
+            .filter(|exchange| path.is_none_or(|path| exchange.path.starts_with(path)))
+            .filter(|exchange| status.is_none_or(|status| exchange.status == status))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Middleware storing exchanges on the configured routes
+///
+/// The request body is buffered so it can be stored and still reach the handler.
+pub async fn capture_requests(
+    State(capture): State<Arc<RequestCapture>>,
+    request: Request,
+    next: Next,
+) -> AppResult<Response> {
+    let path = request
+        .uri()
+        .path_and_query()
+        .map_or_else(|| request.uri().path().to_string(), ToString::to_string);
+    if !capture.wants(request.uri().path()) {
+        return Ok(next.run(request).await);
+    }
+
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, capture.max_request_bytes)
+        .await
+        .map_err(|err| {
+            AppError::PayloadTooLarge(anyhow::anyhow!("Failed to read request body: {err}"))
+        })?;
+    let max_body_bytes = capture.config.max_body_bytes;
+    let request_body = truncate(render_body(&parts.headers, &bytes), max_body_bytes);
+    let method = parts.method.to_string();
+    let request_headers = redact_headers(&parts.headers);
+
+    let started = Instant::now();
+    let response = next
+        .run(Request::from_parts(parts, Body::from(bytes)))
+        .await;
+    let latency_ms = started.elapsed().as_millis() as u64;
+
+    let status = response.status().as_u16();
+    let (response, response_body) = match response.body().size_hint().exact() {
+        Some(len) if len <= max_body_bytes as u64 => {
+            let (parts, body) = response.into_parts();
+            match axum::body::to_bytes(body, max_body_bytes).await {
+                Ok(bytes) => {
+                    let rendered = render_body(&parts.headers, &bytes);
+                    (Response::from_parts(parts, Body::from(bytes)), rendered)
+                }
+                Err(err) => {
+                    tracing::warn!(error = %err, "Failed to buffer response for capture");
+                    (Response::from_parts(parts, Body::empty()), String::new())
+                }
+            }
+        }
+        Some(len) => (response, format!("<{len} bytes, not captured>")),
+        None => (response, "<streamed, not captured>".to_string()),
+    };
+
+    capture.record(CapturedExchange {
+        method,
+        path,
+        request_headers,
+        request_body,
+        status,
+        latency_ms,
+        response_body,
+        captured_at: chrono::Utc::now().to_rfc3339(),
+    });
+    Ok(response)
+}
+
+fn is_sensitive(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    SENSITIVE_NAMES
+        .iter()
+        .any(|sensitive| name.contains(sensitive))
+}
+
+fn redact_headers(headers: &HeaderMap) -> BTreeMap<String, String> {
+    let mut redacted = BTreeMap::<String, String>::new();
+    for (name, value) in headers {
+        let value = if is_sensitive(name.as_str()) {
+            REDACTED.to_string()
+        } else {
+            String::from_utf8_lossy(value.as_bytes()).into_owned()
+        };
+        redacted
+            .entry(name.to_string())
+            .and_modify(|values| {
+                values.push_str(", ");
+                values.push_str(&value);
+            })
+            .or_insert(value);
+    }
+    redacted
+}
+
+/// Text of a body with credentials redacted and uploaded files replaced by their size
+fn render_body(headers: &HeaderMap, bytes: &[u8]) -> String {
+    if bytes.is_empty() {
+        return String::new();
+    }
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if let Some(boundary) = multipart_boundary(content_type) {
+        return render_multipart(bytes, boundary);
+    }
+    if content_type.starts_with("application/x-www-form-urlencoded")
+        && let Ok(fields) = serde_urlencoded::from_bytes::<Vec<(String, String)>>(bytes)
+    {
+        return fields
+            .into_iter()
+            .map(|(key, value)| {
+                let value = if is_sensitive(&key) {
+                    REDACTED.to_string()
+                } else {
+                    value
+                };
+                format!("{key}={value}")
+            })
+            .collect::<Vec<_>>()
+            .join("&");
+    }
+    if let Ok(mut value) = serde_json::from_slice::<Value>(bytes) {
+        redact_json(&mut value);
+        return value.to_string();
+    }
+    redact_text(bytes)
+}
+
+/// Any other body is kept only if it mentions no credential at all
+fn redact_text(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) if !is_sensitive(text) => text.to_string(),
+        Ok(_) => format!("<redacted: {} bytes>", bytes.len()),
+        Err(_) => format!("<binary: {} bytes>", bytes.len()),
+    }
+}
+
+fn redact_json(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map.iter_mut() {
+                if is_sensitive(key) {
+                    *child = Value::String(REDACTED.to_string());
+                } else {
+                    redact_json(child);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact_json),
+        _ => {}
+    }
+}
+
+/// `boundary` parameter of a `multipart/*` content type
+fn multipart_boundary(content_type: &str) -> Option<&str> {
+    if !content_type.starts_with("multipart/") {
+        return None;
+    }
+    content_type.split(';').find_map(|param| {
+        let (name, value) = param.trim().split_once('=')?;
+        name.eq_ignore_ascii_case("boundary")
+            .then(|| value.trim_matches('"'))
+    })
+}
+
+/// Multipart body with each file part's content replaced by its size
+fn render_multipart(bytes: &[u8], boundary: &str) -> String {
+    let delimiter = format!("--{boundary}");
+    let body = String::from_utf8_lossy(bytes);
+    let mut rendered = Vec::new();
+    for part in body.split(delimiter.as_str()).skip(1) {
+        if part.starts_with("--") {
+            break;
+        }
+        let Some((part_headers, content)) = part.split_once("\r\n\r\n") else {
+            continue;
+        };
+        let part_headers = part_headers.trim();
+        let content = content.strip_suffix("\r\n").unwrap_or(content);
+        let disposition = part_headers.to_ascii_lowercase();
+        let content = if disposition.contains("filename=") {
+            // Lossy decoding may have grown the bytes, so measure in the original body
+            format!(
+                "<binary: {} bytes>",
+                part_len(bytes, &delimiter, part_headers)
+            )
+        } else if part_name(&disposition).is_some_and(is_sensitive) || is_sensitive(content) {
+            REDACTED.to_string()
+        } else {
+            content.to_string()
+        };
+        rendered.push(format!("{part_headers}\n\n{content}"));
+    }
+    rendered.join("\n--\n")
+}
+
+/// `name` of a `Content-Disposition: form-data; name="..."` header
+fn part_name(disposition: &str) -> Option<&str> {
+    let start = disposition.find("name=\"")? + "name=\"".len();
+    let end = disposition[start..].find('"')?;
+    Some(&disposition[start..start + end])
+}
+
+/// Bytes of the part whose headers are `part_headers`, up to the next delimiter
+fn part_len(bytes: &[u8], delimiter: &str, part_headers: &str) -> usize {
+    let Some(start) = find(bytes, part_headers.as_bytes())
+        .and_then(|at| find(&bytes[at..], b"\r\n\r\n").map(|end| at + end + 4))
+    else {
+        return 0;
+    };
+    let end = find(&bytes[start..], format!("\r\n{delimiter}").as_bytes())
+        .map_or(bytes.len(), |end| start + end);
+    end - start
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Cut `text` to `max` bytes on a character boundary, noting how much was left out
+fn truncate(mut text: String, max: usize) -> String {
+    if text.len() <= max {
+        return text;
+    }
+    let total = text.len();
+    let mut end = max;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    text.truncate(end);
+    text.push_str(&format!("... <{total} bytes>"));
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{
+        config::{AppSettings, DebugConfig},
+        routes::build_router,
+        test_support::{body_json, state_from, test_settings, test_token},
+    };
+
+    fn capturing(capacity: usize, route_prefixes: &[&str]) -> AppSettings {
+        let mut settings = test_settings();
+        settings.debug = Some(DebugConfig {
+            capture: Some(CaptureConfig {
+                capacity,
+                route_prefixes: route_prefixes.iter().map(|p| p.to_string()).collect(),
+                max_body_bytes: 1024,
+            }),
+        });
+        settings
+    }
+
+    async fn captures(router: &axum::Router, query: &str) -> (u16, Value) {
+        let response = router
+            .clone()
+            .oneshot(
+                Request::get(format!("/api/debug/captures{query}"))
+                    .header("Authorization", format!("Bearer {}", test_token()))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status().as_u16();
+        (status, body_json(response).await)
+    }
+
+    #[tokio::test]
+    async fn test_exchange_is_captured_with_credentials_redacted() {
+        let router = build_router(state_from(&capturing(10, &["/api/aliyun/"])));
+
+        let response = router
+            .clone()
+            .oneshot(
+                Request::post("/api/aliyun/refreshObjectCaches?trace=1")
+                    .header("Authorization", format!("Bearer {}", test_token()))
+                    .header("Cookie", "SESSDATA=abc")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        r#"{"object_path":"https://static.prts.wiki/a.png","dry_run":true,"access_key_id":"LTAI5t"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        // The handler still got the whole body
+        assert_eq!(
+            body_json(response).await["object_paths"],
+            serde_json::json!(["https://static.prts.wiki/a.png"])
+        );
+
+        let (status, body) = captures(&router, "").await;
+        assert_eq!(status, 200);
+        let exchange = &body[0];
+        assert_eq!(exchange["method"], "POST");
+        assert_eq!(exchange["path"], "/api/aliyun/refreshObjectCaches?trace=1");
+        assert_eq!(exchange["status"], 200);
+        assert_eq!(exchange["request_headers"]["authorization"], REDACTED);
+        assert_eq!(exchange["request_headers"]["cookie"], REDACTED);
+        assert_eq!(
+            exchange["request_headers"]["content-type"],
+            "application/json"
+        );
+        let request_body: Value =
+            serde_json::from_str(exchange["request_body"].as_str().unwrap()).unwrap();
+        assert_eq!(request_body["access_key_id"], REDACTED);
+        assert_eq!(
+            request_body["object_path"],
+            "https://static.prts.wiki/a.png"
+        );
+        assert!(
+            exchange["response_body"]
+                .as_str()
+                .unwrap()
+                .contains("\"dry_run\":true")
+        );
+        assert!(exchange["latency_ms"].is_u64());
+
+        // The capture endpoint itself isn't under a captured prefix
+        assert_eq!(body.as_array().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_multipart_files_are_replaced_by_their_size() {
+        let router = build_router(state_from(&capturing(10, &["/api/bilibili/"])));
+        let body = "--X\r\n\
+            Content-Disposition: form-data; name=\"msg\"\r\n\r\n\
+            [{\"type\":1,\"biz_id\":\"\",\"raw_text\":\"hi\"}]\r\n\
+            --X\r\n\
+            Content-Disposition: form-data; name=\"image\"; filename=\"a.png\"\r\n\
+            Content-Type: image/png\r\n\r\n\
+            not-really-a-png\r\n\
+            --X--\r\n";
+
+        let response = router
+            .clone()
+            .oneshot(
+                Request::post("/api/bilibili/createDynamic")
+                    .header("Authorization", format!("Bearer {}", test_token()))
+                    .header("Content-Type", "multipart/form-data; boundary=X")
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400);
+
+        let (_, body) = captures(&router, "?path=/api/bilibili&status=400").await;
+        let request_body = body[0]["request_body"].as_str().unwrap();
+        assert!(
+            request_body.contains("<binary: 16 bytes>"),
+            "{request_body}"
+        );
+        assert!(!request_body.contains("not-really-a-png"));
+        assert!(request_body.contains("raw_text"));
+        let (_, body) = captures(&router, "?status=200").await;
+        assert_eq!(body, serde_json::json!([]));
+    }
+
+    #[tokio::test]
+    async fn test_oldest_exchanges_are_evicted_first() {
+        let router = build_router(state_from(&capturing(2, &["/api/_"])));
+        for path in ["/api/_ping", "/api/_health", "/api/_ping?n=3"] {
+            let response = router
+                .clone()
+                .oneshot(Request::get(path).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 200);
+        }
+
+        let (_, body) = captures(&router, "").await;
+        let paths = body
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|exchange| exchange["path"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, ["/api/_ping?n=3", "/api/_health"]);
+    }
+
+    #[tokio::test]
+    async fn test_capture_is_off_by_default() {
+        let router = build_router(state_from(&test_settings()));
+        let (status, _) = captures(&router, "").await;
+        assert_eq!(status, 404);
+    }
+
+    #[test]
+    fn test_plain_text_mentioning_credentials_is_redacted() {
+        let headers = HeaderMap::new();
+        assert_eq!(render_body(&headers, b"hello"), "hello");
+        assert_eq!(
+            render_body(&headers, b"Authorization: Bearer x"),
+            "<redacted: 23 bytes>"
+        );
+        let mut form = HeaderMap::new();
+        form.insert(
+            header::CONTENT_TYPE,
+            "application/x-www-form-urlencoded".parse().unwrap(),
+        );
+        assert_eq!(
+            render_body(&form, b"csrf=1&access_key_secret=s3cret"),
+            "csrf=1&access_key_secret=<redacted>"
+        );
+        assert_eq!(truncate("abcdef".to_string(), 3), "abc... <6 bytes>");
+    }
+}
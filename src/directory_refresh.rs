@@ -0,0 +1,133 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Object keys of one bucket split into directory refreshes and remaining file refreshes
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct PrefixGroups {
+    /// Directory prefix (ending in `/`) with the indices of the keys it covers
+    pub directories: Vec<(String, Vec<usize>)>,
+    /// Indices of keys left for individual file refreshes
+    pub files: Vec<usize>,
+}
+
+/// Collapse keys sharing a directory into one refresh when more than `threshold` of them do
+///
+/// Deeper directories are preferred, so the purge stays as narrow as possible; a parent is
+/// only chosen when the keys not yet covered by its children still exceed the threshold, and
+/// then absorbs those children. The bucket root is never chosen, as that would purge the
+/// whole domain.
+pub fn group_by_directory(keys: &[&str], threshold: usize) -> PrefixGroups {
+    // Every ancestor directory of every key, with the keys below it
+    let mut below: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (index, key) in keys.iter().enumerate() {
+        for (end, _) in key.match_indices('/') {
+            below.entry(&key[..=end]).or_default().push(index);
+        }
+    }
+
+    let mut candidates = below.into_iter().collect::<Vec<_>>();
+    candidates.sort_by(|(a, _), (b, _)| {
+        b.matches('/')
+            .count()
+            .cmp(&a.matches('/').count())
+            .then(a.cmp(b))
+    });
+
+    let mut covered = BTreeSet::new();
+    let mut groups = PrefixGroups::default();
+    for (prefix, indices) in candidates {
+        let remaining = indices
+            .into_iter()
+            .filter(|index| !covered.contains(index))
+            .collect::<Vec<_>>();
+        if remaining.len() > threshold {
+            covered.extend(remaining.iter().copied());
+            let (mut absorbed, kept) = std::mem::take(&mut groups.directories)
+                .into_iter()
+                .partition::<Vec<_>, _>(|(child, _)| child.starts_with(prefix));
+            groups.directories = kept;
+            let mut indices = remaining;
+            indices.extend(absorbed.drain(..).flat_map(|(_, indices)| indices));
+            indices.sort_unstable();
+            groups.directories.push((prefix.to_string(), indices));
+        }
+    }
+    groups.directories.sort();
+    groups.files = (0..keys.len())
+        .filter(|index| !covered.contains(index))
+        .collect();
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_under_threshold_stay_files() {
+        let keys = ["images/old/a.png", "images/old/b.png"];
+        assert_eq!(
+            group_by_directory(&keys, 2),
+            PrefixGroups {
+                directories: vec![],
+                files: vec![0, 1]
+            }
+        );
+    }
+
+    #[test]
+    fn test_threshold_boundary() {
+        let keys = ["images/old/a.png", "images/old/b.png", "images/old/c.png"];
+        // Exactly at the threshold is not enough
+        assert!(group_by_directory(&keys, 3).directories.is_empty());
+        assert_eq!(
+            group_by_directory(&keys, 2),
+            PrefixGroups {
+                directories: vec![("images/old/".to_string(), vec![0, 1, 2])],
+                files: vec![]
+            }
+        );
+    }
+
+    #[test]
+    fn test_mixed_depth_keys_prefer_deepest_directories() {
+        let keys = [
+            "images/old-event/a/1.png",
+            "images/old-event/a/2.png",
+            "images/old-event/a/3.png",
+            "images/old-event/b/1.png",
+            "images/old-event/c.png",
+            "images/other.png",
+            "root.png",
+        ];
+        // Only a/ has enough keys; its sibling stays a file
+        assert_eq!(
+            group_by_directory(&keys[..4], 2),
+            PrefixGroups {
+                directories: vec![("images/old-event/a/".to_string(), vec![0, 1, 2])],
+                files: vec![3]
+            }
+        );
+        // a/ alone is too small, but old-event/ as a whole qualifies
+        assert_eq!(
+            group_by_directory(&keys, 3),
+            PrefixGroups {
+                directories: vec![("images/old-event/".to_string(), vec![0, 1, 2, 3, 4])],
+                files: vec![5, 6]
+            }
+        );
+        // a/ qualifies first, then the rest of images/ does too and absorbs it
+        assert_eq!(
+            group_by_directory(&keys, 2),
+            PrefixGroups {
+                directories: vec![("images/".to_string(), vec![0, 1, 2, 3, 4, 5])],
+                files: vec![6]
+            }
+        );
+    }
+
+    #[test]
+    fn test_bucket_root_is_never_a_directory() {
+        let keys = ["a.png", "b.png", "c.png"];
+        assert_eq!(group_by_directory(&keys, 0).files, vec![0, 1, 2]);
+    }
+}
@@ -0,0 +1,195 @@
+//! Verification of EventBridge's native HMAC-signed deliveries
+
+use std::time::Duration;
+
+use anyhow::{Context, bail};
+use axum::http::HeaderMap;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::ttl_cache::TtlCache;
+
+/// Hex HMAC-SHA256 of `{timestamp}\n{body}`
+pub const SIGNATURE_HEADER: &str = "x-eventbridge-signature";
+/// Unix seconds at which the delivery was signed
+pub const TIMESTAMP_HEADER: &str = "x-eventbridge-signature-timestamp";
+
+/// Signatures of recently accepted deliveries, so a captured request can't be replayed
+/// while its timestamp is still inside the skew window
+#[derive(Debug, Clone)]
+pub struct ReplayGuard {
+    seen: TtlCache<String, ()>,
+}
+
+impl ReplayGuard {
+    /// Guard against replays within `max_skew_secs` of the signing time, remembering at most
+    /// `max_entries` signatures
+    pub fn new(max_skew_secs: u64, max_entries: usize) -> Self {
+        // Anything older than twice the window fails the timestamp check anyway
+        let ttl = Duration::from_secs(max_skew_secs.saturating_mul(2));
+        Self {
+            seen: TtlCache::new(ttl, max_entries),
+        }
+    }
+
+    /// Record `signature`, returning false if it was already seen
+    fn insert(&self, signature: &str) -> bool {
+        self.seen.insert_new(signature.to_string(), ())
+    }
+}
+
+/// Check the signature headers of a delivery against its raw `body`
+///
+/// `now` is the current Unix time in seconds.
+pub fn verify_signature(
+    headers: &HeaderMap,
+    body: &[u8],
+    secret: &str,
+    max_skew_secs: u64,
+    now: i64,
+    replay: &ReplayGuard,
+) -> anyhow::Result<()> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .with_context(|| format!("Missing {name} header"))?
+            .to_str()
+            .map(str::trim)
+            .with_context(|| format!("Invalid {name} header format"))
+    };
+    let timestamp = header(TIMESTAMP_HEADER)?;
+    let signature = header(SIGNATURE_HEADER)?;
+
+    let signed_at = timestamp
+        .parse::<i64>()
+        .with_context(|| format!("{TIMESTAMP_HEADER} is not a Unix timestamp"))?;
+    if now.abs_diff(signed_at) > max_skew_secs {
+        bail!("{TIMESTAMP_HEADER} is more than {max_skew_secs}s away from server time");
+    }
+
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC can take key of any size");
+    mac.update(timestamp.as_bytes());
+    mac.update(b"\n");
+    mac.update(body);
+    let expected = hex_decode(signature).context("Signature is not hex")?;
+    mac.verify_slice(&expected)
+        .map_err(|_| anyhow::anyhow!("Signature does not match the body"))?;
+
+    // The hex case must not make a replay look like a new signature
+    if !replay.insert(&signature.to_ascii_lowercase()) {
+        bail!("Delivery was already accepted (replayed signature)");
+    }
+    Ok(())
+}
+
+/// Sign `body` the way EventBridge does
+#[cfg(test)]
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC can take key of any size");
+    mac.update(format!("{timestamp}\n").as_bytes());
+    mac.update(body);
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn hex_decode(input: &str) -> Option<Vec<u8>> {
+    if !input.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..input.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(input.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "eventbridge-secret";
+    const NOW: i64 = 1_760_000_000;
+    const BODY: &[u8] = br#"{"id":"event-1"}"#;
+
+    fn headers(timestamp: i64, signature: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(TIMESTAMP_HEADER, timestamp.to_string().parse().unwrap());
+        headers.insert(SIGNATURE_HEADER, signature.parse().unwrap());
+        headers
+    }
+
+    fn replay_guard() -> ReplayGuard {
+        ReplayGuard::new(300, 100)
+    }
+
+    fn verify(headers: &HeaderMap, body: &[u8], replay: &ReplayGuard) -> Result<(), String> {
+        verify_signature(headers, body, SECRET, 300, NOW, replay).map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn test_valid_signature_is_accepted() {
+        let signed = headers(NOW - 10, &sign(SECRET, NOW - 10, BODY));
+        assert_eq!(verify(&signed, BODY, &replay_guard()), Ok(()));
+    }
+
+    #[test]
+    fn test_signature_matches_known_vector() {
+        // printf '1760000000\n{"id":"event-1"}' | openssl dgst -sha256 -hmac eventbridge-secret
+        const EXPECTED: &str = "b180dc19437b337840fbbe66917c5b5362880fd589020aeb5829ec0eb154afd0";
+        assert_eq!(sign(SECRET, NOW, BODY), EXPECTED);
+        assert_eq!(
+            verify(&headers(NOW, EXPECTED), BODY, &replay_guard()),
+            Ok(())
+        );
+        // Upper-case hex is the same signature, also to the replay check
+        let replay = replay_guard();
+        assert_eq!(verify(&headers(NOW, EXPECTED), BODY, &replay), Ok(()));
+        assert!(verify(&headers(NOW, &EXPECTED.to_uppercase()), BODY, &replay).is_err());
+    }
+
+    #[test]
+    fn test_tampered_body_is_rejected() {
+        let signed = headers(NOW, &sign(SECRET, NOW, BODY));
+        assert_eq!(
+            verify(&signed, br#"{"id":"event-2"}"#, &replay_guard()),
+            Err("Signature does not match the body".to_string())
+        );
+    }
+
+    #[test]
+    fn test_expired_timestamp_is_rejected() {
+        let signed = headers(NOW - 301, &sign(SECRET, NOW - 301, BODY));
+        assert_eq!(
+            verify(&signed, BODY, &replay_guard()),
+            Err(
+                "x-eventbridge-signature-timestamp is more than 300s away from server time"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_replayed_signature_is_rejected() {
+        let replay = replay_guard();
+        let signed = headers(NOW, &sign(SECRET, NOW, BODY));
+        assert_eq!(verify(&signed, BODY, &replay), Ok(()));
+        assert_eq!(
+            verify(&signed, BODY, &replay),
+            Err("Delivery was already accepted (replayed signature)".to_string())
+        );
+    }
+
+    #[test]
+    fn test_missing_signature_is_rejected() {
+        let mut unsigned = HeaderMap::new();
+        unsigned.insert(TIMESTAMP_HEADER, NOW.to_string().parse().unwrap());
+        assert_eq!(
+            verify(&unsigned, BODY, &replay_guard()),
+            Err("Missing x-eventbridge-signature header".to_string())
+        );
+    }
+}
@@ -0,0 +1,60 @@
+use std::time::Duration;
+
+use crate::ttl_cache::TtlCache;
+
+/// Identity of an OSS object version: bucket, object key and ETag
+pub type EventKey = (String, String, String);
+
+/// Remembers recently refreshed object versions so redelivered or duplicated OSS events
+/// don't purge the same URL again
+#[derive(Debug, Clone)]
+pub struct EventDedup {
+    entries: TtlCache<EventKey, String>,
+}
+
+impl EventDedup {
+    /// A zero `ttl` disables deduplication; past `max_entries`, the oldest are forgotten first
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            entries: TtlCache::new(ttl, max_entries),
+        }
+    }
+
+    /// Task id of a refresh for `key` issued within the TTL
+    pub fn recent_task(&self, key: &EventKey) -> Option<String> {
+        self.entries.get(key)
+    }
+
+    /// Remember that `key` was refreshed as `task_id`
+    pub fn record(&self, key: EventKey, task_id: String) {
+        self.entries.insert(key, task_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(etag: &str) -> EventKey {
+        ("prts-static".into(), "a.png".into(), etag.into())
+    }
+
+    #[test]
+    fn test_recent_refresh_is_remembered_until_ttl() {
+        let dedup = EventDedup::new(Duration::from_millis(50), 100);
+        dedup.record(key("v1"), "42".to_string());
+
+        assert_eq!(dedup.recent_task(&key("v1")).as_deref(), Some("42"));
+        assert_eq!(dedup.recent_task(&key("v2")), None);
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(dedup.recent_task(&key("v1")), None);
+    }
+
+    #[test]
+    fn test_zero_ttl_disables_dedup() {
+        let dedup = EventDedup::new(Duration::ZERO, 100);
+        dedup.record(key("v1"), "42".to_string());
+        assert_eq!(dedup.recent_task(&key("v1")), None);
+    }
+}
@@ -0,0 +1,245 @@
+//! Dead letters: OSS events that can never be processed as delivered
+//!
+//! EventBridge redelivers a rejected event for 24 hours and then drops it, so the purge never
+//! happens. Events failing validation, and queued refreshes that exhausted their retries, are
+//! parked here instead and acknowledged; they can be replayed once the cause is fixed (e.g. the
+//! bucket is mapped). Like deferred events, dead letters live in memory and are lost on restart.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use tracing::{error, warn};
+
+pub use crate::api::aliyun::{DeadLetter, DeadLetterPage, DeadLetterStatus};
+use crate::error::{AppError, AppResult};
+
+/// Dead letters kept at most; beyond it the oldest are dropped, resolved ones first
+const MAX_DEAD_LETTERS: usize = 1000;
+
+/// Largest page `list` serves
+pub const MAX_DEAD_LETTER_PAGE_SIZE: usize = 100;
+
+#[derive(Default)]
+struct Store {
+    next_id: u64,
+    entries: BTreeMap<u64, DeadLetter>,
+}
+
+/// Dead-lettered OSS events, by id
+#[derive(Clone, Default)]
+pub struct DeadLetters {
+    store: Arc<Mutex<Store>>,
+}
+
+impl std::fmt::Debug for DeadLetters {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let store = self.store.lock().expect("dead letter lock poisoned");
+        f.debug_struct("DeadLetters")
+            .field("entries", &store.entries.len())
+            .finish()
+    }
+}
+
+impl DeadLetters {
+    /// Park `event` that failed with `err` after `attempts` tries, returning its id
+    ///
+    /// Never fails: when full the oldest dead letter makes room, so the delivery can still be
+    /// acknowledged.
+    pub fn push(&self, event: serde_json::Value, err: &AppError, attempts: u32) -> u64 {
+        let mut store = self.store.lock().expect("dead letter lock poisoned");
+        if store.entries.len() >= MAX_DEAD_LETTERS {
+            let oldest = store
+                .entries
+                .values()
+                .find(|entry| entry.status == DeadLetterStatus::Resolved)
+                .or_else(|| store.entries.values().next())
+                .map(|entry| (entry.id, entry.status));
+            if let Some((id, status)) = oldest {
+                if status == DeadLetterStatus::Pending {
+                    warn!(
+                        dead_letter_id = id,
+                        "Dead letter store is full, dropping the oldest pending event"
+                    );
+                }
+                store.entries.remove(&id);
+            }
+        }
+
+        store.next_id += 1;
+        let now = chrono::Utc::now().to_rfc3339();
+        let entry = DeadLetter {
+            id: store.next_id,
+            status: DeadLetterStatus::Pending,
+            event,
+            error: format!("{err:#}"),
+            attempts,
+            replays: 0,
+            resolution: None,
+            created_at: now.clone(),
+            updated_at: now,
+        };
+        error!(
+            dead_letter_id = entry.id,
+            error = entry.error,
+            attempts,
+            "OSS event dead-lettered"
+        );
+        let id = entry.id;
+        store.entries.insert(id, entry);
+        id
+    }
+
+    /// Page `page_number` (from 1) of dead letters, newest first, optionally only with `status`
+    pub fn list(
+        &self,
+        status: Option<DeadLetterStatus>,
+        page_number: usize,
+        page_size: usize,
+    ) -> DeadLetterPage {
+        let store = self.store.lock().expect("dead letter lock poisoned");
+        let matching = store
+            .entries
+            .values()
+            .rev()
+            .filter(|entry| status.is_none_or(|status| entry.status == status))
+            .collect::<Vec<_>>();
+        DeadLetterPage {
+            total: matching.len(),
+            page_number,
+            page_size,
+            items: matching
+                .into_iter()
+                .skip(page_number.saturating_sub(1).saturating_mul(page_size))
+                .take(page_size)
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// The stored event of a pending dead letter, counting a replay
+    pub fn start_replay(&self, id: u64) -> AppResult<serde_json::Value> {
+        let mut store = self.store.lock().expect("dead letter lock poisoned");
+        let entry = store
+            .entries
+            .get_mut(&id)
+            .ok_or_else(|| AppError::NotFound(anyhow::anyhow!("Dead letter {} not found", id)))?;
+        if entry.status == DeadLetterStatus::Resolved {
+            return Err(AppError::BadRequest(anyhow::anyhow!(
+                "Dead letter {} is already resolved",
+                id
+            )));
+        }
+        entry.replays += 1;
+        Ok(entry.event.clone())
+    }
+
+    /// Record the outcome of a replay: `Ok` resolves the dead letter with that message
+    pub fn finish_replay(&self, id: u64, outcome: Result<&str, &AppError>) -> Option<DeadLetter> {
+        let mut store = self.store.lock().expect("dead letter lock poisoned");
+        let entry = store.entries.get_mut(&id)?;
+        match outcome {
+            Ok(message) => {
+                entry.status = DeadLetterStatus::Resolved;
+                entry.resolution = Some(message.to_string());
+            }
+            Err(err) => entry.error = format!("{err:#}"),
+        }
+        entry.updated_at = chrono::Utc::now().to_rfc3339();
+        Some(entry.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn bad_request(message: &str) -> AppError {
+        AppError::BadRequest(anyhow::anyhow!(message.to_string()))
+    }
+
+    #[test]
+    fn test_listing_pages_newest_first_and_filters() {
+        let letters = DeadLetters::default();
+        for index in 0..5 {
+            letters.push(
+                json!({"id": index}),
+                &bad_request("Unsupported bucket: x"),
+                1,
+            );
+        }
+        letters.start_replay(1).unwrap();
+        letters.finish_replay(1, Ok("refreshed")).unwrap();
+
+        let page = letters.list(None, 1, 2);
+        assert_eq!(page.total, 5);
+        assert_eq!(
+            page.items.iter().map(|entry| entry.id).collect::<Vec<_>>(),
+            [5, 4]
+        );
+        let last = letters.list(None, 3, 2);
+        assert_eq!(last.items[0].id, 1);
+        assert_eq!(last.items[0].resolution.as_deref(), Some("refreshed"));
+        assert_eq!(
+            letters.list(Some(DeadLetterStatus::Pending), 1, 10).total,
+            4
+        );
+    }
+
+    #[test]
+    fn test_only_pending_dead_letters_replay() {
+        let letters = DeadLetters::default();
+        let id = letters.push(json!({}), &bad_request("Unsupported bucket: x"), 1);
+
+        letters.start_replay(id).unwrap();
+        let failed = letters
+            .finish_replay(id, Err(&bad_request("Unsupported bucket: y")))
+            .unwrap();
+        assert_eq!(failed.status, DeadLetterStatus::Pending);
+        assert_eq!(failed.error, "Bad request: Unsupported bucket: y");
+
+        letters.start_replay(id).unwrap();
+        let resolved = letters.finish_replay(id, Ok("refreshed")).unwrap();
+        assert_eq!(resolved.replays, 2);
+        assert!(matches!(
+            letters.start_replay(id),
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(matches!(
+            letters.start_replay(99),
+            Err(AppError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_full_store_drops_resolved_dead_letters_first() {
+        let letters = DeadLetters::default();
+        for _ in 0..MAX_DEAD_LETTERS {
+            letters.push(json!({}), &bad_request("Unsupported bucket: x"), 1);
+        }
+        letters.start_replay(2).unwrap();
+        letters.finish_replay(2, Ok("refreshed"));
+
+        letters.push(json!({}), &bad_request("Unsupported bucket: x"), 1);
+        let ids = letters
+            .list(None, 1, MAX_DEAD_LETTERS)
+            .items
+            .iter()
+            .map(|entry| entry.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids.len(), MAX_DEAD_LETTERS);
+        assert!(ids.contains(&1) && !ids.contains(&2));
+
+        letters.push(json!({}), &bad_request("Unsupported bucket: x"), 1);
+        assert!(
+            !letters
+                .list(None, 1, MAX_DEAD_LETTERS)
+                .items
+                .iter()
+                .any(|entry| entry.id == 1)
+        );
+    }
+}
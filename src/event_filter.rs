@@ -0,0 +1,265 @@
+use std::{collections::HashMap, fmt};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+use crate::config::AliyunEventsConfig;
+
+/// Decides which OSS events trigger a CDN refresh, compiled once from `[aliyun.events]`
+#[derive(Debug, Clone)]
+pub struct EventFilter {
+    allowed_event_prefixes: Vec<String>,
+    ignored_keys: IgnoredKeys,
+    /// Bucket -> patterns that only apply to its keys
+    bucket_ignored_keys: HashMap<String, IgnoredKeys>,
+}
+
+/// Compiled ignore patterns, kept next to their source so a match can be named
+#[derive(Debug, Clone)]
+struct IgnoredKeys {
+    patterns: Vec<String>,
+    set: GlobSet,
+}
+
+impl IgnoredKeys {
+    /// `path` names the setting in the error, which also quotes the bad pattern
+    fn new(path: &str, patterns: &[String]) -> Result<Self, String> {
+        let mut set = GlobSetBuilder::new();
+        for pattern in patterns {
+            set.add(Glob::new(pattern).map_err(|err| format!("{path}: {err}"))?);
+        }
+        Ok(Self {
+            patterns: patterns.to_vec(),
+            set: set.build().map_err(|err| format!("{path}: {err}"))?,
+        })
+    }
+
+    /// The first pattern matching `object_key`
+    fn matching(&self, object_key: &str) -> Option<&str> {
+        let index = *self.set.matches(object_key).first()?;
+        Some(&self.patterns[index])
+    }
+}
+
+/// Why an OSS event is acknowledged without a refresh
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Skip {
+    /// The event kind is not in `allowed_event_prefixes`
+    EventKind(String),
+    /// The object key matches this ignore pattern
+    IgnoredKey(String),
+}
+
+impl Skip {
+    /// Label of the `reason` dimension in metrics and logs
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::EventKind(_) => "event_kind",
+            Self::IgnoredKey(_) => "ignored_key",
+        }
+    }
+}
+
+impl fmt::Display for Skip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EventKind(event_name) => write!(f, "event {event_name} is not refreshed"),
+            Self::IgnoredKey(pattern) => write!(f, "ignored by rule {pattern}"),
+        }
+    }
+}
+
+impl EventFilter {
+    /// Fails with a message naming the setting and the pattern that does not compile
+    pub fn new(config: &AliyunEventsConfig) -> Result<Self, String> {
+        let mut bucket_ignored_keys = HashMap::new();
+        for (bucket, patterns) in &config.bucket_ignore_key_patterns {
+            let path = format!("aliyun.events.bucket_ignore_key_patterns.{bucket}");
+            bucket_ignored_keys.insert(bucket.clone(), IgnoredKeys::new(&path, patterns)?);
+        }
+        Ok(Self {
+            allowed_event_prefixes: config.allowed_event_prefixes.clone(),
+            ignored_keys: IgnoredKeys::new(
+                "aliyun.events.ignore_key_patterns",
+                &config.ignore_key_patterns,
+            )?,
+            bucket_ignored_keys,
+        })
+    }
+
+    /// Why the event should not be refreshed, if it shouldn't
+    ///
+    /// Events without an `eventName` are only filtered by key. Patterns of the bucket are
+    /// checked before the global ones.
+    pub fn skip_reason(
+        &self,
+        event_name: Option<&str>,
+        bucket: &str,
+        object_key: &str,
+    ) -> Option<Skip> {
+        if let Some(event_name) = event_name {
+            // Event names look like `ObjectCreated:PutObject`
+            let kind = event_name.split(':').next().unwrap_or(event_name);
+            if !self
+                .allowed_event_prefixes
+                .iter()
+                .any(|prefix| prefix == kind)
+            {
+                return Some(Skip::EventKind(event_name.to_string()));
+            }
+        }
+        self.bucket_ignored_keys
+            .get(bucket)
+            .and_then(|ignored| ignored.matching(object_key))
+            .or_else(|| self.ignored_keys.matching(object_key))
+            .map(|pattern| Skip::IgnoredKey(pattern.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(patterns: &[&str]) -> EventFilter {
+        EventFilter::new(&AliyunEventsConfig {
+            ignore_key_patterns: patterns.iter().map(|p| p.to_string()).collect(),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_only_allowed_event_kinds_pass() {
+        let filter = filter(&[]);
+        assert_eq!(
+            filter.skip_reason(Some("ObjectCreated:PutObject"), "prts-static", "a.png"),
+            None
+        );
+        assert_eq!(
+            filter.skip_reason(Some("ObjectRemoved:DeleteObject"), "prts-static", "a.png"),
+            None
+        );
+        assert_eq!(filter.skip_reason(None, "prts-static", "a.png"), None);
+        assert_eq!(
+            filter.skip_reason(
+                Some("ObjectModified:UpdateObjectMeta"),
+                "prts-static",
+                "a.png"
+            ),
+            Some(Skip::EventKind(
+                "ObjectModified:UpdateObjectMeta".to_string()
+            ))
+        );
+        // Prefixes match the whole kind, not any string prefix
+        assert!(
+            filter
+                .skip_reason(Some("ObjectCreatedX:Put"), "prts-static", "a.png")
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn test_ignored_key_patterns() {
+        let filter = filter(&["tmp/*", "*.part"]);
+        for key in [
+            "tmp/a.png",
+            "tmp/上传/b+c.png",
+            "images/a.png.part",
+            "分段/文件 1+1.part",
+        ] {
+            assert!(
+                filter.skip_reason(None, "prts-static", key).is_some(),
+                "{key} should be ignored"
+            );
+        }
+        for key in [
+            "images/tmp/a.png",
+            "a.part.png",
+            "明日方舟/头像+1.png",
+            "tmpfile.png",
+            "c++/a.png",
+        ] {
+            assert_eq!(
+                filter.skip_reason(None, "prts-static", key),
+                None,
+                "{key} should pass"
+            );
+        }
+    }
+
+    #[test]
+    fn test_plus_and_brackets_are_matched_literally_when_escaped() {
+        let filter = filter(&["a+b/*", "[[]draft]*"]);
+        assert!(
+            filter
+                .skip_reason(None, "prts-static", "a+b/c.png")
+                .is_some()
+        );
+        assert!(
+            filter
+                .skip_reason(None, "prts-static", "aab/c.png")
+                .is_none()
+        );
+        assert!(
+            filter
+                .skip_reason(None, "prts-static", "[draft]立绘.png")
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn test_bucket_patterns_only_apply_to_their_bucket() {
+        let filter = EventFilter::new(&AliyunEventsConfig {
+            ignore_key_patterns: vec!["*.part".to_string()],
+            bucket_ignore_key_patterns: HashMap::from([(
+                "prts-static".to_string(),
+                vec!["thumb/*".to_string(), "*.psd".to_string()],
+            )]),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            filter.skip_reason(None, "prts-static", "立绘/阿米娅.psd"),
+            Some(Skip::IgnoredKey("*.psd".to_string()))
+        );
+        assert_eq!(
+            filter
+                .skip_reason(None, "prts-static", "thumb/a.png")
+                .unwrap()
+                .to_string(),
+            "ignored by rule thumb/*"
+        );
+        assert_eq!(
+            filter.skip_reason(None, "prts-static", "a.png.part"),
+            Some(Skip::IgnoredKey("*.part".to_string()))
+        );
+        assert_eq!(filter.skip_reason(None, "ak-media", "thumb/a.png"), None);
+    }
+
+    #[test]
+    fn test_invalid_pattern_is_an_error() {
+        let err = EventFilter::new(&AliyunEventsConfig {
+            ignore_key_patterns: vec!["images/[".to_string()],
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert!(
+            err.starts_with("aliyun.events.ignore_key_patterns: "),
+            "{err}"
+        );
+        assert!(err.contains("images/["), "{err}");
+
+        let err = EventFilter::new(&AliyunEventsConfig {
+            bucket_ignore_key_patterns: HashMap::from([(
+                "prts-static".to_string(),
+                vec!["thumb/*".to_string(), "{a,b".to_string()],
+            )]),
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert!(
+            err.starts_with("aliyun.events.bucket_ignore_key_patterns.prts-static: "),
+            "{err}"
+        );
+        assert!(err.contains("{a,b"), "{err}");
+    }
+}
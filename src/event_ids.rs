@@ -0,0 +1,86 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::{api::aliyun::OssEventResult, ttl_cache::TtlCache};
+
+/// Answers given to recent EventBridge deliveries, by event `id`, so a redelivery of the same
+/// event is answered the same way instead of refreshing again
+///
+/// Only finished answers are kept: a redelivery arriving while the first one is still being
+/// processed is processed too.
+#[derive(Debug, Clone)]
+pub struct EventIds {
+    entries: TtlCache<String, (DateTime<Utc>, OssEventResult)>,
+}
+
+impl EventIds {
+    /// A zero `window` disables the lookup; past `max_entries`, the oldest are forgotten first
+    pub fn new(window: Duration, max_entries: usize) -> Self {
+        Self {
+            entries: TtlCache::new(window, max_entries),
+        }
+    }
+
+    /// The answer to event `id` given within the window, and when it was given
+    pub fn answer(&self, id: &str) -> Option<(DateTime<Utc>, OssEventResult)> {
+        self.entries.get(&id.to_string())
+    }
+
+    /// Remember the answer to event `id`, unless one is already remembered
+    pub fn record(&self, id: &str, result: &OssEventResult) {
+        self.entries
+            .insert_new(id.to_string(), (Utc::now(), result.clone()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::aliyun::OssEventStatus;
+
+    fn result(task_id: &str) -> OssEventResult {
+        OssEventResult {
+            id: None,
+            status: OssEventStatus::Refreshed,
+            message: "CDN refresh triggered".to_string(),
+            task_id: Some(task_id.to_string()),
+            task_ids: vec![task_id.to_string()],
+            job_id: None,
+            dead_letter_id: None,
+        }
+    }
+
+    #[test]
+    fn test_first_answer_is_kept_until_the_window_ends() {
+        let ids = EventIds::new(Duration::from_millis(50), 100);
+        ids.record("event-1", &result("1"));
+        ids.record("event-1", &result("2"));
+
+        let (_, answer) = ids.answer("event-1").unwrap();
+        assert_eq!(answer.task_id.as_deref(), Some("1"));
+        assert!(ids.answer("event-2").is_none());
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(ids.answer("event-1").is_none());
+    }
+
+    #[test]
+    fn test_oldest_ids_make_room_when_full() {
+        let ids = EventIds::new(Duration::from_secs(60), 2);
+        ids.record("event-1", &result("1"));
+        ids.record("event-2", &result("2"));
+        ids.record("event-3", &result("3"));
+
+        assert!(ids.answer("event-1").is_none());
+        assert!(ids.answer("event-2").is_some());
+        assert!(ids.answer("event-3").is_some());
+    }
+
+    #[test]
+    fn test_zero_window_disables_the_lookup() {
+        let ids = EventIds::new(Duration::ZERO, 100);
+        ids.record("event-1", &result("1"));
+        assert!(ids.answer("event-1").is_none());
+    }
+}
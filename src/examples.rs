@@ -0,0 +1,504 @@
+//! Response example recording for keeping OpenAPI examples in sync with real traffic.
+//!
+//! Intended for non-production environments only. When `[examples]` is absent the
+//! middleware is never layered, so there is no per-request cost.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    body::{Body, HttpBody},
+    extract::{MatchedPath, Request, State},
+    http::header,
+    middleware::Next,
+    response::Response,
+};
+use chrono::{NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::config::ExamplesConfig;
+
+/// Responses larger than this are never sampled
+const MAX_EXAMPLE_BYTES: u64 = 256 * 1024;
+
+const MASKED: &str = "<masked>";
+
+/// A scrubbed response captured from real traffic
+#[derive(ToSchema, Serialize, Deserialize, Debug, Clone)]
+pub struct RecordedExample {
+    /// Operation key, e.g. `post /api/bilibili/createDynamic`
+    pub operation: String,
+    pub status: u16,
+    /// RFC3339 capture time
+    pub recorded_at: String,
+    pub body: Value,
+}
+
+#[derive(Debug, Default)]
+struct OperationSamples {
+    day: Option<NaiveDate>,
+    taken_today: usize,
+    examples: Vec<RecordedExample>,
+}
+
+/// Samples the first N successful JSON responses per operation per day
+#[derive(Debug)]
+pub struct ExampleRecorder {
+    config: ExamplesConfig,
+    samples: Mutex<HashMap<String, OperationSamples>>,
+}
+
+impl ExampleRecorder {
+    pub fn new(config: ExamplesConfig) -> Self {
+        Self {
+            config,
+            samples: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether another sample for `operation` would be kept today
+    fn wants(&self, operation: &str) -> bool {
+        let samples = self.samples.lock().expect("examples lock poisoned");
+        match samples.get(operation) {
+            Some(entry) if entry.day == Some(Utc::now().date_naive()) => {
+                entry.taken_today < self.config.samples_per_day
+            }
+            _ => self.config.samples_per_day > 0,
+        }
+    }
+
+    fn record(&self, operation: String, status: u16, mut body: Value) {
+        scrub(&mut body, &self.config.mask_fields, "");
+
+        let now = Utc::now();
+        let mut samples = self.samples.lock().expect("examples lock poisoned");
+        let entry = samples.entry(operation.clone()).or_default();
+        if entry.day != Some(now.date_naive()) {
+            entry.day = Some(now.date_naive());
+            entry.taken_today = 0;
+        }
+        if entry.taken_today >= self.config.samples_per_day {
+            return;
+        }
+        entry.taken_today += 1;
+        entry.examples.push(RecordedExample {
+            operation,
+            status,
+            recorded_at: now.to_rfc3339(),
+            body,
+        });
+        // Keep only the most recent day's worth of candidates
+        let excess = entry
+            .examples
+            .len()
+            .saturating_sub(self.config.samples_per_day);
+        entry.examples.drain(..excess);
+    }
+
+    /// All candidate examples, grouped by operation
+    pub fn examples(&self) -> Vec<RecordedExample> {
+        let samples = self.samples.lock().expect("examples lock poisoned");
+        let mut examples = samples
+            .values()
+            .flat_map(|entry| entry.examples.iter().cloned())
+            .collect::<Vec<_>>();
+        examples.sort_by(|a, b| a.operation.cmp(&b.operation));
+        examples
+    }
+}
+
+/// Middleware sampling successful JSON responses into the recorder
+pub async fn record_examples(
+    State(recorder): State<Arc<ExampleRecorder>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let operation = request.extensions().get::<MatchedPath>().map(|path| {
+        format!(
+            "{} {}",
+            request.method().as_str().to_ascii_lowercase(),
+            path.as_str()
+        )
+    });
+
+    let response = next.run(request).await;
+
+    let Some(operation) = operation else {
+        return response;
+    };
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    let small_enough = response
+        .body()
+        .size_hint()
+        .exact()
+        .is_some_and(|len| len <= MAX_EXAMPLE_BYTES);
+    if !response.status().is_success() || !is_json || !small_enough || !recorder.wants(&operation) {
+        return response;
+    }
+
+    let status = response.status().as_u16();
+    let (parts, body) = response.into_parts();
+    let bytes = match axum::body::to_bytes(body, MAX_EXAMPLE_BYTES as usize).await {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            tracing::warn!(error = %err, "Failed to buffer response for example recording");
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+    if let Ok(value) = serde_json::from_slice::<Value>(&bytes) {
+        recorder.record(operation, status, value);
+    }
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+/// Replace masked fields with a placeholder of the same JSON type
+///
+/// A mask matches either a bare key name (`task_id`) or a dotted path (`data.dynamic_id`).
+fn scrub(value: &mut Value, masks: &[String], path: &str) {
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map.iter_mut() {
+                let child_path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                if masks
+                    .iter()
+                    .any(|mask| *mask == *key || *mask == child_path)
+                {
+                    *child = masked_like(child);
+                } else {
+                    scrub(child, masks, &child_path);
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                scrub(item, masks, path);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn masked_like(value: &Value) -> Value {
+    match value {
+        Value::String(_) => Value::String(MASKED.to_string()),
+        Value::Number(_) => Value::from(0),
+        Value::Bool(_) => Value::Bool(false),
+        Value::Array(_) => Value::Array(Vec::new()),
+        Value::Object(_) => Value::Object(serde_json::Map::new()),
+        Value::Null => Value::Null,
+    }
+}
+
+/// A difference between a recorded response and its documented example
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExampleDrift {
+    /// The operation or status has no documented example at all
+    MissingExample { operation: String, status: u16 },
+    /// A field sent by the handler is absent from the documented example
+    MissingField { operation: String, path: String },
+    /// The documented example uses a different JSON type for a field
+    TypeMismatch {
+        operation: String,
+        path: String,
+        documented: &'static str,
+        recorded: &'static str,
+    },
+}
+
+impl fmt::Display for ExampleDrift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExampleDrift::MissingExample { operation, status } => {
+                write!(f, "{operation} [{status}]: no documented example")
+            }
+            ExampleDrift::MissingField { operation, path } => {
+                write!(f, "{operation}: field `{path}` missing from example")
+            }
+            ExampleDrift::TypeMismatch {
+                operation,
+                path,
+                documented,
+                recorded,
+            } => write!(
+                f,
+                "{operation}: field `{path}` documented as {documented}, recorded as {recorded}"
+            ),
+        }
+    }
+}
+
+/// Compare recorded examples against the examples embedded in an OpenAPI document
+pub fn compare_with_spec(spec: &Value, recorded: &[RecordedExample]) -> Vec<ExampleDrift> {
+    let mut drifts = Vec::new();
+    for example in recorded {
+        let Some((method, path)) = example.operation.split_once(' ') else {
+            continue;
+        };
+        match documented_example(spec, method, path, example.status) {
+            Some(documented) => compare_shapes(
+                &example.operation,
+                "",
+                documented,
+                &example.body,
+                &mut drifts,
+            ),
+            None => drifts.push(ExampleDrift::MissingExample {
+                operation: example.operation.clone(),
+                status: example.status,
+            }),
+        }
+    }
+    drifts.dedup();
+    drifts
+}
+
+/// Find the response example for an operation, falling back to the referenced schema example
+fn documented_example<'a>(
+    spec: &'a Value,
+    method: &str,
+    path: &str,
+    status: u16,
+) -> Option<&'a Value> {
+    let content = spec
+        .get("paths")?
+        .get(path)?
+        .get(method)?
+        .get("responses")?
+        .get(status.to_string())?
+        .get("content")?
+        .get("application/json")?;
+    if let Some(example) = content.get("example") {
+        return Some(example);
+    }
+    let schema_name = content
+        .get("schema")?
+        .get("$ref")?
+        .as_str()?
+        .strip_prefix("#/components/schemas/")?;
+    spec.get("components")?
+        .get("schemas")?
+        .get(schema_name)?
+        .get("example")
+}
+
+fn compare_shapes(
+    operation: &str,
+    path: &str,
+    documented: &Value,
+    recorded: &Value,
+    drifts: &mut Vec<ExampleDrift>,
+) {
+    match (documented, recorded) {
+        (Value::Object(documented), Value::Object(recorded)) => {
+            for (key, recorded_child) in recorded {
+                let child_path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                match documented.get(key) {
+                    Some(documented_child) => compare_shapes(
+                        operation,
+                        &child_path,
+                        documented_child,
+                        recorded_child,
+                        drifts,
+                    ),
+                    None => drifts.push(ExampleDrift::MissingField {
+                        operation: operation.to_string(),
+                        path: child_path,
+                    }),
+                }
+            }
+        }
+        (Value::Array(documented), Value::Array(recorded)) => {
+            if let (Some(documented), Some(recorded)) = (documented.first(), recorded.first()) {
+                compare_shapes(
+                    operation,
+                    &format!("{path}[]"),
+                    documented,
+                    recorded,
+                    drifts,
+                );
+            }
+        }
+        _ if json_type(documented) != json_type(recorded) => {
+            drifts.push(ExampleDrift::TypeMismatch {
+                operation: operation.to_string(),
+                path: path.to_string(),
+                documented: json_type(documented),
+                recorded: json_type(recorded),
+            })
+        }
+        _ => {}
+    }
+}
+
+fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Request;
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{
+        routes::{build_router, openapi_spec},
+        test_support::{state_from, test_settings, test_state},
+    };
+
+    fn recorder_config() -> ExamplesConfig {
+        ExamplesConfig {
+            samples_per_day: 2,
+            mask_fields: vec!["task_id".to_string(), "data.dynamic_id".to_string()],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_records_first_samples_per_operation() {
+        let mut settings = test_settings();
+        settings.examples = Some(recorder_config());
+        let state = state_from(&settings);
+        let recorder = state.example_recorder.clone().unwrap();
+        let router = build_router(state);
+
+        for _ in 0..3 {
+            let response = router
+                .clone()
+                .oneshot(Request::get("/api/_ping").body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            // The response body must survive the round trip through the recorder
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(&body[..], br#"{"ok":true}"#);
+        }
+
+        let examples = recorder.examples();
+        assert_eq!(examples.len(), 2);
+        assert_eq!(examples[0].operation, "get /api/_ping");
+        assert_eq!(examples[0].body, json!({"ok": true}));
+    }
+
+    #[test]
+    fn test_scrub_masks_keys_and_paths_preserving_type() {
+        let recorder = ExampleRecorder::new(recorder_config());
+        recorder.record(
+            "post /api/bilibili/createDynamic".to_string(),
+            200,
+            json!({
+                "code": 0,
+                "task_id": "123456",
+                "data": {"dynamic_id": 987654321, "doc_id": 42},
+                "items": [{"task_id": "abc"}]
+            }),
+        );
+
+        let body = &recorder.examples()[0].body;
+        assert_eq!(
+            *body,
+            json!({
+                "code": 0,
+                "task_id": "<masked>",
+                "data": {"dynamic_id": 0, "doc_id": 42},
+                "items": [{"task_id": "<masked>"}]
+            })
+        );
+    }
+
+    #[test]
+    fn test_compare_against_real_spec() {
+        let spec = serde_json::to_value(openapi_spec(&test_state())).unwrap();
+        let recorded = RecordedExample {
+            operation: "get /api/_ping".to_string(),
+            status: 200,
+            recorded_at: String::new(),
+            body: json!({"ok": true}),
+        };
+        assert!(compare_with_spec(&spec, &[recorded]).is_empty());
+    }
+
+    #[test]
+    fn test_compare_flags_drifted_example() {
+        let spec = json!({
+            "paths": {
+                "/api/bilibili/createDynamic": {
+                    "post": {"responses": {"200": {"content": {"application/json": {
+                        "schema": {"$ref": "#/components/schemas/DynamicResponse"}
+                    }}}}}
+                },
+                "/api/_ping": {
+                    "get": {"responses": {"200": {"content": {"application/json": {
+                        "example": {"ok": true}
+                    }}}}}
+                }
+            },
+            "components": {"schemas": {"DynamicResponse": {
+                "example": {"code": "0", "data": {"doc_id": 1}}
+            }}}
+        });
+        let recorded = |operation: &str, body: Value| RecordedExample {
+            operation: operation.to_string(),
+            status: 200,
+            recorded_at: String::new(),
+            body,
+        };
+
+        let drifts = compare_with_spec(
+            &spec,
+            &[
+                recorded("get /api/_ping", json!({"ok": true})),
+                recorded(
+                    "post /api/bilibili/createDynamic",
+                    json!({"code": 0, "data": {"doc_id": 1, "dynamic_id": 2}}),
+                ),
+                recorded("get /api/_health", json!({"ok": true})),
+            ],
+        );
+
+        let operation = "post /api/bilibili/createDynamic".to_string();
+        assert_eq!(
+            drifts,
+            vec![
+                ExampleDrift::TypeMismatch {
+                    operation: operation.clone(),
+                    path: "code".to_string(),
+                    documented: "string",
+                    recorded: "number",
+                },
+                ExampleDrift::MissingField {
+                    operation,
+                    path: "data.dynamic_id".to_string(),
+                },
+                ExampleDrift::MissingExample {
+                    operation: "get /api/_health".to_string(),
+                    status: 200,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,202 @@
+//! Outbound HTTP clients and their proxy settings
+
+use std::time::Duration;
+
+use tracing::warn;
+
+use crate::{
+    config::{HttpClientConfig, ProxySettings},
+    error::AppError,
+};
+
+/// Build a client with the shared timeouts that reaches its upstreams as `proxy` says
+///
+/// Proxy URLs are validated at config load.
+pub fn build_http_client(config: &HttpClientConfig, proxy: &ProxySettings) -> reqwest::Client {
+    let mut builder = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+        .timeout(Duration::from_secs(config.request_timeout_secs));
+    if let Some(url) = &proxy.proxy_url {
+        let no_proxy = reqwest::NoProxy::from_string(&proxy.no_proxy.join(","));
+        builder = builder.proxy(
+            reqwest::Proxy::all(url)
+                .expect("proxy URLs are validated at config load")
+                .no_proxy(no_proxy),
+        );
+    }
+    if proxy.accept_invalid_certs {
+        warn!(
+            proxy = proxy.proxy_host(),
+            "TLS certificate verification is DISABLED for outbound calls, \
+             only use accept_invalid_certs behind a trusted intercepting proxy"
+        );
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+    builder.build().expect("HTTP client should build")
+}
+
+/// Error for an upstream request that never got an answer
+///
+/// A proxy refusing the credentials is a network error naming the proxy, never the
+/// credentials; anything else converts as usual.
+pub fn request_error(err: reqwest::Error, what: &str, proxy: Option<&str>) -> AppError {
+    let err = anyhow::Error::new(err).context(what.to_string());
+    match proxy {
+        Some(proxy) if proxy_auth_failed(&err) => AppError::NetworkError(err.context(format!(
+            "Proxy {proxy} refused the credentials in http_client.proxy_url"
+        ))),
+        _ => AppError::from(err),
+    }
+}
+
+/// Whether the proxy answered the tunnel request with `407 Proxy Authentication Required`
+fn proxy_auth_failed(err: &anyhow::Error) -> bool {
+    err.chain()
+        .any(|cause| cause.to_string().ends_with("proxy authorization required"))
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{header, method, path},
+    };
+
+    use super::*;
+
+    fn proxied(proxy_url: &str, no_proxy: &[&str]) -> ProxySettings {
+        ProxySettings {
+            proxy_url: Some(proxy_url.to_string()),
+            no_proxy: no_proxy.iter().map(|host| host.to_string()).collect(),
+            accept_invalid_certs: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_requests_go_through_the_proxy_with_its_credentials() {
+        // For plain-HTTP targets the proxy sees the request itself, so wiremock can stand in
+        let proxy = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/x/web-interface/nav"))
+            .and(header("proxy-authorization", "Basic amFudXM6czNjcmV0"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&proxy)
+            .await;
+        let proxy_url = proxy.uri().replace("http://", "http://janus:s3cret@");
+
+        let client = build_http_client(&HttpClientConfig::default(), &proxied(&proxy_url, &[]));
+        let response = client
+            .get("http://api.bilibili.invalid/x/web-interface/nav")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        proxy.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_no_proxy_hosts_are_reached_directly() {
+        let upstream = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&upstream)
+            .await;
+
+        // Nothing listens on port 9, so a proxied request would fail
+        let client = build_http_client(
+            &HttpClientConfig::default(),
+            &proxied("http://127.0.0.1:9", &["127.0.0.1"]),
+        );
+        let response = client.get(upstream.uri()).send().await.unwrap();
+        assert_eq!(response.status(), 200);
+        upstream.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_accept_invalid_certs_trusts_self_signed_upstreams() {
+        use crate::{
+            config::TlsConfig, routes::build_router, test_support::test_state, tls::ServerTls,
+        };
+
+        let fixture =
+            |name: &str| format!("{}/tests/fixtures/tls/{name}", env!("CARGO_MANIFEST_DIR"));
+        let tls = ServerTls::load(&TlsConfig {
+            cert_path: fixture("cert.pem"),
+            key_path: fixture("key.pem"),
+        })
+        .await
+        .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!(
+            "https://localhost:{}/api/_ping",
+            listener.local_addr().unwrap().port()
+        );
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(async move {
+            tls.serve(listener, build_router(test_state()), async {
+                let _ = stopped.await;
+            })
+            .await
+        });
+
+        let strict = build_http_client(&HttpClientConfig::default(), &ProxySettings::default());
+        assert!(strict.get(&url).send().await.is_err());
+        let lenient = build_http_client(
+            &HttpClientConfig::default(),
+            &ProxySettings {
+                accept_invalid_certs: true,
+                ..ProxySettings::default()
+            },
+        );
+        assert_eq!(lenient.get(&url).send().await.unwrap().status(), 200);
+
+        stop.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_proxy_auth_failure_names_the_proxy_not_the_credentials() {
+        // HTTPS targets are tunneled with CONNECT, which this proxy turns down
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0; 1024];
+            let _ = socket.read(&mut request).await.unwrap();
+            socket
+                .write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n")
+                .await
+                .unwrap();
+        });
+        let proxy = proxied(&format!("http://janus:s3cret@{address}"), &[]);
+
+        let client = build_http_client(&HttpClientConfig::default(), &proxy);
+        let err = client
+            .get("https://api.bilibili.com/x/web-interface/nav")
+            .send()
+            .await
+            .unwrap_err();
+        let err = request_error(err, "Nav request failed", proxy.proxy_host().as_deref());
+
+        assert!(matches!(err, AppError::NetworkError(_)));
+        let message = format!("{:#}", anyhow::Error::new(err));
+        assert!(message.contains(&address.to_string()), "{message}");
+        assert!(!message.contains("s3cret"), "{message}");
+    }
+
+    #[tokio::test]
+    async fn test_other_failures_convert_as_usual() {
+        let client = build_http_client(&HttpClientConfig::default(), &ProxySettings::default());
+        let err = client.get("http://127.0.0.1:9").send().await.unwrap_err();
+        assert!(matches!(
+            request_error(err, "Nav request failed", Some("proxy.corp:3128")),
+            AppError::InternalError(_)
+        ));
+    }
+}
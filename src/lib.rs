@@ -1,10 +1,71 @@
+#[cfg(feature = "server")]
 pub mod aliyun;
+pub mod api;
+#[cfg(feature = "server")]
 pub mod app;
+#[cfg(feature = "server")]
 pub mod auth;
+#[cfg(feature = "server")]
+pub mod bilibili;
+#[cfg(feature = "client")]
+pub mod client;
+#[cfg(feature = "server")]
 mod config;
+#[cfg(feature = "server")]
+mod config_check;
+#[cfg(feature = "server")]
+mod debug_capture;
+#[cfg(feature = "server")]
+mod directory_refresh;
+#[cfg(feature = "server")]
 pub mod error;
+#[cfg(feature = "server")]
+mod event_auth;
+#[cfg(feature = "server")]
+mod event_dedup;
+#[cfg(feature = "server")]
+mod event_dlq;
+#[cfg(feature = "server")]
+mod event_filter;
+#[cfg(feature = "server")]
+mod event_ids;
+#[cfg(feature = "server")]
+mod examples;
+#[cfg(feature = "server")]
+mod http_client;
+#[cfg(feature = "server")]
+mod load_shed;
+#[cfg(feature = "server")]
+mod metrics;
+#[cfg(feature = "server")]
 mod middleware;
+#[cfg(feature = "server")]
+mod rate_limit;
+#[cfg(feature = "server")]
+mod read_only;
+#[cfg(feature = "server")]
+mod refresh_jobs;
+#[cfg(feature = "server")]
+mod refresh_log;
+#[cfg(feature = "server")]
+mod reload;
+#[cfg(feature = "server")]
 mod routes;
+#[cfg(feature = "server")]
+mod scheduled_dynamics;
+#[cfg(feature = "server")]
 mod shutdown;
+#[cfg(feature = "server")]
 mod state;
+#[cfg(all(test, feature = "server"))]
+mod test_support;
+#[cfg(feature = "server")]
+mod tls;
+#[cfg(feature = "server")]
 mod tracing;
+#[cfg(feature = "server")]
+mod ttl_cache;
+#[cfg(feature = "server")]
+mod url_dedup;
+#[cfg(feature = "server")]
+mod webhooks;
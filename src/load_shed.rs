@@ -0,0 +1,221 @@
+//! Caps on requests in flight, shedding the excess instead of queuing it
+//!
+//! Queued requests would keep holding upstream connections and memory during a purge storm,
+//! so a request over a cap is answered `503` with `Retry-After` at once.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::warn;
+
+use crate::{
+    config::ConcurrencyLimitConfig,
+    error::{AppError, AppResult},
+};
+
+/// Probes must keep answering while everything else is shed
+const EXEMPT_PATHS: &[&str] = &["/api/_ping", "/api/_health"];
+
+/// One semaphore per limited route group
+#[derive(Debug)]
+pub struct ConcurrencyLimits {
+    global: Option<Arc<Semaphore>>,
+    aliyun: Option<Arc<Semaphore>>,
+    bilibili_create: Option<Arc<Semaphore>>,
+    retry_after_secs: u64,
+}
+
+impl ConcurrencyLimits {
+    pub fn new(config: &ConcurrencyLimitConfig) -> Self {
+        let semaphore = |limit: Option<std::num::NonZeroUsize>| {
+            limit.map(|limit| Arc::new(Semaphore::new(limit.get())))
+        };
+        Self {
+            global: semaphore(config.global),
+            aliyun: semaphore(config.aliyun),
+            bilibili_create: semaphore(config.bilibili_create),
+            retry_after_secs: config.retry_after_secs,
+        }
+    }
+
+    /// Take a slot of `semaphore`, or fail without waiting when all are taken
+    fn acquire(
+        &self,
+        semaphore: Option<&Arc<Semaphore>>,
+        group: &'static str,
+        path: &str,
+    ) -> AppResult<Option<OwnedSemaphorePermit>> {
+        let Some(semaphore) = semaphore else {
+            return Ok(None);
+        };
+        match semaphore.clone().try_acquire_owned() {
+            Ok(permit) => Ok(Some(permit)),
+            Err(_) => {
+                warn!(group, path, "Concurrency limit reached, shedding request");
+                Err(AppError::Overloaded(self.retry_after_secs))
+            }
+        }
+    }
+}
+
+/// Middleware holding a slot of the global cap and of the route's group until it answers
+///
+/// Streamed bodies are not counted once their headers are sent.
+pub async fn shed_load(
+    State(limits): State<Arc<ConcurrencyLimits>>,
+    request: Request,
+    next: Next,
+) -> AppResult<Response> {
+    let path = request.uri().path();
+    if EXEMPT_PATHS.contains(&path) {
+        return Ok(next.run(request).await);
+    }
+
+    let group = if path.starts_with("/api/aliyun/") {
+        Some((limits.aliyun.as_ref(), "aliyun"))
+    } else if path == "/api/bilibili/createDynamic" {
+        Some((limits.bilibili_create.as_ref(), "bilibili_create"))
+    } else {
+        None
+    };
+    let _global = limits.acquire(limits.global.as_ref(), "global", path)?;
+    let _group = match group {
+        Some((semaphore, name)) => limits.acquire(semaphore, name, path)?,
+        None => None,
+    };
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{num::NonZeroUsize, time::Duration};
+
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode, header},
+    };
+    use tokio::time::Instant;
+    use tower::ServiceExt;
+    use wiremock::{Mock, ResponseTemplate, matchers::method};
+
+    use crate::{
+        config::ConcurrencyLimitConfig,
+        test_support::{body_json, test_app_with, test_token},
+    };
+
+    const TASK: &str = r#"{"RequestId":"E0C2EF95","TotalCount":1,"Tasks":[{"TaskId":"17772470467","ObjectPath":"https://static.prts.wiki/a.png","ObjectType":"file","Status":"Complete","Process":"100%","CreationTime":"2026-10-16T02:00:00Z"}]}"#;
+
+    fn describe_task() -> Request<Body> {
+        Request::get("/api/aliyun/refreshTask/17772470467")
+            .header("Authorization", format!("Bearer {}", test_token()))
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_requests_over_the_limit_are_shed_at_once() {
+        let app = test_app_with(|settings| {
+            settings.server.concurrency = Some(ConcurrencyLimitConfig {
+                global: NonZeroUsize::new(10),
+                aliyun: NonZeroUsize::new(2),
+                bilibili_create: None,
+                retry_after_secs: 3,
+            });
+        })
+        .await;
+        Mock::given(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(TASK)
+                    .set_delay(Duration::from_millis(800)),
+            )
+            .mount(&app.aliyun)
+            .await;
+
+        // Two slow calls take both Aliyun slots
+        let in_flight = (0..2)
+            .map(|_| tokio::spawn(app.router.clone().oneshot(describe_task())))
+            .collect::<Vec<_>>();
+        while app.aliyun.received_requests().await.unwrap().len() < 2 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        let started = Instant::now();
+        let response = app.router.clone().oneshot(describe_task()).await.unwrap();
+        assert!(started.elapsed() < Duration::from_millis(200));
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "3");
+        assert_eq!(
+            body_json(response).await,
+            serde_json::json!({"code": 1, "msg": "Server is overloaded, retry in 3 seconds"})
+        );
+
+        // Probes and other groups are unaffected
+        let health = app
+            .router
+            .clone()
+            .oneshot(Request::get("/api/_health").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(health.status(), StatusCode::OK);
+
+        for call in in_flight {
+            assert_eq!(call.await.unwrap().unwrap().status(), StatusCode::OK);
+        }
+        // Finished calls give their slots back
+        let response = app.router.clone().oneshot(describe_task()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_global_limit_covers_every_route_but_health() {
+        let app = test_app_with(|settings| {
+            settings.server.concurrency = Some(ConcurrencyLimitConfig {
+                global: NonZeroUsize::new(1),
+                aliyun: None,
+                bilibili_create: None,
+                retry_after_secs: 1,
+            });
+        })
+        .await;
+        Mock::given(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(TASK)
+                    .set_delay(Duration::from_millis(500)),
+            )
+            .mount(&app.aliyun)
+            .await;
+
+        let slow = tokio::spawn(app.router.clone().oneshot(describe_task()));
+        while app.aliyun.received_requests().await.unwrap().is_empty() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        let jobs = app
+            .router
+            .clone()
+            .oneshot(
+                Request::get("/api/aliyun/jobs/1")
+                    .header("Authorization", format!("Bearer {}", test_token()))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(jobs.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let ping = app
+            .router
+            .clone()
+            .oneshot(Request::get("/api/_ping").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(ping.status(), StatusCode::OK);
+        assert_eq!(slow.await.unwrap().unwrap().status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::Response,
+};
+use metrics::{counter, gauge, histogram};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+
+use crate::config::MetricsConfig;
+
+pub const HTTP_REQUEST_DURATION: &str = "janus_http_request_duration_seconds";
+pub const BILIBILI_UPLOADS: &str = "janus_bilibili_uploads_total";
+pub const BILIBILI_UPLOAD_DURATION: &str = "janus_bilibili_upload_duration_seconds";
+pub const ALIYUN_API_CALLS: &str = "janus_aliyun_api_calls_total";
+pub const ALIYUN_API_DURATION: &str = "janus_aliyun_api_duration_seconds";
+pub const OSS_EVENTS_SKIPPED: &str = "janus_oss_events_skipped_total";
+pub const ALIYUN_REFRESH_BUDGET_USED: &str = "janus_aliyun_refresh_budget_used_urls";
+pub const ALIYUN_REFRESH_BUDGET_LIMIT: &str = "janus_aliyun_refresh_budget_limit_urls";
+
+/// Histogram buckets (seconds) shared by all `*_duration_seconds` metrics
+const DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+/// The Prometheus recorder can only be installed once per process
+static PROMETHEUS_HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+
+/// Prometheus exporter state shared through `AppState`
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    handle: PrometheusHandle,
+    token: Option<String>,
+}
+
+impl Metrics {
+    /// Install the global Prometheus recorder (once) and return the exporter state
+    pub fn init(config: &MetricsConfig) -> Self {
+        let handle = PROMETHEUS_HANDLE
+            .get_or_init(|| {
+                PrometheusBuilder::new()
+                    .set_buckets_for_metric(
+                        Matcher::Suffix("duration_seconds".to_string()),
+                        DURATION_BUCKETS,
+                    )
+                    .expect("bucket list is not empty")
+                    .install_recorder()
+                    .expect("failed to install Prometheus recorder")
+            })
+            .clone();
+
+        Self {
+            handle,
+            token: config.token.clone(),
+        }
+    }
+
+    /// Bearer token required to scrape the endpoint, if configured
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    /// Render the current snapshot in Prometheus text format
+    pub fn render(&self) -> String {
+        self.handle.render()
+    }
+}
+
+/// Middleware recording request latency labeled by matched route and status
+pub async fn track_http_metrics(request: Request, next: Next) -> Response {
+    let started = Instant::now();
+    // Use the route template rather than the raw path to keep label cardinality bounded
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", MatchedPath::as_str)
+        .to_string();
+    let method = request.method().to_string();
+
+    let response = next.run(request).await;
+
+    histogram!(
+        HTTP_REQUEST_DURATION,
+        "method" => method,
+        "route" => route,
+        "status" => response.status().as_u16().to_string(),
+    )
+    .record(started.elapsed().as_secs_f64());
+
+    response
+}
+
+/// Record the outcome of a single Bilibili image upload
+pub fn record_bilibili_upload(success: bool, elapsed: Duration) {
+    let result = if success { "success" } else { "failure" };
+    counter!(BILIBILI_UPLOADS, "result" => result).increment(1);
+    histogram!(BILIBILI_UPLOAD_DURATION, "result" => result).record(elapsed.as_secs_f64());
+}
+
+/// Record the outcome of a single Aliyun OpenAPI call
+///
+/// `result` is `ok`, the Aliyun error `Code`, or `network_error`.
+pub fn record_aliyun_call(action: &str, result: &str, elapsed: Duration) {
+    counter!(ALIYUN_API_CALLS, "action" => action.to_string(), "result" => result.to_string())
+        .increment(1);
+    histogram!(ALIYUN_API_DURATION, "action" => action.to_string()).record(elapsed.as_secs_f64());
+}
+
+/// Record an OSS event acknowledged without a refresh
+///
+/// `reason` is `event_kind`, `ignored_key`, `ignored_removal`, `prefix_rule` or `unknown_bucket`.
+pub fn record_oss_event_skipped(reason: &'static str) {
+    counter!(OSS_EVENTS_SKIPPED, "reason" => reason).increment(1);
+}
+
+/// Record the consumption of one `aliyun.refresh_budget` window
+pub fn record_refresh_budget(window: &'static str, used: u32, limit: u32) {
+    gauge!(ALIYUN_REFRESH_BUDGET_USED, "window" => window).set(used);
+    gauge!(ALIYUN_REFRESH_BUDGET_LIMIT, "window" => window).set(limit);
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{routes::build_router, test_support::test_state};
+
+    #[tokio::test]
+    async fn test_metrics_endpoint_exposes_recorded_metrics() {
+        let mut state = test_state();
+        state.metrics = Some(Metrics::init(&MetricsConfig {
+            token: Some("scrape-token".to_string()),
+        }));
+        let router = build_router(state);
+
+        for _ in 0..3 {
+            let response = router
+                .clone()
+                .oneshot(Request::get("/api/_ping").body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 200);
+        }
+        record_bilibili_upload(false, Duration::from_millis(120));
+        record_aliyun_call(
+            "RefreshObjectCaches",
+            "Throttling.User",
+            Duration::from_millis(80),
+        );
+        record_oss_event_skipped("ignored_key");
+
+        let unauthorized = router
+            .clone()
+            .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(unauthorized.status(), 401);
+
+        let response = router
+            .oneshot(
+                Request::get("/metrics")
+                    .header("Authorization", "Bearer scrape-token")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+
+        assert!(body.contains("janus_http_request_duration_seconds_bucket"));
+        assert!(body.contains(r#"route="/api/_ping""#));
+        assert!(body.contains(r#"janus_bilibili_uploads_total{result="failure"}"#));
+        assert!(body.contains(
+            r#"janus_aliyun_api_calls_total{action="RefreshObjectCaches",result="Throttling.User"}"#
+        ));
+        assert!(body.contains(r#"janus_oss_events_skipped_total{reason="ignored_key"}"#));
+    }
+}
@@ -1,9 +1,318 @@
-use axum::Router;
-use std::time::Duration;
-use tower_http::{compression::CompressionLayer, timeout::RequestBodyTimeoutLayer};
+use axum::{
+    Router,
+    body::Body,
+    extract::MatchedPath,
+    http::{HeaderName, HeaderValue, Method, Request, Response, header},
+    middleware,
+};
+use std::{sync::Arc, time::Duration};
+use tower_http::{
+    compression::CompressionLayer,
+    cors::{AllowOrigin, Any, CorsLayer},
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    sensitive_headers::SetSensitiveRequestHeadersLayer,
+    timeout::RequestBodyTimeoutLayer,
+    trace::TraceLayer,
+};
+use tracing::{Span, field};
 
-pub fn apply_axum_middleware(router: Router) -> Router {
-    router
-        .layer(RequestBodyTimeoutLayer::new(Duration::from_secs(10)))
+use crate::{
+    config::{CorsConfig, ServerConfig},
+    load_shed::{ConcurrencyLimits, shed_load},
+    metrics::track_http_metrics,
+};
+
+pub fn apply_axum_middleware(router: Router, config: &ServerConfig) -> Router {
+    // Inside the access log and metrics, so shed requests still show up there
+    let router = match &config.concurrency {
+        Some(limits) => router.layer(middleware::from_fn_with_state(
+            Arc::new(ConcurrencyLimits::new(limits)),
+            shed_load,
+        )),
+        None => router,
+    };
+    let router = router
+        .layer(RequestBodyTimeoutLayer::new(Duration::from_secs(
+            config.request_body_timeout_secs,
+        )))
         .layer(CompressionLayer::new())
+        .layer(middleware::from_fn(track_http_metrics))
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(access_log_span)
+                .on_request(())
+                .on_response(log_access)
+                .on_failure(()),
+        )
+        // Outside the access log so every logged request already carries its id
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+        // Credentials never show up in logged headers
+        .layer(SetSensitiveRequestHeadersLayer::new([
+            header::AUTHORIZATION,
+            HeaderName::from_static("x-eventbridge-signature-token"),
+            HeaderName::from_static(crate::event_auth::SIGNATURE_HEADER),
+        ]));
+
+    // CORS is outermost so preflight requests are answered before any auth layer runs
+    match &config.cors {
+        Some(cors) => router.layer(cors_layer(cors)),
+        None => router,
+    }
+}
+
+/// Span of one request; `subject` is filled in once a JWT has been verified, and
+/// `aliyun_request_id` with the `RequestId` of each Aliyun call made for it
+fn access_log_span(request: &Request<Body>) -> Span {
+    // The route template keeps object keys and ids out of the logs
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", MatchedPath::as_str);
+    let request_id = request
+        .headers()
+        .get("x-request-id")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    tracing::info_span!(
+        "request",
+        method = %request.method(),
+        route,
+        request_id,
+        subject = field::Empty,
+        aliyun_request_id = field::Empty,
+    )
+}
+
+/// Emit the access log line, louder for client and server errors
+fn log_access(response: &Response<Body>, latency: Duration, _span: &Span) {
+    let status = response.status().as_u16();
+    let latency_ms = latency.as_millis() as u64;
+    if response.status().is_server_error() {
+        tracing::error!(status, latency_ms, "request completed");
+    } else if response.status().is_client_error() {
+        tracing::warn!(status, latency_ms, "request completed");
+    } else {
+        tracing::info!(status, latency_ms, "request completed");
+    }
+}
+
+/// Build the CORS layer; values were already validated when the config was loaded
+fn cors_layer(config: &CorsConfig) -> CorsLayer {
+    let origins = if config.allows_any_origin() {
+        AllowOrigin::from(Any)
+    } else {
+        AllowOrigin::list(
+            config
+                .allowed_origins
+                .iter()
+                .filter_map(|origin| origin.parse::<HeaderValue>().ok()),
+        )
+    };
+
+    let mut layer = CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods(
+            config
+                .allowed_methods
+                .iter()
+                .filter_map(|method| method.parse::<Method>().ok())
+                .collect::<Vec<_>>(),
+        )
+        .allow_headers(
+            config
+                .allowed_headers
+                .iter()
+                .filter_map(|header| header.parse::<HeaderName>().ok())
+                .collect::<Vec<_>>(),
+        )
+        .allow_credentials(config.allow_credentials);
+    if let Some(max_age) = config.max_age_secs {
+        layer = layer.max_age(Duration::from_secs(max_age));
+    }
+    layer
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{
+        config::LogFormat,
+        routes::build_router,
+        test_support::{state_from, test_app, test_settings, test_token},
+    };
+
+    fn cors_router() -> Router {
+        let mut settings = test_settings();
+        settings.server.cors = Some(CorsConfig {
+            allowed_origins: vec!["https://prts.wiki".to_string()],
+            allowed_methods: vec!["GET".to_string(), "POST".to_string()],
+            allowed_headers: vec!["authorization".to_string(), "content-type".to_string()],
+            allow_credentials: true,
+            max_age_secs: Some(600),
+        });
+        build_router(state_from(&settings))
+    }
+
+    #[tokio::test]
+    async fn test_cors_preflight_bypasses_jwt() {
+        let response = cors_router()
+            .oneshot(
+                Request::options("/api/bilibili/createDynamic")
+                    .header("Origin", "https://prts.wiki")
+                    .header("Access-Control-Request-Method", "POST")
+                    .header("Access-Control-Request-Headers", "authorization")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        let headers = response.headers();
+        assert_eq!(headers["access-control-allow-origin"], "https://prts.wiki");
+        assert_eq!(headers["access-control-allow-credentials"], "true");
+        assert_eq!(headers["access-control-max-age"], "600");
+        assert!(
+            headers["access-control-allow-methods"]
+                .to_str()
+                .unwrap()
+                .contains("POST")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cors_headers_on_cross_origin_post() {
+        let router = cors_router();
+        let request = |origin: &str| {
+            Request::post("/api/bilibili/createDynamic")
+                .header("Origin", origin)
+                .header("Authorization", format!("Bearer {}", test_token()))
+                .header("Content-Type", "multipart/form-data; boundary=X")
+                .body(Body::from("--X--\r\n"))
+                .unwrap()
+        };
+
+        let response = router
+            .clone()
+            .oneshot(request("https://prts.wiki"))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers()["access-control-allow-origin"],
+            "https://prts.wiki"
+        );
+
+        let response = router
+            .oneshot(request("https://evil.example"))
+            .await
+            .unwrap();
+        assert!(
+            !response
+                .headers()
+                .contains_key("access-control-allow-origin")
+        );
+    }
+
+    /// Log sink shared with the subscriber under test
+    #[derive(Clone, Default)]
+    struct Captured(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_access_log_records_rejected_request() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let layer = crate::tracing::init_layer(move || writer.clone(), &LogFormat::Json, false);
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(layer));
+
+        let response = test_app()
+            .await
+            .router
+            .oneshot(
+                Request::get("/api/admin/readOnly")
+                    .header("Authorization", "Bearer not-a-jwt")
+                    .header("x-request-id", "req-42")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 401);
+        assert_eq!(response.headers()["x-request-id"], "req-42");
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        assert!(!output.contains("not-a-jwt"));
+        let access = output
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .find(|line| line["fields"]["message"] == "request completed")
+            .expect("one access log line per request");
+        assert_eq!(access["level"], "WARN");
+        assert_eq!(access["fields"]["status"], 401);
+        assert!(access["fields"]["latency_ms"].is_u64());
+        assert_eq!(access["span"]["method"], "GET");
+        assert_eq!(access["span"]["route"], "/api/admin/readOnly");
+        assert_eq!(access["span"]["request_id"], "req-42");
+    }
+
+    #[tokio::test]
+    async fn test_access_log_records_aliyun_request_id() {
+        use tracing_subscriber::layer::SubscriberExt;
+        use wiremock::{Mock, ResponseTemplate, matchers::method};
+
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let layer = crate::tracing::init_layer(move || writer.clone(), &LogFormat::Json, false);
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(layer));
+
+        let app = test_app().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(400).set_body_string(
+                r#"{"RequestId":"A3","Code":"QuotaExceeded.Refresh","Message":"The refresh quota is used up."}"#,
+            ))
+            .mount(&app.aliyun)
+            .await;
+        let response = app
+            .router
+            .oneshot(
+                Request::post("/api/aliyun/refreshObjectCaches")
+                    .header("Authorization", format!("Bearer {}", test_token()))
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        r#"{"object_path":"https://static.prts.wiki/a.png"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 429);
+        assert_eq!(
+            crate::test_support::body_json(response).await["aliyun_request_id"],
+            "A3"
+        );
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let access = output
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .find(|line| line["fields"]["message"] == "request completed")
+            .expect("one access log line per request");
+        assert_eq!(access["span"]["aliyun_request_id"], "A3");
+    }
 }
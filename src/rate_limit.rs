@@ -0,0 +1,236 @@
+use std::{net::SocketAddr, num::NonZeroU32, sync::Arc};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use governor::{
+    DefaultKeyedRateLimiter, Quota, RateLimiter,
+    clock::{Clock, DefaultClock},
+};
+
+use crate::{
+    auth::Claims,
+    config::RateLimitConfig,
+    error::{AppError, AppResult},
+    state::AppState,
+};
+
+type KeyedLimiter = Arc<DefaultKeyedRateLimiter<String>>;
+
+/// Token buckets for the rate-limited route groups
+#[derive(Debug, Clone, Default)]
+pub struct RateLimiters {
+    aliyun_refresh: Option<KeyedLimiter>,
+    bilibili_create: Option<KeyedLimiter>,
+}
+
+fn per_minute(limit: Option<NonZeroU32>) -> Option<KeyedLimiter> {
+    limit.map(|limit| Arc::new(RateLimiter::keyed(Quota::per_minute(limit))))
+}
+
+impl RateLimiters {
+    pub fn new(config: Option<&RateLimitConfig>) -> Self {
+        let Some(config) = config else {
+            return Self::default();
+        };
+        Self {
+            aliyun_refresh: per_minute(config.aliyun_refresh_per_minute),
+            bilibili_create: per_minute(config.bilibili_create_per_minute),
+        }
+    }
+}
+
+/// Take one token for `key`, or fail with the seconds until the next one
+fn check(limiter: Option<&KeyedLimiter>, key: String) -> AppResult<()> {
+    let Some(limiter) = limiter else {
+        return Ok(());
+    };
+    limiter.check_key(&key).map_err(|not_until| {
+        let wait = not_until.wait_time_from(DefaultClock::default().now());
+        AppError::RateLimited(wait.as_secs_f64().ceil().max(1.0) as u64)
+    })
+}
+
+/// Caller identity: the JWT subject when authenticated, otherwise the client IP
+fn caller_key(request: &Request) -> String {
+    if let Some(claims) = request.extensions().get::<Claims>() {
+        return format!("sub:{}", claims.sub);
+    }
+    match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+        Some(ConnectInfo(addr)) => format!("ip:{}", addr.ip()),
+        None => "ip:unknown".to_string(),
+    }
+}
+
+/// Middleware limiting OSS event deliveries and the refresh routes, which each trigger a CDN
+/// refresh
+pub async fn limit_aliyun_refresh(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> AppResult<Response> {
+    check(
+        state.rate_limiters.load().aliyun_refresh.as_ref(),
+        caller_key(&request),
+    )?;
+    Ok(next.run(request).await)
+}
+
+/// Middleware limiting createDynamic; must run after JWT auth so the subject is known
+pub async fn limit_bilibili_create(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> AppResult<Response> {
+    check(
+        state.rate_limiters.load().bilibili_create.as_ref(),
+        caller_key(&request),
+    )?;
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt;
+
+    use crate::{
+        auth::generate_token,
+        routes::build_router,
+        test_support::{TEST_PRIVATE_KEY, body_json, state_from, test_settings},
+    };
+
+    fn create_dynamic_request(subject: &str) -> Request<Body> {
+        let token = generate_token(subject.to_string(), TEST_PRIVATE_KEY).unwrap();
+        Request::post("/api/bilibili/createDynamic")
+            .header("Authorization", format!("Bearer {token}"))
+            .header("Content-Type", "multipart/form-data; boundary=X")
+            .body(Body::from("--X--\r\n"))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_create_dynamic_is_limited_per_subject() {
+        let mut settings = test_settings();
+        settings.server.rate_limit = Some(crate::config::RateLimitConfig {
+            bilibili_create_per_minute: Some(3.try_into().unwrap()),
+            ..Default::default()
+        });
+        let router = build_router(state_from(&settings));
+
+        // The empty form is rejected by the handler, but still counts against the limit
+        for _ in 0..3 {
+            let response = router
+                .clone()
+                .oneshot(create_dynamic_request("pipeline"))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 400);
+        }
+
+        let response = router
+            .clone()
+            .oneshot(create_dynamic_request("pipeline"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 429);
+        let retry_after: u64 = response.headers()["Retry-After"]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((1..=60).contains(&retry_after));
+        let body = body_json(response).await;
+        assert_eq!(body["code"], 1);
+        assert!(
+            body["msg"]
+                .as_str()
+                .unwrap()
+                .starts_with("Rate limit exceeded")
+        );
+
+        // Other subjects have their own bucket
+        let response = router
+            .oneshot(create_dynamic_request("someone-else"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn test_oss_events_are_limited_per_client() {
+        let mut settings = test_settings();
+        settings.server.rate_limit = Some(crate::config::RateLimitConfig {
+            aliyun_refresh_per_minute: Some(2.try_into().unwrap()),
+            ..Default::default()
+        });
+        let router = build_router(state_from(&settings));
+        let event = || {
+            Request::post("/api/aliyun/events")
+                .header("Content-Type", "application/json")
+                .body(Body::from("{}"))
+                .unwrap()
+        };
+
+        // Unauthenticated deliveries are rejected by the handler but still counted
+        for _ in 0..2 {
+            let response = router.clone().oneshot(event()).await.unwrap();
+            assert_eq!(response.status(), 401);
+        }
+        let response = router.oneshot(event()).await.unwrap();
+        assert_eq!(response.status(), 429);
+    }
+
+    #[tokio::test]
+    async fn test_health_probes_are_never_limited() {
+        let mut settings = test_settings();
+        settings.server.rate_limit = Some(crate::config::RateLimitConfig {
+            aliyun_refresh_per_minute: Some(1.try_into().unwrap()),
+            ..Default::default()
+        });
+        let router = build_router(state_from(&settings));
+
+        for uri in ["/api/_ping", "/api/_health"].repeat(3) {
+            let response = router
+                .clone()
+                .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 200, "{uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_refresh_routes_are_limited_per_subject() {
+        let mut settings = test_settings();
+        settings.server.rate_limit = Some(crate::config::RateLimitConfig {
+            aliyun_refresh_per_minute: Some(2.try_into().unwrap()),
+            ..Default::default()
+        });
+        let router = build_router(state_from(&settings));
+        let token = generate_token("pipeline".to_string(), TEST_PRIVATE_KEY).unwrap();
+        let refresh = |uri: &str| {
+            Request::post(uri)
+                .header("Authorization", format!("Bearer {token}"))
+                .header("Content-Type", "application/json")
+                .body(Body::from("{}"))
+                .unwrap()
+        };
+
+        // The empty body is rejected by the handler, but still counts against the limit
+        for uri in [
+            "/api/aliyun/refreshObjectCaches",
+            "/api/aliyun/refreshDirectory",
+        ] {
+            let response = router.clone().oneshot(refresh(uri)).await.unwrap();
+            assert_ne!(response.status(), 429, "{uri}");
+        }
+        let response = router
+            .oneshot(refresh("/api/aliyun/pushObjectCaches"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 429);
+    }
+}
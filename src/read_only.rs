@@ -0,0 +1,237 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, RwLock},
+};
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use chrono::Utc;
+
+pub use crate::api::ReadOnlyStatus;
+use crate::{
+    error::{AppError, AppResult},
+    state::AppState,
+};
+
+/// Maximum number of OSS events held back while read-only mode is engaged
+const MAX_DEFERRED_EVENTS: usize = 10_000;
+
+const DEFAULT_MESSAGE: &str = "Service is in read-only mode";
+
+/// Runtime switch guaranteeing nothing posts to Bilibili or purges CDN
+#[derive(Debug, Clone, Default)]
+pub struct ReadOnlyMode {
+    status: Arc<RwLock<ReadOnlyStatus>>,
+    deferred: Arc<Mutex<VecDeque<serde_json::Value>>>,
+}
+
+impl ReadOnlyMode {
+    pub fn new(enabled: bool, message: Option<String>) -> Self {
+        let mode = Self::default();
+        if enabled {
+            mode.set(true, message);
+        }
+        mode
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.status.read().expect("read-only lock poisoned").enabled
+    }
+
+    pub fn status(&self) -> ReadOnlyStatus {
+        self.status.read().expect("read-only lock poisoned").clone()
+    }
+
+    /// Engage or release read-only mode
+    pub fn set(&self, enabled: bool, message: Option<String>) {
+        let mut status = self.status.write().expect("read-only lock poisoned");
+        *status = ReadOnlyStatus {
+            enabled,
+            message: message.filter(|_| enabled),
+            changed_at: Some(Utc::now().to_rfc3339()),
+        };
+    }
+
+    /// Fail with a 503 `READ_ONLY` error when the mode is engaged
+    pub fn check(&self) -> AppResult<()> {
+        let status = self.status.read().expect("read-only lock poisoned");
+        if status.enabled {
+            return Err(AppError::ReadOnly(
+                status
+                    .message
+                    .clone()
+                    .unwrap_or_else(|| DEFAULT_MESSAGE.to_string()),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Hold back a raw OSS event until read-only mode is released
+    ///
+    /// Returns `false` when the buffer is full and the event was dropped.
+    pub fn defer_event(&self, event: serde_json::Value) -> bool {
+        let mut deferred = self.deferred.lock().expect("deferred events lock poisoned");
+        if deferred.len() >= MAX_DEFERRED_EVENTS {
+            return false;
+        }
+        deferred.push_back(event);
+        true
+    }
+
+    pub fn deferred_len(&self) -> usize {
+        self.deferred
+            .lock()
+            .expect("deferred events lock poisoned")
+            .len()
+    }
+
+    /// Take every deferred event, oldest first
+    pub fn drain_deferred(&self) -> Vec<serde_json::Value> {
+        self.deferred
+            .lock()
+            .expect("deferred events lock poisoned")
+            .drain(..)
+            .collect()
+    }
+}
+
+/// Middleware rejecting mutating routes while read-only mode is engaged
+pub async fn read_only_guard(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> AppResult<Response> {
+    state.read_only.check()?;
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, body::Body, http::Request};
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    use crate::{
+        routes::build_router,
+        test_support::{body_json, test_state, test_token},
+    };
+
+    async fn send(router: &Router, request: Request<Body>) -> axum::response::Response {
+        router.clone().oneshot(request).await.unwrap()
+    }
+
+    fn authed(method: &str, uri: &str) -> axum::http::request::Builder {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("Authorization", format!("Bearer {}", test_token()))
+    }
+
+    fn create_dynamic_request() -> Request<Body> {
+        authed("POST", "/api/bilibili/createDynamic")
+            .header("Content-Type", "multipart/form-data; boundary=X")
+            .body(Body::from("--X--\r\n"))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_read_only_mode_toggles_at_runtime() {
+        let state = test_state();
+        let router = build_router(state.clone());
+
+        // Not engaged: the handler runs and rejects the empty form itself
+        let response = send(&router, create_dynamic_request()).await;
+        assert_eq!(response.status(), 400);
+
+        let response = send(
+            &router,
+            authed("PUT", "/api/admin/readOnly")
+                .header("Content-Type", "application/json")
+                .body(Body::from(
+                    json!({"enabled": true, "message": "incident #42"}).to_string(),
+                ))
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(response.status(), 200);
+
+        let response = send(&router, create_dynamic_request()).await;
+        assert_eq!(response.status(), 503);
+        let body = body_json(response).await;
+        assert_eq!(body["error"], "READ_ONLY");
+        assert_eq!(body["msg"], "incident #42");
+
+        // OSS events are acknowledged and queued rather than processed
+        let event = json!({
+            "id": "event-1",
+            "source": "acs.oss",
+            "data": {
+                "oss": {
+                    "bucket": {"name": "prts-static"},
+                    "object": {"key": "images/a.png"}
+                }
+            }
+        });
+        let response = send(
+            &router,
+            Request::post("/api/aliyun/events")
+                .header("Content-Type", "application/json")
+                .header("x-eventbridge-signature-token", test_token())
+                .body(Body::from(event.to_string()))
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(state.read_only.deferred_len(), 1);
+
+        // Reads keep working
+        for uri in ["/api/_ping", "/api/openapi.json"] {
+            let response = send(&router, Request::get(uri).body(Body::empty()).unwrap()).await;
+            assert_eq!(response.status(), 200, "{uri}");
+        }
+        let response = send(
+            &router,
+            Request::get("/api/_health?verbose=true")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        let body = body_json(response).await;
+        assert_eq!(body["read_only"]["enabled"], true);
+        assert_eq!(body["deferred_oss_events"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_full_deferred_buffer_asks_for_redelivery() {
+        let state = test_state();
+        state.read_only.set(true, None);
+        while state.read_only.defer_event(json!({})) {}
+        let router = build_router(state.clone());
+
+        let event = json!({
+            "id": "event-1",
+            "source": "acs.oss",
+            "data": {
+                "oss": {
+                    "bucket": {"name": "prts-static"},
+                    "object": {"key": "images/a.png"}
+                }
+            }
+        });
+        let response = send(
+            &router,
+            Request::post("/api/aliyun/events")
+                .header("Content-Type", "application/json")
+                .header("x-eventbridge-signature-token", test_token())
+                .body(Body::from(event.to_string()))
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(response.status(), 503);
+        assert_eq!(body_json(response).await["error"], "READ_ONLY");
+        assert_eq!(state.read_only.deferred_len(), super::MAX_DEFERRED_EVENTS);
+    }
+}
@@ -0,0 +1,1097 @@
+//! In-memory queue of CDN refreshes requested by OSS events
+//!
+//! The events handler only queues the refresh and answers right away, so a slow Aliyun can't
+//! make EventBridge time out and redeliver. One background worker calls Aliyun with retries,
+//! job by job or, with `aliyun.jobs.batch_interval_secs`, every pending job at once.
+//! Jobs live in memory: pending ones are lost on restart, like deferred read-only events.
+
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use futures::{StreamExt, stream};
+use tokio::sync::Notify;
+use tracing::{error, info, warn};
+
+pub use crate::api::aliyun::{RefreshJob, RefreshJobStatus};
+use crate::{
+    aliyun::{
+        AliyunCdnClient, RefreshObjectCachesRequest, RefreshObjectCachesResponse,
+        decode_object_key, url_host,
+    },
+    api::aliyun::OssEventPayload,
+    error::{AppError, AppResult},
+    event_dedup::EventKey,
+    state::AppState,
+    webhooks::WebhookEvent,
+};
+
+struct Entry {
+    job: RefreshJob,
+    /// Recorded in the event dedup once the refresh succeeds
+    dedup_key: Option<EventKey>,
+    /// The OSS event, dead-lettered if every attempt fails
+    event: Option<serde_json::Value>,
+    /// Preload the URLs once the refresh succeeds
+    preload: bool,
+    /// `Force` of the queued request
+    force: bool,
+    /// Queued for a `PrefixMode::Directory` rule, so remembered in the directory dedup
+    directory_rule: bool,
+    finished_at: Option<Instant>,
+}
+
+#[derive(Default)]
+struct Queue {
+    next_id: u64,
+    jobs: HashMap<u64, Entry>,
+    pending: VecDeque<u64>,
+}
+
+/// Queued refresh jobs, shared by the events handler and the worker
+#[derive(Clone, Default)]
+pub struct RefreshJobs {
+    queue: Arc<Mutex<Queue>>,
+    notify: Arc<Notify>,
+    stopping: Arc<AtomicBool>,
+    stop: Arc<Notify>,
+}
+
+impl std::fmt::Debug for RefreshJobs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let queue = self.queue.lock().expect("job queue lock poisoned");
+        f.debug_struct("RefreshJobs")
+            .field("jobs", &queue.jobs.len())
+            .field("pending", &queue.pending.len())
+            .finish()
+    }
+}
+
+impl RefreshJobs {
+    /// Queue a refresh, or return the unfinished job for the same path that does at least as
+    /// much (`Force` and preload)
+    ///
+    /// With `preload`, the URLs are preloaded once the refresh succeeds. Finished jobs older
+    /// than `retention` are dropped.
+    pub fn enqueue(
+        &self,
+        request: &RefreshObjectCachesRequest,
+        dedup_key: Option<EventKey>,
+        event: Option<serde_json::Value>,
+        preload: bool,
+        directory_rule: bool,
+        retention: Duration,
+    ) -> RefreshJob {
+        let object_type = request.object_type.as_deref().unwrap_or("File");
+        let mut queue = self.queue.lock().expect("job queue lock poisoned");
+        queue.jobs.retain(|_, entry| {
+            entry
+                .finished_at
+                .is_none_or(|finished| finished.elapsed() < retention)
+        });
+
+        // A redelivered event must not purge twice, but a removal must not lose its Force to
+        // the refresh of the creation before it
+        let force = request.force.unwrap_or(false);
+        let unfinished = queue.jobs.values().find(|entry| {
+            entry.finished_at.is_none()
+                && entry.job.object_path == request.object_path
+                && entry.job.object_type == object_type
+                && (entry.force || !force)
+                && (entry.preload || !preload)
+        });
+        if let Some(entry) = unfinished {
+            return entry.job.clone();
+        }
+
+        queue.next_id += 1;
+        let now = chrono::Utc::now().to_rfc3339();
+        let job = RefreshJob {
+            id: queue.next_id,
+            status: RefreshJobStatus::Pending,
+            object_path: request.object_path.clone(),
+            object_type: object_type.to_string(),
+            attempts: 0,
+            task_id: None,
+            error: None,
+            created_at: now.clone(),
+            updated_at: now,
+        };
+        queue.jobs.insert(
+            job.id,
+            Entry {
+                job: job.clone(),
+                dedup_key,
+                event,
+                preload,
+                force,
+                directory_rule,
+                finished_at: None,
+            },
+        );
+        queue.pending.push_back(job.id);
+        drop(queue);
+        self.notify.notify_one();
+        job
+    }
+
+    pub fn get(&self, id: u64) -> Option<RefreshJob> {
+        let queue = self.queue.lock().expect("job queue lock poisoned");
+        queue.jobs.get(&id).map(|entry| entry.job.clone())
+    }
+
+    /// Jobs waiting for the worker
+    pub fn pending_len(&self) -> usize {
+        let queue = self.queue.lock().expect("job queue lock poisoned");
+        queue.pending.len()
+    }
+
+    /// Whether job `id` preloads its URLs after the refresh
+    fn preloads(&self, id: u64) -> bool {
+        let queue = self.queue.lock().expect("job queue lock poisoned");
+        queue.jobs.get(&id).is_some_and(|entry| entry.preload)
+    }
+
+    /// Whether job `id` refreshes with `Force`
+    fn forces(&self, id: u64) -> bool {
+        let queue = self.queue.lock().expect("job queue lock poisoned");
+        queue.jobs.get(&id).is_some_and(|entry| entry.force)
+    }
+
+    /// Whether job `id` was queued for a `PrefixMode::Directory` rule
+    fn for_directory_rule(&self, id: u64) -> bool {
+        let queue = self.queue.lock().expect("job queue lock poisoned");
+        queue
+            .jobs
+            .get(&id)
+            .is_some_and(|entry| entry.directory_rule)
+    }
+
+    /// Have the worker return once the job or flush in hand is done
+    pub fn stop(&self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        self.stop.notify_one();
+    }
+
+    /// Wait out `interval`, or less if [`Self::stop`] is called; true when stopping
+    async fn stopped_within(&self, interval: Duration) -> bool {
+        if self.stopping.load(Ordering::SeqCst) {
+            return true;
+        }
+        let _ = tokio::time::timeout(interval, self.stop.notified()).await;
+        self.stopping.load(Ordering::SeqCst)
+    }
+
+    /// Wait for the oldest pending job and mark it running, or `None` once stopping
+    async fn next(&self) -> Option<(RefreshJob, Option<EventKey>, Option<serde_json::Value>)> {
+        loop {
+            if self.stopping.load(Ordering::SeqCst) {
+                return None;
+            }
+            {
+                let mut queue = self.queue.lock().expect("job queue lock poisoned");
+                while let Some(id) = queue.pending.pop_front() {
+                    // Pruned jobs are only finished ones, but stay defensive
+                    if let Some(entry) = queue.jobs.get_mut(&id) {
+                        entry.job.status = RefreshJobStatus::Running;
+                        return Some((
+                            entry.job.clone(),
+                            entry.dedup_key.clone(),
+                            entry.event.take(),
+                        ));
+                    }
+                }
+            }
+            tokio::select! {
+                () = self.notify.notified() => {}
+                () = self.stop.notified() => {}
+            }
+        }
+    }
+
+    /// Mark every pending job running and hand them over, oldest first
+    ///
+    /// Events stay with their jobs until [`Self::take_event`], since a job may be retried by a
+    /// later flush.
+    fn claim_pending(&self) -> Vec<(RefreshJob, Option<EventKey>)> {
+        let mut queue = self.queue.lock().expect("job queue lock poisoned");
+        let Queue { jobs, pending, .. } = &mut *queue;
+        pending
+            .drain(..)
+            .filter_map(|id| {
+                let entry = jobs.get_mut(&id)?;
+                entry.job.status = RefreshJobStatus::Running;
+                Some((entry.job.clone(), entry.dedup_key.clone()))
+            })
+            .collect()
+    }
+
+    fn take_event(&self, id: u64) -> Option<serde_json::Value> {
+        let mut queue = self.queue.lock().expect("job queue lock poisoned");
+        queue.jobs.get_mut(&id).and_then(|entry| entry.event.take())
+    }
+
+    /// Put job `id` back in line for the next flush
+    fn requeue(&self, id: u64, apply: impl FnOnce(&mut RefreshJob)) {
+        self.update(id, |job| {
+            job.status = RefreshJobStatus::Pending;
+            apply(job);
+        });
+        let mut queue = self.queue.lock().expect("job queue lock poisoned");
+        queue.pending.push_back(id);
+    }
+
+    fn update(&self, id: u64, apply: impl FnOnce(&mut RefreshJob)) {
+        let mut queue = self.queue.lock().expect("job queue lock poisoned");
+        if let Some(entry) = queue.jobs.get_mut(&id) {
+            apply(&mut entry.job);
+            entry.job.updated_at = chrono::Utc::now().to_rfc3339();
+            if matches!(
+                entry.job.status,
+                RefreshJobStatus::Succeeded | RefreshJobStatus::Failed
+            ) {
+                entry.finished_at = Some(Instant::now());
+            }
+        }
+    }
+}
+
+/// Work off queued refreshes until [`RefreshJobs::stop`] is called
+///
+/// One at a time, or with `aliyun.jobs.batch_interval_secs` all of them together that often.
+/// Jobs still pending when it returns are left for a final [`flush`].
+pub async fn run_refresh_worker(state: AppState) {
+    loop {
+        let interval = state.aliyun_config.load().jobs.batch_interval_secs;
+        if interval == 0 {
+            let Some((job, dedup_key, event)) = state.refresh_jobs.next().await else {
+                return;
+            };
+            run_job(&state, job, dedup_key, event).await;
+        } else {
+            if state
+                .refresh_jobs
+                .stopped_within(Duration::from_secs(interval))
+                .await
+            {
+                return;
+            }
+            flush(&state).await;
+        }
+    }
+}
+
+/// Send every pending job at once, one call per CDN domain, object type and `Force`
+///
+/// A job whose calls all succeeded is done. The others wait for the next flush, or fail once
+/// they used up `aliyun.jobs.max_attempts`; an exhausted refresh budget uses up no attempt.
+pub async fn flush(state: &AppState) {
+    // Purges stay held back while read-only mode is engaged
+    if state.read_only.is_enabled() {
+        return;
+    }
+    let claimed = state.refresh_jobs.claim_pending();
+    if claimed.is_empty() {
+        return;
+    }
+
+    let requests = claimed
+        .iter()
+        .map(|(job, _)| job_request(state, job))
+        .collect::<Vec<_>>();
+    let mut groups = BTreeMap::<(String, String, bool), Vec<&str>>::new();
+    for request in &requests {
+        let object_type = request.object_type.clone().unwrap_or_default();
+        let force = request.force.unwrap_or(false);
+        for url in request.object_path.lines() {
+            let key = (
+                url_host(url).to_ascii_lowercase(),
+                object_type.clone(),
+                force,
+            );
+            let urls = groups.entry(key).or_default();
+            if !urls.contains(&url) {
+                urls.push(url);
+            }
+        }
+    }
+    // Every URL points at the chunk it was sent in
+    let mut chunk_of = HashMap::new();
+    let mut chunks = Vec::new();
+    for ((_, object_type, force), urls) in groups {
+        let request = RefreshObjectCachesRequest {
+            object_path: urls.join("\n"),
+            object_type: Some(object_type.clone()),
+            force: Some(force),
+            product: None,
+        };
+        for chunk in request.split() {
+            for url in chunk.object_path.lines() {
+                chunk_of.insert((url.to_string(), object_type.clone(), force), chunks.len());
+            }
+            chunks.push(chunk);
+        }
+    }
+    info!(
+        jobs = claimed.len(),
+        calls = chunks.len(),
+        "Flushing queued CDN refreshes"
+    );
+
+    let concurrency = state.aliyun_config.load().refresh_concurrency.get();
+    let outcomes = stream::iter(chunks)
+        .map(|chunk| async move {
+            match state.aliyun_cdn() {
+                Ok(client) => refresh_routed(client, &chunk).await,
+                Err(err) => Err(err),
+            }
+        })
+        .buffered(concurrency)
+        .collect::<Vec<_>>()
+        .await;
+    for sent in outcomes.iter().flatten() {
+        for (request, response) in sent {
+            state
+                .refresh_log
+                .record("oss_event", request, &response.refresh_task_id);
+        }
+    }
+
+    let config = state.aliyun_config.load().jobs.clone();
+    for ((job, dedup_key), request) in claimed.into_iter().zip(requests) {
+        let object_type = request.object_type.clone().unwrap_or_default();
+        let force = request.force.unwrap_or(false);
+        let mut indices = request
+            .object_path
+            .lines()
+            .map(|url| chunk_of[&(url.to_string(), object_type.clone(), force)])
+            .collect::<Vec<_>>();
+        indices.sort_unstable();
+        indices.dedup();
+        let error = indices
+            .iter()
+            .find_map(|&index| outcomes[index].as_ref().err());
+        match error {
+            None => {
+                let mut task_ids = Vec::new();
+                for &index in &indices {
+                    for (_, response) in outcomes[index].iter().flatten() {
+                        if !task_ids.contains(&response.refresh_task_id) {
+                            task_ids.push(response.refresh_task_id.clone());
+                        }
+                    }
+                }
+                succeeded(
+                    state,
+                    &job,
+                    dedup_key,
+                    state.refresh_jobs.take_event(job.id),
+                    &request,
+                    task_ids.join(","),
+                    job.attempts + 1,
+                )
+                .await;
+            }
+            Some(AppError::RateLimited(wait)) => {
+                info!(
+                    job_id = job.id,
+                    retry_in_secs = wait,
+                    "Refresh budget exhausted, queued CDN refresh waits"
+                );
+                let message = format!("{:#}", AppError::RateLimited(*wait));
+                state
+                    .refresh_jobs
+                    .requeue(job.id, |job| job.error = Some(message));
+            }
+            Some(err) if job.attempts + 1 < config.max_attempts.get() => {
+                warn!(
+                    job_id = job.id,
+                    attempts = job.attempts + 1,
+                    error = ?err,
+                    "Queued CDN refresh failed, retrying with the next flush"
+                );
+                state.refresh_jobs.requeue(job.id, |job| {
+                    job.attempts += 1;
+                    job.error = Some(format!("{err:#}"));
+                });
+            }
+            Some(err) => {
+                let event = state.refresh_jobs.take_event(job.id);
+                failed(state, &job, event, &request, err, job.attempts + 1);
+            }
+        }
+    }
+}
+
+/// The request job `job` refreshes
+fn job_request(state: &AppState, job: &RefreshJob) -> RefreshObjectCachesRequest {
+    RefreshObjectCachesRequest {
+        object_path: job.object_path.clone(),
+        object_type: Some(job.object_type.clone()),
+        force: Some(state.refresh_jobs.forces(job.id)),
+        product: None,
+    }
+}
+
+async fn run_job(
+    state: &AppState,
+    job: RefreshJob,
+    dedup_key: Option<EventKey>,
+    event: Option<serde_json::Value>,
+) {
+    let request = job_request(state, &job);
+    let mut attempts = job.attempts;
+    loop {
+        // Purges stay held back while read-only mode is engaged
+        while state.read_only.is_enabled() {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+
+        let config = state.aliyun_config.load().jobs.clone();
+        let outcome = match state.aliyun_cdn() {
+            Ok(client) => refresh_routed(client, &request).await,
+            Err(err) => Err(err),
+        };
+        // An exhausted refresh budget holds the job back without using up its attempts
+        if let Err(AppError::RateLimited(wait)) = outcome {
+            info!(
+                job_id = job.id,
+                retry_in_secs = wait,
+                "Refresh budget exhausted, queued CDN refresh waits"
+            );
+            state.refresh_jobs.update(job.id, |job| {
+                job.status = RefreshJobStatus::Pending;
+                job.error = Some(format!("{:#}", AppError::RateLimited(wait)));
+            });
+            tokio::time::sleep(Duration::from_secs(wait)).await;
+            state
+                .refresh_jobs
+                .update(job.id, |job| job.status = RefreshJobStatus::Running);
+            continue;
+        }
+        attempts += 1;
+
+        match outcome {
+            Ok(sent) => {
+                let task_ids = sent
+                    .iter()
+                    .map(|(_, response)| response.refresh_task_id.as_str())
+                    .collect::<Vec<_>>()
+                    .join(",");
+                for (request, response) in &sent {
+                    state
+                        .refresh_log
+                        .record("oss_event", request, &response.refresh_task_id);
+                }
+                succeeded(state, &job, dedup_key, event, &request, task_ids, attempts).await;
+                return;
+            }
+            Err(err) if attempts < config.max_attempts.get() => {
+                // Exponential backoff: base, 2x base, 4x base, ...
+                let backoff = Duration::from_millis(config.retry_backoff_ms)
+                    .saturating_mul(1 << (attempts - 1).min(16));
+                warn!(
+                    job_id = job.id,
+                    attempts,
+                    retry_in_ms = backoff.as_millis() as u64,
+                    error = ?err,
+                    "Queued CDN refresh failed, retrying"
+                );
+                state.refresh_jobs.update(job.id, |job| {
+                    job.status = RefreshJobStatus::Pending;
+                    job.attempts = attempts;
+                    job.error = Some(format!("{err:#}"));
+                });
+                tokio::time::sleep(backoff).await;
+                state
+                    .refresh_jobs
+                    .update(job.id, |job| job.status = RefreshJobStatus::Running);
+            }
+            Err(err) => {
+                failed(state, &job, event, &request, &err, attempts);
+                return;
+            }
+        }
+    }
+}
+
+/// Record the refresh of job `job` for `event` and mark it succeeded, preloading first when
+/// it asks to
+async fn succeeded(
+    state: &AppState,
+    job: &RefreshJob,
+    dedup_key: Option<EventKey>,
+    event: Option<serde_json::Value>,
+    request: &RefreshObjectCachesRequest,
+    task_ids: String,
+    attempts: u32,
+) {
+    info!(
+        job_id = job.id,
+        object_path = %request.object_path,
+        task_id = %task_ids,
+        attempts,
+        "Queued CDN refresh succeeded"
+    );
+    if let Some(key) = dedup_key {
+        state.event_dedup.record(key, task_ids.clone());
+    }
+    // A re-upload right after a removal must still drop the cached 404
+    if request.force != Some(true) {
+        let dedup = if state.refresh_jobs.for_directory_rule(job.id) {
+            &state.directory_dedup
+        } else {
+            &state.url_dedup
+        };
+        dedup.record(request.object_path.lines(), &task_ids);
+    }
+    state
+        .webhooks
+        .notify(WebhookEvent::cdn_refresh("oss_event", request, Ok(&task_ids)).with_job(job.id));
+    if let Some(payload) =
+        event.and_then(|event| serde_json::from_value::<OssEventPayload>(event).ok())
+    {
+        let bucket = &payload.data.oss.bucket.name;
+        let encoding = state.aliyun_config.load().events.key_encoding_for(bucket);
+        let object_paths = request
+            .object_path
+            .lines()
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        state.webhooks.notify(
+            WebhookEvent::object_refreshed(
+                bucket,
+                &decode_object_key(&payload.data.oss.object.key, encoding),
+                &object_paths,
+                &task_ids,
+            )
+            .with_job(job.id),
+        );
+    }
+    if state.refresh_jobs.preloads(job.id) {
+        preload(state, job.id, &request.object_path).await;
+    }
+    state.refresh_jobs.update(job.id, |job| {
+        job.status = RefreshJobStatus::Succeeded;
+        job.attempts = attempts;
+        job.task_id = Some(task_ids);
+        job.error = None;
+    });
+}
+
+/// Give up on job `job`, dead-lettering its event
+fn failed(
+    state: &AppState,
+    job: &RefreshJob,
+    event: Option<serde_json::Value>,
+    request: &RefreshObjectCachesRequest,
+    err: &AppError,
+    attempts: u32,
+) {
+    error!(
+        job_id = job.id,
+        object_path = %request.object_path,
+        attempts,
+        error = ?err,
+        "Queued CDN refresh failed permanently"
+    );
+    state
+        .webhooks
+        .notify(WebhookEvent::cdn_refresh("oss_event", request, Err(err)).with_job(job.id));
+    if let Some(event) = event {
+        state.dead_letters.push(event, err, attempts);
+    }
+    state.refresh_jobs.update(job.id, |job| {
+        job.status = RefreshJobStatus::Failed;
+        job.attempts = attempts;
+        job.error = Some(format!("{err:#}"));
+    });
+}
+
+/// Preload the URLs of a succeeded job; the refresh already counts, so failures are only logged
+async fn preload(state: &AppState, job_id: u64, object_path: &str) {
+    let outcome = match state.aliyun_cdn() {
+        Ok(client) => client.preload_object_caches(object_path, None).await,
+        Err(err) => Err(err),
+    };
+    match outcome {
+        Ok(response) => info!(
+            job_id,
+            object_path,
+            task_id = %response.push_task_id,
+            "CDN preload after queued refresh"
+        ),
+        Err(err) => warn!(
+            job_id,
+            object_path,
+            error = %err,
+            "CDN preload after queued refresh failed"
+        ),
+    }
+}
+
+/// Refresh each product's share of `request`, stopping at the first failure
+///
+/// A retry sends every share again; refreshing a URL twice is harmless.
+async fn refresh_routed(
+    client: &AliyunCdnClient,
+    request: &RefreshObjectCachesRequest,
+) -> AppResult<Vec<(RefreshObjectCachesRequest, RefreshObjectCachesResponse)>> {
+    let mut sent = Vec::new();
+    for request in client.route(request) {
+        let response = client.refresh_object_caches(&request).await?;
+        sent.push((request, response));
+    }
+    Ok(sent)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{body_string_contains, header, method},
+    };
+
+    use super::*;
+    use crate::test_support::{state_from, test_settings};
+
+    fn request(path: &str) -> RefreshObjectCachesRequest {
+        RefreshObjectCachesRequest {
+            object_path: path.to_string(),
+            object_type: Some("File".to_string()),
+            force: Some(false),
+            product: None,
+        }
+    }
+
+    /// A state whose CDN client talks to `server`, retrying quickly
+    ///
+    /// The client itself doesn't retry, so every call is one job attempt.
+    fn state_with_cdn(server: &MockServer) -> AppState {
+        let mut settings = test_settings();
+        settings.aliyun.endpoint = server.uri();
+        settings.aliyun.jobs.retry_backoff_ms = 1;
+        settings.aliyun.retry.max_attempts = NonZeroU32::MIN;
+        state_from(&settings)
+    }
+
+    /// Run the worker until job `id` is finished
+    async fn finish(state: &AppState, id: u64) -> RefreshJob {
+        let worker = tokio::spawn(run_refresh_worker(state.clone()));
+        let job = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let job = state.refresh_jobs.get(id).unwrap();
+                if matches!(
+                    job.status,
+                    RefreshJobStatus::Succeeded | RefreshJobStatus::Failed
+                ) {
+                    return job;
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("job should finish");
+        worker.abort();
+        job
+    }
+
+    async fn mount_refresh(server: &MockServer, status: u16, times: u64) {
+        let response = if status == 200 {
+            ResponseTemplate::new(200)
+                .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"17772470467"}"#)
+        } else {
+            ResponseTemplate::new(status)
+                .set_body_string(r#"{"Code":"ServiceUnavailable","Message":"busy"}"#)
+        };
+        Mock::given(method("POST"))
+            .and(header("x-acs-action", "RefreshObjectCaches"))
+            .respond_with(response)
+            .up_to_n_times(times)
+            .expect(times)
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_worker_records_task_id_on_success() {
+        let server = MockServer::start().await;
+        mount_refresh(&server, 200, 1).await;
+        let state = state_with_cdn(&server);
+        let key = ("prts-static".into(), "a.png".into(), "etag".into());
+
+        let queued = state.refresh_jobs.enqueue(
+            &request("https://static.prts.wiki/a.png"),
+            Some(key.clone()),
+            None,
+            false,
+            false,
+            Duration::from_secs(60),
+        );
+        assert_eq!(queued.status, RefreshJobStatus::Pending);
+
+        let job = finish(&state, queued.id).await;
+        assert_eq!(job.status, RefreshJobStatus::Succeeded);
+        assert_eq!(job.attempts, 1);
+        assert_eq!(job.task_id.as_deref(), Some("17772470467"));
+        assert_eq!(
+            state.event_dedup.recent_task(&key).as_deref(),
+            Some("17772470467")
+        );
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_worker_preloads_after_the_refresh_when_asked() {
+        let server = MockServer::start().await;
+        mount_refresh(&server, 200, 1).await;
+        Mock::given(method("POST"))
+            .and(header("x-acs-action", "PushObjectCache"))
+            .respond_with(
+                ResponseTemplate::new(400)
+                    .set_body_string(r#"{"Code":"InvalidParameter","Message":"bad"}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let state = state_with_cdn(&server);
+
+        let queued = state.refresh_jobs.enqueue(
+            &request("https://static.prts.wiki/a.png"),
+            None,
+            None,
+            true,
+            false,
+            Duration::from_secs(60),
+        );
+        // The refresh went through, so a failed preload doesn't fail the job
+        let job = finish(&state, queued.id).await;
+        assert_eq!(job.status, RefreshJobStatus::Succeeded);
+        assert_eq!(job.task_id.as_deref(), Some("17772470467"));
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_worker_retries_until_aliyun_recovers() {
+        let server = MockServer::start().await;
+        mount_refresh(&server, 503, 2).await;
+        mount_refresh(&server, 200, 1).await;
+        let state = state_with_cdn(&server);
+
+        let queued = state.refresh_jobs.enqueue(
+            &request("https://static.prts.wiki/a.png"),
+            None,
+            None,
+            false,
+            false,
+            Duration::from_secs(60),
+        );
+        let job = finish(&state, queued.id).await;
+        assert_eq!(job.status, RefreshJobStatus::Succeeded);
+        assert_eq!(job.attempts, 3);
+        assert_eq!(job.error, None);
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_worker_gives_up_after_max_attempts() {
+        let server = MockServer::start().await;
+        mount_refresh(&server, 503, 3).await;
+        let state = state_with_cdn(&server);
+
+        let queued = state.refresh_jobs.enqueue(
+            &request("https://static.prts.wiki/a.png"),
+            None,
+            Some(serde_json::json!({"id": "evt-1"})),
+            false,
+            false,
+            Duration::from_secs(60),
+        );
+        let job = finish(&state, queued.id).await;
+        assert_eq!(job.status, RefreshJobStatus::Failed);
+        assert_eq!(job.attempts, 3);
+        assert!(job.error.unwrap().contains("ServiceUnavailable"));
+
+        // The event is kept for a replay
+        let dead_letters = state.dead_letters.list(None, 1, 10);
+        assert_eq!(dead_letters.total, 1);
+        assert_eq!(dead_letters.items[0].event["id"], "evt-1");
+        assert_eq!(dead_letters.items[0].attempts, 3);
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_worker_keeps_the_force_of_the_request() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("Force=true"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"17772470467"}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let state = state_with_cdn(&server);
+
+        let queued = state.refresh_jobs.enqueue(
+            &RefreshObjectCachesRequest {
+                force: Some(true),
+                ..request("https://static.prts.wiki/a.png")
+            },
+            None,
+            None,
+            false,
+            false,
+            Duration::from_secs(60),
+        );
+        let job = finish(&state, queued.id).await;
+        assert_eq!(job.status, RefreshJobStatus::Succeeded);
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_stopped_worker_leaves_pending_jobs_for_the_final_flush() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"1"}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let mut settings = test_settings();
+        settings.aliyun.endpoint = server.uri();
+        settings.aliyun.jobs.batch_interval_secs = 3600;
+        let state = state_from(&settings);
+        let worker = tokio::spawn(run_refresh_worker(state.clone()));
+        let queued = state.refresh_jobs.enqueue(
+            &request("https://static.prts.wiki/a.png"),
+            None,
+            None,
+            false,
+            false,
+            Duration::from_secs(60),
+        );
+
+        state.refresh_jobs.stop();
+        tokio::time::timeout(Duration::from_secs(5), worker)
+            .await
+            .expect("worker should return once stopped")
+            .unwrap();
+        assert_eq!(
+            state.refresh_jobs.get(queued.id).unwrap().status,
+            RefreshJobStatus::Pending
+        );
+
+        flush(&state).await;
+        assert_eq!(
+            state.refresh_jobs.get(queued.id).unwrap().status,
+            RefreshJobStatus::Succeeded
+        );
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_directory_rule_job_is_remembered_as_a_directory() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"1"}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let mut settings = test_settings();
+        settings.aliyun.endpoint = server.uri();
+        settings.aliyun.url_dedup_window_secs = 60;
+        settings.aliyun.events.directory_rule_window_secs = 60;
+        let state = state_from(&settings);
+        let directory = "https://static.prts.wiki/charts/latest/";
+
+        let queued = state.refresh_jobs.enqueue(
+            &RefreshObjectCachesRequest {
+                object_type: Some("Directory".to_string()),
+                ..request(directory)
+            },
+            None,
+            None,
+            false,
+            true,
+            Duration::from_secs(60),
+        );
+        finish(&state, queued.id).await;
+        assert_eq!(
+            state.directory_dedup.recent_task(directory).as_deref(),
+            Some("1")
+        );
+        assert!(state.url_dedup.recent_task(directory).is_none());
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_forced_refresh_is_not_remembered_for_dedup() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"1"}"#),
+            )
+            .expect(2)
+            .mount(&server)
+            .await;
+        let mut settings = test_settings();
+        settings.aliyun.endpoint = server.uri();
+        settings.aliyun.url_dedup_window_secs = 60;
+        let state = state_from(&settings);
+
+        for force in [true, false] {
+            let queued = state.refresh_jobs.enqueue(
+                &RefreshObjectCachesRequest {
+                    force: Some(force),
+                    ..request("https://static.prts.wiki/a.png")
+                },
+                None,
+                None,
+                false,
+                false,
+                Duration::from_secs(60),
+            );
+            finish(&state, queued.id).await;
+            let remembered = state
+                .url_dedup
+                .recent_task("https://static.prts.wiki/a.png");
+            assert_eq!(remembered.is_some(), !force);
+        }
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_flush_sends_one_call_per_domain() {
+        let server = MockServer::start().await;
+        for (host, status, body) in [
+            (
+                "static.prts.wiki",
+                200,
+                r#"{"RequestId":"r","RefreshTaskId":"1"}"#,
+            ),
+            (
+                "media.prts.wiki",
+                400,
+                r#"{"Code":"InvalidDomain.Offline","Message":"domain is offline"}"#,
+            ),
+        ] {
+            Mock::given(method("POST"))
+                .and(body_string_contains(host))
+                .respond_with(ResponseTemplate::new(status).set_body_string(body))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+        let state = state_with_cdn(&server);
+        let enqueue = |path: &str| {
+            state.refresh_jobs.enqueue(
+                &request(path),
+                None,
+                None,
+                false,
+                false,
+                Duration::from_secs(60),
+            )
+        };
+        let first = enqueue("https://static.prts.wiki/a.png");
+        let second = enqueue("https://static.prts.wiki/b.png");
+        let third = enqueue("https://media.prts.wiki/c.png");
+        assert_eq!(state.refresh_jobs.pending_len(), 3);
+
+        flush(&state).await;
+        for job in [&first, &second] {
+            let job = state.refresh_jobs.get(job.id).unwrap();
+            assert_eq!(job.status, RefreshJobStatus::Succeeded);
+            assert_eq!(job.task_id.as_deref(), Some("1"));
+        }
+        // The failed job waits for the next flush
+        let job = state.refresh_jobs.get(third.id).unwrap();
+        assert_eq!(job.status, RefreshJobStatus::Pending);
+        assert_eq!(job.attempts, 1);
+        assert!(job.error.unwrap().contains("InvalidDomain.Offline"));
+        assert_eq!(state.refresh_jobs.pending_len(), 1);
+        server.verify().await;
+    }
+
+    #[test]
+    fn test_pending_job_for_the_same_path_is_reused() {
+        let jobs = RefreshJobs::default();
+        let retention = Duration::from_secs(60);
+        let first = jobs.enqueue(
+            &request("https://static.prts.wiki/a.png"),
+            None,
+            None,
+            false,
+            false,
+            retention,
+        );
+        let again = jobs.enqueue(
+            &request("https://static.prts.wiki/a.png"),
+            None,
+            None,
+            false,
+            false,
+            retention,
+        );
+        let other = jobs.enqueue(
+            &request("https://static.prts.wiki/b.png"),
+            None,
+            None,
+            false,
+            false,
+            retention,
+        );
+
+        assert_eq!(first.id, again.id);
+        assert_ne!(first.id, other.id);
+    }
+
+    #[test]
+    fn test_force_and_preload_are_not_folded_into_a_weaker_job() {
+        let jobs = RefreshJobs::default();
+        let retention = Duration::from_secs(60);
+        let forced = RefreshObjectCachesRequest {
+            force: Some(true),
+            ..request("https://static.prts.wiki/a.png")
+        };
+        let plain = jobs.enqueue(
+            &request("https://static.prts.wiki/a.png"),
+            None,
+            None,
+            false,
+            false,
+            retention,
+        );
+        let force = jobs.enqueue(&forced, None, None, false, false, retention);
+        let preload = jobs.enqueue(&forced, None, None, true, false, retention);
+        // A job that does more covers a weaker redelivery
+        let again = jobs.enqueue(
+            &request("https://static.prts.wiki/a.png"),
+            None,
+            None,
+            false,
+            false,
+            retention,
+        );
+
+        assert_ne!(plain.id, force.id);
+        assert_ne!(force.id, preload.id);
+        assert_eq!(jobs.pending_len(), 3);
+        assert!([plain.id, force.id, preload.id].contains(&again.id));
+    }
+}
@@ -0,0 +1,426 @@
+//! In-memory log of refresh tasks Aliyun accepted, reconciled with their final status
+//!
+//! Aliyun occasionally accepts a refresh and marks the task `Failed` later. A background
+//! reconciler polls recent unfinished tasks with DescribeRefreshTaskById until they are
+//! `Complete` or `Failed`, and reports failures. Like refresh jobs, the log lives in memory:
+//! it is lost on restart and keeps only the newest entries.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use tracing::{debug, error, warn};
+
+pub use crate::api::aliyun::RefreshLogEntry;
+use crate::{
+    aliyun::{
+        CdnProduct, RefreshObjectCachesRequest, RefreshTask, RefreshTaskStatus,
+        cdn::MAX_DESCRIBE_TASK_IDS,
+    },
+    state::AppState,
+    webhooks::WebhookEvent,
+};
+
+/// Entries kept at most, the oldest are dropped first
+const MAX_ENTRIES: usize = 1000;
+
+struct Entry {
+    entry: RefreshLogEntry,
+    source: &'static str,
+    created: DateTime<Utc>,
+}
+
+/// Recent refresh tasks, newest last
+#[derive(Clone, Default)]
+pub struct RefreshLog {
+    entries: Arc<Mutex<VecDeque<Entry>>>,
+}
+
+impl std::fmt::Debug for RefreshLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let entries = self.entries.lock().expect("refresh log lock poisoned");
+        f.debug_struct("RefreshLog")
+            .field("entries", &entries.len())
+            .finish()
+    }
+}
+
+impl RefreshLog {
+    /// Log the tasks of an accepted refresh; `task_ids` is Aliyun's comma-separated list
+    ///
+    /// `request` is one [`crate::aliyun::AliyunCdnClient::route`] returned, so its tasks live on its product.
+    pub fn record(
+        &self,
+        source: &'static str,
+        request: &RefreshObjectCachesRequest,
+        task_ids: &str,
+    ) {
+        let task_ids = task_ids
+            .split(',')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .collect::<Vec<_>>();
+        let paths = request.object_path.lines().collect::<Vec<_>>();
+        let now = Utc::now();
+        let mut entries = self.entries.lock().expect("refresh log lock poisoned");
+        for (index, task_id) in task_ids.iter().enumerate() {
+            // Aliyun answers with one task per path, in order; otherwise keep them all
+            let object_paths = if task_ids.len() == paths.len() {
+                vec![paths[index].to_string()]
+            } else {
+                paths.iter().map(|path| path.to_string()).collect()
+            };
+            entries.push_back(Entry {
+                entry: RefreshLogEntry {
+                    task_id: task_id.to_string(),
+                    product: request.product.unwrap_or_default(),
+                    source: source.to_string(),
+                    object_paths,
+                    object_type: request
+                        .object_type
+                        .clone()
+                        .unwrap_or_else(|| "File".to_string()),
+                    status: None,
+                    process: None,
+                    description: None,
+                    created_at: now.to_rfc3339(),
+                    updated_at: now.to_rfc3339(),
+                },
+                source,
+                created: now,
+            });
+        }
+        let excess = entries.len().saturating_sub(MAX_ENTRIES);
+        entries.drain(..excess);
+    }
+
+    /// Logged tasks, newest first, optionally only those with Aliyun status `status`
+    pub fn list(&self, status: Option<RefreshTaskStatus>) -> Vec<RefreshLogEntry> {
+        let entries = self.entries.lock().expect("refresh log lock poisoned");
+        entries
+            .iter()
+            .rev()
+            .map(|entry| &entry.entry)
+            .filter(|entry| status.is_none_or(|status| entry.status == Some(status)))
+            .cloned()
+            .collect()
+    }
+
+    /// Ids of tasks logged since `since` that haven't reached a terminal status, by product
+    fn unfinished(&self, since: DateTime<Utc>) -> BTreeMap<CdnProduct, Vec<String>> {
+        let entries = self.entries.lock().expect("refresh log lock poisoned");
+        entries
+            .iter()
+            .filter(|entry| {
+                entry.created >= since
+                    && !entry
+                        .entry
+                        .status
+                        .is_some_and(RefreshTaskStatus::is_terminal)
+            })
+            .fold(BTreeMap::new(), |mut unfinished, entry| {
+                unfinished
+                    .entry(entry.entry.product)
+                    .or_insert_with(Vec::new)
+                    .push(entry.entry.task_id.clone());
+                unfinished
+            })
+    }
+
+    /// Apply the status Aliyun reports, returning the entry and its source if it just failed
+    fn update(&self, task: &RefreshTask) -> Option<(RefreshLogEntry, &'static str)> {
+        let mut entries = self.entries.lock().expect("refresh log lock poisoned");
+        let entry = entries
+            .iter_mut()
+            .rev()
+            .find(|entry| entry.entry.task_id == task.task_id)?;
+        let newly_failed = task.status == RefreshTaskStatus::Failed
+            && entry.entry.status != Some(RefreshTaskStatus::Failed);
+        entry.entry.status = Some(task.status);
+        entry.entry.process = Some(task.process.clone());
+        entry.entry.description.clone_from(&task.description);
+        entry.entry.updated_at = Utc::now().to_rfc3339();
+        newly_failed.then(|| (entry.entry.clone(), entry.source))
+    }
+}
+
+/// Reconcile logged tasks every `aliyun.reconcile.poll_interval_secs` until the task is aborted
+pub async fn run_refresh_reconciler(state: AppState) {
+    loop {
+        let poll_interval = state.aliyun_config.load().reconcile.poll_interval_secs;
+        tokio::time::sleep(Duration::from_secs(poll_interval.max(1))).await;
+        reconcile(&state).await;
+    }
+}
+
+/// Look up the unfinished tasks of the lookback window on their product, in batches
+/// DescribeRefreshTaskById accepts, and record what Aliyun reports
+async fn reconcile(state: &AppState) {
+    let lookback = state.aliyun_config.load().reconcile.lookback_secs;
+    let since = Utc::now() - Duration::from_secs(lookback);
+    let unfinished = state.refresh_log.unfinished(since);
+    if unfinished.is_empty() {
+        return;
+    }
+    let Ok(client) = state.aliyun_cdn() else {
+        return;
+    };
+    debug!(
+        tasks = unfinished.values().map(Vec::len).sum::<usize>(),
+        "Reconciling CDN refresh tasks"
+    );
+
+    for (product, task_ids) in unfinished {
+        for batch in task_ids.chunks(MAX_DESCRIBE_TASK_IDS) {
+            let task_ids = batch.join(",");
+            let response = match client
+                .describe_refresh_task_by_id_on(product, &task_ids)
+                .await
+            {
+                Ok(response) => response,
+                Err(err) => {
+                    warn!(task_ids, ?product, error = ?err, "Refresh task lookup failed");
+                    continue;
+                }
+            };
+            record_statuses(state, &response.tasks);
+        }
+    }
+}
+
+/// Record the statuses Aliyun reported, reporting tasks that just failed
+fn record_statuses(state: &AppState, tasks: &[RefreshTask]) {
+    for task in tasks {
+        let Some((entry, source)) = state.refresh_log.update(task) else {
+            continue;
+        };
+        error!(
+            task_id = %entry.task_id,
+            object_path = %task.object_path,
+            description = entry.description.as_deref(),
+            "Aliyun marked a CDN refresh task failed"
+        );
+        state
+            .webhooks
+            .notify(WebhookEvent::refresh_task_failed(source, &entry));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{header, method, path, query_param},
+    };
+
+    use super::*;
+    use crate::{
+        config::WebhookConfig,
+        test_support::{state_from, test_settings},
+        webhooks::run_webhook_dispatcher,
+    };
+
+    fn request(paths: &[&str]) -> RefreshObjectCachesRequest {
+        RefreshObjectCachesRequest {
+            object_path: paths.join("\n"),
+            object_type: Some("File".to_string()),
+            force: Some(false),
+            product: None,
+        }
+    }
+
+    fn describe_response(status: &str, process: &str, description: Option<&str>) -> String {
+        serde_json::json!({
+            "RequestId": "E0C2EF95",
+            "TotalCount": 1,
+            "Tasks": [{
+                "TaskId": "17772470467",
+                "ObjectPath": "https://static.prts.wiki/a.png",
+                "ObjectType": "file",
+                "Status": status,
+                "Process": process,
+                "CreationTime": "2026-10-16T02:00:00Z",
+                "Description": description,
+            }]
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_task_failing_after_acceptance_is_reported() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(header("x-acs-action", "DescribeRefreshTaskById"))
+            .and(query_param("TaskId", "17772470467"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_string(describe_response(
+                    "Refreshing",
+                    "50%",
+                    None,
+                )),
+            )
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(header("x-acs-action", "DescribeRefreshTaskById"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_string(describe_response(
+                    "Failed",
+                    "0%",
+                    Some("InternalError"),
+                )),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/hook"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let mut settings = test_settings();
+        settings.aliyun.endpoint = server.uri();
+        settings.webhooks = vec![WebhookConfig {
+            url: format!("{}/hook", server.uri()),
+            secret: "webhook-secret".to_string(),
+            events: vec!["cdn.refresh.failed".to_string()],
+            max_attempts: NonZeroU32::new(1).unwrap(),
+            retry_backoff_ms: 0,
+            bearer_token: None,
+            timeout_secs: 10,
+        }];
+        let state = state_from(&settings);
+        let dispatcher = tokio::spawn(run_webhook_dispatcher(
+            state.webhooks.clone(),
+            state.http_client.clone(),
+        ));
+        state.refresh_log.record(
+            "oss_event",
+            &request(&["https://static.prts.wiki/a.png"]),
+            "17772470467",
+        );
+
+        // First cycle: still refreshing
+        reconcile(&state).await;
+        let entry = &state.refresh_log.list(None)[0];
+        assert_eq!(entry.status, Some(RefreshTaskStatus::Refreshing));
+        assert_eq!(entry.process.as_deref(), Some("50%"));
+        assert!(
+            state
+                .refresh_log
+                .list(Some(RefreshTaskStatus::Failed))
+                .is_empty()
+        );
+
+        // Second cycle: failed, reported once and never polled again
+        reconcile(&state).await;
+        reconcile(&state).await;
+        let failed = state.refresh_log.list(Some(RefreshTaskStatus::Failed));
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].description.as_deref(), Some("InternalError"));
+
+        let hook = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let requests = server.received_requests().await.unwrap();
+                if let Some(hook) = requests.into_iter().find(|r| r.url.path() == "/hook") {
+                    return hook;
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("webhook should be delivered");
+        let event: serde_json::Value = serde_json::from_slice(&hook.body).unwrap();
+        assert_eq!(event["type"], "cdn.refresh.failed");
+        assert_eq!(event["source"], "oss_event");
+        assert_eq!(event["task_id"], "17772470467");
+        assert_eq!(
+            event["object_paths"],
+            serde_json::json!(["https://static.prts.wiki/a.png"])
+        );
+        dispatcher.abort();
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_only_recent_unfinished_tasks_are_polled() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(header("x-acs-action", "DescribeRefreshTaskById"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(describe_response("Complete", "100%", None)),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let mut settings = test_settings();
+        settings.aliyun.endpoint = server.uri();
+        let state = state_from(&settings);
+        state.refresh_log.record(
+            "manual",
+            &request(&["https://static.prts.wiki/a.png"]),
+            "17772470467",
+        );
+
+        reconcile(&state).await;
+        // Complete is terminal, so nothing is left to look up
+        reconcile(&state).await;
+        assert_eq!(
+            state.refresh_log.list(Some(RefreshTaskStatus::Complete))[0]
+                .process
+                .as_deref(),
+            Some("100%")
+        );
+
+        // Tasks older than the lookback window are left alone
+        state.refresh_log.record(
+            "manual",
+            &request(&["https://static.prts.wiki/b.png"]),
+            "17772470468",
+        );
+        assert!(
+            state
+                .refresh_log
+                .unfinished(Utc::now() + chrono::Duration::seconds(1))
+                .is_empty()
+        );
+        server.verify().await;
+    }
+
+    #[test]
+    fn test_tasks_map_to_their_paths_and_the_log_is_capped() {
+        let log = RefreshLog::default();
+        log.record(
+            "manual",
+            &request(&[
+                "https://static.prts.wiki/a.png",
+                "https://static.prts.wiki/b.png",
+            ]),
+            "1,2",
+        );
+        let entries = log.list(None);
+        assert_eq!(entries[0].task_id, "2");
+        assert_eq!(entries[0].object_paths, ["https://static.prts.wiki/b.png"]);
+        assert_eq!(entries[1].object_paths, ["https://static.prts.wiki/a.png"]);
+
+        for id in 0..MAX_ENTRIES {
+            log.record(
+                "manual",
+                &request(&["https://static.prts.wiki/c.png"]),
+                &(MAX_ENTRIES + id).to_string(),
+            );
+        }
+        let entries = log.list(None);
+        assert_eq!(entries.len(), MAX_ENTRIES);
+        assert!(entries.iter().all(|entry| entry.task_id != "1"));
+    }
+}
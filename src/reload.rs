@@ -0,0 +1,296 @@
+//! Reload of the config sections that can change without a restart
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::{
+    aliyun::Credentials,
+    config::AppSettings,
+    config_check::{Severity, check_settings},
+    event_filter::EventFilter,
+    rate_limit::RateLimiters,
+    state::AppState,
+    tls::ServerTls,
+};
+
+/// Whether two config values serialize identically
+fn same<T: Serialize>(a: &T, b: &T) -> bool {
+    toml::Value::try_from(a).ok() == toml::Value::try_from(b).ok()
+}
+
+/// Swap the reload-safe sections of `next` into `state`, returning the ones that changed
+///
+/// Bilibili cookies, JWT keys, the Aliyun section and rate limits are swapped; everything
+/// else (binding, logger, HTTP client, ...) is only reported as needing a restart.
+pub fn apply_reload(
+    state: &AppState,
+    current: &AppSettings,
+    next: &AppSettings,
+) -> Vec<&'static str> {
+    let mut changed = Vec::new();
+
+    let accounts = next.bilibili.all_accounts();
+    let mut cookies_changed = false;
+    for (name, account) in &accounts {
+        let Some(client) = state.bilibili_clients.get(name) else {
+            warn!(
+                account = name,
+                "New Bilibili account needs a restart to be used"
+            );
+            continue;
+        };
+        let previous = client.account();
+        if previous.sessdata != account.sessdata || previous.bili_jct != account.bili_jct {
+            client.set_account(account.clone());
+            cookies_changed = true;
+        }
+    }
+    for name in state.bilibili_clients.keys() {
+        if !accounts.contains_key(name) {
+            warn!(
+                account = name,
+                "Removed Bilibili account stays usable until a restart"
+            );
+        }
+    }
+    if cookies_changed {
+        changed.push("bilibili");
+    }
+
+    if !same(&current.jwt, &next.jwt) {
+        state.jwt_config.store(Arc::new(next.jwt.clone()));
+        changed.push("jwt");
+    }
+
+    if !same(&current.aliyun, &next.aliyun) {
+        let (old, new) = (&current.aliyun, &next.aliyun);
+        if old.endpoint != new.endpoint
+            || old.host != new.host
+            || old.oss_endpoint != new.oss_endpoint
+            || old.event_dedup_ttl_secs != new.event_dedup_ttl_secs
+            || old.events_max_skew_secs != new.events_max_skew_secs
+            || old.url_dedup_window_secs != new.url_dedup_window_secs
+            || old.url_dedup_max_entries != new.url_dedup_max_entries
+            || old.event_id_window_secs != new.event_id_window_secs
+            || old.event_id_max_entries != new.event_id_max_entries
+            || old.events.directory_rule_window_secs != new.events.directory_rule_window_secs
+            || !same(&old.sts, &new.sts)
+            || old.credential_source != new.credential_source
+            || !same(&old.ecs_ram_role, &new.ecs_ram_role)
+            || !same(&old.refresh_budget, &new.refresh_budget)
+            || old.is_configured() != new.is_configured()
+        {
+            warn!(
+                "aliyun endpoint, host, oss_endpoint, sts, credential_source, ecs_ram_role, refresh_budget, event_dedup_ttl_secs, events_max_skew_secs, url_dedup_*, event_id_*, events.directory_rule_window_secs and enabling Aliyun need a restart"
+            );
+        }
+        let keys_changed = old.access_key_id != new.access_key_id
+            || old.access_key_secret != new.access_key_secret
+            || old.security_token != new.security_token;
+        if keys_changed && state.aliyun_credential_provider.rotates() {
+            warn!(
+                "aliyun access keys changed but are unused while STS or the ECS role supplies credentials"
+            );
+        } else if keys_changed {
+            // Calls already signed keep their signature; the next one signs with the new keys
+            let credentials = Credentials::from_config(new);
+            info!(
+                access_key_id = %credentials.key_id_prefix(),
+                previous_access_key_id = %Credentials::from_config(old).key_id_prefix(),
+                "Aliyun access keys reloaded"
+            );
+            *state
+                .aliyun_credentials
+                .write()
+                .expect("credentials lock poisoned") = credentials;
+        }
+        state.event_filter.store(Arc::new(
+            EventFilter::new(&new.events)
+                .expect("event filter patterns are validated at config load"),
+        ));
+        state.aliyun_config.store(Arc::new(new.clone()));
+        changed.push("aliyun");
+    }
+
+    if !same(&current.server.rate_limit, &next.server.rate_limit) {
+        // Rebuilding resets the buckets, so only do it when the limits changed
+        state
+            .rate_limiters
+            .store(Arc::new(RateLimiters::new(next.server.rate_limit.as_ref())));
+        changed.push("server.rate_limit");
+    }
+
+    let restart_only = [
+        ("logger", same(&current.logger, &next.logger)),
+        ("http_client", same(&current.http_client, &next.http_client)),
+        ("metrics", same(&current.metrics, &next.metrics)),
+        ("sentry", same(&current.sentry, &next.sentry)),
+        ("examples", same(&current.examples, &next.examples)),
+        ("mailer", same(&current.mailer, &next.mailer)),
+        ("webhooks", same(&current.webhooks, &next.webhooks)),
+        (
+            "server",
+            same(
+                &(
+                    &current.server.binding,
+                    current.server.port,
+                    &current.server.host,
+                    &current.server.tls,
+                ),
+                &(
+                    &next.server.binding,
+                    next.server.port,
+                    &next.server.host,
+                    &next.server.tls,
+                ),
+            ),
+        ),
+    ];
+    for (section, unchanged) in restart_only {
+        if !unchanged {
+            warn!(
+                section,
+                "Config section changed but needs a restart to apply"
+            );
+        }
+    }
+
+    changed
+}
+
+/// Re-read `path` and apply it, keeping the old settings when it doesn't load or check cleanly
+fn reload(path: &Path, state: &AppState, current: AppSettings) -> AppSettings {
+    let next = match AppSettings::new(path) {
+        Ok(next) => next,
+        Err(err) => {
+            warn!(error = %err, "Config reload rejected, keeping the current settings");
+            return current;
+        }
+    };
+    let errors = check_settings(&next)
+        .into_iter()
+        .filter(|issue| issue.severity == Severity::Error)
+        .map(|issue| format!("{}: {}", issue.path, issue.message))
+        .collect::<Vec<_>>();
+    if !errors.is_empty() {
+        warn!(
+            ?errors,
+            "Config reload rejected, keeping the current settings"
+        );
+        return current;
+    }
+
+    let changed = apply_reload(state, &current, &next);
+    info!(?changed, "Config reloaded");
+    next
+}
+
+/// Reload the config from `path`, and the TLS certificate if served, on every SIGHUP until
+/// the task is aborted
+///
+/// Does nothing on platforms without SIGHUP.
+pub async fn reload_on_sighup(
+    path: PathBuf,
+    mut current: AppSettings,
+    state: AppState,
+    tls: Option<ServerTls>,
+) {
+    #[cfg(unix)]
+    {
+        let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+            .expect("failed to install SIGHUP handler");
+        while hangup.recv().await.is_some() {
+            info!(path = %path.display(), "SIGHUP received, reloading config");
+            current = reload(&path, &state, current);
+            if let Some(tls) = &tls {
+                match tls.reload().await {
+                    Ok(()) => info!("TLS certificate reloaded"),
+                    Err(err) => warn!(
+                        error = ?err,
+                        "TLS certificate reload failed, keeping the current one"
+                    ),
+                }
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = (path, &mut current, state, tls);
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{header_regex, method},
+    };
+
+    use super::*;
+    use crate::{
+        routes::build_router,
+        test_support::{state_from, test_settings, test_token},
+    };
+
+    #[tokio::test]
+    async fn test_reloaded_aliyun_keys_sign_later_requests() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header_regex(
+                "Authorization",
+                "^ACS3-HMAC-SHA256 Credential=rotated-key-id,",
+            ))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"7"}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut current = test_settings();
+        current.aliyun.endpoint = server.uri();
+        let state = state_from(&current);
+        let router = build_router(state.clone());
+
+        let mut next = current.clone();
+        next.aliyun.access_key_id = "rotated-key-id".to_string();
+        next.aliyun.access_key_secret = "rotated-secret".to_string();
+        next.bilibili.sessdata = Some("rotated-sessdata".to_string());
+        assert_eq!(
+            apply_reload(&state, &current, &next),
+            ["bilibili", "aliyun"]
+        );
+
+        let response = router
+            .oneshot(
+                Request::post("/api/aliyun/refreshObjectCaches")
+                    .header("Authorization", format!("Bearer {}", test_token()))
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        r#"{"object_path":"https://static.prts.wiki/a.png"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            state.bilibili_clients["default"].account().sessdata,
+            "rotated-sessdata"
+        );
+        server.verify().await;
+    }
+
+    #[test]
+    fn test_unchanged_settings_reload_nothing() {
+        let settings = test_settings();
+        let state = state_from(&settings);
+        assert!(apply_reload(&state, &settings, &settings.clone()).is_empty());
+    }
+}
@@ -0,0 +1,137 @@
+use axum::{
+    Json, debug_handler,
+    extract::{Query, State},
+};
+use serde::Deserialize;
+use tracing::warn;
+use utoipa::IntoParams;
+
+use crate::{
+    debug_capture::CapturedExchange,
+    error::{AppError, AppResult, ErrorBody},
+    examples::RecordedExample,
+    read_only::ReadOnlyStatus,
+    state::AppState,
+};
+
+use super::aliyun_handlers::replay_deferred_events;
+pub use crate::api::SetReadOnlyPayload;
+
+/// Get the current read-only mode state
+#[debug_handler]
+#[utoipa::path(
+    get,
+    tag = "admin",
+    path = "/admin/readOnly",
+    responses(
+        (status = OK, body = ReadOnlyStatus),
+        (status = UNAUTHORIZED, body = ErrorBody, description = "Missing or invalid bearer token")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn get_read_only(State(state): State<AppState>) -> Json<ReadOnlyStatus> {
+    Json(state.read_only.status())
+}
+
+/// Engage or release read-only mode without a restart
+///
+/// Releasing the mode replays OSS events that were acknowledged while it was engaged.
+#[debug_handler]
+#[utoipa::path(
+    put,
+    tag = "admin",
+    path = "/admin/readOnly",
+    request_body = SetReadOnlyPayload,
+    responses(
+        (status = OK, body = ReadOnlyStatus),
+        (status = UNAUTHORIZED, body = ErrorBody, description = "Missing or invalid bearer token")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn set_read_only(
+    State(state): State<AppState>,
+    Json(payload): Json<SetReadOnlyPayload>,
+) -> Json<ReadOnlyStatus> {
+    state.read_only.set(payload.enabled, payload.message);
+    let status = state.read_only.status();
+    warn!(
+        enabled = status.enabled,
+        message = status.message.as_deref(),
+        "Read-only mode changed"
+    );
+
+    if !status.enabled {
+        tokio::spawn(replay_deferred_events(state));
+    }
+
+    Json(status)
+}
+
+/// List candidate response examples sampled from real traffic
+///
+/// Empty unless `[examples]` recording is configured.
+#[debug_handler]
+#[utoipa::path(
+    get,
+    tag = "admin",
+    path = "/admin/examples",
+    responses(
+        (status = OK, body = Vec<RecordedExample>),
+        (status = UNAUTHORIZED, body = ErrorBody, description = "Missing or invalid bearer token")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn list_examples(State(state): State<AppState>) -> Json<Vec<RecordedExample>> {
+    Json(
+        state
+            .example_recorder
+            .as_ref()
+            .map(|recorder| recorder.examples())
+            .unwrap_or_default(),
+    )
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct CaptureQuery {
+    /// Only requests whose path starts with this, e.g. `/api/bilibili/`
+    pub path: Option<String>,
+    /// Only exchanges answered with this status code
+    pub status: Option<u16>,
+}
+
+/// List recently captured requests and responses, newest first
+///
+/// Only requests under the configured route prefixes are captured, with credentials redacted
+/// and uploaded files replaced by their size.
+#[debug_handler]
+#[utoipa::path(
+    get,
+    tag = "admin",
+    path = "/debug/captures",
+    params(CaptureQuery),
+    responses(
+        (status = OK, description = "Captured exchanges; kept in memory, newest `capacity` only", body = Vec<CapturedExchange>),
+        (status = UNAUTHORIZED, body = ErrorBody, description = "Missing or invalid bearer token"),
+        (status = NOT_FOUND, body = ErrorBody, description = "`[debug.capture]` is not configured")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn list_captures(
+    State(state): State<AppState>,
+    Query(query): Query<CaptureQuery>,
+) -> AppResult<Json<Vec<CapturedExchange>>> {
+    let capture = state.request_capture.as_ref().ok_or_else(|| {
+        AppError::NotFound(anyhow::anyhow!(
+            "Debug capture is disabled, configure [debug.capture]"
+        ))
+    })?;
+    Ok(Json(capture.exchanges(query.path.as_deref(), query.status)))
+}
@@ -1,197 +1,5837 @@
-use axum::{Json, extract::State, http::HeaderMap};
-use percent_encoding::{AsciiSet, percent_encode};
-use serde::{Deserialize, Serialize};
-use tracing::info;
-use utoipa::ToSchema;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    time::Duration,
+};
+
+use anyhow::Context;
+use axum::{
+    Json,
+    body::{Body, Bytes},
+    extract::{Extension, Path, Query, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use futures::{StreamExt, stream};
+use serde::Deserialize;
+use tracing::{debug, info, warn};
+use utoipa::IntoParams;
 
-use crate::aliyun::UNRESERVED;
+pub use crate::api::aliyun::{
+    DomainStatsQuery, FailedRefreshChunk, InvokeAliyunPayload, OssBatchEventResponse, OssBucket,
+    OssData, OssEventData, OssEventPayload, OssEventResponse, OssEventResult, OssEventStatus,
+    OssEventsPayload, OssEventsResponse, OssObject, PushObjectCachesPayload,
+    PushObjectCachesResult, RawAliyunCallPayload, RefreshAndPreloadPayload,
+    RefreshAndPreloadResult, RefreshAndWaitPayload, RefreshDirectoryPayload,
+    RefreshObjectCachesPayload, RefreshObjectCachesResult,
+};
+use crate::auth::Claims;
+use crate::directory_refresh::group_by_directory;
+use crate::event_dedup::EventKey;
+use crate::event_dlq::{DeadLetter, DeadLetterPage, DeadLetterStatus, MAX_DEAD_LETTER_PAGE_SIZE};
+use crate::metrics::record_oss_event_skipped;
+use crate::refresh_jobs::RefreshJob;
+use crate::refresh_log::RefreshLogEntry;
 use crate::state::AppState;
 use crate::{
-    aliyun::{AliyunCdnClient, RefreshObjectCachesRequest},
-    error::{AppError, AppResult},
+    aliyun::{
+        CdnDomainDetail, CdnDomainLogsResponse, CdnProduct, DescribeCdnDomainLogsPayload,
+        DescribeRefreshQuotaResponse, DescribeRefreshTaskByIdResponse, DescribeRefreshTasksPayload,
+        DescribeRefreshTasksResponse, DescribeUserDomainsPayload, DescribeUserDomainsResponse,
+        DomainBpsData, DomainHitRateData, DownloadCdnDomainLogPayload, InvokeAliyunResult,
+        PushObjectCachesRequest, PushObjectCachesResponse, RefreshAndWaitResult,
+        RefreshObjectCachesRequest, RefreshObjectCachesResponse, RefreshTask, RefreshTaskStatus,
+        cdn::{
+            CacheStatus, MAX_DESCRIBE_TASK_IDS, MAX_DOMAIN_LOGS_PAGE_SIZE, MAX_PUSH_PATHS,
+            MAX_REFRESH_TASKS_PAGE_SIZE, MAX_USER_DOMAINS_PAGE_SIZE,
+        },
+        decode_object_key, normalize_directory_path, object_urls, parse_object_paths, url_host,
+        validate_object_paths,
+    },
+    config::{AliyunConfig, EventPrefixRule, EventsAuth, PrefixMode, UnknownBucketBehavior},
+    error::{AppError, AppResult, ErrorBody},
+    webhooks::WebhookEvent,
 };
-pub const URI: &AsciiSet = &UNRESERVED
-    // gen-delims
-    .remove(b':')
-    .remove(b'/')
-    .remove(b'?')
-    .remove(b'#')
-    .remove(b'[')
-    .remove(b']')
-    .remove(b'@')
-    // sub-delims
-    .remove(b'!')
-    .remove(b'$')
-    .remove(b'&')
-    .remove(b'$')
-    .remove(b'\'')
-    .remove(b'(')
-    .remove(b')')
-    .remove(b'*')
-    .remove(b'+')
-    .remove(b',')
-    .remove(b';')
-    .remove(b'=');
-
-/// OSS bucket information in event data
-#[derive(ToSchema, Serialize, Deserialize, Debug)]
-pub struct OssBucket {
-    pub name: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub arn: Option<String>,
-    #[serde(rename = "ownerIdentity", skip_serializing_if = "Option::is_none")]
-    pub owner_identity: Option<String>,
-}
-
-/// OSS object information in event data
-#[derive(ToSchema, Serialize, Deserialize, Debug)]
-pub struct OssObject {
-    pub key: String,
-    #[serde(rename = "eTag", skip_serializing_if = "Option::is_none")]
-    pub etag: Option<String>,
-    #[serde(rename = "deltaSize", skip_serializing_if = "Option::is_none")]
-    pub delta_size: Option<i64>,
-}
-
-/// OSS-specific data in event
-#[derive(ToSchema, Serialize, Deserialize, Debug)]
-pub struct OssData {
-    pub bucket: OssBucket,
-    pub object: OssObject,
-    #[serde(rename = "ossSchemaVersion", skip_serializing_if = "Option::is_none")]
-    pub oss_schema_version: Option<String>,
-}
-
-/// Complete event data structure from OSS
-#[derive(ToSchema, Serialize, Deserialize, Debug)]
-pub struct OssEventData {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub region: Option<String>,
-    #[serde(rename = "eventVersion", skip_serializing_if = "Option::is_none")]
-    pub event_version: Option<String>,
-    #[serde(rename = "eventSource", skip_serializing_if = "Option::is_none")]
-    pub event_source: Option<String>,
-    #[serde(rename = "eventName", skip_serializing_if = "Option::is_none")]
-    pub event_name: Option<String>,
-    #[serde(rename = "eventTime", skip_serializing_if = "Option::is_none")]
-    pub event_time: Option<String>,
-    pub oss: OssData,
-}
-
-/// EventBridge OSS event payload
-#[derive(ToSchema, Serialize, Deserialize, Debug)]
-pub struct OssEventPayload {
-    pub id: String,
-    pub source: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub specversion: Option<String>,
-    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
-    pub event_type: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub datacontenttype: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub subject: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub time: Option<String>,
-    pub data: OssEventData,
-}
-
-/// Response for OSS event handler
-#[derive(ToSchema, Serialize, Deserialize, Debug)]
-pub struct OssEventResponse {
-    pub message: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub task_id: Option<String>,
+
+/// Call Aliyun, or only build the request with `dry_run`; real calls are announced to the
+/// webhooks as coming from `source`
+///
+/// Paths are sent to the product serving their domain, one call per product, and the task
+/// ids of all calls are joined with commas.
+async fn refresh(
+    state: &AppState,
+    request: &RefreshObjectCachesRequest,
+    dry_run: bool,
+    source: &'static str,
+) -> AppResult<RefreshObjectCachesResponse> {
+    let client = state.aliyun_cdn()?;
+    let response = if dry_run {
+        client.refresh_object_caches_dry_run(request)?
+    } else {
+        let mut merged: Option<RefreshObjectCachesResponse> = None;
+        for request in client.route(request) {
+            let outcome = client.refresh_object_caches(&request).await;
+            state.webhooks.notify(WebhookEvent::cdn_refresh(
+                source,
+                &request,
+                outcome
+                    .as_ref()
+                    .map(|response| response.refresh_task_id.as_str()),
+            ));
+            let response = outcome?;
+            state
+                .refresh_log
+                .record(source, &request, &response.refresh_task_id);
+            match &mut merged {
+                Some(merged) => merged.append(response),
+                None => merged = Some(response),
+            }
+        }
+        merged.expect("route returns at least one request")
+    };
+    info!(
+        object_path = %request.object_path,
+        object_type = request.object_type.as_deref(),
+        task_id = %response.refresh_task_id,
+        dry_run,
+        "CDN refresh"
+    );
+    Ok(response)
 }
 
-/// Handle Aliyun EventBridge OSS events
+/// Refresh `request`, then preload the same URLs so the first visitors after the purge don't
+/// all go back to the origin
+///
+/// The refresh can't be taken back, so a failed preload comes back next to its response.
+async fn refresh_and_preload(
+    state: &AppState,
+    request: &RefreshObjectCachesRequest,
+    area: Option<String>,
+    source: &'static str,
+) -> AppResult<(
+    RefreshObjectCachesResponse,
+    AppResult<PushObjectCachesResponse>,
+)> {
+    let client = state.aliyun_cdn()?;
+    let response = refresh(state, request, false, source).await?;
+    let preloaded = client
+        .preload_object_caches(&request.object_path, area.clone())
+        .await;
+    match &preloaded {
+        Ok(preloaded) => info!(
+            object_path = %request.object_path,
+            area = area.as_deref(),
+            task_id = %preloaded.push_task_id,
+            "CDN preload after refresh"
+        ),
+        Err(err) => warn!(
+            object_path = %request.object_path,
+            refresh_task_id = %response.refresh_task_id,
+            error = %err,
+            "CDN preload after refresh failed"
+        ),
+    }
+    Ok((response, preloaded))
+}
+
+/// Refresh the chunks of a split request, up to `aliyun.refresh_concurrency` calls at a time
+///
+/// Every chunk is tried whatever happens to the others; outcomes come back in chunk order.
+async fn refresh_many(
+    state: &AppState,
+    chunks: Vec<RefreshObjectCachesRequest>,
+    dry_run: bool,
+    source: &'static str,
+) -> Vec<(
+    usize,
+    RefreshObjectCachesRequest,
+    AppResult<RefreshObjectCachesResponse>,
+)> {
+    let concurrency = state.aliyun_config.load().refresh_concurrency.get();
+    let mut outcomes = stream::iter(chunks.into_iter().enumerate())
+        .map(|(chunk, request)| async move {
+            let outcome = refresh(state, &request, dry_run, source).await;
+            (chunk, request, outcome)
+        })
+        .buffer_unordered(concurrency)
+        .collect::<Vec<_>>()
+        .await;
+    outcomes.sort_by_key(|(chunk, _, _)| *chunk);
+    outcomes
+}
+
+/// Refresh CDN caches for the given URLs
 #[utoipa::path(
     post,
     tag = "aliyun",
-    path = "/aliyun/events",
-    request_body = OssEventPayload,
+    path = "/aliyun/refreshObjectCaches",
+    request_body = RefreshObjectCachesPayload,
     responses(
-        (status = OK, description = "Successfully processed OSS event and triggered CDN refresh", body = OssEventResponse),
-        (status = UNAUTHORIZED, description = "Missing or invalid x-eventbridge-signature-token"),
-        (status = BAD_REQUEST, description = "Invalid request or unsupported bucket"),
-        (status = INTERNAL_SERVER_ERROR, description = "Internal server error")
+        (status = OK, description = "Refresh submitted, or only prepared with `dry_run`; over 1000 files or 100 directories are split into several calls, and `failed_chunks` lists those Aliyun rejected", body = RefreshObjectCachesResult),
+        (status = BAD_REQUEST, body = ErrorBody, description = "Invalid object type, object paths that aren't absolute http(s) URLs or are directories without a trailing `/`, or a parameter Aliyun rejected"),
+        (status = UNAUTHORIZED, body = ErrorBody, description = "Missing or invalid bearer token"),
+        (status = SERVICE_UNAVAILABLE, body = ErrorBody, description = "Service is in read-only mode, or Aliyun is not configured"),
+        (status = GATEWAY_TIMEOUT, body = ErrorBody, description = "Aliyun did not answer in time"),
+        (status = TOO_MANY_REQUESTS, body = ErrorBody, description = "Aliyun throttled the call or the quota is used up"),
+        (status = BAD_GATEWAY, body = ErrorBody, description = "Aliyun rejected every call of the refresh; `exception` carries its error")
     ),
     security(
-        ("eventbridge_token" = [])
+        ("bearer_auth" = [])
     )
 )]
-pub async fn handle_oss_events(
+pub async fn refresh_object_caches(
     State(state): State<AppState>,
-    headers: HeaderMap,
-    Json(raw_payload): Json<serde_json::Value>,
-) -> AppResult<Json<OssEventResponse>> {
-    let token = headers
-        .get("x-eventbridge-signature-token")
-        .ok_or_else(|| {
-            AppError::Unauthorized(anyhow::anyhow!(
-                "Missing x-eventbridge-signature-token header"
-            ))
-        })?
-        .to_str()
-        .map_err(|_| {
-            AppError::Unauthorized(anyhow::anyhow!(
-                "Invalid x-eventbridge-signature-token header format"
-            ))
-        })?
-        .trim();
+    Json(payload): Json<RefreshObjectCachesPayload>,
+) -> AppResult<Json<RefreshObjectCachesResult>> {
+    state.aliyun_cdn()?;
+    let object_type = payload.object_type.unwrap_or_else(|| "File".to_string());
+    if object_type != "File" && object_type != "Directory" {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "object_type must be File or Directory, got '{}'",
+            object_type
+        )));
+    }
+    let object_paths = parse_object_paths(&payload.object_path, &object_type)?;
 
-    crate::auth::verify_token(token, &state.jwt_config.public_key).map_err(|err| {
-        AppError::Unauthorized(anyhow::anyhow!(
-            "JWT verification failed (x-eventbridge-signature-token): {err}"
-        ))
-    })?;
+    let request = RefreshObjectCachesRequest {
+        object_path: object_paths.join("\n"),
+        object_type: Some(object_type.clone()),
+        force: payload.force,
+        product: payload.product,
+    };
+    // A rejected chunk doesn't stop the rest
+    let mut task_ids = Vec::new();
+    let mut aliyun_request_id = None;
+    let mut failed_chunks = Vec::new();
+    let mut first_error = None;
+    for (chunk, request, outcome) in
+        refresh_many(&state, request.split(), payload.dry_run, "manual").await
+    {
+        match outcome {
+            Ok(response) => {
+                if !payload.dry_run {
+                    aliyun_request_id.get_or_insert(response.request_id);
+                }
+                task_ids.push(response.refresh_task_id);
+            }
+            Err(err) => {
+                warn!(chunk, error = %err, "CDN refresh chunk failed");
+                failed_chunks.push(FailedRefreshChunk {
+                    chunk,
+                    object_paths: request.object_path.lines().map(str::to_string).collect(),
+                    error: format!("{err:#}"),
+                });
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+    let Some(task_id) = task_ids.first().cloned() else {
+        return Err(first_error.expect("every chunk either succeeds or fails"));
+    };
 
-    // Parse the raw JSON into OssEventPayload
-    let payload: OssEventPayload = serde_json::from_value(raw_payload).map_err(|err| {
-        AppError::BadRequest(anyhow::anyhow!(
-            "Failed to parse OSS event payload: {}",
-            err
-        ))
-    })?;
+    Ok(Json(RefreshObjectCachesResult {
+        task_id,
+        task_ids,
+        object_type,
+        object_paths,
+        dry_run: payload.dry_run,
+        failed_chunks,
+        aliyun_request_id,
+    }))
+}
 
-    info!(
-        event = ?payload,
-        "Received OSS event"
-    );
+/// Longest `timeout_seconds` a waiting refresh may hold its request open
+const MAX_WAIT_TIMEOUT_SECS: u64 = 600;
 
-    let bucket_name = &payload.data.oss.bucket.name;
-    let object_key = &payload.data.oss.object.key;
+/// Refresh CDN caches and wait until Aliyun has finished
+///
+/// The task is looked up with DescribeRefreshTasks right after it is submitted, then every
+/// `poll_interval_seconds` until it is `Complete` or `Failed`. Only paths of CDN (not DCDN)
+/// domains that fit a single call can be waited on.
+#[utoipa::path(
+    post,
+    tag = "aliyun",
+    path = "/aliyun/refreshObjectCaches:wait",
+    request_body = RefreshAndWaitPayload,
+    responses(
+        (status = OK, description = "The task finished; `status` tells whether it completed or failed", body = RefreshAndWaitResult),
+        (status = ACCEPTED, description = "The task didn't finish within `timeout_seconds`; keep polling `GET /api/aliyun/refreshTasks/{task_id}`", body = RefreshAndWaitResult),
+        (status = BAD_REQUEST, body = ErrorBody, description = "Invalid object type or object paths, more paths than one call accepts, DCDN domains, a timeout or poll interval out of range, or a parameter Aliyun rejected"),
+        (status = UNAUTHORIZED, body = ErrorBody, description = "Missing or invalid bearer token"),
+        (status = SERVICE_UNAVAILABLE, body = ErrorBody, description = "Service is in read-only mode, or Aliyun is not configured"),
+        (status = GATEWAY_TIMEOUT, body = ErrorBody, description = "Aliyun did not answer in time"),
+        (status = TOO_MANY_REQUESTS, body = ErrorBody, description = "Aliyun throttled the call, the quota is used up, or the refresh budget has no room"),
+        (status = BAD_GATEWAY, body = ErrorBody, description = "Aliyun rejected the refresh; `exception` carries its error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn refresh_object_caches_and_wait(
+    State(state): State<AppState>,
+    Json(payload): Json<RefreshAndWaitPayload>,
+) -> AppResult<(StatusCode, Json<RefreshAndWaitResult>)> {
+    let client = state.aliyun_cdn()?;
+    if !(1..=MAX_WAIT_TIMEOUT_SECS).contains(&payload.timeout_seconds) {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "timeout_seconds must be between 1 and {}, got {}",
+            MAX_WAIT_TIMEOUT_SECS,
+            payload.timeout_seconds
+        )));
+    }
+    if payload.poll_interval_seconds == 0 {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "poll_interval_seconds must be at least 1"
+        )));
+    }
+    let object_type = payload.object_type.unwrap_or_else(|| "File".to_string());
+    if object_type != "File" && object_type != "Directory" {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "object_type must be File or Directory, got '{}'",
+            object_type
+        )));
+    }
 
-    // Get URL template from bucket map
-    let url_template = state
-        .aliyun_config
-        .bucket_url_map
-        .get(bucket_name)
-        .ok_or_else(|| {
-            AppError::BadRequest(anyhow::anyhow!("Unsupported bucket: {}", bucket_name))
-        })?;
+    let request = RefreshObjectCachesRequest {
+        object_path: validate_object_paths(&payload.object_path, &object_type)?.join("\n"),
+        object_type: Some(object_type),
+        force: payload.force,
+        product: None,
+    };
+    client.ensure_waitable(&request)?;
+    let response = refresh(&state, &request, false, "manual").await?;
+    let mut result = client
+        .wait_for_refresh_task(
+            &response.refresh_task_id,
+            Duration::from_secs(payload.timeout_seconds),
+            Duration::from_secs(payload.poll_interval_seconds),
+        )
+        .await?;
+    result.aliyun_request_id = Some(response.request_id);
+    let status = if result.timed_out {
+        StatusCode::ACCEPTED
+    } else {
+        StatusCode::OK
+    };
+    Ok((status, Json(result)))
+}
+
+/// Refresh whole CDN directories
+///
+/// Each directory is trimmed, loses any query string or fragment and gains a trailing `/` when
+/// it lacks one, so it can't be mistaken for a file; duplicates after that are refreshed once.
+/// Blank entries are ignored. The directories go out as `ObjectType=Directory` in a single call
+/// per product, so at most 100 are accepted.
+#[utoipa::path(
+    post,
+    tag = "aliyun",
+    path = "/aliyun/refreshDirectory",
+    request_body = RefreshDirectoryPayload,
+    responses(
+        (status = OK, description = "Refresh submitted, or only prepared with `dry_run`; `object_paths` are the normalized directories", body = RefreshObjectCachesResult),
+        (status = BAD_REQUEST, body = ErrorBody, description = "No directory, more than 100, directories that aren't absolute http(s) URLs, or a parameter Aliyun rejected"),
+        (status = UNAUTHORIZED, body = ErrorBody, description = "Missing or invalid bearer token"),
+        (status = SERVICE_UNAVAILABLE, body = ErrorBody, description = "Service is in read-only mode, or Aliyun is not configured"),
+        (status = GATEWAY_TIMEOUT, body = ErrorBody, description = "Aliyun did not answer in time"),
+        (status = TOO_MANY_REQUESTS, body = ErrorBody, description = "Aliyun throttled the call, the quota is used up, or the refresh budget has no room"),
+        (status = BAD_GATEWAY, body = ErrorBody, description = "Aliyun rejected the refresh; `exception` carries its error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn refresh_directory(
+    State(state): State<AppState>,
+    Json(payload): Json<RefreshDirectoryPayload>,
+) -> AppResult<Json<RefreshObjectCachesResult>> {
+    state.aliyun_cdn()?;
+    let mut directories = Vec::new();
+    for directory in payload.directories.iter().filter(|d| !d.trim().is_empty()) {
+        let directory = normalize_directory_path(directory);
+        if !directories.contains(&directory) {
+            directories.push(directory);
+        }
+    }
+    let object_paths = validate_object_paths(&directories.join("\n"), "Directory")?;
+
+    let request = RefreshObjectCachesRequest {
+        object_path: object_paths.join("\n"),
+        object_type: Some("Directory".to_string()),
+        force: payload.force,
+        product: payload.product,
+    };
+    let response = refresh(&state, &request, payload.dry_run, "manual").await?;
+    let task_ids = split_task_ids(&response.refresh_task_id);
+
+    Ok(Json(RefreshObjectCachesResult {
+        task_id: task_ids
+            .first()
+            .cloned()
+            .unwrap_or(response.refresh_task_id),
+        task_ids,
+        object_type: "Directory".to_string(),
+        object_paths,
+        dry_run: payload.dry_run,
+        failed_chunks: Vec::new(),
+        aliyun_request_id: (!payload.dry_run).then_some(response.request_id),
+    }))
+}
+
+/// Preload the given URLs onto CDN edge nodes
+#[utoipa::path(
+    post,
+    tag = "aliyun",
+    path = "/aliyun/pushObjectCaches",
+    request_body = PushObjectCachesPayload,
+    responses(
+        (status = OK, description = "Preload submitted", body = PushObjectCachesResult),
+        (status = BAD_REQUEST, body = ErrorBody, description = "Invalid area, object paths that aren't absolute http(s) URLs or exceed 100 URLs, or a parameter Aliyun rejected"),
+        (status = UNAUTHORIZED, body = ErrorBody, description = "Missing or invalid bearer token"),
+        (status = SERVICE_UNAVAILABLE, body = ErrorBody, description = "Service is in read-only mode, or Aliyun is not configured"),
+        (status = GATEWAY_TIMEOUT, body = ErrorBody, description = "Aliyun did not answer in time"),
+        (status = TOO_MANY_REQUESTS, body = ErrorBody, description = "Aliyun throttled the call or the quota is used up"),
+        (status = BAD_GATEWAY, body = ErrorBody, description = "Aliyun rejected the preload; `exception` carries its error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn push_object_caches(
+    State(state): State<AppState>,
+    Json(payload): Json<PushObjectCachesPayload>,
+) -> AppResult<Json<PushObjectCachesResult>> {
+    let client = state.aliyun_cdn()?;
+    if let Some(area) = &payload.area
+        && area != "domestic"
+        && area != "overseas"
+    {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "area must be domestic or overseas, got '{}'",
+            area
+        )));
+    }
+    let object_paths = validate_object_paths(&payload.object_path, "File")?;
+    if object_paths.len() > MAX_PUSH_PATHS {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "object_path has {} entries, Aliyun preloads at most {} per call",
+            object_paths.len(),
+            MAX_PUSH_PATHS
+        )));
+    }
+
+    let request = PushObjectCachesRequest {
+        object_path: object_paths.join("\n"),
+        area: payload.area,
+        l2_preload: payload.l2_preload,
+    };
+    let response = client.push_object_caches(&request).await?;
+    info!(
+        object_path = %request.object_path,
+        area = request.area.as_deref(),
+        task_id = %response.push_task_id,
+        "CDN preload"
+    );
 
-    // Build the full URL by replacing {object_key} with the actual encoded object key
-    let encoded_object_key = percent_encode(object_key.as_bytes(), URI).to_string();
-    let object_url = url_template.replace("{object_key}", &encoded_object_key);
+    Ok(Json(PushObjectCachesResult {
+        task_id: response.push_task_id,
+        object_paths,
+        aliyun_request_id: Some(response.request_id),
+    }))
+}
 
-    // Create CDN client
-    let client = AliyunCdnClient::new(&state.aliyun_config, state.http_client.clone());
+/// Refresh the given URLs, then preload them again
+///
+/// A preload that fails after the refresh went through answers `207` with `preload_error`
+/// instead of an error, since the refresh itself can't be retried away.
+#[utoipa::path(
+    post,
+    tag = "aliyun",
+    path = "/aliyun/refreshAndPreload",
+    request_body = RefreshAndPreloadPayload,
+    responses(
+        (status = OK, description = "Refresh and preload submitted", body = RefreshAndPreloadResult),
+        (status = MULTI_STATUS, description = "Refresh submitted but the preload failed; `preload_error` tells why", body = RefreshAndPreloadResult),
+        (status = BAD_REQUEST, body = ErrorBody, description = "Invalid area, object paths that aren't absolute http(s) URLs or exceed 100 URLs, or a parameter Aliyun rejected"),
+        (status = UNAUTHORIZED, body = ErrorBody, description = "Missing or invalid bearer token"),
+        (status = SERVICE_UNAVAILABLE, body = ErrorBody, description = "Service is in read-only mode, or Aliyun is not configured"),
+        (status = GATEWAY_TIMEOUT, body = ErrorBody, description = "Aliyun did not answer the refresh in time"),
+        (status = TOO_MANY_REQUESTS, body = ErrorBody, description = "Aliyun throttled the refresh, the quota is used up, or the refresh budget has no room"),
+        (status = BAD_GATEWAY, body = ErrorBody, description = "Aliyun rejected the refresh; `exception` carries its error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn refresh_and_preload_object_caches(
+    State(state): State<AppState>,
+    Json(payload): Json<RefreshAndPreloadPayload>,
+) -> AppResult<(StatusCode, Json<RefreshAndPreloadResult>)> {
+    state.aliyun_cdn()?;
+    if let Some(area) = &payload.area
+        && area != "domestic"
+        && area != "overseas"
+    {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "area must be domestic or overseas, got '{}'",
+            area
+        )));
+    }
+    let object_paths = validate_object_paths(&payload.object_path, "File")?;
+    if object_paths.len() > MAX_PUSH_PATHS {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "object_path has {} entries, Aliyun preloads at most {} per call",
+            object_paths.len(),
+            MAX_PUSH_PATHS
+        )));
+    }
 
-    // Refresh the object cache
     let request = RefreshObjectCachesRequest {
-        object_path: object_url.clone(),
+        object_path: object_paths.join("\n"),
         object_type: Some("File".to_string()),
         force: Some(false),
+        product: None,
     };
+    let (response, preloaded) =
+        refresh_and_preload(&state, &request, payload.area, "manual").await?;
+    let (status, preload_task_id, preload_error) = match preloaded {
+        Ok(preloaded) => (StatusCode::OK, Some(preloaded.push_task_id), None),
+        Err(err) => (StatusCode::MULTI_STATUS, None, Some(format!("{err:#}"))),
+    };
+    Ok((
+        status,
+        Json(RefreshAndPreloadResult {
+            refresh_task_id: response.refresh_task_id,
+            preload_task_id,
+            preload_error,
+            object_paths,
+        }),
+    ))
+}
+
+/// Call any CDN OpenAPI action and return Aliyun's raw JSON answer
+///
+/// Disabled unless `aliyun.allow_raw_api` is set.
+#[utoipa::path(
+    post,
+    tag = "aliyun",
+    path = "/aliyun/raw",
+    request_body = RawAliyunCallPayload,
+    responses(
+        (status = OK, description = "Aliyun's response", body = Object),
+        (status = BAD_REQUEST, body = ErrorBody, description = "Unsupported method"),
+        (status = UNAUTHORIZED, body = ErrorBody, description = "Missing or invalid bearer token"),
+        (status = NOT_FOUND, body = ErrorBody, description = "The raw API is disabled, or Aliyun reported a missing resource"),
+        (status = SERVICE_UNAVAILABLE, body = ErrorBody, description = "Service is in read-only mode, or Aliyun is not configured"),
+        (status = GATEWAY_TIMEOUT, body = ErrorBody, description = "Aliyun did not answer in time"),
+        (status = TOO_MANY_REQUESTS, body = ErrorBody, description = "Aliyun throttled the call or the quota is used up"),
+        (status = BAD_GATEWAY, body = ErrorBody, description = "Aliyun rejected the call; `exception` carries its error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn raw_aliyun_call(
+    State(state): State<AppState>,
+    Json(payload): Json<RawAliyunCallPayload>,
+) -> AppResult<Json<serde_json::Value>> {
+    if !state.aliyun_config.load().allow_raw_api {
+        return Err(AppError::NotFound(anyhow::anyhow!(
+            "The raw Aliyun API is disabled, set aliyun.allow_raw_api to enable it"
+        )));
+    }
+    let client = state.aliyun_cdn()?;
+    let method = openapi_method(&payload.method)?;
 
-    let response = client.refresh_object_caches(&request).await?;
+    warn!(action = %payload.action, version = %payload.version, "Raw Aliyun API call");
+    let response = client
+        .call(
+            &payload.action,
+            &payload.version,
+            method,
+            payload.params,
+            None,
+        )
+        .await?;
+    Ok(Json(response))
+}
 
-    Ok(Json(OssEventResponse {
-        message: format!(
-            "CDN refresh triggered for {} in bucket {}",
-            object_key, bucket_name
-        ),
-        task_id: Some(response.refresh_task_id),
-    }))
+/// Call an allowlisted CDN OpenAPI action and return Aliyun's answer with its status
+///
+/// Only actions listed in `aliyun.invoke_allowed_actions` are sent. Aliyun's error answers are
+/// returned as they are rather than mapped to an error response.
+#[utoipa::path(
+    post,
+    tag = "aliyun",
+    path = "/aliyun/invoke",
+    request_body = InvokeAliyunPayload,
+    responses(
+        (status = OK, description = "Aliyun's status and response", body = InvokeAliyunResult),
+        (status = BAD_REQUEST, body = ErrorBody, description = "Unsupported method"),
+        (status = UNAUTHORIZED, body = ErrorBody, description = "Missing or invalid bearer token"),
+        (status = FORBIDDEN, body = ErrorBody, description = "The action is not in `aliyun.invoke_allowed_actions`"),
+        (status = SERVICE_UNAVAILABLE, body = ErrorBody, description = "Service is in read-only mode, or Aliyun is not configured"),
+        (status = GATEWAY_TIMEOUT, body = ErrorBody, description = "Aliyun did not answer in time")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn invoke_aliyun(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Json(payload): Json<InvokeAliyunPayload>,
+) -> AppResult<Json<InvokeAliyunResult>> {
+    let allowed = state
+        .aliyun_config
+        .load()
+        .invoke_allowed_actions
+        .contains(&payload.action);
+    if !allowed {
+        warn!(action = %payload.action, subject = %claims.sub, "Aliyun action not allowed");
+        return Err(AppError::Forbidden(anyhow::anyhow!(
+            "{} is not in aliyun.invoke_allowed_actions",
+            payload.action
+        )));
+    }
+    let client = state.aliyun_cdn()?;
+    let method = openapi_method(&payload.method)?;
+
+    let result = client
+        .invoke(
+            &payload.action,
+            &payload.version,
+            method,
+            payload.query_params,
+            payload.body,
+        )
+        .await?;
+    info!(
+        action = %payload.action,
+        subject = %claims.sub,
+        status = result.status,
+        aliyun_request_id = result.aliyun_request_id.as_deref(),
+        "Invoked Aliyun action"
+    );
+    Ok(Json(result))
+}
+
+/// HTTP method of an OpenAPI call, which Aliyun takes as `GET` or `POST`
+fn openapi_method(method: &str) -> AppResult<reqwest::Method> {
+    match method.to_ascii_uppercase().as_str() {
+        "GET" => Ok(reqwest::Method::GET),
+        "POST" => Ok(reqwest::Method::POST),
+        other => Err(AppError::BadRequest(anyhow::anyhow!(
+            "method must be GET or POST, got '{}'",
+            other
+        ))),
+    }
+}
+
+/// Look up refresh tasks by id, cheaper to poll than filtered task listings
+#[utoipa::path(
+    get,
+    tag = "aliyun",
+    path = "/aliyun/refreshTask/{task_id}",
+    params(
+        ("task_id" = String, Path, description = "Refresh task id, or up to 10 comma-separated ids")
+    ),
+    responses(
+        (status = OK, body = DescribeRefreshTaskByIdResponse),
+        (status = BAD_REQUEST, body = ErrorBody, description = "Malformed or too many task ids"),
+        (status = UNAUTHORIZED, body = ErrorBody, description = "Missing or invalid bearer token"),
+        (status = NOT_FOUND, body = ErrorBody, description = "No such refresh task"),
+        (status = SERVICE_UNAVAILABLE, body = ErrorBody, description = "Aliyun is not configured"),
+        (status = GATEWAY_TIMEOUT, body = ErrorBody, description = "Aliyun did not answer in time"),
+        (status = TOO_MANY_REQUESTS, body = ErrorBody, description = "Aliyun throttled the call or the quota is used up"),
+        (status = BAD_GATEWAY, body = ErrorBody, description = "Aliyun rejected the lookup; `exception` carries its error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn describe_refresh_task(
+    State(state): State<AppState>,
+    Path(task_id): Path<String>,
+) -> AppResult<Json<DescribeRefreshTaskByIdResponse>> {
+    let client = state.aliyun_cdn()?;
+    let task_ids = task_id
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .collect::<Vec<_>>();
+    if task_ids.is_empty() || task_ids.len() > MAX_DESCRIBE_TASK_IDS {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "Expected 1 to {} comma-separated task ids, got {}",
+            MAX_DESCRIBE_TASK_IDS,
+            task_ids.len()
+        )));
+    }
+    if let Some(id) = task_ids
+        .iter()
+        .find(|id| !id.chars().all(|c| c.is_ascii_digit()))
+    {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "Task id '{}' is not numeric",
+            id
+        )));
+    }
+
+    let response = client
+        .describe_refresh_task_by_id(&task_ids.join(","))
+        .await?;
+    Ok(Json(response))
+}
+
+/// Look up one refresh task through DescribeRefreshTasks, returning the task itself
+#[utoipa::path(
+    get,
+    tag = "aliyun",
+    path = "/aliyun/refreshTasks/{task_id}",
+    params(
+        ("task_id" = String, Path, description = "Refresh task id")
+    ),
+    responses(
+        (status = OK, body = RefreshTask),
+        (status = BAD_REQUEST, body = ErrorBody, description = "Task id is not numeric"),
+        (status = UNAUTHORIZED, body = ErrorBody, description = "Missing or invalid bearer token"),
+        (status = NOT_FOUND, body = ErrorBody, description = "No such refresh task"),
+        (status = SERVICE_UNAVAILABLE, body = ErrorBody, description = "Aliyun is not configured"),
+        (status = GATEWAY_TIMEOUT, body = ErrorBody, description = "Aliyun did not answer in time"),
+        (status = TOO_MANY_REQUESTS, body = ErrorBody, description = "Aliyun throttled the call or the quota is used up"),
+        (status = BAD_GATEWAY, body = ErrorBody, description = "Aliyun rejected the lookup; `exception` carries its error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn get_refresh_task(
+    State(state): State<AppState>,
+    Path(task_id): Path<String>,
+) -> AppResult<Json<RefreshTask>> {
+    let client = state.aliyun_cdn()?;
+    if task_id.is_empty() || !task_id.chars().all(|c| c.is_ascii_digit()) {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "Task id '{}' is not numeric",
+            task_id
+        )));
+    }
+
+    let response = client
+        .describe_refresh_tasks(&DescribeRefreshTasksPayload {
+            task_id: Some(task_id.clone()),
+            ..Default::default()
+        })
+        .await?;
+    if response.total_count == 0 {
+        return Err(AppError::NotFound(anyhow::anyhow!(
+            "Refresh task {} not found",
+            task_id
+        )));
+    }
+    let task = response.tasks.cdn_tasks.into_iter().next().ok_or_else(|| {
+        AppError::InternalError(anyhow::anyhow!(
+            "Aliyun counted refresh task {} but returned no task",
+            task_id
+        ))
+    })?;
+    Ok(Json(task))
+}
+
+/// Today's refresh and preload quota and how much of it is left
+///
+/// Aliyun rejects refreshes once a quota is used up, so callers can check before a large batch.
+#[utoipa::path(
+    get,
+    tag = "aliyun",
+    path = "/aliyun/refreshQuota",
+    responses(
+        (status = OK, body = DescribeRefreshQuotaResponse),
+        (status = UNAUTHORIZED, body = ErrorBody, description = "Missing or invalid bearer token"),
+        (status = SERVICE_UNAVAILABLE, body = ErrorBody, description = "Aliyun is not configured"),
+        (status = GATEWAY_TIMEOUT, body = ErrorBody, description = "Aliyun did not answer in time"),
+        (status = TOO_MANY_REQUESTS, body = ErrorBody, description = "Aliyun throttled the call or the quota is used up"),
+        (status = BAD_GATEWAY, body = ErrorBody, description = "Aliyun rejected the lookup; `exception` carries its error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn describe_refresh_quota(
+    State(state): State<AppState>,
+) -> AppResult<Json<DescribeRefreshQuotaResponse>> {
+    let response = state.aliyun_cdn()?.describe_refresh_quota().await?;
+    Ok(Json(response))
+}
+
+/// List the account's CDN domains, to tell whether a domain is onboarded and online
+#[utoipa::path(
+    get,
+    tag = "aliyun",
+    path = "/aliyun/domains",
+    params(DescribeUserDomainsPayload),
+    responses(
+        (status = OK, body = DescribeUserDomainsResponse),
+        (status = BAD_REQUEST, body = ErrorBody, description = "Page number below 1 or page size outside 1 to 500"),
+        (status = UNAUTHORIZED, body = ErrorBody, description = "Missing or invalid bearer token"),
+        (status = SERVICE_UNAVAILABLE, body = ErrorBody, description = "Aliyun is not configured"),
+        (status = GATEWAY_TIMEOUT, body = ErrorBody, description = "Aliyun did not answer in time"),
+        (status = TOO_MANY_REQUESTS, body = ErrorBody, description = "Aliyun throttled the call"),
+        (status = BAD_GATEWAY, body = ErrorBody, description = "Aliyun rejected the listing; `exception` carries its error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn describe_user_domains(
+    State(state): State<AppState>,
+    Query(query): Query<DescribeUserDomainsPayload>,
+) -> AppResult<Json<DescribeUserDomainsResponse>> {
+    let client = state.aliyun_cdn()?;
+    if query.page_number == Some(0) {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "page_number starts at 1"
+        )));
+    }
+    if let Some(size) = query.page_size
+        && !(1..=MAX_USER_DOMAINS_PAGE_SIZE).contains(&size)
+    {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "page_size must be between 1 and {}, got {}",
+            MAX_USER_DOMAINS_PAGE_SIZE,
+            size
+        )));
+    }
+
+    Ok(Json(client.describe_user_domains(&query).await?))
+}
+
+/// Longest window the statistics endpoints ask Aliyun about
+const MAX_STATS_WINDOW_DAYS: i64 = 90;
+
+/// Data point intervals Aliyun offers, in seconds
+const STATS_INTERVALS: [u32; 3] = [300, 3600, 86400];
+
+/// Check a statistics query, returning its window in UTC
+fn stats_window(query: &DomainStatsQuery) -> AppResult<(DateTime<Utc>, DateTime<Utc>)> {
+    require_domain(&query.domain_name)?;
+    let parse = |name: &str, value: &str| {
+        DateTime::parse_from_rfc3339(value)
+            .map(|time| time.with_timezone(&Utc))
+            .map_err(|err| {
+                AppError::BadRequest(anyhow::anyhow!(
+                    "{name} must be an RFC 3339 time, got '{value}': {err}"
+                ))
+            })
+    };
+    let start = parse("start_time", &query.start_time)?;
+    let end = parse("end_time", &query.end_time)?;
+    if start >= end {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "start_time must be before end_time"
+        )));
+    }
+    if end - start > chrono::Duration::days(MAX_STATS_WINDOW_DAYS) {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "The window may span at most {MAX_STATS_WINDOW_DAYS} days"
+        )));
+    }
+    if let Some(interval) = query.interval
+        && !STATS_INTERVALS.contains(&interval)
+    {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "interval must be 300, 3600 or 86400, got {interval}"
+        )));
+    }
+    Ok((start, end))
+}
+
+/// Bandwidth of a CDN domain over a window, to see what a refresh cost
+#[utoipa::path(
+    get,
+    tag = "aliyun",
+    path = "/aliyun/stats/bps",
+    params(DomainStatsQuery),
+    responses(
+        (status = OK, body = DomainBpsData),
+        (status = BAD_REQUEST, body = ErrorBody, description = "Missing domain, times that aren't RFC 3339, a window not ascending or over 90 days, or an unknown interval"),
+        (status = UNAUTHORIZED, body = ErrorBody, description = "Missing or invalid bearer token"),
+        (status = SERVICE_UNAVAILABLE, body = ErrorBody, description = "Aliyun is not configured"),
+        (status = GATEWAY_TIMEOUT, body = ErrorBody, description = "Aliyun did not answer in time"),
+        (status = TOO_MANY_REQUESTS, body = ErrorBody, description = "Aliyun throttled the call"),
+        (status = BAD_GATEWAY, body = ErrorBody, description = "Aliyun rejected the query; `exception` carries its error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn describe_domain_bps_data(
+    State(state): State<AppState>,
+    Query(query): Query<DomainStatsQuery>,
+) -> AppResult<Json<DomainBpsData>> {
+    let client = state.aliyun_cdn()?;
+    let (start, end) = stats_window(&query)?;
+    let data = client
+        .describe_domain_bps_data(&query.domain_name, start, end, query.interval)
+        .await?;
+    Ok(Json(data))
+}
+
+/// Byte hit rate of a CDN domain over a window, to see whether a refresh hurt it
+#[utoipa::path(
+    get,
+    tag = "aliyun",
+    path = "/aliyun/stats/hitRate",
+    params(DomainStatsQuery),
+    responses(
+        (status = OK, body = DomainHitRateData),
+        (status = BAD_REQUEST, body = ErrorBody, description = "Missing domain, times that aren't RFC 3339, a window not ascending or over 90 days, or an unknown interval"),
+        (status = UNAUTHORIZED, body = ErrorBody, description = "Missing or invalid bearer token"),
+        (status = SERVICE_UNAVAILABLE, body = ErrorBody, description = "Aliyun is not configured"),
+        (status = GATEWAY_TIMEOUT, body = ErrorBody, description = "Aliyun did not answer in time"),
+        (status = TOO_MANY_REQUESTS, body = ErrorBody, description = "Aliyun throttled the call"),
+        (status = BAD_GATEWAY, body = ErrorBody, description = "Aliyun rejected the query; `exception` carries its error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn describe_domain_hit_rate_data(
+    State(state): State<AppState>,
+    Query(query): Query<DomainStatsQuery>,
+) -> AppResult<Json<DomainHitRateData>> {
+    let client = state.aliyun_cdn()?;
+    let (start, end) = stats_window(&query)?;
+    let data = client
+        .describe_domain_hit_rate_data(&query.domain_name, start, end, query.interval)
+        .await?;
+    Ok(Json(data))
+}
+
+/// Configuration of one CDN domain: status, HTTPS, origins
+#[utoipa::path(
+    get,
+    tag = "aliyun",
+    path = "/aliyun/domains/{domain_name}",
+    params(
+        ("domain_name" = String, Path, description = "Accelerated domain, e.g. `static.prts.wiki`")
+    ),
+    responses(
+        (status = OK, body = CdnDomainDetail),
+        (status = UNAUTHORIZED, body = ErrorBody, description = "Missing or invalid bearer token"),
+        (status = NOT_FOUND, body = ErrorBody, description = "The domain is not on CDN"),
+        (status = SERVICE_UNAVAILABLE, body = ErrorBody, description = "Aliyun is not configured"),
+        (status = GATEWAY_TIMEOUT, body = ErrorBody, description = "Aliyun did not answer in time"),
+        (status = TOO_MANY_REQUESTS, body = ErrorBody, description = "Aliyun throttled the call"),
+        (status = BAD_GATEWAY, body = ErrorBody, description = "Aliyun rejected the lookup; `exception` carries its error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn describe_cdn_domain_detail(
+    State(state): State<AppState>,
+    Path(domain_name): Path<String>,
+) -> AppResult<Json<CdnDomainDetail>> {
+    let client = state.aliyun_cdn()?;
+    Ok(Json(client.describe_cdn_domain_detail(&domain_name).await?))
+}
+
+/// Longest window DescribeRefreshTasks accepts between `StartTime` and `EndTime`
+const MAX_REFRESH_TASKS_WINDOW_DAYS: i64 = 3;
+
+/// Rewrite the RFC 3339 times of a task listing in the UTC form Aliyun wants
+///
+/// Aliyun answers times with an offset with an error or nothing at all, so they are converted
+/// here, and a window it would reject is refused up front.
+fn normalize_task_window(payload: &mut DescribeRefreshTasksPayload) -> AppResult<()> {
+    let parse = |name: &str, value: &Option<String>| {
+        value
+            .as_deref()
+            .map(|value| {
+                DateTime::parse_from_rfc3339(value.trim())
+                    .map(|time| time.with_timezone(&Utc))
+                    .map_err(|err| {
+                        AppError::BadRequest(anyhow::anyhow!(
+                            "{name} must be an RFC 3339 time, got '{value}': {err}"
+                        ))
+                    })
+            })
+            .transpose()
+    };
+    let start = parse("start_time", &payload.start_time)?;
+    let end = parse("end_time", &payload.end_time)?;
+    if let (Some(start), Some(end)) = (start, end) {
+        if start >= end {
+            return Err(AppError::BadRequest(anyhow::anyhow!(
+                "start_time must be before end_time"
+            )));
+        }
+        let max_window = chrono::Duration::days(MAX_REFRESH_TASKS_WINDOW_DAYS);
+        if end - start > max_window {
+            return Err(AppError::BadRequest(anyhow::anyhow!(
+                "The window may span at most {MAX_REFRESH_TASKS_WINDOW_DAYS} days: end_time must be \
+                 at most {} for this start_time, or start_time at least {} for this end_time",
+                aliyun_time(start + max_window),
+                aliyun_time(end - max_window)
+            )));
+        }
+    }
+    payload.start_time = start.map(aliyun_time);
+    payload.end_time = end.map(aliyun_time);
+    Ok(())
+}
+
+/// `time` as Aliyun's `yyyy-MM-ddTHH:mm:ssZ`
+fn aliyun_time(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+/// Response header telling whether `describeRefreshTasks` was answered from the cache
+const CACHE_HEADER: &str = "x-janus-cache";
+
+/// List refresh tasks, one page or with `fetch_all` every page
+#[utoipa::path(
+    post,
+    tag = "aliyun",
+    path = "/aliyun/describeRefreshTasks",
+    request_body = DescribeRefreshTasksPayload,
+    responses(
+        (status = OK, description = "Matching tasks; `Warning` is set when `fetch_all` stopped before `TotalCount`, at `aliyun.fetch_all.max_pages`, `max_results` or a page Aliyun repeated", body = DescribeRefreshTasksResponse,
+            headers(("x-janus-cache" = String, description = "`hit` when the answer came from `aliyun.tasks_cache`, `miss` otherwise; absent with the cache disabled"))),
+        (status = BAD_REQUEST, body = ErrorBody, description = "Page number below 1, page size outside 1 to 100, `max_results` of 0, times that aren't RFC 3339, or a window not ascending or over 3 days"),
+        (status = UNAUTHORIZED, body = ErrorBody, description = "Missing or invalid bearer token"),
+        (status = SERVICE_UNAVAILABLE, body = ErrorBody, description = "Aliyun is not configured"),
+        (status = GATEWAY_TIMEOUT, body = ErrorBody, description = "Aliyun did not answer in time"),
+        (status = TOO_MANY_REQUESTS, body = ErrorBody, description = "Aliyun throttled the call or the quota is used up"),
+        (status = BAD_GATEWAY, body = ErrorBody, description = "Aliyun rejected the listing; `exception` carries its error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn describe_refresh_tasks(
+    State(state): State<AppState>,
+    Json(mut payload): Json<DescribeRefreshTasksPayload>,
+) -> AppResult<(HeaderMap, Json<DescribeRefreshTasksResponse>)> {
+    let client = state.aliyun_cdn()?;
+    if payload.page_number == Some(0) {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "page_number starts at 1"
+        )));
+    }
+    if payload.max_results == Some(0) {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "max_results must be at least 1"
+        )));
+    }
+    if let Some(size) = payload.page_size
+        && !(1..=MAX_REFRESH_TASKS_PAGE_SIZE).contains(&size)
+    {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "page_size must be between 1 and {}, got {}",
+            MAX_REFRESH_TASKS_PAGE_SIZE,
+            size
+        )));
+    }
+    normalize_task_window(&mut payload)?;
+
+    let fetch_all = state.aliyun_config.load().fetch_all.clone();
+    let (response, cache) = client
+        .describe_refresh_tasks_cached(&payload, &fetch_all)
+        .await?;
+    let mut headers = HeaderMap::new();
+    if let Some(cache) = cache {
+        headers.insert(CACHE_HEADER, HeaderValue::from_static(cache.as_str()));
+    }
+    if cache != Some(CacheStatus::Hit)
+        && let Some(warning) = &response.warning
+    {
+        warn!(total_count = response.total_count, "{warning}");
+    }
+    Ok((headers, Json(response)))
+}
+
+/// List a domain's CDN access log files with their download URLs
+#[utoipa::path(
+    post,
+    tag = "aliyun",
+    path = "/aliyun/domainLogs",
+    request_body = DescribeCdnDomainLogsPayload,
+    responses(
+        (status = OK, body = CdnDomainLogsResponse),
+        (status = BAD_REQUEST, body = ErrorBody, description = "Missing domain, page number below 1 or page size outside 1 to 1000"),
+        (status = UNAUTHORIZED, body = ErrorBody, description = "Missing or invalid bearer token"),
+        (status = SERVICE_UNAVAILABLE, body = ErrorBody, description = "Aliyun is not configured"),
+        (status = GATEWAY_TIMEOUT, body = ErrorBody, description = "Aliyun did not answer in time"),
+        (status = TOO_MANY_REQUESTS, body = ErrorBody, description = "Aliyun throttled the call or the quota is used up"),
+        (status = BAD_GATEWAY, body = ErrorBody, description = "Aliyun rejected the listing; `exception` carries its error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn describe_domain_logs(
+    State(state): State<AppState>,
+    Json(payload): Json<DescribeCdnDomainLogsPayload>,
+) -> AppResult<Json<CdnDomainLogsResponse>> {
+    list_domain_logs(&state, payload).await
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct DomainLogsQuery {
+    /// ISO 8601 UTC, e.g. `2026-10-16T00:00:00Z`
+    pub start: Option<String>,
+    pub end: Option<String>,
+    /// 1-based page
+    pub page_number: Option<u32>,
+    /// 1 to 1000, Aliyun's default 300 if omitted
+    pub page_size: Option<u32>,
+}
+
+/// List a domain's CDN access log files with their download URLs, as a GET
+#[utoipa::path(
+    get,
+    tag = "aliyun",
+    path = "/aliyun/domains/{domain_name}/logs",
+    params(
+        ("domain_name" = String, Path, description = "Accelerated domain, e.g. `static.prts.wiki`"),
+        DomainLogsQuery
+    ),
+    responses(
+        (status = OK, body = CdnDomainLogsResponse),
+        (status = BAD_REQUEST, body = ErrorBody, description = "Page number below 1 or page size outside 1 to 1000"),
+        (status = UNAUTHORIZED, body = ErrorBody, description = "Missing or invalid bearer token"),
+        (status = SERVICE_UNAVAILABLE, body = ErrorBody, description = "Aliyun is not configured"),
+        (status = GATEWAY_TIMEOUT, body = ErrorBody, description = "Aliyun did not answer in time"),
+        (status = TOO_MANY_REQUESTS, body = ErrorBody, description = "Aliyun throttled the call or the quota is used up"),
+        (status = BAD_GATEWAY, body = ErrorBody, description = "Aliyun rejected the listing; `exception` carries its error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn get_domain_logs(
+    State(state): State<AppState>,
+    Path(domain_name): Path<String>,
+    Query(query): Query<DomainLogsQuery>,
+) -> AppResult<Json<CdnDomainLogsResponse>> {
+    let payload = DescribeCdnDomainLogsPayload {
+        domain_name,
+        start_time: query.start,
+        end_time: query.end,
+        page_number: query.page_number,
+        page_size: query.page_size,
+    };
+    list_domain_logs(&state, payload).await
+}
+
+/// Check the listing and ask Aliyun for it
+async fn list_domain_logs(
+    state: &AppState,
+    payload: DescribeCdnDomainLogsPayload,
+) -> AppResult<Json<CdnDomainLogsResponse>> {
+    let client = state.aliyun_cdn()?;
+    require_domain(&payload.domain_name)?;
+    if payload.page_number == Some(0) {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "page_number starts at 1"
+        )));
+    }
+    if let Some(size) = payload.page_size
+        && !(1..=MAX_DOMAIN_LOGS_PAGE_SIZE).contains(&size)
+    {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "page_size must be between 1 and {}, got {}",
+            MAX_DOMAIN_LOGS_PAGE_SIZE,
+            size
+        )));
+    }
+
+    Ok(Json(client.describe_cdn_domain_logs(&payload).await?))
+}
+
+/// Stream a CDN access log file through Janus, for callers that can't reach OSS themselves
+///
+/// The file is looked up among the first 1000 logs of the window, so narrow `start_time` and
+/// `end_time` for long ones.
+#[utoipa::path(
+    post,
+    tag = "aliyun",
+    path = "/aliyun/domainLogs/download",
+    request_body = DownloadCdnDomainLogPayload,
+    responses(
+        (status = OK, description = "The gzip log file, streamed as it downloads", content_type = "application/gzip"),
+        (status = BAD_REQUEST, body = ErrorBody, description = "Missing domain or log name"),
+        (status = UNAUTHORIZED, body = ErrorBody, description = "Missing or invalid bearer token"),
+        (status = NOT_FOUND, body = ErrorBody, description = "No such log file in the window"),
+        (status = SERVICE_UNAVAILABLE, body = ErrorBody, description = "Aliyun is not configured"),
+        (status = GATEWAY_TIMEOUT, body = ErrorBody, description = "Aliyun did not answer in time"),
+        (status = TOO_MANY_REQUESTS, body = ErrorBody, description = "Aliyun throttled the call or the quota is used up"),
+        (status = INTERNAL_SERVER_ERROR, body = ErrorBody, description = "The download failed"),
+        (status = BAD_GATEWAY, body = ErrorBody, description = "Aliyun rejected the listing; `exception` carries its error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn download_domain_log(
+    State(state): State<AppState>,
+    Json(payload): Json<DownloadCdnDomainLogPayload>,
+) -> AppResult<Response> {
+    let client = state.aliyun_cdn()?;
+    require_domain(&payload.domain_name)?;
+    if payload.log_name.is_empty() {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "log_name is required"
+        )));
+    }
+
+    // Only URLs Aliyun listed are fetched, never one from the caller
+    let listing = client
+        .describe_cdn_domain_logs(&DescribeCdnDomainLogsPayload {
+            domain_name: payload.domain_name.clone(),
+            start_time: payload.start_time,
+            end_time: payload.end_time,
+            page_number: None,
+            page_size: Some(MAX_DOMAIN_LOGS_PAGE_SIZE),
+        })
+        .await?;
+    let log = listing
+        .logs
+        .into_iter()
+        .find(|log| log.name == payload.log_name)
+        .ok_or_else(|| {
+            AppError::NotFound(anyhow::anyhow!(
+                "Log file {} not found for {}",
+                payload.log_name,
+                payload.domain_name
+            ))
+        })?;
+
+    let download = client.open_domain_log(&log).await?;
+    info!(
+        domain_name = payload.domain_name,
+        log_name = log.name,
+        size = log.size,
+        "Streaming CDN log file"
+    );
+    let mut response = Response::builder()
+        .header(header::CONTENT_TYPE, "application/gzip")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", log.name),
+        );
+    if let Some(length) = download.content_length() {
+        response = response.header(header::CONTENT_LENGTH, length);
+    }
+    let response = response
+        .body(Body::from_stream(download.bytes_stream()))
+        .context("Failed to build the log file response")?;
+    Ok(response)
+}
+
+fn require_domain(domain_name: &str) -> AppResult<()> {
+    if domain_name.is_empty() {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "domain_name is required"
+        )));
+    }
+    Ok(())
+}
+
+/// Handle Aliyun EventBridge OSS events
+#[utoipa::path(
+    post,
+    tag = "aliyun",
+    path = "/aliyun/events",
+    request_body = OssEventsPayload,
+    responses(
+        (status = OK, description = "Successfully processed OSS event and triggered CDN refresh (or deferred it in read-only mode), or dead-lettered an event that can't succeed as delivered (`dead_letter_id`). For a batch, not every event failed and `results` details each one", body = OssEventsResponse),
+        (status = ACCEPTED, description = "Refresh queued for the background worker (unless `aliyun.jobs.synchronous`); poll `GET /api/aliyun/jobs/{job_id}`", body = OssEventsResponse),
+        (status = UNAUTHORIZED, body = ErrorBody, description = "Missing or invalid x-eventbridge-signature-token, or with `events_auth = \"eventbridge_hmac\"` (or `\"both\"`) a bad, stale or replayed signature"),
+        (status = BAD_REQUEST, body = ErrorBody, description = "Empty batch"),
+        (status = TOO_MANY_REQUESTS, body = ErrorBody, description = "Rate limit exceeded, see `Retry-After`"),
+        (status = SERVICE_UNAVAILABLE, body = ErrorBody, description = "Aliyun is not configured, or read-only mode can't hold back any more events (`READ_ONLY`), so EventBridge should redeliver"),
+        (status = INTERNAL_SERVER_ERROR, body = ErrorBody, description = "Internal server error")
+    ),
+    security(
+        ("eventbridge_token" = []),
+        ("eventbridge_hmac" = [])
+    )
+)]
+pub async fn handle_oss_events(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> AppResult<(StatusCode, Json<OssEventsResponse>)> {
+    // The HMAC covers the exact bytes received, so parse only after verifying
+    let aliyun = state.aliyun_config.load_full();
+    let verify_signature = || {
+        crate::event_auth::verify_signature(
+            &headers,
+            &body,
+            aliyun.events_hmac_secret.as_deref().unwrap_or_default(),
+            aliyun.events_max_skew_secs,
+            chrono::Utc::now().timestamp(),
+            &state.event_replay,
+        )
+        .map_err(AppError::Unauthorized)
+    };
+    let subject = match aliyun.events_auth {
+        EventsAuth::Jwt => verify_event_token(&state, &headers)?,
+        EventsAuth::EventbridgeHmac => {
+            verify_signature()?;
+            "eventbridge".to_string()
+        }
+        // The token goes first, so a delivery it rejects doesn't use up its signature
+        EventsAuth::Both => {
+            let subject = verify_event_token(&state, &headers)?;
+            verify_signature()?;
+            subject
+        }
+    };
+    tracing::Span::current().record("subject", subject.as_str());
+    let raw_payload = match serde_json::from_slice::<serde_json::Value>(&body) {
+        Ok(payload) => payload,
+        Err(err) => {
+            let event = serde_json::Value::String(String::from_utf8_lossy(&body).into_owned());
+            let response = dead_letter(&state, event, &AppError::from(err));
+            return Ok((StatusCode::OK, Json(OssEventsResponse::Single(response))));
+        }
+    };
+    // Fail the delivery so EventBridge retries once Aliyun is configured
+    state.aliyun_cdn()?;
+
+    let (queued, response) = match raw_payload {
+        serde_json::Value::Array(events) => {
+            let batch = process_oss_batch(&state, events).await?;
+            let queued = batch
+                .results
+                .iter()
+                .any(|result| result.status == OssEventStatus::Queued);
+            (queued, OssEventsResponse::Batch(batch))
+        }
+        event => {
+            let id = event_id(&event);
+            if let Some(answer) = earlier_answer(&state, id.as_deref()) {
+                return Ok((
+                    StatusCode::OK,
+                    Json(OssEventsResponse::Single(OssEventResponse {
+                        message: answer.message,
+                        task_id: answer.task_id,
+                        task_ids: answer.task_ids,
+                        job_id: answer.job_id,
+                        dead_letter_id: answer.dead_letter_id,
+                        ..OssEventResponse::default()
+                    })),
+                ));
+            }
+            let (status, response) = match accept_oss_event(&state, event.clone()).await {
+                Ok(outcome) => outcome,
+                Err(err @ AppError::BadRequest(_)) => (
+                    OssEventStatus::DeadLettered,
+                    dead_letter(&state, event, &err),
+                ),
+                Err(err) => return Err(err),
+            };
+            if let Some(id) = &id {
+                state
+                    .event_ids
+                    .record(id, &event_result_of(None, status, &response));
+            }
+            (
+                status == OssEventStatus::Queued,
+                OssEventsResponse::Single(response),
+            )
+        }
+    };
+    let status = if queued {
+        StatusCode::ACCEPTED
+    } else {
+        StatusCode::OK
+    };
+    Ok((status, Json(response)))
+}
+
+/// Answer of an earlier delivery of event `id`, within `aliyun.event_id_window_secs`
+fn earlier_answer(state: &AppState, id: Option<&str>) -> Option<OssEventResult> {
+    let id = id?;
+    let (answered_at, answer) = state.event_ids.answer(id)?;
+    debug!(
+        event_id = id,
+        %answered_at,
+        "Redelivered OSS event answered like its first delivery"
+    );
+    Some(answer)
+}
+
+/// Park an event that failed validation and acknowledge it, since every redelivery would
+/// fail the same way
+fn dead_letter(state: &AppState, event: serde_json::Value, err: &AppError) -> OssEventResponse {
+    let id = state.dead_letters.push(event, err, 1);
+    OssEventResponse {
+        message: format!("dead-lettered: {err:#}"),
+        dead_letter_id: Some(id),
+        ..OssEventResponse::default()
+    }
+}
+
+/// Subject of the JWT in `x-eventbridge-signature-token`
+fn verify_event_token(state: &AppState, headers: &HeaderMap) -> AppResult<String> {
+    let token = headers
+        .get("x-eventbridge-signature-token")
+        .ok_or_else(|| {
+            AppError::Unauthorized(anyhow::anyhow!(
+                "Missing x-eventbridge-signature-token header"
+            ))
+        })?
+        .to_str()
+        .map_err(|_| {
+            AppError::Unauthorized(anyhow::anyhow!(
+                "Invalid x-eventbridge-signature-token header format"
+            ))
+        })?
+        .trim();
+
+    let claims =
+        crate::auth::verify_token(token, &state.jwt_config.load().public_key).map_err(|err| {
+            AppError::Unauthorized(anyhow::anyhow!(
+                "JWT verification failed (x-eventbridge-signature-token): {err}"
+            ))
+        })?;
+    Ok(claims.sub)
+}
+
+/// Process each event of a batch delivery
+///
+/// Succeeds when at least one event was processed, because EventBridge redelivers the whole
+/// batch on any non-2xx answer; failures are detailed per event instead. Refreshes sent right
+/// away go out together, see [`refresh_by_domain`].
+async fn process_oss_batch(
+    state: &AppState,
+    events: Vec<serde_json::Value>,
+) -> AppResult<OssBatchEventResponse> {
+    if events.is_empty() {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "OSS event batch is empty"
+        )));
+    }
+
+    let total = events.len();
+    // Redelivered events keep their first answer and are left out of any refresh
+    let mut collapsed = events
+        .iter()
+        .map(|event| {
+            let id = event_id(event);
+            earlier_answer(state, id.as_deref()).map(|answer| OssEventResult { id, ..answer })
+        })
+        .collect::<Vec<_>>();
+    let mut first_error = None;
+    if let Some(threshold) = state
+        .aliyun_config
+        .load()
+        .events
+        .directory_refresh_threshold
+        && !state.read_only.is_enabled()
+    {
+        first_error = collapse_removed_prefixes(state, &events, threshold, &mut collapsed).await;
+    }
+
+    // Refreshes sent right away are combined across the batch, one call per CDN domain
+    let aliyun = state.aliyun_config.load_full();
+    let combine =
+        (aliyun.events_dry_run || aliyun.jobs.synchronous) && !state.read_only.is_enabled();
+    let mut results = Vec::with_capacity(total);
+    let mut pending = Vec::new();
+    for (index, (event, collapsed)) in events.into_iter().zip(collapsed).enumerate() {
+        if let Some(result) = collapsed {
+            results.push(Some(result));
+            continue;
+        }
+        let id = event_id(&event);
+        let outcome = if combine {
+            match resolve_oss_event(state, &aliyun, event.clone()).await {
+                // Preloads follow their own refresh, so those events go alone
+                Ok(ResolvedEvent::Refresh(refresh)) if !refresh.preload => {
+                    pending.push((index, id, refresh));
+                    results.push(None);
+                    continue;
+                }
+                Ok(ResolvedEvent::Refresh(refresh)) => {
+                    submit_oss_event(state, &aliyun, refresh).await
+                }
+                Ok(ResolvedEvent::Done(status, response)) => Ok((status, response)),
+                Err(err) => Err(err),
+            }
+        } else {
+            accept_oss_event(state, event.clone()).await
+        };
+        results.push(Some(event_result(
+            state,
+            id,
+            event,
+            outcome,
+            &mut first_error,
+        )));
+    }
+    if !pending.is_empty() {
+        let (slots, refreshes): (Vec<_>, Vec<_>) = pending
+            .into_iter()
+            .map(|(index, id, refresh)| ((index, id, refresh.raw_payload.clone()), refresh))
+            .unzip();
+        let outcomes = refresh_by_domain(state, &aliyun, refreshes).await;
+        for ((index, id, event), outcome) in slots.into_iter().zip(outcomes) {
+            results[index] = Some(event_result(state, id, event, outcome, &mut first_error));
+        }
+    }
+    let results = results
+        .into_iter()
+        .map(|result| result.expect("every event gets a result"))
+        .collect::<Vec<_>>();
+    for result in &results {
+        if let Some(id) = &result.id
+            && result.status != OssEventStatus::Failed
+        {
+            state.event_ids.record(id, result);
+        }
+    }
+
+    let failed = results
+        .iter()
+        .filter(|result| result.status == OssEventStatus::Failed)
+        .count();
+    if failed == total {
+        return Err(first_error.expect("a failed event records its error"));
+    }
+    let dead_lettered = results
+        .iter()
+        .filter(|result| result.status == OssEventStatus::DeadLettered)
+        .count();
+
+    Ok(OssBatchEventResponse {
+        message: format!(
+            "processed {} of {} events",
+            total - failed - dead_lettered,
+            total
+        ),
+        results,
+    })
+}
+
+/// Batch entry for the outcome of one event, keeping the first failure for the batch answer
+fn event_result(
+    state: &AppState,
+    id: Option<String>,
+    event: serde_json::Value,
+    outcome: AppResult<(OssEventStatus, OssEventResponse)>,
+    first_error: &mut Option<AppError>,
+) -> OssEventResult {
+    match outcome {
+        Ok((status, response)) => event_result_of(id, status, &response),
+        Err(err @ AppError::BadRequest(_)) => {
+            let response = dead_letter(state, event, &err);
+            OssEventResult {
+                id,
+                status: OssEventStatus::DeadLettered,
+                message: response.message,
+                task_id: None,
+                task_ids: Vec::new(),
+                job_id: None,
+                dead_letter_id: response.dead_letter_id,
+            }
+        }
+        Err(err) => {
+            warn!(event_id = id.as_deref(), error = ?err, "Failed to process OSS event in batch");
+            let result = OssEventResult {
+                id,
+                status: OssEventStatus::Failed,
+                message: format!("{err:#}"),
+                task_id: None,
+                task_ids: Vec::new(),
+                job_id: None,
+                dead_letter_id: None,
+            };
+            first_error.get_or_insert(err);
+            result
+        }
+    }
+}
+
+/// Batch entry, or remembered answer, for an event answered with `response`
+fn event_result_of(
+    id: Option<String>,
+    status: OssEventStatus,
+    response: &OssEventResponse,
+) -> OssEventResult {
+    OssEventResult {
+        id,
+        status,
+        message: response.message.clone(),
+        task_id: response.task_id.clone(),
+        task_ids: response.task_ids.clone(),
+        job_id: response.job_id,
+        dead_letter_id: response.dead_letter_id,
+    }
+}
+
+/// Refresh the URLs of several events with one call per CDN domain, object type and `force`
+///
+/// Each event gets the task ids of every call holding one of its URLs. One whose calls all
+/// failed fails; one with only some failed is refreshed, naming the failed domains like a
+/// single event does.
+async fn refresh_by_domain(
+    state: &AppState,
+    aliyun: &AliyunConfig,
+    pending: Vec<PendingRefresh>,
+) -> Vec<AppResult<(OssEventStatus, OssEventResponse)>> {
+    let mut groups = BTreeMap::<(String, &'static str, bool), Vec<String>>::new();
+    for refresh in &pending {
+        for url in &refresh.object_paths {
+            let host = url_host(url).to_ascii_lowercase();
+            let key = (host, refresh.object_type, refresh.force);
+            let urls = groups.entry(key).or_default();
+            if !urls.contains(url) {
+                urls.push(url.clone());
+            }
+        }
+    }
+
+    // Every URL points at the chunk it was sent in
+    let mut chunk_of = HashMap::new();
+    let mut chunks = Vec::new();
+    for ((host, object_type, force), urls) in groups {
+        let request = RefreshObjectCachesRequest {
+            object_path: urls.join("\n"),
+            object_type: Some(object_type.to_string()),
+            force: Some(force),
+            product: None,
+        };
+        for chunk in request.split() {
+            for url in chunk.object_path.lines() {
+                chunk_of.insert((url.to_string(), object_type, force), chunks.len());
+            }
+            chunks.push((host.clone(), chunk));
+        }
+    }
+    info!(
+        events = pending.len(),
+        calls = chunks.len(),
+        "Refreshing OSS event batch by CDN domain"
+    );
+    let hosts = chunks
+        .iter()
+        .map(|(host, _)| host.clone())
+        .collect::<Vec<_>>();
+    let requests = chunks.into_iter().map(|(_, chunk)| chunk).collect();
+    let mut outcomes = refresh_many(state, requests, aliyun.events_dry_run, "oss_event")
+        .await
+        .into_iter()
+        .map(|(_, _, outcome)| {
+            outcome
+                .map(|response| split_task_ids(&response.refresh_task_id))
+                .map_err(Some)
+        })
+        .collect::<Vec<_>>();
+    let messages = outcomes
+        .iter()
+        .zip(&hosts)
+        .map(|(outcome, host)| match outcome {
+            Err(Some(err)) => Some(format!("{host}: {err:#}")),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let mut results = Vec::with_capacity(pending.len());
+    for refresh in pending {
+        let mut indices = refresh
+            .object_paths
+            .iter()
+            .map(|url| chunk_of[&(url.clone(), refresh.object_type, refresh.force)])
+            .collect::<Vec<_>>();
+        indices.sort_unstable();
+        indices.dedup();
+        let mut task_ids = Vec::new();
+        let mut failures = Vec::new();
+        for &index in &indices {
+            match &outcomes[index] {
+                Ok(ids) => {
+                    for task_id in ids {
+                        if !task_ids.contains(task_id) {
+                            task_ids.push(task_id.clone());
+                        }
+                    }
+                }
+                Err(_) => failures.extend(messages[index].clone()),
+            }
+        }
+        if task_ids.is_empty() && !failures.is_empty() {
+            // The first event to fail on a call carries its error, the others its message
+            let err = indices
+                .iter()
+                .find_map(|&index| outcomes[index].as_mut().err().and_then(Option::take))
+                .unwrap_or_else(|| AppError::InternalError(anyhow::anyhow!(failures.join("; "))));
+            results.push(Err(err));
+            continue;
+        }
+        results.push(Ok(refreshed(state, aliyun, refresh, task_ids, failures)));
+    }
+    results
+}
+
+fn event_id(event: &serde_json::Value) -> Option<String> {
+    event
+        .get("id")
+        .and_then(serde_json::Value::as_str)
+        .map(str::to_string)
+}
+
+/// Purge bulk deletions with one directory refresh per shared prefix
+///
+/// Groups the batch's `ObjectRemoved` events per bucket and refreshes each directory holding
+/// more than `threshold` of them, filling `results` for the events it covered. The rest are
+/// left for individual processing, and events that already have a result are left out.
+/// Returns the first failed refresh, if any.
+async fn collapse_removed_prefixes(
+    state: &AppState,
+    events: &[serde_json::Value],
+    threshold: usize,
+    results: &mut [Option<OssEventResult>],
+) -> Option<AppError> {
+    let aliyun = state.aliyun_config.load_full();
+    let event_filter = state.event_filter.load_full();
+    // Bucket -> (event index, object key) of removals eligible for a directory refresh
+    let mut removals: BTreeMap<String, Vec<(usize, String)>> = BTreeMap::new();
+    for (index, event) in events.iter().enumerate() {
+        if results[index].is_some() {
+            continue;
+        }
+        let Ok(payload) = serde_json::from_value::<OssEventPayload>(event.clone()) else {
+            continue;
+        };
+        let event_name = payload.data.event_name.as_deref().unwrap_or_default();
+        let bucket = payload.data.oss.bucket.name;
+        let key = decode_object_key(
+            &payload.data.oss.object.key,
+            aliyun.events.key_encoding_for(&bucket),
+        );
+        // Directory URLs only make sense when the key is the end of every URL
+        let template_fits = aliyun.bucket_url_map.get(&bucket).is_some_and(|urls| {
+            !urls.templates().is_empty()
+                && urls
+                    .templates()
+                    .iter()
+                    .all(|template| template.ends_with("{object_key}"))
+        });
+        if event_name.starts_with("ObjectRemoved")
+            && template_fits
+            && event_filter
+                .skip_reason(Some(event_name), &bucket, &key)
+                .is_none()
+            && !aliyun.events.ignores_removal(&bucket, &key)
+        {
+            removals.entry(bucket).or_default().push((index, key));
+        }
+    }
+
+    if let Err(err) = state.aliyun_cdn() {
+        return Some(err);
+    }
+    let mut first_error = None;
+    for (bucket, removed) in removals {
+        let keys = removed
+            .iter()
+            .map(|(_, key)| key.as_str())
+            .collect::<Vec<_>>();
+        for (prefix, covered) in group_by_directory(&keys, threshold).directories {
+            let object_path = object_urls(&aliyun.bucket_url_map[&bucket], &prefix).join("\n");
+            let request = RefreshObjectCachesRequest {
+                object_path,
+                object_type: Some("Directory".to_string()),
+                force: Some(true),
+                product: None,
+            };
+            let outcome = match validate_object_paths(&request.object_path, "Directory") {
+                Ok(_) => submit_refresh(state, &aliyun, &request, None, None, false, false).await,
+                Err(err) => Err(err),
+            };
+            info!(
+                bucket,
+                prefix,
+                events = covered.len(),
+                ok = outcome.is_ok(),
+                "Collapsed OSS removals into a directory refresh"
+            );
+
+            let (status, message, task_ids, job_id) = match outcome {
+                Ok(Submitted::Sent(response)) => (
+                    OssEventStatus::Refreshed,
+                    format!(
+                        "CDN directory refresh triggered for {} in bucket {}",
+                        prefix, bucket
+                    ),
+                    split_task_ids(&response.refresh_task_id),
+                    None,
+                ),
+                Ok(Submitted::Queued(job)) => (
+                    OssEventStatus::Queued,
+                    format!(
+                        "CDN directory refresh queued for {} in bucket {}",
+                        prefix, bucket
+                    ),
+                    Vec::new(),
+                    Some(job.id),
+                ),
+                Err(err) => {
+                    let message = format!("{err:#}");
+                    first_error.get_or_insert(err);
+                    (OssEventStatus::Failed, message, Vec::new(), None)
+                }
+            };
+            let object_paths = request
+                .object_path
+                .lines()
+                .map(str::to_string)
+                .collect::<Vec<_>>();
+            for position in covered {
+                let (index, key) = &removed[position];
+                let index = *index;
+                if status == OssEventStatus::Refreshed && !aliyun.events_dry_run {
+                    state.webhooks.notify(WebhookEvent::object_refreshed(
+                        &bucket,
+                        key,
+                        &object_paths,
+                        &task_ids.join(","),
+                    ));
+                }
+                results[index] = Some(OssEventResult {
+                    id: event_id(&events[index]),
+                    status,
+                    message: message.clone(),
+                    task_id: task_ids.first().cloned(),
+                    task_ids: task_ids.clone(),
+                    job_id,
+                    dead_letter_id: None,
+                });
+            }
+        }
+    }
+    first_error
+}
+
+/// Process one delivered event, or hold it back in read-only mode
+async fn accept_oss_event(
+    state: &AppState,
+    raw_payload: serde_json::Value,
+) -> AppResult<(OssEventStatus, OssEventResponse)> {
+    // In read-only mode acknowledge the delivery but hold the event back instead of purging
+    if state.read_only.is_enabled() {
+        // Without room to hold it, have EventBridge redeliver the event after the outage
+        if !state.read_only.defer_event(raw_payload) {
+            warn!("Deferred OSS event buffer is full, asking for redelivery");
+            return Err(AppError::ReadOnly(
+                "Service is in read-only mode and its deferred event buffer is full".to_string(),
+            ));
+        }
+        return Ok((
+            OssEventStatus::Deferred,
+            OssEventResponse {
+                message: "deferred: service is in read-only mode".to_string(),
+                ..OssEventResponse::default()
+            },
+        ));
+    }
+
+    process_oss_event(state, raw_payload).await
+}
+
+/// Replay OSS events deferred while read-only mode was engaged
+pub async fn replay_deferred_events(state: AppState) {
+    let events = state.read_only.drain_deferred();
+    if events.is_empty() {
+        return;
+    }
+    info!(count = events.len(), "Replaying deferred OSS events");
+    for event in events {
+        if let Err(err) = process_oss_event(&state, event).await {
+            warn!(error = ?err, "Failed to replay deferred OSS event");
+        }
+    }
+}
+
+/// Map an OSS event to its CDN URL and trigger the refresh
+async fn process_oss_event(
+    state: &AppState,
+    raw_payload: serde_json::Value,
+) -> AppResult<(OssEventStatus, OssEventResponse)> {
+    // One snapshot per event, so a config reload never splits it
+    let aliyun = state.aliyun_config.load_full();
+    match resolve_oss_event(state, &aliyun, raw_payload).await? {
+        ResolvedEvent::Done(status, response) => Ok((status, response)),
+        ResolvedEvent::Refresh(pending) => submit_oss_event(state, &aliyun, pending).await,
+    }
+}
+
+/// What an OSS event still needs once filters, dedup and lookups are done
+enum ResolvedEvent {
+    /// Nothing to refresh; the outcome is final
+    Done(OssEventStatus, OssEventResponse),
+    Refresh(PendingRefresh),
+}
+
+/// URLs of an OSS event left to refresh
+struct PendingRefresh {
+    bucket_name: String,
+    object_key: String,
+    object_paths: Vec<String>,
+    object_type: &'static str,
+    /// The URLs are the files of an expanded directory event
+    expanded: bool,
+    /// Purged for an `ObjectRemoved` event, so the CDN drops its copy whatever the origin says
+    force: bool,
+    preload: bool,
+    dedup_key: Option<EventKey>,
+    /// `aliyun.events.prefix_rules` entry that chose how to refresh the event
+    rule: Option<EventPrefixRule>,
+    raw_payload: serde_json::Value,
+}
+
+/// Parse an OSS event and work out the URLs it needs refreshed
+async fn resolve_oss_event(
+    state: &AppState,
+    aliyun: &AliyunConfig,
+    raw_payload: serde_json::Value,
+) -> AppResult<ResolvedEvent> {
+    // Parse the raw JSON into OssEventPayload, keeping the event for a dead letter
+    let payload = OssEventPayload::deserialize(&raw_payload).map_err(|err| {
+        AppError::BadRequest(anyhow::anyhow!(
+            "Failed to parse OSS event payload: {}",
+            err
+        ))
+    })?;
+
+    info!(
+        event = ?payload,
+        "Received OSS event"
+    );
+
+    let bucket_name = &payload.data.oss.bucket.name;
+    // Filters, dedup and the URL all work on the key as stored in the bucket
+    let object_key = &decode_object_key(
+        &payload.data.oss.object.key,
+        aliyun.events.key_encoding_for(bucket_name),
+    );
+    let removed = payload
+        .data
+        .event_name
+        .as_deref()
+        .is_some_and(|name| name.starts_with("ObjectRemoved"));
+
+    // Acknowledge unwanted events; a 4xx would make EventBridge redeliver them forever
+    if let Some((label, reason)) = state
+        .event_filter
+        .load()
+        .skip_reason(payload.data.event_name.as_deref(), bucket_name, object_key)
+        .map(|skip| (skip.label(), skip.to_string()))
+        .or_else(|| {
+            (removed && aliyun.events.ignores_removal(bucket_name, object_key)).then(|| {
+                (
+                    "ignored_removal",
+                    format!("removal of {object_key} is not purged"),
+                )
+            })
+        })
+    {
+        info!(
+            bucket_name,
+            object_key,
+            reason,
+            skip = label,
+            "OSS event skipped"
+        );
+        record_oss_event_skipped(label);
+        return Ok(ResolvedEvent::Done(
+            OssEventStatus::Skipped,
+            OssEventResponse::skipped(format!("skipped: {reason}")),
+        ));
+    }
+
+    let rule = aliyun.events.prefix_rule(bucket_name, object_key).cloned();
+    if let Some(rule) = &rule {
+        info!(bucket_name, object_key, %rule, "OSS event matched a prefix rule");
+    }
+    // Ignored events are rejected or skipped just like those of an unmapped bucket
+    if let Some(rule) = rule.as_ref().filter(|rule| rule.mode == PrefixMode::Ignore) {
+        if aliyun.events.unknown_bucket_behavior == UnknownBucketBehavior::Reject {
+            return Err(AppError::BadRequest(anyhow::anyhow!(
+                "Ignored by prefix rule {}",
+                rule
+            )));
+        }
+        record_oss_event_skipped("prefix_rule");
+        return Ok(ResolvedEvent::Done(
+            OssEventStatus::Skipped,
+            OssEventResponse::skipped(format!("skipped: prefix rule {rule}")),
+        ));
+    }
+    let directory_rule = rule
+        .as_ref()
+        .filter(|rule| rule.mode == PrefixMode::Directory);
+
+    // Redelivered or duplicated events for the same object version were already purged. A
+    // removal shares the ETag of the creation before it but still needs its Force purge
+    let dedup_key = payload
+        .data
+        .oss
+        .object
+        .etag
+        .as_ref()
+        .filter(|_| !removed)
+        .map(|etag| (bucket_name.clone(), object_key.clone(), etag.clone()));
+    if let Some(task_id) = dedup_key
+        .as_ref()
+        .and_then(|key| state.event_dedup.recent_task(key))
+    {
+        info!(
+            bucket_name,
+            object_key, task_id, "Duplicate OSS event ignored"
+        );
+        return Ok(ResolvedEvent::Done(
+            OssEventStatus::Skipped,
+            OssEventResponse::skipped("duplicate event ignored")
+                .with_task_ids(split_task_ids(&task_id)),
+        ));
+    }
+
+    let Some(urls) = aliyun.bucket_url_map.get(bucket_name) else {
+        if aliyun.events.unknown_bucket_behavior == UnknownBucketBehavior::Reject {
+            return Err(AppError::BadRequest(anyhow::anyhow!(
+                "Unsupported bucket: {}",
+                bucket_name
+            )));
+        }
+        info!(
+            bucket_name,
+            object_key,
+            skip = "unknown_bucket",
+            "OSS event skipped"
+        );
+        record_oss_event_skipped("unknown_bucket");
+        return Ok(ResolvedEvent::Done(
+            OssEventStatus::Skipped,
+            OssEventResponse::skipped(format!("skipped: no mapping for bucket {bucket_name}")),
+        ));
+    };
+
+    // Every CDN domain in front of the bucket is purged by the same call
+    let (object_urls, object_type, expanded) = if let Some(rule) = directory_rule {
+        (object_urls(urls, &rule.prefix), "Directory", false)
+    } else if aliyun.events.expands(bucket_name, object_key) {
+        let region = payload.data.region.as_deref();
+        match expand_directory(state, aliyun, region, bucket_name, object_key).await {
+            Some(keys) => {
+                let urls = keys
+                    .iter()
+                    .flat_map(|key| object_urls(urls, key))
+                    .collect::<Vec<_>>();
+                (urls, "File", true)
+            }
+            None => (object_urls(urls, object_key), "Directory", false),
+        }
+    } else {
+        (object_urls(urls, object_key), "File", false)
+    };
+    debug!(
+        raw_key = payload.data.oss.object.key,
+        ?object_urls,
+        object_type,
+        "Built CDN URLs for OSS event"
+    );
+    // A bad template would otherwise only fail at Aliyun
+    let mut object_paths = validate_object_paths(&object_urls.join("\n"), object_type)?;
+
+    // An object saved again and again within the window is purged once, and so is a directory
+    // regenerated file by file; a removal is never folded into a refresh without Force
+    let dedup = match directory_rule {
+        Some(_) => &state.directory_dedup,
+        None => &state.url_dedup,
+    };
+    let mut recent_task_ids = Vec::new();
+    object_paths.retain(|url| match dedup.recent_task(url).filter(|_| !removed) {
+        Some(task_id) => {
+            recent_task_ids.extend(split_task_ids(&task_id));
+            false
+        }
+        None => true,
+    });
+    if object_paths.is_empty() {
+        let mut seen = HashSet::new();
+        recent_task_ids.retain(|task_id| seen.insert(task_id.clone()));
+        info!(
+            bucket_name,
+            object_key,
+            task_id = recent_task_ids.join(","),
+            "OSS event coalesced with a recent refresh of its URLs"
+        );
+        let message = match directory_rule {
+            Some(rule) => {
+                format!("deduplicated: directory was refreshed recently, prefix rule {rule}")
+            }
+            None => "deduplicated: URLs were refreshed recently".to_string(),
+        };
+        return Ok(ResolvedEvent::Done(
+            OssEventStatus::Skipped,
+            OssEventResponse {
+                deduplicated: true,
+                ..OssEventResponse::skipped(message).with_task_ids(recent_task_ids)
+            },
+        ));
+    }
+
+    // Aliyun rejects refreshes of domains that aren't online, don't spend a call on them
+    if aliyun.verify_domain_online
+        && let Ok(client) = state.aliyun_cdn()
+    {
+        let mut offline = BTreeSet::new();
+        let hosts = object_paths
+            .iter()
+            .filter(|url| client.product_for(url) == CdnProduct::Cdn)
+            .map(|url| url_host(url).to_ascii_lowercase())
+            .collect::<BTreeSet<_>>();
+        for host in hosts {
+            match client.is_domain_online(&host).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    offline.insert(host);
+                }
+                Err(err) => {
+                    warn!(host, error = %err, "DescribeUserDomains failed, refreshing anyway");
+                }
+            }
+        }
+        if !offline.is_empty() {
+            object_paths.retain(|url| !offline.contains(&url_host(url).to_ascii_lowercase()));
+            let offline = offline.into_iter().collect::<Vec<_>>().join(", ");
+            warn!(
+                bucket_name,
+                object_key, offline, "Dropping URLs of CDN domains not online"
+            );
+            if object_paths.is_empty() {
+                return Ok(ResolvedEvent::Done(
+                    OssEventStatus::Skipped,
+                    OssEventResponse::skipped(format!("skipped: CDN domain not online: {offline}")),
+                ));
+            }
+        }
+    }
+
+    // An object deleted or renamed since the event would only purge URLs that 404. A removal
+    // still needs its purge, and a failed lookup refreshes anyway rather than lose the event.
+    if aliyun.verify_object_exists
+        && !removed
+        && directory_rule.is_none()
+        && let Some(oss) = &state.aliyun_oss
+        && let Some(region) = payload.data.region.as_deref()
+    {
+        match oss.head_object(region, bucket_name, object_key).await {
+            Ok(true) => {}
+            Ok(false) => {
+                info!(bucket_name, object_key, "OSS event skipped, object is gone");
+                return Ok(ResolvedEvent::Done(
+                    OssEventStatus::Skipped,
+                    OssEventResponse::skipped("skipped: object no longer exists"),
+                ));
+            }
+            Err(err) => {
+                warn!(bucket_name, object_key, error = %err, "HeadObject failed, refreshing anyway");
+            }
+        }
+    }
+
+    // Preloading a directory would need its listing, so only file refreshes are preloaded
+    let preload = object_type == "File" && aliyun.events.preloads(bucket_name, object_key);
+    Ok(ResolvedEvent::Refresh(PendingRefresh {
+        bucket_name: bucket_name.clone(),
+        object_key: object_key.clone(),
+        object_paths,
+        object_type,
+        expanded,
+        force: removed,
+        preload,
+        dedup_key,
+        rule,
+        raw_payload,
+    }))
+}
+
+/// Refresh the URLs of one OSS event, or queue them for the worker
+async fn submit_oss_event(
+    state: &AppState,
+    aliyun: &AliyunConfig,
+    pending: PendingRefresh,
+) -> AppResult<(OssEventStatus, OssEventResponse)> {
+    let object_paths = &pending.object_paths;
+    let object_type = pending.object_type;
+    let request = RefreshObjectCachesRequest {
+        object_path: object_paths.join("\n"),
+        object_type: Some(object_type.to_string()),
+        force: Some(pending.force),
+        product: None,
+    };
+
+    let submitted = submit_refresh(
+        state,
+        aliyun,
+        &request,
+        pending.dedup_key.clone(),
+        Some(pending.raw_payload.clone()),
+        pending.preload,
+        pending
+            .rule
+            .as_ref()
+            .is_some_and(|rule| rule.mode == PrefixMode::Directory),
+    );
+    let (task_ids, failures) = match submitted.await {
+        Ok(Submitted::Sent(response)) => (split_task_ids(&response.refresh_task_id), Vec::new()),
+        Ok(Submitted::Queued(job)) => {
+            let mut message = format!(
+                "CDN refresh queued for {} in bucket {}",
+                pending.object_key, pending.bucket_name
+            );
+            if let Some(rule) = &pending.rule {
+                message.push_str(&format!(", prefix rule {rule}"));
+                // The job isn't done yet, but a second one for the directory would be wasted
+                if rule.mode == PrefixMode::Directory && !pending.force {
+                    state
+                        .directory_dedup
+                        .record(object_paths.iter().map(String::as_str), "");
+                }
+            }
+            return Ok((
+                OssEventStatus::Queued,
+                OssEventResponse {
+                    message,
+                    job_id: Some(job.id),
+                    ..OssEventResponse::default()
+                },
+            ));
+        }
+        // Aliyun rejects the whole call over one bad domain, so don't let it block the others
+        // One call per URL is no fallback for a directory, nor for a whole expanded listing
+        Err(err @ (AppError::Aliyun(_) | AppError::InternalError(_) | AppError::NotFound(_)))
+            if object_paths.len() > 1 && object_type == "File" && !pending.expanded =>
+        {
+            refresh_each(state, object_paths, pending.force, err).await?
+        }
+        Err(err) => return Err(err),
+    };
+
+    Ok(refreshed(state, aliyun, pending, task_ids, failures))
+}
+
+/// Outcome of an event whose refresh went out, remembering it for dedup when nothing failed
+fn refreshed(
+    state: &AppState,
+    aliyun: &AliyunConfig,
+    pending: PendingRefresh,
+    task_ids: Vec<String>,
+    failures: Vec<String>,
+) -> (OssEventStatus, OssEventResponse) {
+    let PendingRefresh {
+        bucket_name,
+        object_key,
+        object_paths,
+        object_type,
+        force,
+        dedup_key,
+        rule,
+        ..
+    } = pending;
+    let rule_note = rule
+        .as_ref()
+        .map(|rule| format!(", prefix rule {rule}"))
+        .unwrap_or_default();
+    if aliyun.events_dry_run {
+        return (
+            OssEventStatus::Refreshed,
+            OssEventResponse {
+                message: format!(
+                    "dry run: CDN refresh prepared for {} in bucket {}{}",
+                    object_key, bucket_name, rule_note
+                ),
+                task_id: task_ids.first().cloned(),
+                task_ids,
+                object_path: Some(object_paths.join("\n")),
+                object_type: Some(object_type.to_string()),
+                ..OssEventResponse::default()
+            },
+        );
+    }
+
+    // A partial failure isn't recorded, so a redelivery purges every domain again
+    if let Some(key) = dedup_key
+        && failures.is_empty()
+    {
+        state.event_dedup.record(key, task_ids.join(","));
+    }
+    if failures.is_empty() {
+        // A re-upload right after a removal must still drop the cached 404
+        if !force {
+            let dedup = match rule.as_ref().map(|rule| rule.mode) {
+                Some(PrefixMode::Directory) => &state.directory_dedup,
+                _ => &state.url_dedup,
+            };
+            dedup.record(object_paths.iter().map(String::as_str), &task_ids.join(","));
+        }
+        // Subscribers are only told once every domain has been purged
+        state.webhooks.notify(WebhookEvent::object_refreshed(
+            &bucket_name,
+            &object_key,
+            &object_paths,
+            &task_ids.join(","),
+        ));
+    }
+
+    let mut message = format!(
+        "CDN refresh triggered for {} in bucket {}{}",
+        object_key, bucket_name, rule_note
+    );
+    if !failures.is_empty() {
+        message.push_str(&format!(", but failed for {}", failures.join("; ")));
+    }
+    (
+        OssEventStatus::Refreshed,
+        OssEventResponse {
+            message,
+            task_id: task_ids.first().cloned(),
+            task_ids,
+            ..OssEventResponse::default()
+        },
+    )
+}
+
+/// Keys of the files under the directory event's `prefix`, or `None` to refresh the directory
+///
+/// Falls back to the directory when the listing holds more than `events.expand_max_objects`
+/// objects, when it fails and when the event has no region to list in.
+async fn expand_directory(
+    state: &AppState,
+    aliyun: &AliyunConfig,
+    region: Option<&str>,
+    bucket: &str,
+    prefix: &str,
+) -> Option<Vec<String>> {
+    let (Some(oss), Some(region)) = (&state.aliyun_oss, region) else {
+        warn!(
+            bucket,
+            prefix, "Cannot list OSS directory, refreshing it whole"
+        );
+        return None;
+    };
+    let max = aliyun.events.expand_max_objects;
+    match oss.list_objects_v2(region, bucket, prefix, max).await {
+        Ok(Some(keys)) => {
+            // Directory markers, the event's own included, aren't files to purge
+            let keys = keys
+                .into_iter()
+                .filter(|key| !key.ends_with('/'))
+                .collect::<Vec<_>>();
+            if keys.is_empty() {
+                info!(
+                    bucket,
+                    prefix, "OSS directory is empty, refreshing it whole"
+                );
+                return None;
+            }
+            info!(
+                bucket,
+                prefix,
+                objects = keys.len(),
+                "Expanded OSS directory event"
+            );
+            Some(keys)
+        }
+        Ok(None) => {
+            info!(
+                bucket,
+                prefix, max, "OSS directory exceeds expand_max_objects, refreshing it whole"
+            );
+            None
+        }
+        Err(err) => {
+            warn!(bucket, prefix, error = %err, "ListObjectsV2 failed, refreshing the directory");
+            None
+        }
+    }
+}
+
+/// Refresh each URL in a call of its own after Aliyun rejected them together
+///
+/// Returns the task ids and a description of each failed URL, or the first error when every
+/// URL failed.
+async fn refresh_each(
+    state: &AppState,
+    object_paths: &[String],
+    force: bool,
+    rejected: AppError,
+) -> AppResult<(Vec<String>, Vec<String>)> {
+    warn!(
+        error = ?rejected,
+        urls = object_paths.len(),
+        "Aliyun rejected the refresh, retrying each URL alone"
+    );
+    let mut task_ids = Vec::new();
+    let mut failures = Vec::new();
+    let mut first_error = None;
+    for object_path in object_paths {
+        let request = RefreshObjectCachesRequest {
+            object_path: object_path.clone(),
+            object_type: Some("File".to_string()),
+            force: Some(force),
+            product: None,
+        };
+        match refresh(state, &request, false, "oss_event").await {
+            Ok(response) => task_ids.extend(split_task_ids(&response.refresh_task_id)),
+            Err(err) => {
+                failures.push(format!("{object_path}: {err:#}"));
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+    match first_error {
+        Some(err) if task_ids.is_empty() => Err(err),
+        _ => Ok((task_ids, failures)),
+    }
+}
+
+/// Aliyun answers with comma-separated ids when one call created several tasks
+fn split_task_ids(refresh_task_id: &str) -> Vec<String> {
+    refresh_task_id
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Where the refresh for an OSS event went
+enum Submitted {
+    /// Sent to Aliyun, or only prepared in dry-run mode
+    Sent(RefreshObjectCachesResponse),
+    /// Left to the background refresh worker
+    Queued(RefreshJob),
+}
+
+/// Queue the refresh for the worker, or send it right away in dry-run or synchronous mode
+///
+/// With `preload`, a successful refresh is followed by a preload of the same URLs; a failed
+/// preload is only logged.
+async fn submit_refresh(
+    state: &AppState,
+    aliyun: &AliyunConfig,
+    request: &RefreshObjectCachesRequest,
+    dedup_key: Option<EventKey>,
+    event: Option<serde_json::Value>,
+    preload: bool,
+    directory_rule: bool,
+) -> AppResult<Submitted> {
+    state.aliyun_cdn()?;
+    if preload && !aliyun.events_dry_run && aliyun.jobs.synchronous {
+        let (response, _) = refresh_and_preload(state, request, None, "oss_event").await?;
+        return Ok(Submitted::Sent(response));
+    }
+    if aliyun.events_dry_run || aliyun.jobs.synchronous {
+        let response = refresh(state, request, aliyun.events_dry_run, "oss_event").await?;
+        return Ok(Submitted::Sent(response));
+    }
+
+    let job = state.refresh_jobs.enqueue(
+        request,
+        dedup_key,
+        event,
+        preload,
+        directory_rule,
+        Duration::from_secs(aliyun.jobs.retention_secs),
+    );
+    info!(
+        job_id = job.id,
+        object_path = %request.object_path,
+        object_type = request.object_type.as_deref(),
+        "CDN refresh queued"
+    );
+    Ok(Submitted::Queued(job))
+}
+
+/// Get a queued CDN refresh
+#[utoipa::path(
+    get,
+    tag = "aliyun",
+    path = "/aliyun/jobs/{id}",
+    params(
+        ("id" = u64, Path, description = "Job id from an OSS event response")
+    ),
+    responses(
+        (status = OK, description = "Job status, with the Aliyun task id once succeeded or the last error", body = RefreshJob),
+        (status = UNAUTHORIZED, body = ErrorBody, description = "Missing or invalid bearer token"),
+        (status = NOT_FOUND, body = ErrorBody, description = "Unknown job, or finished longer ago than `aliyun.jobs.retention_secs`")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn get_refresh_job(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> AppResult<Json<RefreshJob>> {
+    state
+        .refresh_jobs
+        .get(id)
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound(anyhow::anyhow!("Refresh job {} not found", id)))
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct RefreshLogQuery {
+    /// Only tasks Aliyun last reported with this status, in any case
+    #[serde(default, deserialize_with = "crate::api::aliyun::task_status_filter")]
+    pub status: Option<RefreshTaskStatus>,
+}
+
+/// List refresh tasks Aliyun accepted, newest first, with the status reconciliation found
+#[utoipa::path(
+    get,
+    tag = "aliyun",
+    path = "/aliyun/refreshLog",
+    params(RefreshLogQuery),
+    responses(
+        (status = OK, description = "Logged refresh tasks; kept in memory, newest 1000 only", body = Vec<RefreshLogEntry>),
+        (status = UNAUTHORIZED, body = ErrorBody, description = "Missing or invalid bearer token")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn list_refresh_log(
+    State(state): State<AppState>,
+    Query(query): Query<RefreshLogQuery>,
+) -> Json<Vec<RefreshLogEntry>> {
+    Json(state.refresh_log.list(query.status))
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct DeadLetterQuery {
+    /// Only `pending` or only `resolved` dead letters
+    pub status: Option<DeadLetterStatus>,
+    /// Page to return, from 1
+    #[serde(default = "default_dead_letter_page_number")]
+    pub page_number: usize,
+    /// Dead letters per page, at most 100
+    #[serde(default = "default_dead_letter_page_size")]
+    pub page_size: usize,
+}
+
+fn default_dead_letter_page_number() -> usize {
+    1
+}
+
+fn default_dead_letter_page_size() -> usize {
+    20
+}
+
+/// List OSS events that could not be processed, newest first
+#[utoipa::path(
+    get,
+    tag = "aliyun",
+    path = "/aliyun/events/dlq",
+    params(DeadLetterQuery),
+    responses(
+        (status = OK, description = "Dead-lettered events; kept in memory, newest 1000 only", body = DeadLetterPage),
+        (status = BAD_REQUEST, body = ErrorBody, description = "Page number below 1 or page size outside 1 to 100"),
+        (status = UNAUTHORIZED, body = ErrorBody, description = "Missing or invalid bearer token")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn list_dead_letters(
+    State(state): State<AppState>,
+    Query(query): Query<DeadLetterQuery>,
+) -> AppResult<Json<DeadLetterPage>> {
+    if query.page_number == 0 {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "page_number starts at 1"
+        )));
+    }
+    if query.page_size == 0 || query.page_size > MAX_DEAD_LETTER_PAGE_SIZE {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "page_size must be between 1 and {}, got {}",
+            MAX_DEAD_LETTER_PAGE_SIZE,
+            query.page_size
+        )));
+    }
+    Ok(Json(state.dead_letters.list(
+        query.status,
+        query.page_number,
+        query.page_size,
+    )))
+}
+
+/// Run a dead-lettered event through the normal processing again, resolving it on success
+#[utoipa::path(
+    post,
+    tag = "aliyun",
+    path = "/aliyun/events/dlq/{id}/replay",
+    params(
+        ("id" = u64, Path, description = "Dead letter id from an OSS event response or the listing")
+    ),
+    responses(
+        (status = OK, description = "Processed; the dead letter is resolved with the outcome", body = DeadLetter),
+        (status = BAD_REQUEST, body = ErrorBody, description = "Already resolved, or the event still fails validation (the error is recorded)"),
+        (status = UNAUTHORIZED, body = ErrorBody, description = "Missing or invalid bearer token"),
+        (status = NOT_FOUND, body = ErrorBody, description = "Unknown or dropped dead letter"),
+        (status = SERVICE_UNAVAILABLE, body = ErrorBody, description = "Service is in read-only mode, or Aliyun is not configured"),
+        (status = GATEWAY_TIMEOUT, body = ErrorBody, description = "Aliyun did not answer in time"),
+        (status = TOO_MANY_REQUESTS, body = ErrorBody, description = "Aliyun throttled the call or the quota is used up"),
+        (status = BAD_GATEWAY, body = ErrorBody, description = "Aliyun rejected the refresh; `exception` carries its error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn replay_dead_letter(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> AppResult<Json<DeadLetter>> {
+    state.aliyun_cdn()?;
+    let event = state.dead_letters.start_replay(id)?;
+    let outcome =
+        process_oss_event(&state, event)
+            .await
+            .map(|(_, response)| match response.job_id {
+                Some(job_id) => format!("{} (job {})", response.message, job_id),
+                None => response.message,
+            });
+    let dead_letter = state
+        .dead_letters
+        .finish_replay(id, outcome.as_deref())
+        .ok_or_else(|| AppError::NotFound(anyhow::anyhow!("Dead letter {} not found", id)))?;
+    outcome?;
+    info!(dead_letter_id = id, "Dead-lettered OSS event replayed");
+    Ok(Json(dead_letter))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{num::NonZeroU32, time::Duration};
+
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{body_string_contains, header, method, path, query_param},
+    };
+
+    use crate::{
+        config::{AppSettings, BucketPrefixRule, WebhookConfig},
+        routes::build_router,
+        test_support::{TestApp, body_json, state_from, test_app, test_settings, test_token},
+        webhooks::{SIGNATURE_HEADER, run_webhook_dispatcher, sign},
+    };
+
+    /// Settings pointing the CDN endpoint at a stand-in that must never be called
+    async fn unreachable_cdn() -> (MockServer, AppSettings) {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&server)
+            .await;
+        let mut settings = test_settings();
+        settings.aliyun.endpoint = server.uri();
+        (server, settings)
+    }
+
+    fn oss_event(bucket: &str, key: &str) -> serde_json::Value {
+        serde_json::json!({
+            "id": "event-1",
+            "source": "acs.oss",
+            "data": {
+                "oss": {
+                    "bucket": { "name": bucket },
+                    "object": { "key": key }
+                }
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn test_refresh_dry_run_does_not_call_aliyun() {
+        let (server, settings) = unreachable_cdn().await;
+        let response = build_router(state_from(&settings))
+            .oneshot(
+                Request::post("/api/aliyun/refreshObjectCaches")
+                    .header("Authorization", format!("Bearer {}", test_token()))
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        r#"{"object_path":"https://static.prts.wiki/a.png\n\n https://static.prts.wiki/b.png ","dry_run":true}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            body_json(response).await,
+            serde_json::json!({
+                "task_id": "dry-run",
+                "task_ids": ["dry-run"],
+                "object_type": "File",
+                "object_paths": [
+                    "https://static.prts.wiki/a.png",
+                    "https://static.prts.wiki/b.png"
+                ],
+                "dry_run": true
+            })
+        );
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_refresh_notifies_webhooks() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("x-acs-action", "RefreshObjectCaches"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"17772470467"}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/hook"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let mut settings = test_settings();
+        settings.aliyun.endpoint = server.uri();
+        settings.webhooks = vec![WebhookConfig {
+            url: format!("{}/hook", server.uri()),
+            secret: "webhook-secret".to_string(),
+            events: vec!["cdn.*".to_string()],
+            max_attempts: NonZeroU32::new(1).unwrap(),
+            retry_backoff_ms: 0,
+            bearer_token: None,
+            timeout_secs: 10,
+        }];
+        let state = state_from(&settings);
+        let dispatcher = tokio::spawn(run_webhook_dispatcher(
+            state.webhooks.clone(),
+            state.http_client.clone(),
+        ));
+
+        let response = build_router(state)
+            .oneshot(
+                Request::post("/api/aliyun/refreshObjectCaches")
+                    .header("Authorization", format!("Bearer {}", test_token()))
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        r#"{"object_path":"https://static.prts.wiki/a.png"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+
+        let hook = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let requests = server.received_requests().await.unwrap();
+                if let Some(hook) = requests.into_iter().find(|r| r.url.path() == "/hook") {
+                    return hook;
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("webhook should be delivered");
+        assert_eq!(
+            hook.headers[SIGNATURE_HEADER],
+            sign("webhook-secret", &hook.body).as_str()
+        );
+        let event: serde_json::Value = serde_json::from_slice(&hook.body).unwrap();
+        assert_eq!(event["type"], "cdn.refresh.succeeded");
+        assert_eq!(event["source"], "manual");
+        assert_eq!(event["task_id"], "17772470467");
+        assert_eq!(
+            event["object_paths"],
+            serde_json::json!(["https://static.prts.wiki/a.png"])
+        );
+
+        dispatcher.abort();
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_oss_event_refresh_notifies_the_object() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("x-acs-action", "RefreshObjectCaches"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"17772470467"}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/hook"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let mut settings = synchronous_settings(&server);
+        settings.webhooks = vec![WebhookConfig {
+            url: format!("{}/hook", server.uri()),
+            secret: "webhook-secret".to_string(),
+            events: vec!["oss.object.refreshed".to_string()],
+            max_attempts: NonZeroU32::new(1).unwrap(),
+            retry_backoff_ms: 0,
+            bearer_token: None,
+            timeout_secs: 10,
+        }];
+        let state = state_from(&settings);
+        let dispatcher = tokio::spawn(run_webhook_dispatcher(
+            state.webhooks.clone(),
+            state.http_client.clone(),
+        ));
+
+        let (status, _) = post_event(
+            &build_router(state),
+            oss_event("prts-static", "立绘/a.png").to_string(),
+        )
+        .await;
+        assert_eq!(status, 200);
+
+        let hook = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let requests = server.received_requests().await.unwrap();
+                if let Some(hook) = requests.into_iter().find(|r| r.url.path() == "/hook") {
+                    return hook;
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("webhook should be delivered");
+        let event: serde_json::Value = serde_json::from_slice(&hook.body).unwrap();
+        assert_eq!(event["type"], "oss.object.refreshed");
+        assert_eq!(event["bucket"], "prts-static");
+        assert_eq!(event["object_key"], "立绘/a.png");
+        assert_eq!(event["domains"], serde_json::json!(["static.prts.wiki"]));
+        assert_eq!(
+            event["object_paths"],
+            serde_json::json!(["https://static.prts.wiki/%E7%AB%8B%E7%BB%98/a.png"])
+        );
+        assert_eq!(event["task_id"], "17772470467");
+
+        dispatcher.abort();
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_refresh_directory_normalizes_directories() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("x-acs-action", "RefreshObjectCaches"))
+            .and(body_string_contains(
+                "ObjectPath=https%3A%2F%2Fstatic.prts.wiki%2Fimages%2F%0Ahttps%3A%2F%2Fstatic.prts.wiki%2Faudio%2F&",
+            ))
+            .and(body_string_contains("ObjectType=Directory"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"17772470467"}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let mut settings = test_settings();
+        settings.aliyun.endpoint = server.uri();
+        let router = build_router(state_from(&settings));
+        let refresh = |directories: serde_json::Value| {
+            Request::post("/api/aliyun/refreshDirectory")
+                .header("Authorization", format!("Bearer {}", test_token()))
+                .header("Content-Type", "application/json")
+                .body(Body::from(
+                    serde_json::json!({ "directories": directories }).to_string(),
+                ))
+                .unwrap()
+        };
+
+        let response = router
+            .clone()
+            .oneshot(refresh(serde_json::json!([
+                " https://static.prts.wiki/images?version=2",
+                "",
+                "https://static.prts.wiki/images/",
+                "https://static.prts.wiki/audio#top",
+            ])))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let body = body_json(response).await;
+        assert_eq!(body["task_ids"], serde_json::json!(["17772470467"]));
+        assert_eq!(body["aliyun_request_id"], "r");
+        assert_eq!(body["object_type"], "Directory");
+        assert_eq!(
+            body["object_paths"],
+            serde_json::json!([
+                "https://static.prts.wiki/images/",
+                "https://static.prts.wiki/audio/"
+            ])
+        );
+
+        let too_many = (0..=crate::aliyun::MAX_DIRECTORY_PATHS)
+            .map(|index| format!("https://static.prts.wiki/{index}"))
+            .collect::<Vec<_>>();
+        for directories in [serde_json::json!(too_many), serde_json::json!([" "])] {
+            let response = router.clone().oneshot(refresh(directories)).await.unwrap();
+            assert_eq!(response.status(), 400);
+        }
+        server.verify().await;
+    }
+
+    /// Accept one refresh and list its task with `status` on the app's CDN stand-in
+    async fn mount_waited_refresh(app: &TestApp, status: &str) {
+        Mock::given(method("POST"))
+            .and(header("x-acs-action", "RefreshObjectCaches"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"17772470467"}"#),
+            )
+            .expect(1)
+            .mount(&app.aliyun)
+            .await;
+        Mock::given(method("GET"))
+            .and(header("x-acs-action", "DescribeRefreshTasks"))
+            .and(query_param("TaskId", "17772470467"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "RequestId": "r",
+                "PageNumber": 1,
+                "PageSize": 20,
+                "TotalCount": 1,
+                "Tasks": {"CDNTask": [{
+                    "TaskId": "17772470467",
+                    "ObjectPath": "https://static.prts.wiki/a.png",
+                    "ObjectType": "file",
+                    "Status": status,
+                    "Process": "50%",
+                    "CreationTime": "2026-10-16T02:00:00Z"
+                }]}
+            })))
+            .mount(&app.aliyun)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_refresh_and_wait_returns_the_final_status() {
+        let app = test_app().await;
+        mount_waited_refresh(&app, "Failed").await;
+
+        let response = app
+            .router
+            .clone()
+            .oneshot(post_json(
+                "/api/aliyun/refreshObjectCaches:wait",
+                serde_json::json!({"object_path": "https://static.prts.wiki/a.png"}),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let body = body_json(response).await;
+        assert_eq!(body["task_id"], "17772470467");
+        assert_eq!(body["status"], "Failed");
+        assert!(body.get("timed_out").is_none());
+        assert_eq!(app.state.refresh_log.list(None).len(), 1);
+
+        for payload in [
+            serde_json::json!({"object_path": "https://static.prts.wiki/a.png", "timeout_seconds": 601}),
+            serde_json::json!({"object_path": "https://static.prts.wiki/a.png", "poll_interval_seconds": 0}),
+            serde_json::json!({"object_path": "https://static.prts.wiki/a.png", "object_type": "Regex"}),
+        ] {
+            let response = app
+                .router
+                .clone()
+                .oneshot(post_json("/api/aliyun/refreshObjectCaches:wait", payload))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 400);
+        }
+        app.aliyun.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_refresh_and_wait_times_out_with_202() {
+        let app = test_app().await;
+        mount_waited_refresh(&app, "Refreshing").await;
+
+        let response = app
+            .router
+            .clone()
+            .oneshot(post_json(
+                "/api/aliyun/refreshObjectCaches:wait",
+                serde_json::json!({
+                    "object_path": "https://static.prts.wiki/a.png",
+                    "timeout_seconds": 1,
+                    "poll_interval_seconds": 1
+                }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 202);
+        let body = body_json(response).await;
+        assert_eq!(body["task_id"], "17772470467");
+        assert_eq!(body["status"], "Refreshing");
+        assert_eq!(body["timed_out"], true);
+        assert!(body["elapsed_seconds"].as_f64().unwrap() >= 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_and_wait_rejects_dcdn_domains() {
+        let (cdn, mut settings) = unreachable_cdn().await;
+        settings.aliyun.dcdn.domains = vec!["media.prts.wiki".to_string()];
+        let response = build_router(state_from(&settings))
+            .oneshot(post_json(
+                "/api/aliyun/refreshObjectCaches:wait",
+                serde_json::json!({"object_path": "https://media.prts.wiki/a.mp4"}),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400);
+        cdn.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_push_object_caches_returns_push_task_id() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("x-acs-action", "PushObjectCache"))
+            .and(header("x-acs-version", "2018-05-10"))
+            .and(body_string_contains(
+                "ObjectPath=https%3A%2F%2Fstatic.prts.wiki%2Fa.png%0Ahttps%3A%2F%2Fstatic.prts.wiki%2Fb.png",
+            ))
+            .and(body_string_contains("Area=overseas"))
+            .and(body_string_contains("L2Preload=true"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","PushTaskId":"17772470468"}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let mut settings = test_settings();
+        settings.aliyun.endpoint = server.uri();
+
+        let response = build_router(state_from(&settings))
+            .oneshot(
+                Request::post("/api/aliyun/pushObjectCaches")
+                    .header("Authorization", format!("Bearer {}", test_token()))
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        r#"{"object_path":"https://static.prts.wiki/a.png\nhttps://static.prts.wiki/b.png\n","area":"overseas","l2_preload":true}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            body_json(response).await,
+            serde_json::json!({
+                "task_id": "17772470468",
+                "object_paths": [
+                    "https://static.prts.wiki/a.png",
+                    "https://static.prts.wiki/b.png"
+                ],
+                "aliyun_request_id": "r"
+            })
+        );
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_push_object_caches_validates_before_calling_aliyun() {
+        let (server, settings) = unreachable_cdn().await;
+        let router = build_router(state_from(&settings));
+        let too_many = (0..=super::MAX_PUSH_PATHS)
+            .map(|index| format!("https://static.prts.wiki/{index}.png"))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        for payload in [
+            serde_json::json!({"object_path": "https://static.prts.wiki/a.png", "area": "global"}),
+            serde_json::json!({"object_path": "static.prts.wiki/a.png"}),
+            serde_json::json!({"object_path": too_many}),
+        ] {
+            let response = router
+                .clone()
+                .oneshot(
+                    Request::post("/api/aliyun/pushObjectCaches")
+                        .header("Authorization", format!("Bearer {}", test_token()))
+                        .header("Content-Type", "application/json")
+                        .body(Body::from(payload.to_string()))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 400, "{payload}");
+        }
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_refresh_and_preload_reports_a_failed_preload_with_207() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("x-acs-action", "RefreshObjectCaches"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"17772470467"}"#),
+            )
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(header("x-acs-action", "PushObjectCache"))
+            .and(body_string_contains("Area=overseas"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","PushTaskId":"17772470468"}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(header("x-acs-action", "PushObjectCache"))
+            .respond_with(
+                ResponseTemplate::new(400)
+                    .set_body_string(r#"{"Code":"InvalidParameter","Message":"bad area"}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let mut settings = test_settings();
+        settings.aliyun.endpoint = server.uri();
+        let router = build_router(state_from(&settings));
+
+        let response = router
+            .clone()
+            .oneshot(post_json(
+                "/api/aliyun/refreshAndPreload",
+                serde_json::json!({
+                    "object_path": "https://static.prts.wiki/a.png",
+                    "area": "overseas"
+                }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            body_json(response).await,
+            serde_json::json!({
+                "refresh_task_id": "17772470467",
+                "preload_task_id": "17772470468",
+                "object_paths": ["https://static.prts.wiki/a.png"]
+            })
+        );
+
+        let response = router
+            .oneshot(post_json(
+                "/api/aliyun/refreshAndPreload",
+                serde_json::json!({"object_path": "https://static.prts.wiki/a.png"}),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 207);
+        let body = body_json(response).await;
+        assert_eq!(body["refresh_task_id"], "17772470467");
+        assert!(body.get("preload_task_id").is_none());
+        assert!(
+            body["preload_error"]
+                .as_str()
+                .unwrap()
+                .contains("InvalidParameter")
+        );
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_events_under_preload_prefixes_are_preloaded() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("x-acs-action", "RefreshObjectCaches"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"17772470467"}"#),
+            )
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(header("x-acs-action", "PushObjectCache"))
+            .and(body_string_contains("images%2Fhot%2Fa.png"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","PushTaskId":"17772470468"}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let mut settings = synchronous_settings(&server);
+        settings.aliyun.events.preload_prefixes = vec![BucketPrefixRule {
+            bucket: "prts-static".to_string(),
+            prefix: "images/hot/".to_string(),
+        }];
+        let router = build_router(state_from(&settings));
+
+        for key in ["images/hot/a.png", "images/b.png"] {
+            let (status, body) =
+                post_event(&router, oss_event("prts-static", key).to_string()).await;
+            assert_eq!(status, 200, "{body}");
+        }
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_oversized_refresh_is_split_and_reports_the_failed_chunk() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("%2F1200.png"))
+            .respond_with(
+                ResponseTemplate::new(400)
+                    .set_body_string(r#"{"Code":"QuotaExceeded.Refresh","Message":"quota"}"#),
+            )
+            .with_priority(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"17772470467"}"#),
+            )
+            .expect(2)
+            .mount(&server)
+            .await;
+        let mut settings = test_settings();
+        settings.aliyun.endpoint = server.uri();
+        let object_path = (0..2500)
+            .map(|index| format!("https://static.prts.wiki/{index}.png"))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let response = build_router(state_from(&settings))
+            .oneshot(
+                Request::post("/api/aliyun/refreshObjectCaches")
+                    .header("Authorization", format!("Bearer {}", test_token()))
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        serde_json::json!({ "object_path": object_path }).to_string(),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        let body = body_json(response).await;
+        assert_eq!(
+            body["task_ids"],
+            serde_json::json!(["17772470467", "17772470467"])
+        );
+        assert_eq!(body["object_paths"].as_array().unwrap().len(), 2500);
+        let failed = &body["failed_chunks"];
+        assert_eq!(failed.as_array().unwrap().len(), 1);
+        assert_eq!(failed[0]["chunk"], 1);
+        assert_eq!(failed[0]["object_paths"].as_array().unwrap().len(), 1000);
+        assert_eq!(
+            failed[0]["object_paths"][0],
+            "https://static.prts.wiki/1000.png"
+        );
+        assert!(
+            failed[0]["error"]
+                .as_str()
+                .unwrap()
+                .contains("QuotaExceeded.Refresh")
+        );
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_chunks_run_concurrently_and_keep_their_order() {
+        let server = MockServer::start().await;
+        let accepted = |task_id: &str| {
+            ResponseTemplate::new(200).set_body_string(format!(
+                r#"{{"RequestId":"r","RefreshTaskId":"{task_id}"}}"#
+            ))
+        };
+        // The first chunk answers last, after the others finished
+        Mock::given(method("POST"))
+            .and(body_string_contains("%2F0.png"))
+            .respond_with(accepted("0").set_delay(Duration::from_millis(300)))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("%2F1200.png"))
+            .respond_with(
+                ResponseTemplate::new(400)
+                    .set_body_string(r#"{"Code":"InvalidObjectPath.Malformed","Message":"bad"}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("%2F2400.png"))
+            .respond_with(accepted("2"))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let mut settings = test_settings();
+        settings.aliyun.endpoint = server.uri();
+        let object_path = (0..2500)
+            .map(|index| format!("https://static.prts.wiki/{index}.png"))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let response = build_router(state_from(&settings))
+            .oneshot(post_json(
+                "/api/aliyun/refreshObjectCaches",
+                serde_json::json!({ "object_path": object_path }),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        let body = body_json(response).await;
+        assert_eq!(body["task_id"], "0");
+        assert_eq!(body["task_ids"], serde_json::json!(["0", "2"]));
+        let failed = body["failed_chunks"].as_array().unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0]["chunk"], 1);
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_refresh_fails_when_every_chunk_fails() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(400).set_body_string(r#"{"Code":"QuotaExceeded.Refresh"}"#),
+            )
+            .expect(2)
+            .mount(&server)
+            .await;
+        let mut settings = test_settings();
+        settings.aliyun.endpoint = server.uri();
+        let object_path = vec!["https://static.prts.wiki/images/"; 101].join("\n");
+
+        let response = build_router(state_from(&settings))
+            .oneshot(
+                Request::post("/api/aliyun/refreshObjectCaches")
+                    .header("Authorization", format!("Bearer {}", test_token()))
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        serde_json::json!({
+                            "object_path": object_path,
+                            "object_type": "Directory"
+                        })
+                        .to_string(),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 429);
+        assert_eq!(
+            body_json(response).await["exception"]["Code"],
+            "QuotaExceeded.Refresh"
+        );
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_refresh_rejects_unknown_object_type() {
+        let response = build_router(state_from(&test_settings()))
+            .oneshot(
+                Request::post("/api/aliyun/refreshObjectCaches")
+                    .header("Authorization", format!("Bearer {}", test_token()))
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        r#"{"object_path":"https://static.prts.wiki/","object_type":"Regex","dry_run":true}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn test_oss_event_dry_run_reports_mapped_url() {
+        let (server, mut settings) = unreachable_cdn().await;
+        settings.aliyun.events_dry_run = true;
+        let response = build_router(state_from(&settings))
+            .oneshot(
+                Request::post("/api/aliyun/events")
+                    .header("x-eventbridge-signature-token", test_token())
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        oss_event("prts-static", "images/a b.png").to_string(),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        let body = body_json(response).await;
+        assert_eq!(body["task_id"], "dry-run");
+        assert_eq!(
+            body["object_path"],
+            "https://static.prts.wiki/images/a%20b.png"
+        );
+        assert_eq!(body["object_type"], "File");
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_oss_event_url_encoded_key_is_encoded_once() {
+        let (server, mut settings) = unreachable_cdn().await;
+        settings.aliyun.events_dry_run = true;
+        settings.aliyun.events.key_encoding = crate::config::KeyEncoding::Url;
+        let response = build_router(state_from(&settings))
+            .oneshot(
+                Request::post("/api/aliyun/events")
+                    .header("x-eventbridge-signature-token", test_token())
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        oss_event("prts-static", "%E7%AB%8B%E7%BB%98/a+b%231.png").to_string(),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            body_json(response).await["object_path"],
+            "https://static.prts.wiki/%E7%AB%8B%E7%BB%98/a%20b%231.png"
+        );
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_bucket_key_encoding_overrides_the_default() {
+        let (server, mut settings) = unreachable_cdn().await;
+        settings.aliyun.events_dry_run = true;
+        settings.aliyun.events.key_encoding = crate::config::KeyEncoding::Url;
+        settings.aliyun.events.bucket_key_encoding = std::collections::HashMap::from([(
+            "prts-static".to_string(),
+            crate::config::KeyEncoding::Percent,
+        )]);
+        let router = build_router(state_from(&settings));
+
+        let (status, body) = post_event(
+            &router,
+            oss_event("prts-static", "%E7%AB%8B%E7%BB%98/a+b%20c%3F.png").to_string(),
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert_eq!(
+            body["object_path"],
+            "https://static.prts.wiki/%E7%AB%8B%E7%BB%98/a+b%20c%3F.png"
+        );
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_eventbridge_hmac_mode_verifies_raw_body() {
+        use crate::event_auth::{SIGNATURE_HEADER, TIMESTAMP_HEADER, sign};
+
+        let (server, mut settings) = unreachable_cdn().await;
+        settings.aliyun.events_dry_run = true;
+        settings.aliyun.events_auth = crate::config::EventsAuth::EventbridgeHmac;
+        settings.aliyun.events_hmac_secret = Some("eventbridge-secret".to_string());
+        let router = build_router(state_from(&settings));
+
+        let body = oss_event("prts-static", "a.png").to_string();
+        let now = chrono::Utc::now().timestamp();
+        let deliver = |body: String, signature: String| {
+            router.clone().oneshot(
+                Request::post("/api/aliyun/events")
+                    .header(TIMESTAMP_HEADER, now)
+                    .header(SIGNATURE_HEADER, signature)
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+        };
+        let signature = sign("eventbridge-secret", now, body.as_bytes());
+
+        let response = deliver(body.replace("a.png", "b.png"), signature.clone())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 401);
+
+        let response = deliver(body.clone(), signature.clone()).await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(body_json(response).await["task_id"], "dry-run");
+
+        // The JWT header alone is no longer accepted
+        let response = router
+            .clone()
+            .oneshot(
+                Request::post("/api/aliyun/events")
+                    .header("x-eventbridge-signature-token", test_token())
+                    .body(Body::from(body.clone()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 401);
+
+        // Replayed
+        let response = deliver(body, signature).await.unwrap();
+        assert_eq!(response.status(), 401);
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_both_mode_needs_token_and_signature() {
+        use crate::event_auth::{SIGNATURE_HEADER, TIMESTAMP_HEADER, sign};
+
+        let (server, mut settings) = unreachable_cdn().await;
+        settings.aliyun.events_dry_run = true;
+        settings.aliyun.events_auth = crate::config::EventsAuth::Both;
+        settings.aliyun.events_hmac_secret = Some("eventbridge-secret".to_string());
+        let router = build_router(state_from(&settings));
+
+        let body = oss_event("prts-static", "a.png").to_string();
+        let now = chrono::Utc::now().timestamp();
+        let signature = sign("eventbridge-secret", now, body.as_bytes());
+        let deliver = |token: Option<String>| {
+            let mut request = Request::post("/api/aliyun/events")
+                .header(TIMESTAMP_HEADER, now)
+                .header(SIGNATURE_HEADER, signature.clone())
+                .header("Content-Type", "application/json");
+            if let Some(token) = token {
+                request = request.header("x-eventbridge-signature-token", token);
+            }
+            router
+                .clone()
+                .oneshot(request.body(Body::from(body.clone())).unwrap())
+        };
+
+        // A missing token fails before the signature is recorded as seen
+        let response = deliver(None).await.unwrap();
+        assert_eq!(response.status(), 401);
+        let (status, body) = post_event(&router, body.clone()).await;
+        assert_eq!(status, 401, "{body}");
+
+        let response = deliver(Some(test_token())).await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(body_json(response).await["task_id"], "dry-run");
+        server.verify().await;
+    }
+
+    async fn describe_task_via(app: &TestApp, task_id: &str) -> axum::response::Response {
+        app.router
+            .clone()
+            .oneshot(
+                Request::get(format!("/api/aliyun/refreshTask/{task_id}"))
+                    .header("Authorization", format!("Bearer {}", test_token()))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_describe_refresh_task_by_id() {
+        let app = test_app().await;
+        Mock::given(method("GET"))
+            .and(header("x-acs-action", "DescribeRefreshTaskById"))
+            .and(query_param("TaskId", "17772470467,17772470468"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"RequestId":"E0C2EF95","TotalCount":1,"Tasks":[{"TaskId":"17772470467","ObjectPath":"https://static.prts.wiki/a.png","ObjectType":"file","Status":"Complete","Process":"100%","CreationTime":"2026-10-16T02:00:00Z"}]}"#,
+            ))
+            .expect(1)
+            .mount(&app.aliyun)
+            .await;
+
+        let response = describe_task_via(&app, "17772470467,17772470468").await;
+        assert_eq!(response.status(), 200);
+        let body = body_json(response).await;
+        assert_eq!(body["TotalCount"], 1);
+        assert_eq!(body["Tasks"][0]["Status"], "Complete");
+    }
+
+    #[tokio::test]
+    async fn test_unknown_refresh_task_is_404() {
+        let app = test_app().await;
+        Mock::given(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"E0C2EF95","TotalCount":0,"Tasks":[]}"#),
+            )
+            .mount(&app.aliyun)
+            .await;
+
+        let response = describe_task_via(&app, "1").await;
+        assert_eq!(response.status(), 404);
+        assert!(
+            body_json(response).await["msg"]
+                .as_str()
+                .unwrap()
+                .contains("Refresh task 1 not found")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_refresh_task_returns_the_task_itself() {
+        let app = test_app().await;
+        Mock::given(method("GET"))
+            .and(header("x-acs-action", "DescribeRefreshTasks"))
+            .and(query_param("TaskId", "17772470467"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"RequestId":"E0C2EF95","PageNumber":1,"PageSize":20,"TotalCount":1,"Tasks":{"CDNTask":[{"TaskId":"17772470467","ObjectPath":"https://static.prts.wiki/a.png","ObjectType":"file","Status":"Complete","Process":"100%","CreationTime":"2026-10-16T02:00:00Z"}]}}"#,
+            ))
+            .expect(1)
+            .mount(&app.aliyun)
+            .await;
+        Mock::given(method("GET"))
+            .and(query_param("TaskId", "1"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"RequestId":"E0C2EF96","PageNumber":1,"PageSize":20,"TotalCount":0,"Tasks":{"CDNTask":[]}}"#,
+            ))
+            .mount(&app.aliyun)
+            .await;
+        let get = |task_id: &str| {
+            app.router.clone().oneshot(
+                Request::get(format!("/api/aliyun/refreshTasks/{task_id}"))
+                    .header("Authorization", format!("Bearer {}", test_token()))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+
+        let response = get("17772470467").await.unwrap();
+        assert_eq!(response.status(), 200);
+        let body = body_json(response).await;
+        assert_eq!(body["TaskId"], "17772470467");
+        assert_eq!(body["Status"], "Complete");
+
+        assert_eq!(get("1").await.unwrap().status(), 404);
+        assert_eq!(get("abc").await.unwrap().status(), 400);
+        app.aliyun.verify().await;
+    }
+
+    /// Serve DescribeRefreshTasks page `page` of `total` tasks, `page_size` per page
+    async fn mount_tasks_page(server: &MockServer, page: u64, page_size: u64, total: u64) {
+        let first = (page - 1) * page_size;
+        let tasks = (first..total.min(first + page_size))
+            .map(|id| {
+                serde_json::json!({
+                    "TaskId": id.to_string(),
+                    "ObjectPath": format!("https://static.prts.wiki/{id}.png"),
+                    "ObjectType": "file",
+                    "Status": "Complete",
+                    "Process": "100%",
+                    "CreationTime": "2026-10-16T00:00:00Z"
+                })
+            })
+            .collect::<Vec<_>>();
+        Mock::given(method("GET"))
+            .and(header("x-acs-action", "DescribeRefreshTasks"))
+            .and(query_param("PageNumber", page.to_string()))
+            .and(query_param("PageSize", page_size.to_string()))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "RequestId": format!("request-{page}"),
+                "PageNumber": page,
+                "PageSize": page_size,
+                "TotalCount": total,
+                "Tasks": { "CDNTask": tasks }
+            })))
+            .expect(1)
+            .mount(server)
+            .await;
+    }
+
+    /// List every task of `aliyun` in pages of 2, at most `max_pages` of them
+    async fn fetch_all_tasks(aliyun: &MockServer, max_pages: u32) -> serde_json::Value {
+        fetch_all_tasks_with(
+            aliyun,
+            max_pages,
+            r#"{"domain_name":"static.prts.wiki","page_size":2,"fetch_all":true}"#,
+        )
+        .await
+    }
+
+    async fn fetch_all_tasks_with(
+        aliyun: &MockServer,
+        max_pages: u32,
+        payload: &'static str,
+    ) -> serde_json::Value {
+        let mut settings = test_settings();
+        settings.aliyun.endpoint = aliyun.uri();
+        settings.aliyun.fetch_all.max_pages = NonZeroU32::new(max_pages).unwrap();
+        settings.aliyun.fetch_all.page_delay_ms = 0;
+        let response = build_router(state_from(&settings))
+            .oneshot(
+                Request::post("/api/aliyun/describeRefreshTasks")
+                    .header("Authorization", format!("Bearer {}", test_token()))
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(payload))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        body_json(response).await
+    }
+
+    fn task_ids(body: &serde_json::Value) -> Vec<&str> {
+        body["Tasks"]["CDNTask"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|task| task["TaskId"].as_str().unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_fetch_all_follows_every_page() {
+        let aliyun = MockServer::start().await;
+        for page in 1..=3 {
+            mount_tasks_page(&aliyun, page, 2, 5).await;
+        }
+
+        let body = fetch_all_tasks(&aliyun, 50).await;
+        assert_eq!(task_ids(&body), ["0", "1", "2", "3", "4"]);
+        assert_eq!(body["TotalCount"], 5);
+        assert!(body.get("Warning").is_none());
+        aliyun.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_fetch_all_stops_on_exactly_full_last_page() {
+        let aliyun = MockServer::start().await;
+        // A third request would find no mock and fail the call
+        for page in 1..=2 {
+            mount_tasks_page(&aliyun, page, 2, 4).await;
+        }
+
+        let body = fetch_all_tasks(&aliyun, 50).await;
+        assert_eq!(task_ids(&body), ["0", "1", "2", "3"]);
+        assert_eq!(body["TotalCount"], 4);
+        aliyun.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_fetch_all_warns_when_page_cap_truncates() {
+        let aliyun = MockServer::start().await;
+        for page in 1..=2 {
+            mount_tasks_page(&aliyun, page, 2, 5).await;
+        }
+
+        let body = fetch_all_tasks(&aliyun, 2).await;
+        assert_eq!(task_ids(&body), ["0", "1", "2", "3"]);
+        assert_eq!(body["TotalCount"], 5);
+        assert_eq!(body["Warning"], "Stopped after 2 pages with 4 of 5 tasks");
+        aliyun.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_fetch_all_stops_at_max_results() {
+        let aliyun = MockServer::start().await;
+        for page in 1..=2 {
+            mount_tasks_page(&aliyun, page, 2, 9).await;
+        }
+
+        let body = fetch_all_tasks_with(
+            &aliyun,
+            50,
+            r#"{"page_size":2,"fetch_all":true,"max_results":3}"#,
+        )
+        .await;
+        assert_eq!(task_ids(&body), ["0", "1", "2"]);
+        assert_eq!(body["Warning"], "Stopped at max_results with 3 of 9 tasks");
+        aliyun.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_fetch_all_stops_when_aliyun_repeats_a_page() {
+        let aliyun = MockServer::start().await;
+        mount_tasks_page(&aliyun, 1, 2, 9).await;
+        // Page 2 answers with page 1's tasks, as an inconsistent total would
+        Mock::given(method("GET"))
+            .and(query_param("PageNumber", "2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "RequestId": "request-2",
+                "PageNumber": 2,
+                "PageSize": 2,
+                "TotalCount": 9,
+                "Tasks": { "CDNTask": [
+                    { "TaskId": "0", "ObjectPath": "https://static.prts.wiki/0.png", "ObjectType": "file", "Status": "Complete", "Process": "100%", "CreationTime": "2026-10-16T00:00:00Z" },
+                    { "TaskId": "1", "ObjectPath": "https://static.prts.wiki/1.png", "ObjectType": "file", "Status": "Complete", "Process": "100%", "CreationTime": "2026-10-16T00:00:00Z" }
+                ] }
+            })))
+            .expect(1)
+            .mount(&aliyun)
+            .await;
+
+        let body = fetch_all_tasks(&aliyun, 50).await;
+        assert_eq!(task_ids(&body), ["0", "1"]);
+        assert_eq!(
+            body["Warning"],
+            "Page 2 repeated earlier tasks, stopped with 2 of 9 tasks"
+        );
+        aliyun.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_describe_refresh_tasks_caches_successful_answers() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(header("x-acs-action", "DescribeRefreshTasks"))
+            .and(query_param("TaskId", "1"))
+            .respond_with(
+                ResponseTemplate::new(400)
+                    .set_body_string(r#"{"Code":"InvalidTaskId.Malformed","Message":"bad"}"#),
+            )
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(header("x-acs-action", "DescribeRefreshTasks"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "RequestId": "r",
+                "PageNumber": 1,
+                "PageSize": 20,
+                "TotalCount": 0,
+                "Tasks": { "CDNTask": [] }
+            })))
+            .expect(3)
+            .mount(&server)
+            .await;
+        let mut settings = test_settings();
+        settings.aliyun.endpoint = server.uri();
+        let router = build_router(state_from(&settings));
+        let describe = |payload: serde_json::Value| {
+            let router = router.clone();
+            async move {
+                let response = router
+                    .oneshot(post_json("/api/aliyun/describeRefreshTasks", payload))
+                    .await
+                    .unwrap();
+                (
+                    response.status().as_u16(),
+                    response
+                        .headers()
+                        .get("x-janus-cache")
+                        .map(|value| value.to_str().unwrap().to_string()),
+                )
+            }
+        };
+
+        let domain = serde_json::json!({"domain_name": "static.prts.wiki"});
+        assert_eq!(describe(domain.clone()).await, (200, Some("miss".into())));
+        assert_eq!(describe(domain.clone()).await, (200, Some("hit".into())));
+        // Another query is a separate entry
+        let page = serde_json::json!({"domain_name": "static.prts.wiki", "page_number": 2});
+        assert_eq!(describe(page).await, (200, Some("miss".into())));
+        // Errors are asked again every time
+        let bad = serde_json::json!({"task_id": "1"});
+        assert_eq!(describe(bad.clone()).await.0, 400);
+        assert_eq!(describe(bad).await.0, 400);
+
+        // Disabled, every query reaches Aliyun and no header is sent
+        settings.aliyun.tasks_cache.enabled = false;
+        let router = build_router(state_from(&settings));
+        let response = router
+            .oneshot(post_json("/api/aliyun/describeRefreshTasks", domain))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert!(response.headers().get("x-janus-cache").is_none());
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_describe_refresh_tasks_sends_times_in_utc() {
+        let app = test_app().await;
+        Mock::given(method("GET"))
+            .and(header("x-acs-action", "DescribeRefreshTasks"))
+            .and(query_param("StartTime", "2026-10-16T02:00:00Z"))
+            .and(query_param("EndTime", "2026-10-17T00:00:00Z"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "RequestId": "r",
+                "PageNumber": 1,
+                "PageSize": 20,
+                "TotalCount": 0,
+                "Tasks": { "CDNTask": [] }
+            })))
+            .expect(1)
+            .mount(&app.aliyun)
+            .await;
+
+        let response = app
+            .router
+            .clone()
+            .oneshot(post_json(
+                "/api/aliyun/describeRefreshTasks",
+                serde_json::json!({
+                    "start_time": "2026-10-16T10:00:00+08:00",
+                    "end_time": "2026-10-17T00:00:00Z"
+                }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+
+        for (start_time, end_time, message) in [
+            (
+                "2026-10-16 10:00",
+                "2026-10-17T00:00:00Z",
+                "start_time must be an RFC 3339 time",
+            ),
+            (
+                "2026-10-17T00:00:00Z",
+                "2026-10-17T08:00:00+08:00",
+                "start_time must be before end_time",
+            ),
+            (
+                "2026-10-10T00:00:00Z",
+                "2026-10-16T00:00:00Z",
+                "end_time must be at most 2026-10-13T00:00:00Z for this start_time, \
+                 or start_time at least 2026-10-13T00:00:00Z",
+            ),
+        ] {
+            let response = app
+                .router
+                .clone()
+                .oneshot(post_json(
+                    "/api/aliyun/describeRefreshTasks",
+                    serde_json::json!({"start_time": start_time, "end_time": end_time}),
+                ))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 400, "{start_time}");
+            let body = body_json(response).await;
+            assert!(body["msg"].as_str().unwrap().contains(message), "{body}");
+        }
+        app.aliyun.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_describe_refresh_tasks_rejects_oversized_page() {
+        let response = build_router(state_from(&test_settings()))
+            .oneshot(
+                Request::post("/api/aliyun/describeRefreshTasks")
+                    .header("Authorization", format!("Bearer {}", test_token()))
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(r#"{"page_size":101}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn test_describe_refresh_tasks_rejects_unknown_status() {
+        let response = build_router(state_from(&test_settings()))
+            .oneshot(post_json(
+                "/api/aliyun/describeRefreshTasks",
+                serde_json::json!({"object_type": "File", "status": "complete"}),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 422);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let message = String::from_utf8_lossy(&body);
+        assert!(
+            message.contains(
+                "unknown variant `complete`, expected one of `Complete`, `Refreshing`, `Failed`, `Pending`"
+            ),
+            "{message}"
+        );
+    }
+
+    /// Serve one DescribeCdnDomainLogs page listing `log_path` on the app's CDN stand-in
+    async fn mount_domain_logs(app: &TestApp, log_path: &str) {
+        Mock::given(method("GET"))
+            .and(header("x-acs-action", "DescribeCdnDomainLogs"))
+            .and(query_param("DomainName", "static.prts.wiki"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "RequestId": "r",
+                "DomainLogDetails": {"DomainLogDetail": [{
+                    "DomainName": "static.prts.wiki",
+                    "LogCount": 1,
+                    "PageInfos": {"PageIndex": 1, "PageSize": 1000, "Total": 1},
+                    "LogInfos": {"LogInfoDetail": [{
+                        "LogName": "static.prts.wiki_2026_10_16_0000_0100.gz",
+                        "LogPath": log_path,
+                        "LogSize": 11,
+                        "StartTime": "2026-10-16T00:00:00Z",
+                        "EndTime": "2026-10-16T01:00:00Z"
+                    }]}
+                }]}
+            })))
+            .mount(&app.aliyun)
+            .await;
+    }
+
+    fn post_json(uri: &str, body: serde_json::Value) -> Request<Body> {
+        Request::post(uri)
+            .header("Authorization", format!("Bearer {}", test_token()))
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_domain_logs_lists_files_with_download_urls() {
+        let app = test_app().await;
+        mount_domain_logs(
+            &app,
+            "cdnlog.cn-hangzhou.oss.aliyun-inc.com/static.prts.wiki/a.gz?Expires=1&Signature=s",
+        )
+        .await;
+
+        let response = app
+            .router
+            .oneshot(post_json(
+                "/api/aliyun/domainLogs",
+                serde_json::json!({"domain_name": "static.prts.wiki"}),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let body = body_json(response).await;
+        assert_eq!(body["total_count"], 1);
+        assert_eq!(
+            body["logs"][0],
+            serde_json::json!({
+                "name": "static.prts.wiki_2026_10_16_0000_0100.gz",
+                "size": 11,
+                "start_time": "2026-10-16T00:00:00Z",
+                "end_time": "2026-10-16T01:00:00Z",
+                "download_url": "https://cdnlog.cn-hangzhou.oss.aliyun-inc.com/static.prts.wiki/a.gz?Expires=1&Signature=s"
+            })
+        );
+    }
+
+    fn user_domains(status: &str) -> serde_json::Value {
+        serde_json::json!({
+            "RequestId": "r",
+            "PageNumber": 1,
+            "PageSize": 20,
+            "TotalCount": 1,
+            "Domains": {"PageData": [{
+                "DomainName": "static.prts.wiki",
+                "Cname": "static.prts.wiki.w.kunlunsl.com",
+                "CdnType": "web",
+                "DomainStatus": status,
+                "SslProtocol": "on",
+                "GmtCreated": "2020-01-01T00:00:00Z",
+                "GmtModified": "2026-10-16T00:00:00Z"
+            }]}
+        })
+    }
+
+    #[tokio::test]
+    async fn test_domains_lists_user_domains() {
+        let app = test_app().await;
+        Mock::given(method("GET"))
+            .and(header("x-acs-action", "DescribeUserDomains"))
+            .and(query_param("DomainName", "prts.wiki"))
+            .and(query_param("DomainStatus", "online"))
+            .and(query_param("PageSize", "50"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(user_domains("online")))
+            .expect(1)
+            .mount(&app.aliyun)
+            .await;
+
+        let response = app
+            .router
+            .clone()
+            .oneshot(
+                Request::get(
+                    "/api/aliyun/domains?domain_name=prts.wiki&domain_status=online&page_size=50",
+                )
+                .header("Authorization", format!("Bearer {}", test_token()))
+                .body(Body::empty())
+                .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let body = body_json(response).await;
+        assert_eq!(body["TotalCount"], 1);
+        assert_eq!(
+            body["Domains"]["PageData"][0]["DomainName"],
+            "static.prts.wiki"
+        );
+        assert_eq!(body["Domains"]["PageData"][0]["DomainStatus"], "online");
+
+        let response = app
+            .router
+            .oneshot(
+                Request::get("/api/aliyun/domains?page_size=501")
+                    .header("Authorization", format!("Bearer {}", test_token()))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400);
+        app.aliyun.verify().await;
+    }
+
+    fn get(uri: &str) -> Request<Body> {
+        Request::get(uri)
+            .header("Authorization", format!("Bearer {}", test_token()))
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_stats_ask_aliyun_in_utc() {
+        let app = test_app().await;
+        Mock::given(method("GET"))
+            .and(header("x-acs-action", "DescribeDomainBpsData"))
+            .and(query_param("DomainName", "static.prts.wiki"))
+            .and(query_param("StartTime", "2026-10-16T00:00:00Z"))
+            .and(query_param("EndTime", "2026-10-16T00:10:00Z"))
+            .and(query_param("Interval", "300"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"RequestId":"r","DomainName":"static.prts.wiki","StartTime":"2026-10-16T00:00:00Z","EndTime":"2026-10-16T00:10:00Z","DataInterval":"300","BpsDataPerInterval":{"DataModule":[{"TimeStamp":"2026-10-16T00:00:00Z","Value":"11288111","HttpsValue":"10221048"},{"TimeStamp":"2026-10-16T00:05:00Z","Value":"","HttpsValue":""}]}}"#,
+            ))
+            .expect(1)
+            .mount(&app.aliyun)
+            .await;
+        Mock::given(method("GET"))
+            .and(header("x-acs-action", "DescribeDomainHitRateData"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"RequestId":"r","DomainName":"static.prts.wiki","StartTime":"2026-10-16T00:00:00Z","EndTime":"2026-10-16T00:10:00Z","DataInterval":"300","HitRateInterval":{"DataModule":[{"TimeStamp":"2026-10-16T00:00:00Z","Value":"98.21","HttpsValue":"98.37"}]}}"#,
+            ))
+            .expect(1)
+            .mount(&app.aliyun)
+            .await;
+        let window = "domain_name=static.prts.wiki&start_time=2026-10-16T08:00:00%2B08:00&end_time=2026-10-16T08:10:00%2B08:00&interval=300";
+
+        let response = app
+            .router
+            .clone()
+            .oneshot(get(&format!("/api/aliyun/stats/bps?{window}")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            body_json(response).await,
+            serde_json::json!({
+                "request_id": "r",
+                "domain_name": "static.prts.wiki",
+                "start_time": "2026-10-16T00:00:00Z",
+                "end_time": "2026-10-16T00:10:00Z",
+                "interval": 300,
+                "data": [
+                    {"time_stamp": "2026-10-16T00:00:00Z", "bps": 11288111.0, "https_bps": 10221048.0},
+                    {"time_stamp": "2026-10-16T00:05:00Z", "bps": 0.0}
+                ]
+            })
+        );
+
+        let response = app
+            .router
+            .oneshot(get(&format!("/api/aliyun/stats/hitRate?{window}")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let body = body_json(response).await;
+        assert_eq!(body["data"][0]["hit_rate"], 98.21);
+        assert_eq!(body["data"][0]["https_hit_rate"], 98.37);
+        app.aliyun.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_stats_validate_the_window() {
+        let (server, mut settings) = unreachable_cdn().await;
+        settings.aliyun.endpoint = server.uri();
+        let router = build_router(state_from(&settings));
+        for query in [
+            // End before start
+            "domain_name=static.prts.wiki&start_time=2026-10-16T00:00:00Z&end_time=2026-10-15T00:00:00Z",
+            // Over 90 days
+            "domain_name=static.prts.wiki&start_time=2026-01-01T00:00:00Z&end_time=2026-10-16T00:00:00Z",
+            // Not RFC 3339
+            "domain_name=static.prts.wiki&start_time=2026-10-15&end_time=2026-10-16T00:00:00Z",
+            "domain_name=static.prts.wiki&start_time=2026-10-15T00:00:00Z&end_time=2026-10-16T00:00:00Z&interval=60",
+            "domain_name=&start_time=2026-10-15T00:00:00Z&end_time=2026-10-16T00:00:00Z",
+        ] {
+            let response = router
+                .clone()
+                .oneshot(get(&format!("/api/aliyun/stats/hitRate?{query}")))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 400, "{query}");
+        }
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_domain_detail_of_unknown_domain_is_not_found() {
+        let app = test_app().await;
+        Mock::given(method("GET"))
+            .and(header("x-acs-action", "DescribeCdnDomainDetail"))
+            .and(query_param("DomainName", "gone.prts.wiki"))
+            .respond_with(ResponseTemplate::new(400).set_body_string(
+                r#"{"RequestId":"r","Code":"InvalidDomain.NotFound","Message":"The domain provided does not belong to you."}"#,
+            ))
+            .expect(1)
+            .mount(&app.aliyun)
+            .await;
+
+        let response = app
+            .router
+            .oneshot(
+                Request::get("/api/aliyun/domains/gone.prts.wiki")
+                    .header("Authorization", format!("Bearer {}", test_token()))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 404);
+        app.aliyun.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_events_for_offline_domains_are_skipped() {
+        let server = MockServer::start().await;
+        // Looked up once, then cached
+        Mock::given(method("GET"))
+            .and(header("x-acs-action", "DescribeUserDomains"))
+            .and(query_param("DomainName", "static.prts.wiki"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(user_domains("offline")))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(header("x-acs-action", "RefreshObjectCaches"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&server)
+            .await;
+        let mut settings = synchronous_settings(&server);
+        settings.aliyun.verify_domain_online = true;
+        let router = build_router(state_from(&settings));
+
+        for key in ["a.png", "b.png"] {
+            let (status, body) =
+                post_event(&router, oss_event("prts-static", key).to_string()).await;
+            assert_eq!(status, 200);
+            assert_eq!(
+                body["message"],
+                "skipped: CDN domain not online: static.prts.wiki"
+            );
+        }
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_domain_logs_are_listed_by_path() {
+        let app = test_app().await;
+        Mock::given(method("GET"))
+            .and(header("x-acs-action", "DescribeCdnDomainLogs"))
+            .and(query_param("StartTime", "2026-10-16T00:00:00Z"))
+            .and(query_param("EndTime", "2026-10-16T01:00:00Z"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "RequestId": "r",
+                "DomainLogDetails": {"DomainLogDetail": [{
+                    "DomainName": "static.prts.wiki",
+                    "PageInfos": {"PageIndex": 1, "PageSize": 300, "Total": 1},
+                    "LogInfos": {"LogInfoDetail": [{
+                        "LogName": "static.prts.wiki_2026_10_16_0000_0100.gz",
+                        "LogPath": "cdnlog.cn-hangzhou.oss.aliyun-inc.com/a.gz?Expires=1",
+                        "LogSize": 11
+                    }]}
+                }]}
+            })))
+            .expect(1)
+            .mount(&app.aliyun)
+            .await;
+
+        let response = app
+            .router
+            .oneshot(get(
+                "/api/aliyun/domains/static.prts.wiki/logs?start=2026-10-16T00:00:00Z&end=2026-10-16T01:00:00Z",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let body = body_json(response).await;
+        assert_eq!(
+            body["logs"][0]["download_url"],
+            "https://cdnlog.cn-hangzhou.oss.aliyun-inc.com/a.gz?Expires=1"
+        );
+        app.aliyun.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_domain_logs_validates_the_payload() {
+        let app = test_app().await;
+        for payload in [
+            serde_json::json!({"domain_name": ""}),
+            serde_json::json!({"domain_name": "static.prts.wiki", "page_size": 1001}),
+            serde_json::json!({"domain_name": "static.prts.wiki", "page_number": 0}),
+        ] {
+            let response = app
+                .router
+                .clone()
+                .oneshot(post_json("/api/aliyun/domainLogs", payload.clone()))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 400, "{payload}");
+        }
+        assert!(app.aliyun.received_requests().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_domain_log_download_streams_the_listed_file() {
+        let app = test_app().await;
+        mount_domain_logs(
+            &app,
+            &format!("{}/cdnlog/a.gz?Signature=s", app.aliyun.uri()),
+        )
+        .await;
+        Mock::given(method("GET"))
+            .and(path("/cdnlog/a.gz"))
+            .and(query_param("Signature", "s"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"gzip-bytes!".to_vec()))
+            .expect(1)
+            .mount(&app.aliyun)
+            .await;
+
+        let response = app
+            .router
+            .clone()
+            .oneshot(post_json(
+                "/api/aliyun/domainLogs/download",
+                serde_json::json!({
+                    "domain_name": "static.prts.wiki",
+                    "log_name": "static.prts.wiki_2026_10_16_0000_0100.gz"
+                }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["content-type"], "application/gzip");
+        assert_eq!(
+            response.headers()["content-disposition"],
+            "attachment; filename=\"static.prts.wiki_2026_10_16_0000_0100.gz\""
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"gzip-bytes!");
+
+        // Names that weren't listed are never fetched
+        let response = app
+            .router
+            .oneshot(post_json(
+                "/api/aliyun/domainLogs/download",
+                serde_json::json!({"domain_name": "static.prts.wiki", "log_name": "other.gz"}),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 404);
+        app.aliyun.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_describe_refresh_task_rejects_too_many_ids() {
+        let app = test_app().await;
+        let ids = (1..=11).map(|id| id.to_string()).collect::<Vec<_>>();
+        let response = describe_task_via(&app, &ids.join(",")).await;
+        assert_eq!(response.status(), 400);
+
+        let response = describe_task_via(&app, "abc").await;
+        assert_eq!(response.status(), 400);
+        assert!(app.aliyun.received_requests().await.unwrap().is_empty());
+    }
+
+    /// Settings refreshing through the CDN stand-in inside the request instead of queueing
+    fn synchronous_settings(server: &MockServer) -> AppSettings {
+        let mut settings = test_settings();
+        settings.aliyun.endpoint = server.uri();
+        settings.aliyun.jobs.synchronous = true;
+        settings
+    }
+
+    #[tokio::test]
+    async fn test_manual_refresh_is_logged_and_filtered_by_status() {
+        let app = test_app().await;
+        Mock::given(method("POST"))
+            .and(header("x-acs-action", "RefreshObjectCaches"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"17772470467"}"#),
+            )
+            .mount(&app.aliyun)
+            .await;
+        let get = |uri: &'static str| {
+            app.router.clone().oneshot(
+                Request::get(uri)
+                    .header("Authorization", format!("Bearer {}", test_token()))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+
+        let response = app
+            .router
+            .clone()
+            .oneshot(
+                Request::post("/api/aliyun/refreshObjectCaches")
+                    .header("Authorization", format!("Bearer {}", test_token()))
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        r#"{"object_path":"https://static.prts.wiki/a.png"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+
+        let log = body_json(get("/api/aliyun/refreshLog").await.unwrap()).await;
+        assert_eq!(log[0]["task_id"], "17772470467");
+        assert_eq!(log[0]["source"], "manual");
+        assert!(log[0].get("status").is_none());
+        for uri in [
+            "/api/aliyun/refreshLog?status=Failed",
+            "/api/aliyun/refreshLog?status=failed",
+            "/api/aliyun/refreshLog?status=FAILED",
+        ] {
+            let response = get(uri).await.unwrap();
+            assert_eq!(response.status(), 200, "{uri}");
+            assert_eq!(body_json(response).await, serde_json::json!([]));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_manual_refresh_can_name_dcdn() {
+        let (cdn, mut settings) = unreachable_cdn().await;
+        let dcdn = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("x-acs-action", "RefreshDcdnObjectCaches"))
+            .and(header("x-acs-version", "2018-01-15"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"17772470467"}"#),
+            )
+            .expect(1)
+            .mount(&dcdn)
+            .await;
+        settings.aliyun.dcdn.endpoint = dcdn.uri();
+        let router = build_router(state_from(&settings));
+
+        let response = router
+            .clone()
+            .oneshot(
+                Request::post("/api/aliyun/refreshObjectCaches")
+                    .header("Authorization", format!("Bearer {}", test_token()))
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        r#"{"object_path":"https://static.prts.wiki/a.png","product":"dcdn"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(body_json(response).await["task_id"], "17772470467");
+
+        let log = router
+            .oneshot(
+                Request::get("/api/aliyun/refreshLog")
+                    .header("Authorization", format!("Bearer {}", test_token()))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(body_json(log).await[0]["product"], "dcdn");
+        cdn.verify().await;
+        dcdn.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_refreshes_beyond_the_budget_are_rejected_locally() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"17772470467"}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let mut settings = test_settings();
+        settings.aliyun.endpoint = server.uri();
+        settings.aliyun.refresh_budget.urls_per_day = NonZeroU32::new(3);
+        let router = build_router(state_from(&settings));
+        let refresh = |paths: &str| {
+            Request::post("/api/aliyun/refreshObjectCaches")
+                .header("Authorization", format!("Bearer {}", test_token()))
+                .header("Content-Type", "application/json")
+                .body(Body::from(format!(r#"{{"object_path":"{paths}"}}"#)))
+                .unwrap()
+        };
+
+        let response = router
+            .clone()
+            .oneshot(refresh(
+                r"https://static.prts.wiki/a.png\nhttps://static.prts.wiki/b.png",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+
+        let response = router
+            .clone()
+            .oneshot(refresh(
+                r"https://static.prts.wiki/c.png\nhttps://static.prts.wiki/d.png",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 429);
+        // One URL refills every 8 hours
+        assert_eq!(response.headers()["retry-after"], "28800");
+
+        let health = router
+            .oneshot(
+                Request::get("/api/_health?verbose=true")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            body_json(health).await["refresh_budget"],
+            serde_json::json!({"day": {"limit": 3, "used": 2}})
+        );
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_oss_event_is_queued_and_job_is_queryable() {
+        let (server, settings) = unreachable_cdn().await;
+        let router = build_router(state_from(&settings));
+
+        let response = router
+            .clone()
+            .oneshot(
+                Request::post("/api/aliyun/events")
+                    .header("x-eventbridge-signature-token", test_token())
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(oss_event("prts-static", "a.png").to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 202);
+        let body = body_json(response).await;
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "message": "CDN refresh queued for a.png in bucket prts-static",
+                "job_id": 1
+            })
+        );
+
+        let get_job = |id: &str| {
+            router.clone().oneshot(
+                Request::get(format!("/api/aliyun/jobs/{id}"))
+                    .header("Authorization", format!("Bearer {}", test_token()))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+        // No worker runs here, so the job stays pending and Aliyun is never called
+        let response = get_job("1").await.unwrap();
+        assert_eq!(response.status(), 200);
+        let job = body_json(response).await;
+        assert_eq!(job["status"], "pending");
+        assert_eq!(job["object_path"], "https://static.prts.wiki/a.png");
+        assert_eq!(job["attempts"], 0);
+
+        assert_eq!(get_job("2").await.unwrap().status(), 404);
+        server.verify().await;
+    }
+
+    /// Deliver `event` twice to a CDN stand-in expecting `refreshes` RefreshObjectCaches calls
+    async fn deliver_twice(event: serde_json::Value, refreshes: u64) -> Vec<serde_json::Value> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("x-acs-action", "RefreshObjectCaches"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"17772470467"}"#),
+            )
+            .expect(refreshes)
+            .mount(&server)
+            .await;
+        let router = build_router(state_from(&synchronous_settings(&server)));
+
+        let mut bodies = Vec::new();
+        for _ in 0..2 {
+            let response = router
+                .clone()
+                .oneshot(
+                    Request::post("/api/aliyun/events")
+                        .header("x-eventbridge-signature-token", test_token())
+                        .header("Content-Type", "application/json")
+                        .body(Body::from(event.to_string()))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 200);
+            bodies.push(body_json(response).await);
+        }
+        server.verify().await;
+        bodies
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_oss_event_is_refreshed_once() {
+        let mut event = oss_event("prts-static", "a.png");
+        event["data"]["oss"]["object"]["eTag"] = "0CC175B9C0F1B6A8".into();
+
+        let bodies = deliver_twice(event, 1).await;
+        assert_eq!(bodies[0]["task_id"], "17772470467");
+        assert_eq!(bodies[1]["message"], "duplicate event ignored");
+        assert_eq!(bodies[1]["task_id"], "17772470467");
+    }
+
+    #[tokio::test]
+    async fn test_oss_event_without_etag_bypasses_dedup() {
+        let bodies = deliver_twice(oss_event("prts-static", "a.png"), 2).await;
+        assert_ne!(bodies[1]["message"], "duplicate event ignored");
+    }
+
+    #[tokio::test]
+    async fn test_saving_again_within_the_window_reuses_the_refresh() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("x-acs-action", "RefreshObjectCaches"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"17772470467"}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let mut settings = synchronous_settings(&server);
+        settings.aliyun.url_dedup_window_secs = 60;
+        let router = build_router(state_from(&settings));
+
+        let mut bodies = Vec::new();
+        for etag in ["0CC175B9C0F1B6A8", "92EB5FFEE6AE2FEC"] {
+            let mut event = oss_event("prts-static", "a.png");
+            event["data"]["oss"]["object"]["eTag"] = etag.into();
+            let response = router
+                .clone()
+                .oneshot(
+                    Request::post("/api/aliyun/events")
+                        .header("x-eventbridge-signature-token", test_token())
+                        .header("Content-Type", "application/json")
+                        .body(Body::from(event.to_string()))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 200);
+            bodies.push(body_json(response).await);
+        }
+        assert!(bodies[0].get("deduplicated").is_none());
+        assert_eq!(bodies[1]["deduplicated"], true);
+        assert_eq!(bodies[1]["task_ids"], serde_json::json!(["17772470467"]));
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_events_of_objects_gone_from_oss_are_skipped() {
+        let server = MockServer::start().await;
+        Mock::given(method("HEAD"))
+            .and(path("/gone.png"))
+            .respond_with(ResponseTemplate::new(404))
+            .expect(1)
+            .mount(&server)
+            .await;
+        // A lookup that fails says nothing about the object
+        Mock::given(method("HEAD"))
+            .and(path("/a.png"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(header("x-acs-action", "RefreshObjectCaches"))
+            .and(body_string_contains("a.png"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"17772470467"}"#),
+            )
+            .expect(2)
+            .mount(&server)
+            .await;
+        let mut settings = synchronous_settings(&server);
+        settings.aliyun.verify_object_exists = true;
+        settings.aliyun.oss_endpoint = server.uri();
+        let router = build_router(state_from(&settings));
+        let event = |key: &str, event_name: &str| {
+            let mut event = oss_event("prts-static", key);
+            event["data"]["region"] = "cn-shanghai".into();
+            event["data"]["eventName"] = event_name.into();
+            event.to_string()
+        };
+
+        let (status, body) =
+            post_event(&router, event("gone.png", "ObjectCreated:PutObject")).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["message"], "skipped: object no longer exists");
+
+        let (_, body) = post_event(&router, event("a.png", "ObjectCreated:PutObject")).await;
+        assert_eq!(body["task_id"], "17772470467");
+        // A removal is purged without asking OSS
+        let (_, body) = post_event(&router, event("a.png", "ObjectRemoved:DeleteObject")).await;
+        assert_eq!(body["task_id"], "17772470467");
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_directory_events_expand_into_file_refreshes() {
+        let server = MockServer::start().await;
+        let listing = |keys: &[&str]| {
+            let contents = keys
+                .iter()
+                .map(|key| format!("<Contents><Key>{key}</Key></Contents>"))
+                .collect::<String>();
+            format!(
+                "<ListBucketResult><IsTruncated>false</IsTruncated>{contents}</ListBucketResult>"
+            )
+        };
+        Mock::given(method("GET"))
+            .and(query_param("prefix", "images/"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(listing(&[
+                "images/",
+                "images/a.png",
+                "images/b&amp;c.png",
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(query_param("prefix", "images/large/"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(listing(&[
+                "images/large/1.png",
+                "images/large/2.png",
+                "images/large/3.png",
+                "images/large/4.png",
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(header("x-acs-action", "RefreshObjectCaches"))
+            .and(body_string_contains("ObjectType=File"))
+            .and(body_string_contains("images%2Fa.png"))
+            .and(body_string_contains("images%2Fb%26c.png"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"1"}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        // Too many objects to list, so the directory goes out whole
+        Mock::given(method("POST"))
+            .and(header("x-acs-action", "RefreshObjectCaches"))
+            .and(body_string_contains("ObjectType=Directory"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"2"}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let mut settings = synchronous_settings(&server);
+        settings.aliyun.oss_endpoint = server.uri();
+        settings.aliyun.events.expand_prefixes = vec![BucketPrefixRule {
+            bucket: "prts-static".to_string(),
+            prefix: "images/".to_string(),
+        }];
+        settings.aliyun.events.expand_max_objects = 3;
+        let router = build_router(state_from(&settings));
+        let event = |key: &str| {
+            let mut event = oss_event("prts-static", key);
+            event["data"]["region"] = "cn-shanghai".into();
+            event["data"]["eventName"] = "ObjectCreated:PutObject".into();
+            event.to_string()
+        };
+
+        let (_, body) = post_event(&router, event("images/")).await;
+        assert_eq!(body["task_id"], "1");
+        let (_, body) = post_event(&router, event("images/large/")).await;
+        assert_eq!(body["task_id"], "2");
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_filtered_oss_events_are_acknowledged() {
+        let (server, mut settings) = unreachable_cdn().await;
+        settings.aliyun.events.ignore_key_patterns = vec!["tmp/*".to_string()];
+        let router = build_router(state_from(&settings));
+
+        let mut modified = oss_event("prts-static", "a.png");
+        modified["data"]["eventName"] = "ObjectModified:UpdateObjectMeta".into();
+        let mut created = oss_event("prts-static", "tmp/上传+1.png");
+        created["data"]["eventName"] = "ObjectCreated:PutObject".into();
+
+        for event in [modified, created] {
+            let response = router
+                .clone()
+                .oneshot(
+                    Request::post("/api/aliyun/events")
+                        .header("x-eventbridge-signature-token", test_token())
+                        .header("Content-Type", "application/json")
+                        .body(Body::from(event.to_string()))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 200);
+            let body = body_json(response).await;
+            assert!(body["message"].as_str().unwrap().starts_with("skipped: "));
+        }
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_bucket_ignore_patterns_name_the_rule() {
+        let (server, mut settings) = unreachable_cdn().await;
+        settings.aliyun.events.bucket_ignore_key_patterns = std::collections::HashMap::from([(
+            "prts-static".to_string(),
+            vec!["thumb/*".to_string()],
+        )]);
+        let router = build_router(state_from(&settings));
+
+        let (status, body) = post_event(
+            &router,
+            oss_event("prts-static", "thumb/阿米娅.png").to_string(),
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert_eq!(body["message"], "skipped: ignored by rule thumb/*");
+        server.verify().await;
+    }
+
+    /// Deliver `payload` to a CDN stand-in that accepts every refresh
+    async fn deliver(payload: serde_json::Value) -> (u16, serde_json::Value) {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"17772470467"}"#),
+            )
+            .mount(&server)
+            .await;
+        let response = build_router(state_from(&synchronous_settings(&server)))
+            .oneshot(
+                Request::post("/api/aliyun/events")
+                    .header("x-eventbridge-signature-token", test_token())
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(payload.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status().as_u16();
+        (status, body_json(response).await)
+    }
+
+    #[tokio::test]
+    async fn test_single_event_keeps_its_response_shape() {
+        let (status, body) = deliver(oss_event("prts-static", "a.png")).await;
+        assert_eq!(status, 200);
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "message": "CDN refresh triggered for a.png in bucket prts-static",
+                "task_id": "17772470467",
+                "task_ids": ["17772470467"]
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_batch_events_are_processed_in_order() {
+        let mut skipped = oss_event("prts-static", "b.png");
+        skipped["id"] = "event-2".into();
+        skipped["data"]["eventName"] = "ObjectModified:UpdateObjectMeta".into();
+
+        let (status, body) = deliver(serde_json::json!([
+            oss_event("prts-static", "a.png"),
+            skipped
+        ]))
+        .await;
+        assert_eq!(status, 200);
+        assert_eq!(body["message"], "processed 2 of 2 events");
+        assert_eq!(body["results"][0]["id"], "event-1");
+        assert_eq!(body["results"][0]["status"], "refreshed");
+        assert_eq!(body["results"][0]["task_id"], "17772470467");
+        assert_eq!(body["results"][1]["id"], "event-2");
+        assert_eq!(body["results"][1]["status"], "skipped");
+    }
+
+    #[tokio::test]
+    async fn test_partially_invalid_batch_still_succeeds() {
+        let (status, body) = deliver(serde_json::json!([
+            oss_event("unknown-bucket", "a.png"),
+            {"id": "malformed"},
+            oss_event("prts-static", "a.png"),
+        ]))
+        .await;
+        assert_eq!(status, 200);
+        assert_eq!(body["message"], "processed 1 of 3 events");
+        let statuses = body["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|result| result["status"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(statuses, ["dead_lettered", "dead_lettered", "refreshed"]);
+        assert_eq!(
+            body["results"][0]["message"],
+            "dead-lettered: Bad request: Unsupported bucket: unknown-bucket"
+        );
+        assert_eq!(body["results"][0]["dead_letter_id"], 1);
+        assert_eq!(body["results"][1]["id"], "malformed");
+        assert_eq!(body["results"][1]["dead_letter_id"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_buckets_and_ignored_prefixes_are_dead_lettered_or_skipped() {
+        let (server, mut settings) = unreachable_cdn().await;
+        settings.aliyun.events.prefix_rules = vec![crate::config::EventPrefixRule {
+            bucket: "prts-static".to_string(),
+            prefix: "drafts/".to_string(),
+            mode: crate::config::PrefixMode::Ignore,
+        }];
+        let router = build_router(state_from(&settings));
+        let (status, body) =
+            post_event(&router, oss_event("unknown-bucket", "a.png").to_string()).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["dead_letter_id"], 1);
+        let (status, body) = post_event(
+            &router,
+            oss_event("prts-static", "drafts/d.png").to_string(),
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert_eq!(body["dead_letter_id"], 2);
+        assert_eq!(
+            body["message"],
+            "dead-lettered: Bad request: Ignored by prefix rule prts-static/drafts/ (ignore)"
+        );
+
+        settings.aliyun.events.unknown_bucket_behavior = crate::config::UnknownBucketBehavior::Skip;
+        let state = state_from(&settings);
+        let router = build_router(state.clone());
+        let (status, body) =
+            post_event(&router, oss_event("unknown-bucket", "a.png").to_string()).await;
+        assert_eq!(status, 200);
+        assert_eq!(
+            body["message"],
+            "skipped: no mapping for bucket unknown-bucket"
+        );
+        assert_eq!(body["task_id"], serde_json::Value::Null);
+        assert_eq!(body["dead_letter_id"], serde_json::Value::Null);
+        let (status, body) = post_event(
+            &router,
+            oss_event("prts-static", "drafts/d.png").to_string(),
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert_eq!(
+            body["message"],
+            "skipped: prefix rule prts-static/drafts/ (ignore)"
+        );
+        assert_eq!(body["dead_letter_id"], serde_json::Value::Null);
+        assert_eq!(state.dead_letters.list(None, 1, 10).total, 0);
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_redelivered_event_ids_get_the_first_answer() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"1"}"#),
+            )
+            .expect(2)
+            .mount(&server)
+            .await;
+        let mut settings = synchronous_settings(&server);
+        settings.aliyun.event_dedup_ttl_secs = 0;
+        settings.aliyun.event_id_window_secs = 60;
+        let router = build_router(state_from(&settings));
+
+        let (status, first) =
+            post_event(&router, oss_event("prts-static", "a.png").to_string()).await;
+        assert_eq!(status, 200);
+        let (status, again) =
+            post_event(&router, oss_event("prts-static", "a.png").to_string()).await;
+        assert_eq!(status, 200);
+        assert_eq!(again["message"], first["message"]);
+        assert_eq!(again["task_id"], "1");
+
+        // In a batch only the new event is refreshed
+        let mut other = oss_event("prts-static", "b.png");
+        other["id"] = "event-2".into();
+        let (status, body) = post_event(
+            &router,
+            serde_json::json!([oss_event("prts-static", "a.png"), other]).to_string(),
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert_eq!(body["results"][0]["id"], "event-1");
+        assert_eq!(body["results"][0]["message"], first["message"]);
+        assert_eq!(body["results"][1]["status"], "refreshed");
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_batch_where_every_event_fails_is_rejected() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(500).set_body_string(
+                    r#"{"RequestId":"r","Code":"InternalError","Message":"boom"}"#,
+                ),
+            )
+            .mount(&server)
+            .await;
+        let response = build_router(state_from(&synchronous_settings(&server)))
+            .oneshot(
+                Request::post("/api/aliyun/events")
+                    .header("x-eventbridge-signature-token", test_token())
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        serde_json::json!([oss_event("prts-static", "a.png")]).to_string(),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        // Left for EventBridge to redeliver
+        assert_eq!(response.status(), 502);
+        let (status, _) = deliver(serde_json::json!([])).await;
+        assert_eq!(status, 400);
+    }
+
+    #[tokio::test]
+    async fn test_removals_are_purged_with_force() {
+        let server = MockServer::start().await;
+        for (force, task_id) in [("false", "1"), ("true", "2")] {
+            Mock::given(method("POST"))
+                .and(body_string_contains(format!("Force={force}")))
+                .respond_with(ResponseTemplate::new(200).set_body_string(format!(
+                    r#"{{"RequestId":"r","RefreshTaskId":"{task_id}"}}"#
+                )))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+        let mut settings = synchronous_settings(&server);
+        settings.aliyun.events.ignore_removed_prefixes = vec![crate::config::BucketPrefixRule {
+            bucket: "prts-static".to_string(),
+            prefix: "archive/".to_string(),
+        }];
+        let router = build_router(state_from(&settings));
+        let event = |key: &str, event_name: &str| {
+            let mut event = oss_event("prts-static", key);
+            event["data"]["eventName"] = event_name.into();
+            event.to_string()
+        };
+
+        let (status, body) = post_event(&router, event("a.png", "ObjectCreated:PutObject")).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["task_id"], "1");
+        let (status, body) =
+            post_event(&router, event("b.png", "ObjectRemoved:DeleteObject")).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["task_id"], "2");
+
+        let (status, body) =
+            post_event(&router, event("c.png", "ObjectReplicated:PutObject")).await;
+        assert_eq!(status, 200);
+        assert_eq!(
+            body["message"],
+            "skipped: event ObjectReplicated:PutObject is not refreshed"
+        );
+        let (status, body) = post_event(
+            &router,
+            event("archive/d.png", "ObjectRemoved:DeleteObject"),
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert_eq!(
+            body["message"],
+            "skipped: removal of archive/d.png is not purged"
+        );
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_removal_after_creation_is_not_deduplicated() {
+        let server = MockServer::start().await;
+        for (force, task_id) in [("false", "1"), ("true", "2")] {
+            Mock::given(method("POST"))
+                .and(body_string_contains(format!("Force={force}")))
+                .respond_with(ResponseTemplate::new(200).set_body_string(format!(
+                    r#"{{"RequestId":"r","RefreshTaskId":"{task_id}"}}"#
+                )))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+        let mut settings = synchronous_settings(&server);
+        settings.aliyun.url_dedup_window_secs = 60;
+        let router = build_router(state_from(&settings));
+        // The deletion reports the ETag of the object it removed
+        let event = |event_name: &str| {
+            let mut event = oss_event("prts-static", "a.png");
+            event["data"]["eventName"] = event_name.into();
+            event["data"]["oss"]["object"]["eTag"] = "0123456789ABCDEF".into();
+            event.to_string()
+        };
+
+        let (status, body) = post_event(&router, event("ObjectCreated:PutObject")).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["task_id"], "1");
+        let (status, body) = post_event(&router, event("ObjectRemoved:DeleteObject")).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["task_id"], "2");
+        assert_eq!(body["deduplicated"], serde_json::Value::Null);
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_upload_after_removal_is_not_deduplicated() {
+        let server = MockServer::start().await;
+        for (force, task_id) in [("true", "1"), ("false", "2")] {
+            Mock::given(method("POST"))
+                .and(header("x-acs-action", "RefreshObjectCaches"))
+                .and(body_string_contains(format!("Force={force}")))
+                .respond_with(ResponseTemplate::new(200).set_body_string(format!(
+                    r#"{{"RequestId":"r","RefreshTaskId":"{task_id}"}}"#
+                )))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+        let mut settings = synchronous_settings(&server);
+        settings.aliyun.url_dedup_window_secs = 60;
+        let router = build_router(state_from(&settings));
+        let event = |event_name: &str, etag: &str| {
+            let mut event = oss_event("prts-static", "a.png");
+            event["data"]["eventName"] = event_name.into();
+            event["data"]["oss"]["object"]["eTag"] = etag.into();
+            event.to_string()
+        };
+
+        let (status, body) = post_event(
+            &router,
+            event("ObjectRemoved:DeleteObject", "0123456789ABCDEF"),
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert_eq!(body["task_id"], "1");
+        let (status, body) = post_event(
+            &router,
+            event("ObjectCreated:PutObject", "FEDCBA9876543210"),
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert_eq!(body["task_id"], "2");
+        assert_eq!(body["deduplicated"], serde_json::Value::Null);
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_prefix_rules_choose_how_events_are_refreshed() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("ObjectType=Directory"))
+            .and(body_string_contains(
+                "ObjectPath=https%3A%2F%2Fstatic.prts.wiki%2Fcharts%2Flatest%2F&",
+            ))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"1"}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("ObjectType=File"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"2"}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let mut settings = synchronous_settings(&server);
+        settings.aliyun.events.prefix_rules = [
+            ("charts/latest/", crate::config::PrefixMode::Directory),
+            ("charts/latest/keep/", crate::config::PrefixMode::File),
+            ("drafts/", crate::config::PrefixMode::Ignore),
+        ]
+        .into_iter()
+        .map(|(prefix, mode)| crate::config::EventPrefixRule {
+            bucket: "prts-static".to_string(),
+            prefix: prefix.to_string(),
+            mode,
+        })
+        .collect();
+        settings.aliyun.events.unknown_bucket_behavior = crate::config::UnknownBucketBehavior::Skip;
+        let router = build_router(state_from(&settings));
+        let event = |key: &str| oss_event("prts-static", key).to_string();
+
+        let (_, body) = post_event(&router, event("charts/latest/a.json")).await;
+        assert_eq!(body["task_id"], "1");
+        assert_eq!(
+            body["message"],
+            "CDN refresh triggered for charts/latest/a.json in bucket prts-static, prefix rule prts-static/charts/latest/ (directory)"
+        );
+        // The rest of the regenerated directory doesn't purge it again
+        let (_, body) = post_event(&router, event("charts/latest/b.json")).await;
+        assert_eq!(body["deduplicated"], true);
+        assert_eq!(body["task_id"], "1");
+
+        // The longest prefix wins
+        let (_, body) = post_event(&router, event("charts/latest/keep/c.json")).await;
+        assert_eq!(body["task_id"], "2");
+        assert!(
+            body["message"].as_str().unwrap().ends_with("(file)"),
+            "{body}"
+        );
+        let (status, body) = post_event(&router, event("drafts/d.png")).await;
+        assert_eq!(status, 200);
+        assert_eq!(
+            body["message"],
+            "skipped: prefix rule prts-static/drafts/ (ignore)"
+        );
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_batch_refreshes_once_per_cdn_domain() {
+        let server = MockServer::start().await;
+        for (host, response) in [
+            (
+                "static.prts.wiki",
+                r#"{"RequestId":"r","RefreshTaskId":"1"}"#,
+            ),
+            (
+                "media.prts.wiki",
+                r#"{"RequestId":"r","RefreshTaskId":"2"}"#,
+            ),
+        ] {
+            Mock::given(method("POST"))
+                .and(body_string_contains(host))
+                .respond_with(ResponseTemplate::new(200).set_body_string(response))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+        Mock::given(method("POST"))
+            .and(body_string_contains("media-legacy.prts.wiki"))
+            .respond_with(ResponseTemplate::new(400).set_body_string(
+                r#"{"RequestId":"r","Code":"InvalidDomain.Offline","Message":"domain is offline"}"#,
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let mut settings = synchronous_settings(&server);
+        settings.aliyun.bucket_url_map.insert(
+            "prts-media".to_string(),
+            crate::config::BucketUrls::Many(vec![
+                "https://media.prts.wiki/{object_key}".to_string(),
+                "https://media-legacy.prts.wiki/{object_key}".to_string(),
+            ]),
+        );
+        let (status, body) = post_event(
+            &build_router(state_from(&settings)),
+            serde_json::json!([
+                oss_event("prts-static", "a.png"),
+                oss_event("prts-media", "b.png"),
+                {"id": "malformed"},
+                oss_event("prts-static", "c.png"),
+            ])
+            .to_string(),
+        )
+        .await;
+
+        assert_eq!(status, 200);
+        assert_eq!(body["message"], "processed 3 of 4 events");
+        let results = body["results"].as_array().unwrap();
+        assert_eq!(results[0]["status"], "refreshed");
+        assert_eq!(results[0]["task_ids"], serde_json::json!(["1"]));
+        assert_eq!(results[1]["status"], "refreshed");
+        assert_eq!(results[1]["task_ids"], serde_json::json!(["2"]));
+        assert!(
+            results[1]["message"]
+                .as_str()
+                .unwrap()
+                .contains("but failed for media-legacy.prts.wiki"),
+            "{body}"
+        );
+        assert_eq!(results[2]["status"], "dead_lettered");
+        assert_eq!(results[3]["status"], "refreshed");
+        assert_eq!(results[3]["task_ids"], serde_json::json!(["1"]));
+    }
+
+    async fn post_event(router: &axum::Router, body: impl Into<Body>) -> (u16, serde_json::Value) {
+        let response = router
+            .clone()
+            .oneshot(
+                Request::post("/api/aliyun/events")
+                    .header("x-eventbridge-signature-token", test_token())
+                    .header("Content-Type", "application/json")
+                    .body(body.into())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status().as_u16();
+        (status, body_json(response).await)
+    }
+
+    async fn call_authed(
+        router: &axum::Router,
+        method: &str,
+        uri: &str,
+    ) -> (u16, serde_json::Value) {
+        let response = router
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .header("Authorization", format!("Bearer {}", test_token()))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status().as_u16();
+        (status, body_json(response).await)
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_bucket_event_is_dead_lettered_and_replayed() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("x-acs-action", "RefreshObjectCaches"))
+            .and(body_string_contains("cdn.prts.wiki%2Fa.png"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"17772470467"}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let settings = synchronous_settings(&server);
+        let state = state_from(&settings);
+        let router = build_router(state.clone());
+
+        // Acknowledged, so EventBridge stops redelivering
+        let (status, body) =
+            post_event(&router, oss_event("new-bucket", "a.png").to_string()).await;
+        assert_eq!(status, 200);
+        assert_eq!(
+            body["message"],
+            "dead-lettered: Bad request: Unsupported bucket: new-bucket"
+        );
+        let id = body["dead_letter_id"].as_u64().unwrap();
+
+        let (status, page) =
+            call_authed(&router, "GET", "/api/aliyun/events/dlq?status=pending").await;
+        assert_eq!(status, 200);
+        assert_eq!(page["total"], 1);
+        assert_eq!(
+            page["items"][0]["event"]["data"]["oss"]["bucket"]["name"],
+            "new-bucket"
+        );
+
+        // Still unmapped: the replay fails and the dead letter stays pending
+        let replay = format!("/api/aliyun/events/dlq/{id}/replay");
+        let (status, _) = call_authed(&router, "POST", &replay).await;
+        assert_eq!(status, 400);
+
+        let mut aliyun = settings.aliyun.clone();
+        aliyun.bucket_url_map.insert(
+            "new-bucket".to_string(),
+            crate::config::BucketUrls::One("https://cdn.prts.wiki/{object_key}".to_string()),
+        );
+        state.aliyun_config.store(std::sync::Arc::new(aliyun));
+
+        let (status, resolved) = call_authed(&router, "POST", &replay).await;
+        assert_eq!(status, 200);
+        assert_eq!(resolved["status"], "resolved");
+        assert_eq!(resolved["replays"], 2);
+        assert_eq!(
+            resolved["resolution"],
+            "CDN refresh triggered for a.png in bucket new-bucket"
+        );
+        let (status, _) = call_authed(&router, "POST", &replay).await;
+        assert_eq!(status, 400);
+        let (_, page) = call_authed(&router, "GET", "/api/aliyun/events/dlq?status=pending").await;
+        assert_eq!(page["total"], 0);
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_unparsable_delivery_is_dead_lettered() {
+        let (server, settings) = unreachable_cdn().await;
+        let router = build_router(state_from(&settings));
+
+        let (status, body) = post_event(&router, "{not json").await;
+        assert_eq!(status, 200);
+        assert!(body["dead_letter_id"].is_u64());
+        let (_, page) = call_authed(&router, "GET", "/api/aliyun/events/dlq").await;
+        assert_eq!(page["items"][0]["event"], "{not json");
+
+        let (status, _) = call_authed(&router, "GET", "/api/aliyun/events/dlq?page_size=500").await;
+        assert_eq!(status, 400);
+        server.verify().await;
+    }
+
+    #[test]
+    fn test_events_request_body_is_one_of_single_or_batch() {
+        let spec = serde_json::to_value(crate::routes::openapi_spec(
+            &crate::test_support::test_state(),
+        ))
+        .unwrap();
+        let body = &spec["paths"]["/api/aliyun/events"]["post"]["requestBody"]["content"]["application/json"]
+            ["schema"];
+        assert_eq!(body["$ref"], "#/components/schemas/OssEventsPayload");
+        assert_eq!(
+            spec["components"]["schemas"]["OssEventsPayload"]["oneOf"]
+                .as_array()
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bulk_removals_collapse_into_directory_refresh() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("ObjectType=Directory"))
+            .and(body_string_contains(
+                "ObjectPath=https%3A%2F%2Fstatic.prts.wiki%2Fimages%2Fold-event%2F&",
+            ))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"1"}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("ObjectType=File"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"2"}"#),
+            )
+            // The creation and the removal left over share their domain but not `Force`
+            .expect(2)
+            .mount(&server)
+            .await;
+        let mut settings = synchronous_settings(&server);
+        settings.aliyun.events.directory_refresh_threshold = Some(2);
+
+        let removed = |key: &str| {
+            let mut event = oss_event("prts-static", key);
+            event["data"]["eventName"] = "ObjectRemoved:DeleteObject".into();
+            event
+        };
+        let mut created = oss_event("prts-static", "images/old-event/new.png");
+        created["data"]["eventName"] = "ObjectCreated:PutObject".into();
+        let batch = serde_json::json!([
+            removed("images/old-event/a.png"),
+            created,
+            removed("images/old-event/b/c.png"),
+            removed("images/old-event/d.png"),
+            removed("images/other.png"),
+        ]);
+
+        let response = build_router(state_from(&settings))
+            .oneshot(
+                Request::post("/api/aliyun/events")
+                    .header("x-eventbridge-signature-token", test_token())
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(batch.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let body = body_json(response).await;
+        let task_ids = body["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|result| result["task_id"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(task_ids, ["1", "2", "1", "1", "2"]);
+        assert_eq!(
+            body["results"][0]["message"],
+            "CDN directory refresh triggered for images/old-event/ in bucket prts-static"
+        );
+        server.verify().await;
+    }
+
+    /// Synchronous settings mapping `prts-static` to a second, legacy CDN domain
+    fn two_domain_settings(server: &MockServer) -> AppSettings {
+        let mut settings = synchronous_settings(server);
+        settings.aliyun.bucket_url_map.insert(
+            "prts-static".to_string(),
+            crate::config::BucketUrls::Many(vec![
+                "https://static.prts.wiki/{object_key}".to_string(),
+                "https://static-legacy.prts.wiki/{object_key}".to_string(),
+            ]),
+        );
+        settings
+    }
+
+    const STATIC_URL: &str = "https%3A%2F%2Fstatic.prts.wiki%2Fa.png";
+    const LEGACY_URL: &str = "https%3A%2F%2Fstatic-legacy.prts.wiki%2Fa.png";
+
+    #[tokio::test]
+    async fn test_bucket_with_several_domains_refreshes_every_url_at_once() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("x-acs-action", "RefreshObjectCaches"))
+            .and(body_string_contains(format!(
+                "ObjectPath={STATIC_URL}%0A{LEGACY_URL}&"
+            )))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"1,2"}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let response = build_router(state_from(&two_domain_settings(&server)))
+            .oneshot(
+                Request::post("/api/aliyun/events")
+                    .header("x-eventbridge-signature-token", test_token())
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(oss_event("prts-static", "a.png").to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let body = body_json(response).await;
+        assert_eq!(body["task_id"], "1");
+        assert_eq!(body["task_ids"], serde_json::json!(["1", "2"]));
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_failed_domain_keeps_the_other_task_id() {
+        let server = MockServer::start().await;
+        let rejected = || {
+            ResponseTemplate::new(400).set_body_string(
+                r#"{"RequestId":"r","Code":"InvalidDomain.Offline","Message":"domain is offline"}"#,
+            )
+        };
+        // Aliyun turns the whole call down over the offline legacy domain
+        Mock::given(method("POST"))
+            .and(body_string_contains(format!("{STATIC_URL}%0A{LEGACY_URL}")))
+            .respond_with(rejected())
+            .with_priority(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(body_string_contains(format!("ObjectPath={STATIC_URL}&")))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"1"}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(body_string_contains(format!("ObjectPath={LEGACY_URL}&")))
+            .respond_with(rejected())
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut event = oss_event("prts-static", "a.png");
+        event["data"]["oss"]["object"]["eTag"] = "0CC175B9C0F1B6A8".into();
+        let response = build_router(state_from(&two_domain_settings(&server)))
+            .oneshot(
+                Request::post("/api/aliyun/events")
+                    .header("x-eventbridge-signature-token", test_token())
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(event.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let body = body_json(response).await;
+        assert_eq!(body["task_id"], "1");
+        assert_eq!(body["task_ids"], serde_json::json!(["1"]));
+        let message = body["message"].as_str().unwrap();
+        assert!(
+            message.contains("but failed for https://static-legacy.prts.wiki/a.png"),
+            "{message}"
+        );
+        assert!(message.contains("InvalidDomain.Offline"), "{message}");
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_partial_failure_does_not_notify() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_string_contains(LEGACY_URL))
+            .respond_with(ResponseTemplate::new(400).set_body_string(
+                r#"{"RequestId":"r","Code":"InvalidDomain.Offline","Message":"domain is offline"}"#,
+            ))
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(header("x-acs-action", "RefreshObjectCaches"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","RefreshTaskId":"1"}"#),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/hook"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(0)
+            .mount(&server)
+            .await;
+        let mut settings = two_domain_settings(&server);
+        settings.webhooks = vec![WebhookConfig {
+            url: format!("{}/hook", server.uri()),
+            secret: "webhook-secret".to_string(),
+            events: vec!["oss.object.refreshed".to_string()],
+            max_attempts: NonZeroU32::new(1).unwrap(),
+            retry_backoff_ms: 0,
+            bearer_token: None,
+            timeout_secs: 10,
+        }];
+        let state = state_from(&settings);
+        let dispatcher = tokio::spawn(run_webhook_dispatcher(
+            state.webhooks.clone(),
+            state.http_client.clone(),
+        ));
+
+        let (status, body) = post_event(
+            &build_router(state),
+            oss_event("prts-static", "a.png").to_string(),
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert_eq!(body["task_ids"], serde_json::json!(["1"]));
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        dispatcher.abort();
+        server.verify().await;
+    }
+
+    async fn raw_call_via(
+        server: &MockServer,
+        allow: bool,
+        body: &str,
+    ) -> (u16, serde_json::Value) {
+        let mut settings = test_settings();
+        settings.aliyun.endpoint = server.uri();
+        settings.aliyun.allow_raw_api = allow;
+        let response = build_router(state_from(&settings))
+            .oneshot(
+                Request::post("/api/aliyun/raw")
+                    .header("Authorization", format!("Bearer {}", test_token()))
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status().as_u16();
+        (status, body_json(response).await)
+    }
+
+    #[tokio::test]
+    async fn test_raw_call_returns_aliyun_json() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(header("x-acs-action", "DescribeDomainsBySource"))
+            .and(header("x-acs-version", "2018-05-10"))
+            .and(query_param("Sources", "static.prts.wiki"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"RequestId":"r","DomainsList":{"DomainsData":[]}}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let (status, body) = raw_call_via(
+            &server,
+            true,
+            r#"{"action":"DescribeDomainsBySource","method":"get","params":{"Sources":"static.prts.wiki"}}"#,
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert_eq!(
+            body,
+            serde_json::json!({"RequestId": "r", "DomainsList": {"DomainsData": []}})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_raw_call_is_disabled_by_default() {
+        let server = MockServer::start().await;
+        let (status, _) = raw_call_via(&server, false, r#"{"action":"DescribeUserDomains"}"#).await;
+        assert_eq!(status, 404);
+        assert!(server.received_requests().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_invoke_passes_allowlisted_actions_through() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("x-acs-action", "DescribeCdnDomainConfigs"))
+            .and(query_param("DomainName", "static.prts.wiki"))
+            .and(body_string_contains("FunctionNames=referer_white_list_set"))
+            .respond_with(ResponseTemplate::new(404).set_body_string(
+                r#"{"RequestId":"R1","Code":"InvalidDomain.NotFound","Message":"gone"}"#,
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let mut settings = test_settings();
+        settings.aliyun.endpoint = server.uri();
+        settings.aliyun.invoke_allowed_actions = vec!["DescribeCdnDomainConfigs".to_string()];
+        let router = build_router(state_from(&settings));
+        let invoke = |body: &str| {
+            router.clone().oneshot(post_json(
+                "/api/aliyun/invoke",
+                serde_json::from_str(body).unwrap(),
+            ))
+        };
+
+        // Aliyun's error answer comes back as it is
+        let response = invoke(
+            r#"{"action":"DescribeCdnDomainConfigs","query_params":{"DomainName":"static.prts.wiki"},"body":{"FunctionNames":"referer_white_list_set"}}"#,
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            body_json(response).await,
+            serde_json::json!({
+                "status": 404,
+                "body": {"RequestId": "R1", "Code": "InvalidDomain.NotFound", "Message": "gone"},
+                "aliyun_request_id": "R1"
+            })
+        );
+
+        let response = invoke(r#"{"action":"DeleteCdnDomain"}"#).await.unwrap();
+        assert_eq!(response.status(), 403);
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_aliyun_routes_without_credentials_are_unavailable() {
+        let mut settings = test_settings();
+        settings.aliyun = crate::config::AliyunConfig {
+            allow_raw_api: true,
+            ..Default::default()
+        };
+        let router = build_router(state_from(&settings));
+        let token = test_token();
+        let post = |uri: &str, body: &str| {
+            Request::post(uri)
+                .header("Authorization", format!("Bearer {token}"))
+                .header("x-eventbridge-signature-token", &token)
+                .header("Content-Type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+        let requests = [
+            post(
+                "/api/aliyun/refreshObjectCaches",
+                r#"{"object_path":"https://static.prts.wiki/a.png"}"#,
+            ),
+            post("/api/aliyun/raw", r#"{"action":"DescribeUserDomains"}"#),
+            post(
+                "/api/aliyun/events",
+                &oss_event("prts-static", "a.png").to_string(),
+            ),
+            Request::get("/api/aliyun/refreshTask/1")
+                .header("Authorization", format!("Bearer {token}"))
+                .body(Body::empty())
+                .unwrap(),
+        ];
+
+        for request in requests {
+            let uri = request.uri().clone();
+            let response = router.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), 503, "{uri}");
+            let body = body_json(response).await;
+            assert_eq!(body["msg"], "Aliyun integration not configured", "{uri}");
+        }
+    }
 }
@@ -1,250 +1,234 @@
-use anyhow::Context;
 use axum::{
     Json, debug_handler,
-    extract::{Multipart, State},
+    extract::{Multipart, Path, State, multipart::MultipartError},
+    http::StatusCode,
+    response::{IntoResponse, Response},
 };
-use rand::Rng;
-use reqwest::multipart::{Form, Part};
-use serde::{Deserialize, Serialize};
-use std::time::{SystemTime, UNIX_EPOCH};
+use chrono::{DateTime, Utc};
 use tracing::{info, warn};
 use utoipa::ToSchema;
 
-use crate::error::{AppError, AppResult};
+pub use crate::api::bilibili::{
+    DeleteDynamicPayload, DynamicResponse, ScheduledDynamic, ScheduledDynamicStatus,
+};
+use crate::bilibili::{
+    ContentItem, DynamicDraft, DynamicOptions, Topic, UploadFile, inspect_image, publish_dynamic,
+};
+use crate::config::BilibiliConfig;
+use crate::error::{AppError, AppResult, ErrorBody};
 use crate::state::AppState;
 
-/// Response for createDynamic endpoint
-#[derive(ToSchema, Serialize, Deserialize)]
-pub struct DynamicResponse {
-    pub code: i32,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub msg: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub data: Option<serde_json::Value>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub exception: Option<serde_json::Value>,
-}
-
-/// Bilibili upload response
-#[derive(Debug, Deserialize)]
-struct BilibiliUploadResponse {
-    code: i32,
-    data: Option<BilibiliUploadData>,
+/// Multipart form accepted by createDynamic
+///
+/// Documentation only; the handler reads the parts as they stream in.
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct CreateDynamicForm {
+    /// JSON array of `ContentItem`, or plain text posted as a single text item
+    #[schema(example = r#"[{"type":1,"raw_text":"Hello from Rust API!","biz_id":""}]"#)]
+    pub msg: String,
+    /// Images to attach; any part with a filename counts, whatever its field name
+    #[schema(value_type = Option<Vec<String>>, format = Binary)]
+    pub files: Option<Vec<Vec<u8>>>,
+    /// Numeric ID of the topic (话题) to attach
+    pub topic_id: Option<String>,
+    /// Name of that topic; requires `topic_id`
+    pub topic_name: Option<String>,
+    /// Numeric ID of the dynamic to forward instead of posting an original one
+    pub forward_dynamic_id: Option<String>,
+    /// Configured Bilibili account to post as
+    pub account: Option<String>,
+    /// RFC 3339 time to post at instead of now
+    #[schema(example = "2026-10-16T16:00:00+08:00")]
+    pub publish_at: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
-struct BilibiliUploadData {
-    image_url: String,
-    image_width: f64,
-    image_height: f64,
+/// Parsed createDynamic multipart form
+struct DynamicForm {
+    msg: Option<String>,
+    topic_id: Option<String>,
+    topic_name: Option<String>,
+    forward_dynamic_id: Option<String>,
+    account: Option<String>,
+    publish_at: Option<String>,
+    files: Vec<UploadFile>,
 }
 
-/// Bilibili create dynamic response
-#[derive(Debug, Deserialize, Serialize)]
-struct BilibiliCreateResponse {
-    code: i32,
-    data: Option<BilibiliCreateData>,
-}
-
-#[derive(Debug, Deserialize, Serialize)]
-struct BilibiliCreateData {
-    #[serde(default)]
-    doc_id: Option<u64>,
-    #[serde(default)]
-    dynamic_id: Option<u64>,
-    #[serde(default)]
-    create_result: Option<i32>,
-    #[serde(default)]
-    errmsg: Option<String>,
-}
+/// Read the createDynamic form, enforcing the per-image size and image count limits
+///
+/// File parts are streamed chunk by chunk, so an oversized file is rejected as soon as it
+/// crosses the limit instead of being buffered whole. Every file must sniff as a JPEG, PNG, GIF
+/// or WebP image; anything else is rejected with a 400 naming the field before a single byte
+/// is sent to Bilibili.
+async fn read_dynamic_form(
+    multipart: &mut Multipart,
+    config: &BilibiliConfig,
+) -> AppResult<DynamicForm> {
+    let mut msg: Option<String> = None;
+    let mut topic_id: Option<String> = None;
+    let mut topic_name: Option<String> = None;
+    let mut forward_dynamic_id: Option<String> = None;
+    let mut account: Option<String> = None;
+    let mut publish_at: Option<String> = None;
+    let mut files: Vec<UploadFile> = Vec::new();
 
-/// Picture info for dynamic request
-#[derive(Debug, Serialize)]
-struct PicInfo {
-    img_src: String,
-    img_width: f64,
-    img_height: f64,
-    img_size: f64,
-}
+    while let Some(mut field) = multipart.next_field().await.map_err(multipart_error)? {
+        let field_name = field.name().unwrap_or("").to_string();
 
-/// Generate headers for Bilibili API requests
-fn create_headers(sessdata: &str) -> reqwest::header::HeaderMap {
-    let mut headers = reqwest::header::HeaderMap::new();
-    headers.insert("Accept", "*/*".parse().unwrap());
-    headers.insert(
-        "User-Agent",
-        "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/121.0.0.0 Safari/537.36"
-            .parse()
-            .unwrap(),
-    );
-    headers.insert(
-        "Sec-Ch-Ua",
-        "\"Not A(Brand\";v=\"99\", \"Google Chrome\";v=\"121\", \"Chromium\";v=\"121\""
-            .parse()
-            .unwrap(),
-    );
-    headers.insert("Sec-Ch-Ua-Mobile", "?0".parse().unwrap());
-    headers.insert("Sec-Ch-Ua-Platform", "\"Windows\"".parse().unwrap());
-    headers.insert("Sec-Fetch-Dest", "empty".parse().unwrap());
-    headers.insert("Sec-Fetch-Mode", "cors".parse().unwrap());
-    headers.insert("Sec-Fetch-Site", "same-site".parse().unwrap());
-    headers.insert(
-        "Cookie",
-        format!("SESSDATA={}; l=v", sessdata).parse().unwrap(),
-    );
-    headers
-}
+        let text_slot = match field_name.as_str() {
+            "msg" => Some(&mut msg),
+            "topic_id" => Some(&mut topic_id),
+            "topic_name" => Some(&mut topic_name),
+            "forward_dynamic_id" => Some(&mut forward_dynamic_id),
+            "account" => Some(&mut account),
+            "publish_at" => Some(&mut publish_at),
+            _ => None,
+        };
+        if let Some(slot) = text_slot {
+            *slot = field
+                .text()
+                .await
+                .ok()
+                .filter(|text| !text.trim().is_empty());
+            continue;
+        }
 
-/// Generate random nonce
-fn get_nonce() -> i32 {
-    rand::thread_rng().gen_range(1000..9999)
-}
+        // Any other field with a filename is an uploaded image
+        let Some(file_name) = field.file_name().map(str::to_string) else {
+            continue;
+        };
+        if files.len() >= config.max_images_per_post {
+            return Err(AppError::BadRequest(anyhow::anyhow!(
+                "Too many images: field '{}' ('{}') exceeds the limit of {} images per post",
+                field_name,
+                file_name,
+                config.max_images_per_post
+            )));
+        }
+        let declared_type = field.content_type().map(str::to_string);
 
-/// Get unix timestamp in seconds
-fn get_unix_seconds() -> f64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("System time should be after UNIX epoch")
-        .as_secs_f64()
-}
+        let mut data = Vec::new();
+        while let Some(chunk) = field.chunk().await.map_err(multipart_error)? {
+            if data.len() + chunk.len() > config.max_upload_size_bytes {
+                return Err(AppError::PayloadTooLarge(anyhow::anyhow!(
+                    "File '{}' exceeds the upload limit of {} bytes",
+                    file_name,
+                    config.max_upload_size_bytes
+                )));
+            }
+            data.extend_from_slice(&chunk);
+        }
 
-/// Upload a single image to Bilibili
-async fn upload_image(
-    file_data: Vec<u8>,
-    file_name: String,
-    content_type: String,
-    sessdata: &str,
-    bili_jct: &str,
-    client: &reqwest::Client,
-) -> AppResult<(f64, BilibiliUploadData)> {
-    let file_size_kb = file_data.len() as f64 / 1024.0;
-
-    let file_part = Part::bytes(file_data)
-        .file_name(file_name)
-        .mime_str(&content_type)
-        .map_err(|e| {
-            AppError::InternalError(anyhow::Error::new(e).context("Failed to create file part"))
+        let info = inspect_image(&data).map_err(|reason| {
+            AppError::BadRequest(anyhow::anyhow!(
+                "Field '{}' ('{}') rejected: {}",
+                field_name,
+                file_name,
+                reason
+            ))
         })?;
+        // Trust the declared type unless the client didn't know it
+        let content_type = declared_type
+            .filter(|declared| declared != "application/octet-stream")
+            .unwrap_or_else(|| info.mime.to_string());
 
-    let form = Form::new()
-        .part("file_up", file_part)
-        .text("biz", "draw")
-        .text("category", "daily")
-        .text("csrf", bili_jct.to_string());
-
-    let resp = client
-        .post("https://api.bilibili.com/x/dynamic/feed/draw/upload_bfs")
-        .headers(create_headers(sessdata))
-        .multipart(form)
-        .send()
-        .await
-        .context("Upload request failed")?;
-
-    let resp_text = resp.text().await.context("Failed to read response")?;
-
-    let upload_resp: BilibiliUploadResponse =
-        serde_json::from_str(&resp_text).context("Failed to parse upload response")?;
-
-    if upload_resp.code != 0 {
-        return Err(AppError::InternalError(anyhow::anyhow!(
-            "Bilibili file upload failed, response: {}",
-            resp_text
-        )));
+        files.push(UploadFile {
+            data,
+            file_name,
+            content_type,
+            width: info.width,
+            height: info.height,
+        });
     }
 
-    let data = upload_resp
-        .data
-        .ok_or_else(|| AppError::InternalError(anyhow::anyhow!("Upload response missing data")))?;
-
-    Ok((file_size_kb, data))
+    Ok(DynamicForm {
+        msg,
+        topic_id,
+        topic_name,
+        forward_dynamic_id,
+        account,
+        publish_at,
+        files,
+    })
 }
 
-/// Helper function to handle Bilibili create dynamic response
-async fn handle_create_dynamic_response(
-    result: Result<reqwest::Response, reqwest::Error>,
-) -> AppResult<serde_json::Value> {
-    let resp = result.context("Create dynamic request failed")?;
-
-    let body = resp.text().await.context("Read response failed")?;
+/// Map a multipart read failure, keeping axum's 413 when the body limit was hit
+fn multipart_error(err: MultipartError) -> AppError {
+    warn!(error = %err, "Error reading multipart field");
+    if err.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        AppError::PayloadTooLarge(anyhow::Error::new(err))
+    } else {
+        AppError::BadRequest(anyhow::Error::new(err))
+    }
+}
 
-    info!(
-        response_body = %body,
-        "Create dynamic response received"
-    );
+/// Parse `msg` into the content items sent to Bilibili
+///
+/// Anything starting like a JSON array or object must be an array of [`ContentItem`];
+/// any other string is posted as one plain text item.
+fn parse_msg(msg: &str) -> AppResult<Vec<ContentItem>> {
+    let trimmed = msg.trim_start();
+    if !trimmed.starts_with('[') && !trimmed.starts_with('{') {
+        return Ok(vec![ContentItem::text(msg)]);
+    }
 
-    let r: BilibiliCreateResponse =
-        serde_json::from_str(&body).context("Parse create dynamic response failed")?;
+    let deserializer = &mut serde_json::Deserializer::from_str(msg);
+    let contents: Vec<ContentItem> =
+        serde_path_to_error::deserialize(deserializer).map_err(|err| {
+            let path = err.path().to_string();
+            AppError::BadRequest(anyhow::anyhow!(
+                "Invalid msg at {}: {}",
+                if path == "." {
+                    "$".to_string()
+                } else {
+                    format!("${path}")
+                },
+                err.into_inner()
+            ))
+        })?;
 
-    if r.code != 0 {
-        return Err(AppError::InternalError(anyhow::anyhow!(
-            "Bilibili API returned code {}",
-            r.code
+    if contents.is_empty() {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "msg must contain at least one content item"
         )));
     }
-
-    // Bilibili sometimes returns `code=0` but `data=null`.
-    // Treat `code=0` as success and pass through the raw data.
-    Ok(r.data
-        .as_ref()
-        .map(|d| serde_json::json!(d))
-        .unwrap_or(serde_json::json!(null)))
+    Ok(contents)
 }
 
-/// Helper function to create dynamic with specified scene and optional pics
-async fn create_dynamic_with_scene(
-    contents: serde_json::Value,
-    pics: Option<Vec<PicInfo>>,
-    sessdata: &str,
-    bili_jct: &str,
-    client: &reqwest::Client,
-) -> AppResult<Json<DynamicResponse>> {
-    let upload_id = format!("{}_{}", get_unix_seconds(), get_nonce());
-
-    let mut dyn_req_content = serde_json::json!({
-        "dyn_req": {
-            "content": {
-                "contents": contents
-            },
-            "scene": if pics.is_some() {2} else {1},
-            "attach_card": null,
-            "upload_id": upload_id,
-            "meta": {
-                "app_meta": {
-                    "from": "create.dynamic.web",
-                    "mobi_app": "web"
-                }
-            }
+/// Validate the optional topic and forward fields of the createDynamic form
+fn dynamic_options(
+    topic_id: Option<String>,
+    topic_name: Option<String>,
+    forward_dynamic_id: Option<String>,
+) -> AppResult<DynamicOptions> {
+    let topic = match (topic_id, topic_name) {
+        (Some(id), name) => Some(Topic {
+            id: id.trim().parse().map_err(|_| {
+                AppError::BadRequest(anyhow::anyhow!("topic_id must be a number, got '{id}'"))
+            })?,
+            name,
+        }),
+        (None, Some(_)) => {
+            return Err(AppError::BadRequest(anyhow::anyhow!(
+                "topic_name requires topic_id"
+            )));
         }
-    });
+        (None, None) => None,
+    };
 
-    // Add pics field if provided
-    if let Some(pics) = pics {
-        dyn_req_content["dyn_req"]["pics"] =
-            serde_json::to_value(pics).context("Failed to serialize pics")?;
+    let forward_dynamic_id = forward_dynamic_id.map(|id| id.trim().to_string());
+    if let Some(id) = &forward_dynamic_id
+        && !id.bytes().all(|b| b.is_ascii_digit())
+    {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "forward_dynamic_id must be a numeric string, got '{id}'"
+        )));
     }
 
-    let mut headers = create_headers(sessdata);
-    headers.insert("Content-Type", "application/json".parse().unwrap());
-
-    let url = format!(
-        "https://api.bilibili.com/x/dynamic/feed/create/dyn?platform=web&csrf={}",
-        bili_jct
-    );
-
-    let result = client
-        .post(&url)
-        .headers(headers)
-        .body(dyn_req_content.to_string())
-        .send()
-        .await;
-
-    let data = handle_create_dynamic_response(result).await?;
-    Ok(Json(DynamicResponse {
-        code: 0,
-        msg: None,
-        data: Some(data),
-        exception: None,
-    }))
+    Ok(DynamicOptions {
+        topic,
+        forward_dynamic_id,
+    })
 }
 
 /// Create a Bilibili dynamic post with optional images
@@ -253,17 +237,26 @@ async fn create_dynamic_with_scene(
     post,
     tag = "bilibili",
     path = "/bilibili/createDynamic",
-    request_body(content_type = "multipart/form-data",
+    request_body(content = CreateDynamicForm, content_type = "multipart/form-data",
     description = "
-- **msg** (required, string): JSON value that will be sent to Bilibili as `dyn_req.content.contents`. For example: `[{\"type\":1,\"raw_text\":\"Hello from Rust API!\",\"biz_id\":\"\"}]`.
-- **file(s)** (optional): Any multipart field *with a filename* is treated as an uploaded image. The server does not require a specific field name like `files`, `image`, etc."
+- **msg** (required, string): Either a JSON array of `ContentItem` sent to Bilibili as `dyn_req.content.contents`, e.g. `[{\"type\":1,\"raw_text\":\"Hello from Rust API!\",\"biz_id\":\"\"}]`, or a plain string (not starting with `[` or `{`) posted as a single text item. A malformed array is rejected with a 400 naming the offending path, e.g. `$[0].type`.
+- **file(s)** (optional): Any multipart field *with a filename* is treated as an uploaded image. The server does not require a specific field name like `files`, `image`, etc. Each file is limited to `bilibili.max_upload_size_bytes` (413 otherwise) and at most `bilibili.max_images_per_post` files (Bilibili allows up to 9) are accepted (400 otherwise). Files must be JPEG, PNG, GIF or WebP images by content, not just by declared type (400 naming the rejected field otherwise).
+- **topic_id** (optional, numeric string): Attach the topic (话题) with this ID.
+- **topic_name** (optional, string): Name of that topic; requires `topic_id`.
+- **forward_dynamic_id** (optional, numeric string): Post as a forward (repost) of this dynamic instead of an original post. Cannot be combined with files.
+- **account** (optional, string): Name of the configured Bilibili account to post as; defaults to `bilibili.default_account`. Unknown names are rejected with a 400 listing the configured ones.
+- **publish_at** (optional, RFC 3339): Post at this time instead of now. A future time answers 202 with the scheduled dynamic; its images are kept in memory and uploaded at publish time. A past time posts right away."
     ),
 
     responses(
         (status = OK, body = DynamicResponse),
-        (status = UNAUTHORIZED, body = DynamicResponse),
-        (status = BAD_REQUEST, body = DynamicResponse),
-        (status = INTERNAL_SERVER_ERROR, body = DynamicResponse)
+        (status = ACCEPTED, body = ScheduledDynamic, description = "Scheduled for `publish_at`, see `GET /api/bilibili/scheduledDynamics`"),
+        (status = UNAUTHORIZED, body = ErrorBody),
+        (status = BAD_REQUEST, body = ErrorBody),
+        (status = PAYLOAD_TOO_LARGE, body = ErrorBody),
+        (status = TOO_MANY_REQUESTS, body = ErrorBody, description = "Rate limit exceeded, see `Retry-After`"),
+        (status = SERVICE_UNAVAILABLE, body = ErrorBody, description = "Read-only mode, or `bilibili.schedule.max_pending` dynamics already scheduled"),
+        (status = INTERNAL_SERVER_ERROR, body = ErrorBody)
     ),
     security(
         ("bearer_auth" = [])
@@ -272,103 +265,809 @@ async fn create_dynamic_with_scene(
 pub async fn create_dynamic(
     State(state): State<AppState>,
     mut multipart: Multipart,
-) -> AppResult<Json<DynamicResponse>> {
-    // Extract Bilibili config
-    let bilibili_config = &state.bilibili_config;
-
-    let mut msg: Option<String> = None;
-    let mut files: Vec<(Vec<u8>, String, String)> = Vec::new();
-
-    // Parse multipart form data
-    loop {
-        match multipart.next_field().await {
-            Ok(Some(field)) => {
-                let field_name = field.name().unwrap_or("").to_string();
-
-                match field_name.as_str() {
-                    "msg" => {
-                        msg = field.text().await.ok();
-                    }
-                    _ => {
-                        // Assume it's a file upload
-                        if let Some(file_name) = field.file_name() {
-                            let file_name = file_name.to_string();
-                            let content_type = field
-                                .content_type()
-                                .unwrap_or("application/octet-stream")
-                                .to_string();
-                            if let Ok(data) = field.bytes().await {
-                                files.push((data.to_vec(), file_name, content_type));
-                            }
-                        }
-                    }
-                }
-            }
-            Ok(None) => {
-                // End of multipart fields
-                break;
-            }
-            Err(e) => {
-                warn!(
-                    error = %e,
-                    "Error reading multipart field"
-                );
-                break;
-            }
-        }
+) -> AppResult<Response> {
+    let DynamicForm {
+        msg,
+        topic_id,
+        topic_name,
+        forward_dynamic_id,
+        account,
+        publish_at,
+        files,
+    } = read_dynamic_form(&mut multipart, &state.bilibili_config).await?;
+    // Fail on unknown accounts now rather than at publish time
+    state.bilibili_client(account.as_deref())?;
+    let options = dynamic_options(topic_id, topic_name, forward_dynamic_id)?;
+    if options.forward_dynamic_id.is_some() && !files.is_empty() {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "A forwarded dynamic cannot carry images"
+        )));
     }
+    let publish_at = publish_at.as_deref().map(parse_publish_at).transpose()?;
 
     // Validate msg
     let msg_content = msg
         .filter(|m| !m.is_empty())
         .ok_or_else(|| AppError::BadRequest(anyhow::anyhow!("need msg")))?;
 
-    // Parse msg as JSON
-    let contents: serde_json::Value =
-        serde_json::from_str(&msg_content).context("Invalid msg format")?;
+    let draft = DynamicDraft {
+        contents: parse_msg(&msg_content)?,
+        options,
+        files,
+    };
 
-    // If files are present, upload them first
-    if !files.is_empty() {
-        info!(file_count = files.len(), "Uploading files");
-        let mut pics: Vec<PicInfo> = Vec::new();
+    if let Some(publish_at) = publish_at.filter(|at| *at > Utc::now()) {
+        let schedule = &state.bilibili_config.schedule;
+        let post = state.scheduled_dynamics.schedule(
+            account.unwrap_or_else(|| state.bilibili_default_account.clone()),
+            publish_at,
+            draft,
+            schedule.max_pending,
+            std::time::Duration::from_secs(schedule.retention_secs),
+        )?;
+        info!(
+            schedule_id = post.id,
+            publish_at = post.publish_at,
+            "Dynamic scheduled"
+        );
+        return Ok((StatusCode::ACCEPTED, Json(post)).into_response());
+    }
 
-        for (file_data, file_name, content_type) in files {
-            let (size, data) = upload_image(
-                file_data,
-                file_name,
-                content_type,
-                &bilibili_config.sessdata,
-                &bilibili_config.bili_jct,
-                &state.http_client,
+    let data = publish_dynamic(&state, account.as_deref(), draft).await?;
+    Ok(Json(DynamicResponse {
+        code: 0,
+        msg: None,
+        data: Some(serde_json::json!(data)),
+        exception: None,
+    })
+    .into_response())
+}
+
+fn parse_publish_at(value: &str) -> AppResult<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value.trim())
+        .map(|at| at.with_timezone(&Utc))
+        .map_err(|err| {
+            AppError::BadRequest(anyhow::anyhow!(
+                "publish_at must be an RFC 3339 timestamp, got '{}': {}",
+                value,
+                err
+            ))
+        })
+}
+
+/// List scheduled dynamics, including recently finished and cancelled ones
+#[utoipa::path(
+    get,
+    tag = "bilibili",
+    path = "/bilibili/scheduledDynamics",
+    responses(
+        (status = OK, description = "Oldest first; finished ones stay listed for `bilibili.schedule.retention_secs`", body = Vec<ScheduledDynamic>),
+        (status = UNAUTHORIZED, body = ErrorBody, description = "Missing or invalid bearer token")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn list_scheduled_dynamics(State(state): State<AppState>) -> Json<Vec<ScheduledDynamic>> {
+    Json(state.scheduled_dynamics.list())
+}
+
+/// Cancel a scheduled dynamic before it is published
+#[utoipa::path(
+    delete,
+    tag = "bilibili",
+    path = "/bilibili/scheduledDynamics/{id}",
+    params(
+        ("id" = u64, Path, description = "Id from the createDynamic 202 response")
+    ),
+    responses(
+        (status = OK, description = "The cancelled dynamic", body = ScheduledDynamic),
+        (status = BAD_REQUEST, body = ErrorBody, description = "Already publishing, published, failed or cancelled"),
+        (status = UNAUTHORIZED, body = ErrorBody, description = "Missing or invalid bearer token"),
+        (status = NOT_FOUND, body = ErrorBody, description = "Unknown id, or finished longer ago than `bilibili.schedule.retention_secs`")
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn cancel_scheduled_dynamic(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> AppResult<Json<ScheduledDynamic>> {
+    let post = state.scheduled_dynamics.cancel(id)?;
+    info!(schedule_id = id, "Scheduled dynamic cancelled");
+    Ok(Json(post))
+}
+
+/// Delete a previously posted Bilibili dynamic
+///
+/// On failure Bilibili's response body is returned as `exception`.
+#[debug_handler]
+#[utoipa::path(
+    post,
+    tag = "bilibili",
+    path = "/bilibili/deleteDynamic",
+    request_body = DeleteDynamicPayload,
+    responses(
+        (status = OK, body = DynamicResponse),
+        (status = UNAUTHORIZED, body = ErrorBody),
+        (status = BAD_REQUEST, body = ErrorBody),
+        (status = INTERNAL_SERVER_ERROR, body = ErrorBody)
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn delete_dynamic(
+    State(state): State<AppState>,
+    Json(payload): Json<DeleteDynamicPayload>,
+) -> AppResult<Json<DynamicResponse>> {
+    let dynamic_id = payload.dynamic_id.trim();
+    if dynamic_id.is_empty() || !dynamic_id.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(AppError::BadRequest(anyhow::anyhow!(
+            "dynamic_id must be a numeric string"
+        )));
+    }
+
+    let client = state.bilibili_client(payload.account.as_deref())?;
+    let data = client.remove_dynamic(dynamic_id).await?;
+    info!(dynamic_id, "Dynamic removed");
+
+    Ok(Json(DynamicResponse {
+        code: 0,
+        msg: None,
+        data: Some(data),
+        exception: None,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, extract::FromRequest, http::Request};
+    use tower::ServiceExt;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{method, path, query_param},
+    };
+
+    use super::*;
+    use crate::{
+        routes::build_router,
+        test_support::{body_json, state_from, test_app_with, test_settings, test_token},
+    };
+
+    const BOUNDARY: &str = "janus-test-boundary";
+
+    fn multipart_body(msg: &str, files: &[(&str, usize)]) -> Vec<u8> {
+        let mut body = format!(
+            "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"msg\"\r\n\r\n{msg}\r\n"
+        )
+        .into_bytes();
+        for (file_name, size) in files {
+            body.extend_from_slice(
+                format!(
+                    "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{file_name}\"\r\nContent-Type: image/png\r\n\r\n"
+                )
+                .as_bytes(),
+            );
+            body.extend(png_of_size(*size));
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{BOUNDARY}--\r\n").as_bytes());
+        body
+    }
+
+    /// A 1x1 PNG padded after `IEND` to exactly `size` bytes
+    fn png_of_size(size: usize) -> Vec<u8> {
+        let mut png = std::io::Cursor::new(Vec::new());
+        image::RgbImage::new(1, 1)
+            .write_to(&mut png, image::ImageFormat::Png)
+            .unwrap();
+        let mut png = png.into_inner();
+        assert!(
+            png.len() <= size,
+            "a PNG needs at least {} bytes",
+            png.len()
+        );
+        png.resize(size, 0);
+        png
+    }
+
+    fn multipart_request(uri: &str, body: Vec<u8>) -> Request<Body> {
+        Request::post(uri)
+            .header(
+                "Content-Type",
+                format!("multipart/form-data; boundary={BOUNDARY}"),
             )
-            .await?;
-
-            pics.push(PicInfo {
-                img_src: data.image_url,
-                img_width: data.image_width,
-                img_height: data.image_height,
-                img_size: size,
-            });
+            .header("Authorization", format!("Bearer {}", test_token()))
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    fn limited_config() -> BilibiliConfig {
+        BilibiliConfig {
+            max_upload_size_bytes: 1024,
+            max_images_per_post: 2,
+            ..test_settings().bilibili
         }
+    }
+
+    async fn read_form(files: &[(&str, usize)]) -> AppResult<DynamicForm> {
+        let request = multipart_request("/", multipart_body("[]", files));
+        let mut multipart = Multipart::from_request(request, &()).await.unwrap();
+        read_dynamic_form(&mut multipart, &limited_config()).await
+    }
+
+    #[tokio::test]
+    async fn test_file_just_under_limit_is_accepted() {
+        let form = read_form(&[("a.png", 1024), ("b.png", 100)]).await.unwrap();
+
+        assert_eq!(form.msg.as_deref(), Some("[]"));
+        assert_eq!(form.files.len(), 2);
+        assert_eq!(form.files[0].data.len(), 1024);
+        assert_eq!(form.files[0].file_name, "a.png");
+        assert_eq!(form.files[0].content_type, "image/png");
+    }
+
+    #[tokio::test]
+    async fn test_too_many_images_is_rejected() {
+        let err = read_form(&[("a.png", 100), ("b.png", 100), ("c.png", 100)])
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.status_code(), StatusCode::BAD_REQUEST);
+        assert!(err.to_string().contains("'c.png'"));
+    }
 
-        // Create dynamic with images (scene 2)
-        create_dynamic_with_scene(
-            contents,
-            Some(pics),
-            &bilibili_config.sessdata,
-            &bilibili_config.bili_jct,
-            &state.http_client,
+    /// Read a form holding a single file part with raw `data` and an optional declared type
+    async fn read_single_file(data: &[u8], content_type: Option<&str>) -> AppResult<DynamicForm> {
+        let mut body = format!(
+            "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"image\"; filename=\"upload.bin\"\r\n"
         )
-        .await
-    } else {
-        // Create text-only dynamic (scene 1)
-        create_dynamic_with_scene(
-            contents,
-            None,
-            &bilibili_config.sessdata,
-            &bilibili_config.bili_jct,
-            &state.http_client,
+        .into_bytes();
+        if let Some(content_type) = content_type {
+            body.extend_from_slice(format!("Content-Type: {content_type}\r\n").as_bytes());
+        }
+        body.extend_from_slice(b"\r\n");
+        body.extend_from_slice(data);
+        body.extend_from_slice(format!("\r\n--{BOUNDARY}--\r\n").as_bytes());
+
+        let request = multipart_request("/", body);
+        let mut multipart = Multipart::from_request(request, &()).await.unwrap();
+        read_dynamic_form(&mut multipart, &limited_config()).await
+    }
+
+    #[tokio::test]
+    async fn test_non_image_is_rejected_naming_field() {
+        let err = read_single_file(b"PK\x03\x04 not really an image", Some("image/png"))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.status_code(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            err.to_string(),
+            "Bad request: Field 'image' ('upload.bin') rejected: not a JPEG, PNG, GIF or WebP image"
+        );
+
+        let err = read_single_file(b"", None).await.err().unwrap();
+        assert!(err.to_string().ends_with("rejected: file is empty"));
+    }
+
+    #[tokio::test]
+    async fn test_sniffed_type_replaces_octet_stream() {
+        let png = png_of_size(100);
+
+        let form = read_single_file(&png, Some("application/octet-stream"))
+            .await
+            .unwrap();
+        assert_eq!(form.files[0].content_type, "image/png");
+        assert_eq!((form.files[0].width, form.files[0].height), (1, 1));
+
+        let form = read_single_file(&png, None).await.unwrap();
+        assert_eq!(form.files[0].content_type, "image/png");
+
+        // A declared type is kept as-is for the upload part
+        let form = read_single_file(&png, Some("image/x-png")).await.unwrap();
+        assert_eq!(form.files[0].content_type, "image/x-png");
+    }
+
+    #[tokio::test]
+    async fn test_file_just_over_limit_returns_413() {
+        let mut settings = test_settings();
+        settings.bilibili = limited_config();
+        let router = build_router(state_from(&settings));
+
+        let response = router
+            .oneshot(multipart_request(
+                "/api/bilibili/createDynamic",
+                multipart_body("[]", &[("big.png", 1025)]),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let body = body_json(response).await;
+        assert_eq!(body["code"], 1);
+        assert_eq!(
+            body["msg"],
+            "File 'big.png' exceeds the upload limit of 1024 bytes"
+        );
+    }
+
+    /// Post a dynamic through the full router against a Bilibili stand-in at `api_base_url`
+    async fn create_dynamic_via(api_base_url: &str, files: &[(&str, usize)]) -> (u16, String) {
+        let mut settings = test_settings();
+        settings.bilibili.api_base_url = api_base_url.to_string();
+        let router = build_router(state_from(&settings));
+
+        let response = router
+            .oneshot(multipart_request(
+                "/api/bilibili/createDynamic",
+                multipart_body(r#"[{"type":1,"raw_text":"hi","biz_id":""}]"#, files),
+            ))
+            .await
+            .unwrap();
+        let status = response.status().as_u16();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    async fn mock_create(server: &MockServer, response: ResponseTemplate) {
+        Mock::given(method("POST"))
+            .and(path("/x/dynamic/feed/create/dyn"))
+            .and(query_param("csrf", "test-bili-jct"))
+            .respond_with(response)
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_create_dynamic_success_snapshot() {
+        let server = MockServer::start().await;
+        mock_create(
+            &server,
+            ResponseTemplate::new(200).set_body_string(
+                r#"{"code":0,"data":{"doc_id":0,"dynamic_id":1021451253404745734,"create_result":0,"errmsg":""}}"#,
+            ),
+        )
+        .await;
+
+        let (status, body) = create_dynamic_via(&server.uri(), &[]).await;
+        assert_eq!(status, 200);
+        assert_eq!(
+            body,
+            r#"{"code":0,"data":{"create_result":0,"doc_id":0,"dynamic_id":1021451253404745734,"errmsg":""}}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_dynamic_with_images_snapshot() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/x/dynamic/feed/draw/upload_bfs"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"code":0,"data":{"image_url":"https://i0.hdslb.com/a.png","image_width":10,"image_height":20}}"#,
+            ))
+            .expect(2)
+            .mount(&server)
+            .await;
+        mock_create(
+            &server,
+            ResponseTemplate::new(200).set_body_string(r#"{"code":0,"data":null}"#),
+        )
+        .await;
+
+        let (status, body) =
+            create_dynamic_via(&server.uri(), &[("a.png", 100), ("b.png", 100)]).await;
+        assert_eq!(status, 200);
+        assert_eq!(body, r#"{"code":0,"data":null}"#);
+
+        let requests = server.received_requests().await.unwrap();
+        let create = requests.last().unwrap();
+        let sent: serde_json::Value = serde_json::from_slice(&create.body).unwrap();
+        assert_eq!(sent["dyn_req"]["scene"], 2);
+        assert_eq!(sent["dyn_req"]["pics"].as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_create_dynamic_error_code_snapshot() {
+        let server = MockServer::start().await;
+        mock_create(
+            &server,
+            ResponseTemplate::new(200).set_body_string(r#"{"code":-101,"message":"账号未登录"}"#),
+        )
+        .await;
+
+        let (status, body) = create_dynamic_via(&server.uri(), &[]).await;
+        assert_eq!(status, 500);
+        assert_eq!(body, r#"{"code":1}"#);
+    }
+
+    #[tokio::test]
+    async fn test_create_dynamic_parse_failure_snapshot() {
+        let server = MockServer::start().await;
+        mock_create(
+            &server,
+            ResponseTemplate::new(200).set_body_string("<html>bad gateway</html>"),
+        )
+        .await;
+
+        let (status, body) = create_dynamic_via(&server.uri(), &[]).await;
+        assert_eq!(status, 500);
+        assert_eq!(body, r#"{"code":1}"#);
+    }
+
+    #[tokio::test]
+    async fn test_create_dynamic_network_failure_snapshot() {
+        // Nothing listens on the discard port
+        let (status, body) = create_dynamic_via("http://127.0.0.1:9", &[]).await;
+        assert_eq!(status, 500);
+        assert_eq!(body, r#"{"code":1}"#);
+    }
+
+    #[tokio::test]
+    async fn test_create_dynamic_upstream_timeout_returns_504() {
+        let app = test_app_with(|settings| settings.http_client.request_timeout_secs = 1).await;
+        mock_create(
+            &app.bilibili,
+            ResponseTemplate::new(200)
+                .set_body_string(r#"{"code":0,"data":null}"#)
+                .set_delay(std::time::Duration::from_secs(3)),
+        )
+        .await;
+
+        let response = app
+            .router
+            .oneshot(multipart_request(
+                "/api/bilibili/createDynamic",
+                multipart_body("hi", &[]),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 504);
+        let body = body_json(response).await;
+        assert_eq!(body["code"], 1);
+        assert!(
+            body["msg"]
+                .as_str()
+                .unwrap()
+                .contains("Create dynamic request failed")
+        );
+    }
+
+    async fn delete_dynamic_via(api_base_url: &str, dynamic_id: &str) -> (u16, String) {
+        let mut settings = test_settings();
+        settings.bilibili.api_base_url = api_base_url.to_string();
+        let router = build_router(state_from(&settings));
+
+        let response = router
+            .oneshot(
+                Request::post("/api/bilibili/deleteDynamic")
+                    .header("Content-Type", "application/json")
+                    .header("Authorization", format!("Bearer {}", test_token()))
+                    .body(Body::from(
+                        serde_json::json!({ "dynamic_id": dynamic_id }).to_string(),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status().as_u16();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    async fn mock_remove(server: &MockServer, response: ResponseTemplate) {
+        Mock::given(method("POST"))
+            .and(path("/x/dynamic/feed/operate/remove"))
+            .and(query_param("csrf", "test-bili-jct"))
+            .and(wiremock::matchers::body_json(
+                serde_json::json!({ "dyn_id_str": "1021451253404745734" }),
+            ))
+            .respond_with(response)
+            .expect(1)
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_delete_dynamic_success() {
+        let server = MockServer::start().await;
+        mock_remove(
+            &server,
+            ResponseTemplate::new(200)
+                .set_body_string(r#"{"code":0,"message":"0","ttl":1,"data":null}"#),
+        )
+        .await;
+
+        let (status, body) = delete_dynamic_via(&server.uri(), "1021451253404745734").await;
+        assert_eq!(status, 200);
+        assert_eq!(body, r#"{"code":0,"data":null}"#);
+    }
+
+    #[tokio::test]
+    async fn test_delete_dynamic_error_code_passes_payload_through() {
+        let server = MockServer::start().await;
+        mock_remove(
+            &server,
+            ResponseTemplate::new(200)
+                .set_body_string(r#"{"code":4128002,"message":"动态不存在","ttl":1}"#),
         )
-        .await
+        .await;
+
+        let (status, body) = delete_dynamic_via(&server.uri(), "1021451253404745734").await;
+        assert_eq!(status, 500);
+        let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "code": 1,
+                "exception": {"code": 4_128_002, "message": "动态不存在", "ttl": 1}
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delete_dynamic_network_failure() {
+        let (status, body) = delete_dynamic_via("http://127.0.0.1:9", "1021451253404745734").await;
+        assert_eq!(status, 500);
+        assert_eq!(body, r#"{"code":1}"#);
+    }
+
+    #[tokio::test]
+    async fn test_delete_dynamic_rejects_non_numeric_id() {
+        let (status, _) = delete_dynamic_via("http://127.0.0.1:9", "abc").await;
+        assert_eq!(status, 400);
+    }
+
+    /// Prepend plain text parts to a multipart body built by [`multipart_body`]
+    fn with_text_fields(fields: &[(&str, &str)], body: Vec<u8>) -> Vec<u8> {
+        let mut parts = Vec::new();
+        for (name, value) in fields {
+            parts.extend_from_slice(
+                format!(
+                    "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"{name}\"\r\n\r\n{value}\r\n"
+                )
+                .as_bytes(),
+            );
+        }
+        parts.extend(body);
+        parts
+    }
+
+    #[tokio::test]
+    async fn test_create_dynamic_forwards_with_topic() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/x/dynamic/feed/create/dyn"))
+            .and(wiremock::matchers::body_partial_json(serde_json::json!({
+                "dyn_req": {"scene": 4, "topic": {"id": 1234, "name": "明日方舟"}},
+                "web_repost_src": {"dyn_id_str": "1021451253404745734"}
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"code":0,"data":null}"#))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let mut settings = test_settings();
+        settings.bilibili.api_base_url = server.uri();
+        let router = build_router(state_from(&settings));
+
+        let body = with_text_fields(
+            &[
+                ("topic_id", "1234"),
+                ("topic_name", "明日方舟"),
+                ("forward_dynamic_id", "1021451253404745734"),
+            ],
+            multipart_body("hi", &[]),
+        );
+        let response = router
+            .clone()
+            .oneshot(multipart_request("/api/bilibili/createDynamic", body))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Forwards cannot carry images, and nothing is uploaded before rejecting them
+        let body = with_text_fields(
+            &[("forward_dynamic_id", "1021451253404745734")],
+            multipart_body("hi", &[("a.png", 100)]),
+        );
+        let response = router
+            .clone()
+            .oneshot(multipart_request("/api/bilibili/createDynamic", body))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let body = with_text_fields(&[("topic_name", "明日方舟")], multipart_body("hi", &[]));
+        let response = router
+            .oneshot(multipart_request("/api/bilibili/createDynamic", body))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body_json(response).await["msg"],
+            "topic_name requires topic_id"
+        );
+    }
+
+    #[test]
+    fn test_plain_msg_is_wrapped_as_text() {
+        assert_eq!(
+            parse_msg("Hello from janus").unwrap(),
+            vec![ContentItem::text("Hello from janus")]
+        );
+        // Only input that looks like JSON structure is parsed as such
+        assert_eq!(parse_msg("42").unwrap(), vec![ContentItem::text("42")]);
+    }
+
+    #[test]
+    fn test_json_msg_is_typed() {
+        let contents =
+            parse_msg(r#"[{"type":1,"raw_text":"hi"},{"type":2,"raw_text":"@x","biz_id":"7"}]"#)
+                .unwrap();
+        assert_eq!(contents[0], ContentItem::text("hi"));
+        assert_eq!(contents[1].kind, 2);
+        assert_eq!(contents[1].biz_id.as_deref(), Some("7"));
+        // Missing biz_id is normalized to the empty string Bilibili expects
+        assert_eq!(
+            serde_json::to_value(&contents[0]).unwrap(),
+            serde_json::json!({"raw_text": "hi", "type": 1, "biz_id": ""})
+        );
+    }
+
+    #[test]
+    fn test_malformed_msg_reports_path() {
+        let message = |msg: &str| parse_msg(msg).unwrap_err().to_string();
+
+        assert!(
+            message(r#"{"type":1,"raw_text":"hi"}"#).starts_with(
+                "Bad request: Invalid msg at $: invalid type: map, expected a sequence"
+            ),
+        );
+        assert!(
+            message(r#"[{"type":1,"raw_text":"a"},{"type":1}]"#)
+                .starts_with("Bad request: Invalid msg at $[1]: missing field `raw_text`"),
+        );
+        assert!(
+            message(r#"[{"type":"1","raw_text":"a"}]"#)
+                .starts_with("Bad request: Invalid msg at $[0].type: invalid type: string \"1\""),
+        );
+        assert!(
+            message(r#"[{"type":1,"raw_text":"a""#).starts_with("Bad request: Invalid msg at $")
+        );
+        assert_eq!(
+            message("[]"),
+            "Bad request: msg must contain at least one content item"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_account_field_selects_credentials() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/x/dynamic/feed/create/dyn"))
+            .and(query_param("csrf", "media-bili-jct"))
+            .and(wiremock::matchers::header(
+                "Cookie",
+                "SESSDATA=media-sessdata; l=v",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"code":0,"data":null}"#))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let mut settings = test_settings();
+        settings.bilibili.api_base_url = server.uri();
+        settings.bilibili.accounts.insert(
+            "media".to_string(),
+            crate::config::BilibiliAccount {
+                sessdata: "media-sessdata".to_string(),
+                bili_jct: "media-bili-jct".to_string(),
+            },
+        );
+        let router = build_router(state_from(&settings));
+
+        let body = with_text_fields(&[("account", "media")], multipart_body("hi", &[]));
+        let response = router
+            .clone()
+            .oneshot(multipart_request("/api/bilibili/createDynamic", body))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = with_text_fields(&[("account", "prts")], multipart_body("hi", &[]));
+        let response = router
+            .oneshot(multipart_request("/api/bilibili/createDynamic", body))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body_json(response).await["msg"],
+            "Unknown Bilibili account 'prts', configured accounts: default, media"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_future_publish_at_schedules_instead_of_posting() {
+        let app = test_app_with(|_| {}).await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&app.bilibili)
+            .await;
+        let authorized = |request: Request<Body>| {
+            let (mut parts, body) = request.into_parts();
+            parts.headers.insert(
+                "Authorization",
+                format!("Bearer {}", test_token()).parse().unwrap(),
+            );
+            Request::from_parts(parts, body)
+        };
+
+        let publish_at = (Utc::now() + chrono::Duration::hours(1)).to_rfc3339();
+        let body = with_text_fields(
+            &[("publish_at", &publish_at)],
+            multipart_body("patch notes", &[("a.png", 100)]),
+        );
+        let response = app
+            .router
+            .clone()
+            .oneshot(multipart_request("/api/bilibili/createDynamic", body))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let scheduled = body_json(response).await;
+        assert_eq!(scheduled["status"], "pending");
+        assert_eq!(scheduled["account"], "default");
+        assert_eq!(scheduled["image_count"], 1);
+
+        let response = app
+            .router
+            .clone()
+            .oneshot(authorized(
+                Request::get("/api/bilibili/scheduledDynamics")
+                    .body(Body::empty())
+                    .unwrap(),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(body_json(response).await[0]["id"], scheduled["id"]);
+
+        let cancel = || {
+            app.router.clone().oneshot(authorized(
+                Request::delete(format!(
+                    "/api/bilibili/scheduledDynamics/{}",
+                    scheduled["id"]
+                ))
+                .body(Body::empty())
+                .unwrap(),
+            ))
+        };
+        let response = cancel().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_json(response).await["status"], "cancelled");
+        assert_eq!(cancel().await.unwrap().status(), StatusCode::BAD_REQUEST);
+        app.bilibili.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_malformed_publish_at_is_rejected() {
+        let body = with_text_fields(&[("publish_at", "tomorrow")], multipart_body("hi", &[]));
+        let response = build_router(state_from(&test_settings()))
+            .oneshot(multipart_request("/api/bilibili/createDynamic", body))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(
+            body_json(response).await["msg"]
+                .as_str()
+                .unwrap()
+                .starts_with("publish_at must be an RFC 3339 timestamp, got 'tomorrow'")
+        );
     }
 }
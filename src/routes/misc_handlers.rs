@@ -1,6 +1,21 @@
-use axum::{Json, debug_handler};
-use serde::Serialize;
-use utoipa::ToSchema;
+use anyhow::anyhow;
+use axum::{
+    Json, debug_handler,
+    extract::{Query, State},
+    http::{HeaderMap, header},
+    response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    aliyun::RefreshBudgetStatus,
+    bilibili::{BilibiliSessionStatus, SessionState},
+    error::{AppError, AppResult},
+    read_only::ReadOnlyStatus,
+    state::AppState,
+};
 
 #[derive(ToSchema, Serialize)]
 pub struct Health {
@@ -9,7 +24,175 @@ pub struct Health {
 
 /// /_ping
 #[debug_handler]
-#[utoipa::path(get, path = "/_ping", tag = "health", responses((status = OK, body = Health)))]
+#[utoipa::path(
+    get,
+    path = "/_ping",
+    tag = "health",
+    responses((status = OK, body = Health, example = json!({"ok": true})))
+)]
 pub async fn ping() -> Json<Health> {
     Json(Health { ok: true })
 }
+
+#[derive(Deserialize, IntoParams)]
+pub struct HealthQuery {
+    /// Include runtime mode details
+    #[serde(default)]
+    pub verbose: bool,
+    /// Include the state of upstream dependencies
+    #[serde(default)]
+    pub deep: bool,
+}
+
+/// State of upstream dependencies, reported by `deep=true`
+#[derive(ToSchema, Serialize)]
+pub struct HealthComponents {
+    /// Last result of the background SESSDATA cookie check, by account
+    pub bilibili: BTreeMap<String, BilibiliSessionStatus>,
+}
+
+#[derive(ToSchema, Serialize)]
+pub struct HealthStatus {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_only: Option<ReadOnlyStatus>,
+    /// OSS events acknowledged but held back by read-only mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deferred_oss_events: Option<usize>,
+    /// Queued CDN refreshes not yet sent to Aliyun
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub queued_refreshes: Option<usize>,
+    /// URLs counted against `aliyun.refresh_budget`, when it limits anything
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_budget: Option<RefreshBudgetStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub components: Option<HealthComponents>,
+}
+
+/// /_health
+///
+/// With `deep=true`, `ok` is `false` while Bilibili reports any account's cookie as logged out.
+#[debug_handler]
+#[utoipa::path(
+    get,
+    path = "/_health",
+    tag = "health",
+    params(HealthQuery),
+    responses((status = OK, body = HealthStatus))
+)]
+pub async fn health(
+    State(state): State<AppState>,
+    Query(query): Query<HealthQuery>,
+) -> Json<HealthStatus> {
+    let components = query.deep.then(|| HealthComponents {
+        bilibili: state
+            .bilibili_clients
+            .iter()
+            .map(|(account, client)| (account.clone(), client.session().load().as_ref().clone()))
+            .collect(),
+    });
+    let ok = components.as_ref().is_none_or(|components| {
+        components
+            .bilibili
+            .values()
+            .all(|session| session.state != SessionState::Invalid)
+    });
+
+    if !query.verbose {
+        return Json(HealthStatus {
+            ok,
+            read_only: None,
+            deferred_oss_events: None,
+            queued_refreshes: None,
+            refresh_budget: None,
+            components,
+        });
+    }
+
+    Json(HealthStatus {
+        ok,
+        read_only: Some(state.read_only.status()),
+        deferred_oss_events: Some(state.read_only.deferred_len()),
+        queued_refreshes: Some(state.refresh_jobs.pending_len()),
+        refresh_budget: state
+            .aliyun_cdn
+            .as_deref()
+            .and_then(|client| client.refresh_budget().status()),
+        components,
+    })
+}
+
+/// /metrics in Prometheus text format
+pub async fn metrics(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> AppResult<impl IntoResponse> {
+    let metrics = state
+        .metrics
+        .as_ref()
+        .ok_or_else(|| AppError::InternalError(anyhow!("Metrics recorder not initialized")))?;
+
+    if let Some(expected) = metrics.token() {
+        let token = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        if token != Some(expected) {
+            return Err(AppError::Unauthorized(anyhow!(
+                "Missing or invalid metrics scrape token"
+            )));
+        }
+    }
+
+    if let Some(client) = state.aliyun_cdn.as_deref() {
+        client.refresh_budget().record_metrics();
+    }
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics.render(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::test_support::{body_json, test_app};
+
+    #[tokio::test]
+    async fn test_deep_health_reports_bilibili_session() {
+        let app = test_app().await;
+        let get = |uri: &'static str| {
+            app.router
+                .clone()
+                .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+        };
+
+        let body = body_json(get("/api/_health").await.unwrap()).await;
+        assert_eq!(body, serde_json::json!({"ok": true}));
+
+        let body = body_json(get("/api/_health?deep=true").await.unwrap()).await;
+        assert_eq!(body["ok"], true);
+        assert_eq!(
+            body["components"]["bilibili"]["default"]["state"],
+            "unknown"
+        );
+
+        app.state.bilibili_clients["default"]
+            .session()
+            .store(Arc::new(BilibiliSessionStatus {
+                state: SessionState::Invalid,
+                ..Default::default()
+            }));
+        let body = body_json(get("/api/_health?deep=true").await.unwrap()).await;
+        assert_eq!(body["ok"], false);
+        assert_eq!(
+            body["components"]["bilibili"]["default"]["state"],
+            "invalid"
+        );
+    }
+}
@@ -1,11 +1,31 @@
 #![allow(clippy::needless_for_each)]
+mod admin_handlers;
 mod aliyun_handlers;
 mod bilibili_handlers;
 mod misc_handlers;
 
-use crate::{auth::jwt_auth_middleware, middleware::apply_axum_middleware, state::AppState};
-pub use aliyun_handlers::URI;
-use axum::{Json, Router, middleware, routing::get};
+use crate::{
+    auth::jwt_auth_middleware,
+    debug_capture::capture_requests,
+    examples::record_examples,
+    middleware::apply_axum_middleware,
+    rate_limit::{limit_aliyun_refresh, limit_bilibili_create},
+    read_only::read_only_guard,
+    state::AppState,
+};
+use axum::{
+    Router,
+    body::Bytes,
+    extract::{DefaultBodyLimit, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::Arc,
+};
 use utoipa::OpenApi;
 use utoipa_axum::{router::OpenApiRouter, routes};
 use utoipa_scalar::{Scalar, Servable};
@@ -16,16 +36,73 @@ use utoipa_scalar::{Scalar, Servable};
         (name = "health", description = "Health check endpoints"),
         (name = "bilibili", description = "Bilibili dynamic posting endpoints"),
         (name = "aliyun", description = "Aliyun CDN API endpoints"),
+        (name = "admin", description = "Operational controls"),
     ),
     components(
         schemas(
+            crate::error::ErrorBody,
             bilibili_handlers::DynamicResponse,
+            bilibili_handlers::CreateDynamicForm,
+            bilibili_handlers::DeleteDynamicPayload,
+            bilibili_handlers::ScheduledDynamic,
+            bilibili_handlers::ScheduledDynamicStatus,
+            crate::bilibili::ContentItem,
             aliyun_handlers::OssEventPayload,
+            aliyun_handlers::OssEventsPayload,
             aliyun_handlers::OssEventResponse,
+            aliyun_handlers::OssEventsResponse,
+            aliyun_handlers::OssBatchEventResponse,
+            aliyun_handlers::OssEventResult,
+            aliyun_handlers::OssEventStatus,
             aliyun_handlers::OssEventData,
             aliyun_handlers::OssData,
             aliyun_handlers::OssBucket,
             aliyun_handlers::OssObject,
+            aliyun_handlers::RefreshObjectCachesPayload,
+            aliyun_handlers::RefreshObjectCachesResult,
+            aliyun_handlers::RefreshDirectoryPayload,
+            aliyun_handlers::RefreshAndWaitPayload,
+            crate::aliyun::RefreshAndWaitResult,
+            aliyun_handlers::FailedRefreshChunk,
+            aliyun_handlers::PushObjectCachesPayload,
+            aliyun_handlers::PushObjectCachesResult,
+            aliyun_handlers::RefreshAndPreloadPayload,
+            aliyun_handlers::RefreshAndPreloadResult,
+            crate::aliyun::CdnProduct,
+            aliyun_handlers::RawAliyunCallPayload,
+            aliyun_handlers::InvokeAliyunPayload,
+            crate::aliyun::InvokeAliyunResult,
+            crate::aliyun::DescribeRefreshTaskByIdResponse,
+            crate::aliyun::DescribeRefreshQuotaResponse,
+            crate::aliyun::DescribeUserDomainsResponse,
+            crate::aliyun::cdn::DomainsContainer,
+            crate::aliyun::CdnDomain,
+            crate::aliyun::CdnDomainDetail,
+            crate::aliyun::CdnDomainSource,
+            crate::aliyun::DomainBpsData,
+            crate::aliyun::BpsDataPoint,
+            crate::aliyun::DomainHitRateData,
+            crate::aliyun::HitRateDataPoint,
+            crate::aliyun::RefreshTask,
+            crate::aliyun::RefreshTaskObjectType,
+            crate::aliyun::RefreshTaskStatus,
+            crate::aliyun::DescribeRefreshTasksPayload,
+            crate::aliyun::DescribeRefreshTasksResponse,
+            crate::aliyun::cdn::TasksContainer,
+            crate::aliyun::DescribeCdnDomainLogsPayload,
+            crate::aliyun::CdnDomainLogsResponse,
+            crate::aliyun::CdnLogFile,
+            crate::aliyun::DownloadCdnDomainLogPayload,
+            crate::refresh_jobs::RefreshJob,
+            crate::refresh_jobs::RefreshJobStatus,
+            crate::refresh_log::RefreshLogEntry,
+            crate::event_dlq::DeadLetter,
+            crate::event_dlq::DeadLetterPage,
+            crate::event_dlq::DeadLetterStatus,
+            admin_handlers::SetReadOnlyPayload,
+            crate::read_only::ReadOnlyStatus,
+            crate::examples::RecordedExample,
+            crate::debug_capture::CapturedExchange,
         )
     ),
     modifiers(&SecurityAddon)
@@ -56,23 +133,98 @@ impl utoipa::Modify for SecurityAddon {
                     ),
                 ),
             );
+            components.add_security_scheme(
+                "eventbridge_hmac",
+                utoipa::openapi::security::SecurityScheme::ApiKey(
+                    utoipa::openapi::security::ApiKey::Header(
+                        utoipa::openapi::security::ApiKeyValue::with_description(
+                            crate::event_auth::SIGNATURE_HEADER,
+                            "Hex HMAC-SHA256 of `{timestamp}\\n{body}`, with the Unix timestamp in `x-eventbridge-signature-timestamp`",
+                        ),
+                    ),
+                ),
+            );
         }
     }
 }
 
-pub fn build_router(state: AppState) -> Router {
+/// Build the `/api` routes together with their merged OpenAPI document
+fn api_routes(state: &AppState) -> (Router<AppState>, utoipa::openapi::OpenApi) {
+    // Aliyun EventBridge endpoint, authenticated in the handler (JWT header or EventBridge HMAC)
+    let event_routes = OpenApiRouter::new()
+        .routes(routes!(aliyun_handlers::handle_oss_events))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            limit_aliyun_refresh,
+        ));
+
     // Routes without JWT auth (public + custom auth)
     let (public_routes, openapi_public) = OpenApiRouter::with_openapi(ApiDoc::openapi())
-        // Health endpoints (no auth required)
+        // Health endpoints (no auth required), never rate limited so probes always get through
         .routes(routes!(misc_handlers::ping))
-        // Aliyun EventBridge endpoint with custom JWT auth via `x-eventbridge-signature-token` header
-        .routes(routes!(aliyun_handlers::handle_oss_events))
+        .routes(routes!(misc_handlers::health))
+        .merge(event_routes)
         .split_for_parts();
 
+    // Only createDynamic takes image uploads past the default body limit. Both rate limits
+    // run inside JWT auth, so callers are limited by token subject
+    let create_routes = OpenApiRouter::new()
+        .routes(routes!(bilibili_handlers::create_dynamic))
+        .route_layer(DefaultBodyLimit::max(
+            state.bilibili_config.max_request_size_bytes(),
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            limit_bilibili_create,
+        ));
+    let refresh_routes = OpenApiRouter::new()
+        .routes(routes!(aliyun_handlers::refresh_object_caches))
+        .routes(routes!(aliyun_handlers::refresh_object_caches_and_wait))
+        .routes(routes!(aliyun_handlers::refresh_directory))
+        .routes(routes!(aliyun_handlers::push_object_caches))
+        .routes(routes!(aliyun_handlers::refresh_and_preload_object_caches))
+        .routes(routes!(aliyun_handlers::replay_dead_letter))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            limit_aliyun_refresh,
+        ));
+
     // Routes protected by Authorization header JWT
     let (protected_routes, openapi_protected) = OpenApiRouter::new()
         // Bilibili routes (protected by JWT auth)
-        .routes(routes!(bilibili_handlers::create_dynamic))
+        .merge(create_routes)
+        .routes(routes!(bilibili_handlers::delete_dynamic))
+        .merge(refresh_routes)
+        .routes(routes!(aliyun_handlers::raw_aliyun_call))
+        .routes(routes!(aliyun_handlers::invoke_aliyun))
+        // Mutating routes are blocked while read-only mode is engaged
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            read_only_guard,
+        ))
+        // Admin routes stay reachable so read-only mode can be released
+        .routes(routes!(
+            admin_handlers::get_read_only,
+            admin_handlers::set_read_only
+        ))
+        .routes(routes!(admin_handlers::list_examples))
+        .routes(routes!(admin_handlers::list_captures))
+        .routes(routes!(aliyun_handlers::describe_refresh_task))
+        .routes(routes!(aliyun_handlers::get_refresh_task))
+        .routes(routes!(aliyun_handlers::describe_refresh_tasks))
+        .routes(routes!(aliyun_handlers::describe_refresh_quota))
+        .routes(routes!(aliyun_handlers::describe_user_domains))
+        .routes(routes!(aliyun_handlers::describe_cdn_domain_detail))
+        .routes(routes!(aliyun_handlers::describe_domain_bps_data))
+        .routes(routes!(aliyun_handlers::describe_domain_hit_rate_data))
+        .routes(routes!(aliyun_handlers::describe_domain_logs))
+        .routes(routes!(aliyun_handlers::get_domain_logs))
+        .routes(routes!(aliyun_handlers::download_domain_log))
+        .routes(routes!(aliyun_handlers::get_refresh_job))
+        .routes(routes!(aliyun_handlers::list_refresh_log))
+        .routes(routes!(aliyun_handlers::list_dead_letters))
+        .routes(routes!(bilibili_handlers::list_scheduled_dynamics))
+        .routes(routes!(bilibili_handlers::cancel_scheduled_dynamic))
         .route_layer(middleware::from_fn_with_state(
             state.clone(),
             jwt_auth_middleware,
@@ -92,12 +244,211 @@ pub fn build_router(state: AppState) -> Router {
         .into_iter()
         .map(|(path, item)| (format!("/api{path}"), item))
         .collect::<utoipa::openapi::path::PathsMap<_, _>>();
-    let full_router = Router::new()
-        .nest("/api", api_routes)
-        .merge(Scalar::with_url("/api/scalar", openapi.clone()))
-        .route("/api/openapi.json", get(|| async move { Json(openapi) }))
-        .with_state(state);
+
+    (api_routes, openapi)
+}
+
+/// The OpenAPI document served at `/api/openapi.json`
+pub fn openapi_spec(state: &AppState) -> utoipa::openapi::OpenApi {
+    api_routes(state).1
+}
+
+/// The spec serialized once, with its entity tag
+#[derive(Clone)]
+struct OpenApiJson {
+    body: Bytes,
+    etag: HeaderValue,
+}
+
+impl OpenApiJson {
+    fn new(openapi: &utoipa::openapi::OpenApi) -> Self {
+        let body = Bytes::from(serde_json::to_vec(openapi).expect("OpenAPI spec should serialize"));
+        let mut hasher = DefaultHasher::new();
+        body.hash(&mut hasher);
+        let etag = HeaderValue::from_str(&format!("\"{:016x}\"", hasher.finish()))
+            .expect("ETag is a valid header value");
+        Self { body, etag }
+    }
+}
+
+/// Serve the pre-serialized spec, answering 304 when the client already has it
+async fn serve_openapi_json(State(spec): State<Arc<OpenApiJson>>, headers: HeaderMap) -> Response {
+    let cached = headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|tag| {
+            let tag = tag.trim();
+            tag == "*" || tag.trim_start_matches("W/") == spec.etag
+        });
+    if cached {
+        return (
+            StatusCode::NOT_MODIFIED,
+            [(header::ETAG, spec.etag.clone())],
+        )
+            .into_response();
+    }
+    (
+        [
+            (
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/json"),
+            ),
+            (header::ETAG, spec.etag.clone()),
+        ],
+        spec.body.clone(),
+    )
+        .into_response()
+}
+
+pub fn build_router(state: AppState) -> Router {
+    let (api_routes, openapi) = api_routes(&state);
+    let mut full_router = Router::new().nest("/api", api_routes);
+    if !state.server_config.disable_api_docs {
+        let spec = Arc::new(OpenApiJson::new(&openapi));
+        full_router = full_router
+            .merge(Scalar::with_url("/api/scalar", openapi))
+            .route(
+                "/api/openapi.json",
+                get(serve_openapi_json).with_state(spec),
+            );
+    }
+
+    // Prometheus scrape endpoint lives outside `/api` and is only mounted when enabled
+    if state.metrics.is_some() {
+        full_router = full_router.route("/metrics", get(misc_handlers::metrics));
+    }
+    // Example recording is only layered when configured, so it costs nothing otherwise
+    if let Some(recorder) = state.example_recorder.clone() {
+        full_router = full_router.layer(middleware::from_fn_with_state(recorder, record_examples));
+    }
+    // Likewise request capture, outermost so it sees what the recorder and handlers answered
+    if let Some(capture) = state.request_capture.clone() {
+        full_router = full_router.layer(middleware::from_fn_with_state(capture, capture_requests));
+    }
+    let server_config = state.server_config.clone();
+    let full_router = full_router.with_state(state);
 
     // Apply middleware
-    apply_axum_middleware(full_router)
+    apply_axum_middleware(full_router, &server_config)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt;
+
+    use super::build_router;
+    use crate::test_support::{body_json, state_from, test_settings, test_state};
+
+    #[tokio::test]
+    async fn test_openapi_documents_multipart_form_and_error_bodies() {
+        let response = build_router(test_state())
+            .oneshot(
+                Request::get("/api/openapi.json")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let spec = body_json(response).await;
+
+        let create = &spec["paths"]["/api/bilibili/createDynamic"]["post"];
+        assert_eq!(
+            create["requestBody"]["content"]["multipart/form-data"]["schema"]["$ref"],
+            "#/components/schemas/CreateDynamicForm"
+        );
+        let form = &spec["components"]["schemas"]["CreateDynamicForm"];
+        assert_eq!(form["required"], serde_json::json!(["msg"]));
+        assert_eq!(form["properties"]["files"]["items"]["format"], "binary");
+        for field in ["topic_id", "topic_name", "forward_dynamic_id", "account"] {
+            assert!(form["properties"][field].is_object(), "{field}");
+        }
+
+        assert!(spec["components"]["schemas"]["ErrorBody"]["properties"]["msg"].is_object());
+        for (path, methods) in spec["paths"].as_object().unwrap() {
+            for (method, operation) in methods.as_object().unwrap() {
+                for (status, response) in operation["responses"].as_object().unwrap() {
+                    if status.starts_with('4') || status.starts_with('5') {
+                        assert_eq!(
+                            response["content"]["application/json"]["schema"]["$ref"],
+                            "#/components/schemas/ErrorBody",
+                            "{method} {path} {status}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_openapi_json_revalidates_with_etag() {
+        let router = build_router(test_state());
+        let response = router
+            .clone()
+            .oneshot(
+                Request::get("/api/openapi.json")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["Content-Type"], "application/json");
+        let etag = response.headers()["ETag"].clone();
+
+        let response = router
+            .oneshot(
+                Request::get("/api/openapi.json")
+                    .header("If-None-Match", etag.clone())
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 304);
+        assert_eq!(response.headers()["ETag"], etag);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_api_docs_can_be_disabled() {
+        let mut settings = test_settings();
+        settings.server.disable_api_docs = true;
+        let router = build_router(state_from(&settings));
+        for uri in ["/api/openapi.json", "/api/scalar"] {
+            let response = router
+                .clone()
+                .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 404, "{uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_only_create_dynamic_takes_large_bodies() {
+        let token = crate::auth::generate_token(
+            "pipeline".to_string(),
+            crate::test_support::TEST_PRIVATE_KEY,
+        )
+        .unwrap();
+        let body = format!(r#"{{"object_path":"{}"}}"#, "a".repeat(3 * 1024 * 1024));
+        let response = build_router(test_state())
+            .oneshot(
+                Request::post("/api/aliyun/refreshObjectCaches")
+                    .header("Authorization", format!("Bearer {token}"))
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 413);
+    }
 }
@@ -0,0 +1,351 @@
+//! In-memory schedule of dynamics posted later with `publish_at`
+//!
+//! Posts keep their images until they are due; the images are uploaded to Bilibili only at
+//! publish time, so the CDN links Bilibili hands out are fresh. Like queued refreshes, pending
+//! posts live in memory and are lost on restart.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use tracing::{error, info};
+
+pub use crate::api::bilibili::{ScheduledDynamic, ScheduledDynamicStatus};
+use crate::{
+    bilibili::{DynamicDraft, publish_dynamic},
+    error::{AppError, AppResult},
+    state::AppState,
+};
+
+struct Entry {
+    post: ScheduledDynamic,
+    due: DateTime<Utc>,
+    /// Taken by the scheduler when due, dropped on cancel to free the images
+    draft: Option<DynamicDraft>,
+    finished_at: Option<Instant>,
+}
+
+#[derive(Default)]
+struct Schedule {
+    next_id: u64,
+    entries: BTreeMap<u64, Entry>,
+}
+
+/// Dynamics waiting for their `publish_at`, by id
+#[derive(Clone, Default)]
+pub struct ScheduledDynamics {
+    schedule: Arc<Mutex<Schedule>>,
+}
+
+impl std::fmt::Debug for ScheduledDynamics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let schedule = self.schedule.lock().expect("schedule lock poisoned");
+        f.debug_struct("ScheduledDynamics")
+            .field("entries", &schedule.entries.len())
+            .finish()
+    }
+}
+
+impl ScheduledDynamics {
+    /// Hold `draft` until `publish_at`, failing when `max_pending` posts are already waiting
+    ///
+    /// Finished and cancelled posts older than `retention` are dropped.
+    pub fn schedule(
+        &self,
+        account: String,
+        publish_at: DateTime<Utc>,
+        draft: DynamicDraft,
+        max_pending: usize,
+        retention: Duration,
+    ) -> AppResult<ScheduledDynamic> {
+        let mut schedule = self.schedule.lock().expect("schedule lock poisoned");
+        schedule.entries.retain(|_, entry| {
+            entry
+                .finished_at
+                .is_none_or(|finished| finished.elapsed() < retention)
+        });
+        let pending = schedule
+            .entries
+            .values()
+            .filter(|entry| entry.finished_at.is_none())
+            .count();
+        if pending >= max_pending {
+            return Err(AppError::Unavailable(anyhow::anyhow!(
+                "{} scheduled dynamics are already pending, the limit is {}",
+                pending,
+                max_pending
+            )));
+        }
+
+        schedule.next_id += 1;
+        let now = Utc::now().to_rfc3339();
+        let post = ScheduledDynamic {
+            id: schedule.next_id,
+            status: ScheduledDynamicStatus::Pending,
+            account,
+            publish_at: publish_at.to_rfc3339(),
+            image_count: draft.files.len(),
+            dynamic_id: None,
+            error: None,
+            created_at: now.clone(),
+            updated_at: now,
+        };
+        schedule.entries.insert(
+            post.id,
+            Entry {
+                post: post.clone(),
+                due: publish_at,
+                draft: Some(draft),
+                finished_at: None,
+            },
+        );
+        Ok(post)
+    }
+
+    /// Every retained post, oldest first
+    pub fn list(&self) -> Vec<ScheduledDynamic> {
+        let schedule = self.schedule.lock().expect("schedule lock poisoned");
+        schedule
+            .entries
+            .values()
+            .map(|entry| entry.post.clone())
+            .collect()
+    }
+
+    /// Cancel a post that is still pending
+    pub fn cancel(&self, id: u64) -> AppResult<ScheduledDynamic> {
+        let mut schedule = self.schedule.lock().expect("schedule lock poisoned");
+        let entry = schedule.entries.get_mut(&id).ok_or_else(|| {
+            AppError::NotFound(anyhow::anyhow!("Scheduled dynamic {} not found", id))
+        })?;
+        if entry.post.status != ScheduledDynamicStatus::Pending {
+            return Err(AppError::BadRequest(anyhow::anyhow!(
+                "Scheduled dynamic {} is already {}",
+                id,
+                serde_variant::to_variant_name(&entry.post.status).unwrap_or_default()
+            )));
+        }
+        entry.draft = None;
+        entry.finished_at = Some(Instant::now());
+        entry.post.status = ScheduledDynamicStatus::Cancelled;
+        entry.post.updated_at = Utc::now().to_rfc3339();
+        Ok(entry.post.clone())
+    }
+
+    /// Mark every pending post due by `now` as publishing and hand out its draft
+    fn take_due(&self, now: DateTime<Utc>) -> Vec<(ScheduledDynamic, DynamicDraft)> {
+        let mut schedule = self.schedule.lock().expect("schedule lock poisoned");
+        let updated_at = now.to_rfc3339();
+        schedule
+            .entries
+            .values_mut()
+            .filter(|entry| {
+                entry.post.status == ScheduledDynamicStatus::Pending && entry.due <= now
+            })
+            .filter_map(|entry| {
+                let draft = entry.draft.take()?;
+                entry.post.status = ScheduledDynamicStatus::Publishing;
+                entry.post.updated_at.clone_from(&updated_at);
+                Some((entry.post.clone(), draft))
+            })
+            .collect()
+    }
+
+    fn finish(&self, id: u64, outcome: &AppResult<Option<u64>>) {
+        let mut schedule = self.schedule.lock().expect("schedule lock poisoned");
+        if let Some(entry) = schedule.entries.get_mut(&id) {
+            match outcome {
+                Ok(dynamic_id) => {
+                    entry.post.status = ScheduledDynamicStatus::Published;
+                    entry.post.dynamic_id = dynamic_id.map(|id| id.to_string());
+                }
+                Err(err) => {
+                    entry.post.status = ScheduledDynamicStatus::Failed;
+                    entry.post.error = Some(format!("{err:#}"));
+                }
+            }
+            entry.post.updated_at = Utc::now().to_rfc3339();
+            entry.finished_at = Some(Instant::now());
+        }
+    }
+}
+
+/// Publish due dynamics every `tick` until the task is aborted
+pub async fn run_dynamic_scheduler(state: AppState, tick: Duration) {
+    let mut interval = tokio::time::interval(tick);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        // Posting stays held back while read-only mode is engaged
+        if state.read_only.is_enabled() {
+            continue;
+        }
+        for (post, draft) in state.scheduled_dynamics.take_due(Utc::now()) {
+            let outcome = publish_dynamic(&state, Some(&post.account), draft)
+                .await
+                .map(|data| data.and_then(|data| data.dynamic_id));
+            match &outcome {
+                Ok(dynamic_id) => info!(
+                    schedule_id = post.id,
+                    account = post.account,
+                    dynamic_id,
+                    "Scheduled dynamic published"
+                ),
+                // Never retried: Bilibili may have posted it before failing to answer
+                Err(err) => error!(
+                    schedule_id = post.id,
+                    account = post.account,
+                    error = ?err,
+                    "Scheduled dynamic failed"
+                ),
+            }
+            state.scheduled_dynamics.finish(post.id, &outcome);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{method, path},
+    };
+
+    use super::*;
+    use crate::{
+        bilibili::ContentItem,
+        test_support::{state_from, test_settings},
+    };
+
+    fn draft(text: &str) -> DynamicDraft {
+        DynamicDraft {
+            contents: vec![ContentItem::text(text)],
+            options: Default::default(),
+            files: Vec::new(),
+        }
+    }
+
+    fn schedule_in(
+        posts: &ScheduledDynamics,
+        delay: chrono::Duration,
+        max_pending: usize,
+    ) -> AppResult<ScheduledDynamic> {
+        posts.schedule(
+            "default".to_string(),
+            Utc::now() + delay,
+            draft("patch notes"),
+            max_pending,
+            Duration::from_secs(60),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_scheduler_publishes_due_posts_only() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/x/dynamic/feed/create/dyn"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"code":0,"data":{"doc_id":0,"dynamic_id":1021451253404745734,"create_result":0,"errmsg":""}}"#,
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let mut settings = test_settings();
+        settings.bilibili.api_base_url = server.uri();
+        let state = state_from(&settings);
+
+        let due = schedule_in(&state.scheduled_dynamics, chrono::Duration::zero(), 10).unwrap();
+        let later = schedule_in(&state.scheduled_dynamics, chrono::Duration::hours(1), 10).unwrap();
+        let scheduler = tokio::spawn(run_dynamic_scheduler(
+            state.clone(),
+            Duration::from_millis(10),
+        ));
+        let published = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let posts = state.scheduled_dynamics.list();
+                if posts[0].status == ScheduledDynamicStatus::Published {
+                    return posts;
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("due post should be published");
+        scheduler.abort();
+
+        assert_eq!(published[0].id, due.id);
+        assert_eq!(
+            published[0].dynamic_id.as_deref(),
+            Some("1021451253404745734")
+        );
+        assert_eq!(published[1].id, later.id);
+        assert_eq!(published[1].status, ScheduledDynamicStatus::Pending);
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_failed_post_records_the_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/x/dynamic/feed/create/dyn"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"code":-101,"message":"账号未登录"}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let mut settings = test_settings();
+        settings.bilibili.api_base_url = server.uri();
+        let state = state_from(&settings);
+
+        let post = schedule_in(&state.scheduled_dynamics, chrono::Duration::zero(), 10).unwrap();
+        let (taken, draft) = state.scheduled_dynamics.take_due(Utc::now()).pop().unwrap();
+        assert_eq!(taken.status, ScheduledDynamicStatus::Publishing);
+        let outcome = publish_dynamic(&state, Some(&taken.account), draft)
+            .await
+            .map(|data| data.and_then(|data| data.dynamic_id));
+        state.scheduled_dynamics.finish(post.id, &outcome);
+
+        let failed = &state.scheduled_dynamics.list()[0];
+        assert_eq!(failed.status, ScheduledDynamicStatus::Failed);
+        assert!(failed.error.as_deref().unwrap().contains("Bilibili"));
+        server.verify().await;
+    }
+
+    #[test]
+    fn test_cancel_only_pending_posts() {
+        let posts = ScheduledDynamics::default();
+        let post = schedule_in(&posts, chrono::Duration::hours(1), 10).unwrap();
+
+        let cancelled = posts.cancel(post.id).unwrap();
+        assert_eq!(cancelled.status, ScheduledDynamicStatus::Cancelled);
+        assert!(
+            posts
+                .take_due(Utc::now() + chrono::Duration::hours(2))
+                .is_empty()
+        );
+        assert!(matches!(
+            posts.cancel(post.id),
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(matches!(posts.cancel(99), Err(AppError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_pending_posts_are_capped() {
+        let posts = ScheduledDynamics::default();
+        let first = schedule_in(&posts, chrono::Duration::hours(1), 1).unwrap();
+        assert!(matches!(
+            schedule_in(&posts, chrono::Duration::hours(1), 1),
+            Err(AppError::Unavailable(_))
+        ));
+
+        // Cancelled posts no longer count
+        posts.cancel(first.id).unwrap();
+        schedule_in(&posts, chrono::Duration::hours(1), 1).unwrap();
+    }
+}
@@ -1,18 +1,227 @@
-use crate::config::{AliyunConfig, AppSettings, BilibiliConfig, JwtConfig};
+use arc_swap::ArcSwap;
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use crate::{
+    aliyun::{AliyunCdnClient, CredentialProvider, Credentials, OssClient, SharedCredentials},
+    bilibili::BilibiliClient,
+    config::{
+        AliyunConfig, AppSettings, BilibiliConfig, HttpClientConfig, JwtConfig,
+        LEGACY_BILIBILI_ACCOUNT, ProxySettings, ServerConfig,
+    },
+    debug_capture::RequestCapture,
+    error::{AppError, AppResult},
+    event_auth::ReplayGuard,
+    event_dedup::EventDedup,
+    event_dlq::DeadLetters,
+    event_filter::EventFilter,
+    event_ids::EventIds,
+    examples::ExampleRecorder,
+    http_client::build_http_client,
+    metrics::Metrics,
+    rate_limit::RateLimiters,
+    read_only::ReadOnlyMode,
+    refresh_jobs::RefreshJobs,
+    refresh_log::RefreshLog,
+    scheduled_dynamics::ScheduledDynamics,
+    url_dedup::UrlDedup,
+    webhooks::Webhooks,
+};
+
+/// OSS object versions and event signatures remembered at most, oldest forgotten first
+const MAX_REMEMBERED_EVENTS: usize = 10_000;
 
 #[derive(Debug, Clone)]
 pub struct AppState {
+    pub server_config: ServerConfig,
     pub bilibili_config: BilibiliConfig,
-    pub jwt_config: JwtConfig,
-    pub aliyun_config: AliyunConfig,
+    /// Swapped on config reload, like the other `ArcSwap` cells below
+    pub jwt_config: Arc<ArcSwap<JwtConfig>>,
+    pub aliyun_config: Arc<ArcSwap<AliyunConfig>>,
+    /// Credentials every Aliyun client signs with, rotated in place by the refresh task
+    pub aliyun_credentials: SharedCredentials,
+    /// Source of `aliyun_credentials`, fetched again before they expire
+    pub aliyun_credential_provider: Arc<CredentialProvider>,
+    /// Shared CDN client, `None` when no Aliyun credentials are configured
+    pub aliyun_cdn: Option<Arc<AliyunCdnClient>>,
+    /// OSS client of `aliyun.verify_object_exists` and `aliyun.events.expand_prefixes`, configured
+    /// along with `aliyun_cdn`
+    pub aliyun_oss: Option<Arc<OssClient>>,
+    /// Client for calls to neither Bilibili nor Aliyun, such as webhooks
     pub http_client: reqwest::Client,
+    /// Client for Aliyun calls outside the CDN client, such as STS
+    pub aliyun_http_client: reqwest::Client,
+    pub http_client_config: HttpClientConfig,
+    /// One client per configured Bilibili account
+    pub bilibili_clients: HashMap<String, BilibiliClient>,
+    /// Account used when a request doesn't name one
+    pub bilibili_default_account: String,
+    pub metrics: Option<Metrics>,
+    pub read_only: ReadOnlyMode,
+    pub example_recorder: Option<Arc<ExampleRecorder>>,
+    /// Recent requests and responses, `None` unless `[debug.capture]` is configured
+    pub request_capture: Option<Arc<RequestCapture>>,
+    pub rate_limiters: Arc<ArcSwap<RateLimiters>>,
+    /// Recently refreshed OSS object versions
+    pub event_dedup: EventDedup,
+    /// Recently refreshed CDN URLs, whatever their object version
+    pub url_dedup: UrlDedup,
+    /// Answers to recent EventBridge deliveries, by event id
+    pub event_ids: EventIds,
+    /// Directories recently refreshed by an `aliyun.events.prefix_rules` directory rule
+    pub directory_dedup: UrlDedup,
+    /// Recently accepted EventBridge signatures
+    pub event_replay: ReplayGuard,
+    /// OSS events that could not be processed, kept for replay
+    pub dead_letters: DeadLetters,
+    /// CDN refreshes queued by OSS events
+    pub refresh_jobs: RefreshJobs,
+    /// Refresh tasks Aliyun accepted and their final status
+    pub refresh_log: RefreshLog,
+    /// Dynamics waiting for their `publish_at`
+    pub scheduled_dynamics: ScheduledDynamics,
+    pub event_filter: Arc<ArcSwap<EventFilter>>,
+    /// Outgoing completion notifications
+    pub webhooks: Webhooks,
+}
+
+impl AppState {
+    /// Client for the named Bilibili account, or the default one
+    pub fn bilibili_client(&self, account: Option<&str>) -> AppResult<&BilibiliClient> {
+        let name = account.unwrap_or(&self.bilibili_default_account);
+        self.bilibili_clients.get(name).ok_or_else(|| {
+            let mut names = self.bilibili_clients.keys().cloned().collect::<Vec<_>>();
+            names.sort();
+            AppError::BadRequest(anyhow::anyhow!(
+                "Unknown Bilibili account '{}', configured accounts: {}",
+                name,
+                names.join(", ")
+            ))
+        })
+    }
+
+    /// The CDN client, or 503 when the Aliyun integration isn't configured
+    pub fn aliyun_cdn(&self) -> AppResult<&AliyunCdnClient> {
+        self.aliyun_cdn.as_deref().ok_or_else(|| {
+            AppError::Unavailable(anyhow::anyhow!("Aliyun integration not configured"))
+        })
+    }
 }
 
-pub async fn init_state(config: &AppSettings) -> AppState {
+pub async fn init_state(config: &AppSettings, metrics: Option<Metrics>) -> AppState {
+    let http_client = build_http_client(&config.http_client, &config.http_client.proxy());
+    // Upstreams without proxy settings of their own share the general client's pool
+    let client_for = |proxy: &Option<ProxySettings>| match proxy {
+        Some(proxy) => build_http_client(&config.http_client, proxy),
+        None => http_client.clone(),
+    };
+    let aliyun_http_client = client_for(&config.http_client.aliyun);
+    let bilibili_http_client = client_for(&config.http_client.bilibili);
+    let aliyun_credentials: SharedCredentials =
+        Arc::new(RwLock::new(Credentials::from_config(&config.aliyun)));
+    let aliyun_credential_provider = Arc::new(CredentialProvider::new(&config.aliyun));
+    let aliyun_cdn = config.aliyun.is_configured().then(|| {
+        Arc::new(
+            AliyunCdnClient::new(&config.aliyun, aliyun_http_client.clone())
+                .with_credentials(aliyun_credentials.clone())
+                .with_credential_provider(aliyun_credential_provider.clone())
+                .with_timeout(Duration::from_secs(config.http_client.aliyun_timeout_secs))
+                .with_proxy_host(config.http_client.aliyun_proxy().proxy_host()),
+        )
+    });
+    let aliyun_oss = config.aliyun.is_configured().then(|| {
+        Arc::new(
+            OssClient::new(&config.aliyun, aliyun_http_client.clone())
+                .with_credentials(aliyun_credentials.clone())
+                .with_credential_provider(aliyun_credential_provider.clone())
+                .with_timeout(Duration::from_secs(config.http_client.aliyun_timeout_secs))
+                .with_proxy_host(config.http_client.aliyun_proxy().proxy_host()),
+        )
+    });
     AppState {
+        server_config: config.server.clone(),
         bilibili_config: config.bilibili.clone(),
-        jwt_config: config.jwt.clone(),
-        aliyun_config: config.aliyun.clone(),
-        http_client: reqwest::Client::new(),
+        jwt_config: Arc::new(ArcSwap::from_pointee(config.jwt.clone())),
+        aliyun_config: Arc::new(ArcSwap::from_pointee(config.aliyun.clone())),
+        aliyun_credentials,
+        aliyun_credential_provider,
+        aliyun_cdn,
+        aliyun_oss,
+        bilibili_clients: config
+            .bilibili
+            .all_accounts()
+            .iter()
+            .map(|(name, account)| {
+                let client = BilibiliClient::new(
+                    &config.bilibili.api_base_url,
+                    account,
+                    bilibili_http_client.clone(),
+                )
+                .with_upload_timeout(Duration::from_secs(
+                    config.http_client.bilibili_upload_timeout_secs,
+                ))
+                .with_proxy_host(config.http_client.bilibili_proxy().proxy_host());
+                (name.clone(), client)
+            })
+            .collect(),
+        bilibili_default_account: config
+            .bilibili
+            .default_account_name()
+            .unwrap_or_else(|| LEGACY_BILIBILI_ACCOUNT.to_string()),
+        http_client,
+        aliyun_http_client,
+        http_client_config: config.http_client.clone(),
+        metrics,
+        read_only: ReadOnlyMode::new(
+            config.server.read_only,
+            config.server.read_only_message.clone(),
+        ),
+        example_recorder: config
+            .examples
+            .clone()
+            .map(|examples| Arc::new(ExampleRecorder::new(examples))),
+        request_capture: config
+            .debug
+            .as_ref()
+            .and_then(|debug| debug.capture.clone())
+            .map(|capture| {
+                Arc::new(RequestCapture::new(
+                    capture,
+                    config.bilibili.max_request_size_bytes(),
+                ))
+            }),
+        rate_limiters: Arc::new(ArcSwap::from_pointee(RateLimiters::new(
+            config.server.rate_limit.as_ref(),
+        ))),
+        event_dedup: EventDedup::new(
+            Duration::from_secs(config.aliyun.event_dedup_ttl_secs),
+            MAX_REMEMBERED_EVENTS,
+        ),
+        url_dedup: UrlDedup::new(
+            Duration::from_secs(config.aliyun.url_dedup_window_secs),
+            config.aliyun.url_dedup_max_entries,
+        ),
+        event_ids: EventIds::new(
+            Duration::from_secs(config.aliyun.event_id_window_secs),
+            config.aliyun.event_id_max_entries,
+        ),
+        // One entry per directory rule and CDN domain, so no real cap is needed
+        directory_dedup: UrlDedup::new(
+            Duration::from_secs(config.aliyun.events.directory_rule_window_secs),
+            usize::MAX,
+        ),
+        event_replay: ReplayGuard::new(config.aliyun.events_max_skew_secs, MAX_REMEMBERED_EVENTS),
+        dead_letters: DeadLetters::default(),
+        refresh_jobs: RefreshJobs::default(),
+        refresh_log: RefreshLog::default(),
+        scheduled_dynamics: ScheduledDynamics::default(),
+        event_filter: Arc::new(ArcSwap::from_pointee(
+            EventFilter::new(&config.aliyun.events)
+                .expect("event filter patterns are validated at config load"),
+        )),
+        webhooks: Webhooks::new(config.webhooks.clone()),
     }
 }
@@ -0,0 +1,162 @@
+//! HTTPS termination for deployments without a reverse proxy
+
+use std::{future::Future, net::SocketAddr};
+
+use anyhow::Context;
+use axum::Router;
+use axum_server::{Handle, tls_rustls::RustlsConfig};
+use tokio::net::TcpListener;
+
+use crate::config::TlsConfig;
+
+/// Certificate served to clients, swappable in place for renewals
+#[derive(Clone)]
+pub struct ServerTls {
+    config: TlsConfig,
+    rustls: RustlsConfig,
+}
+
+impl ServerTls {
+    /// Load the certificate and key, naming the file that failed
+    pub async fn load(config: &TlsConfig) -> anyhow::Result<Self> {
+        // Only `ring` is compiled in, but be explicit in case another dependency adds a provider
+        let _ = rustls::crypto::ring::default_provider().install_default();
+
+        let (cert, key) = read_pem(config).await?;
+        let rustls = RustlsConfig::from_pem(cert, key)
+            .await
+            .with_context(|| invalid_pair(config))?;
+        Ok(Self {
+            config: config.clone(),
+            rustls,
+        })
+    }
+
+    /// Re-read the files; on failure the current certificate keeps being served
+    pub async fn reload(&self) -> anyhow::Result<()> {
+        let (cert, key) = read_pem(&self.config).await?;
+        self.rustls
+            .reload_from_pem(cert, key)
+            .await
+            .with_context(|| invalid_pair(&self.config))
+    }
+
+    /// Serve `router` over HTTPS on `listener` until `shutdown` resolves, then drain connections
+    pub async fn serve(
+        &self,
+        listener: TcpListener,
+        router: Router,
+        shutdown: impl Future<Output = ()> + Send + 'static,
+    ) -> std::io::Result<()> {
+        let handle = Handle::new();
+        let shutdown_handle = handle.clone();
+        tokio::spawn(async move {
+            shutdown.await;
+            shutdown_handle.graceful_shutdown(None);
+        });
+
+        axum_server::from_tcp_rustls(listener.into_std()?, self.rustls.clone())
+            .handle(handle)
+            .serve(router.into_make_service_with_connect_info::<SocketAddr>())
+            .await
+    }
+}
+
+async fn read_pem(config: &TlsConfig) -> anyhow::Result<(Vec<u8>, Vec<u8>)> {
+    let cert = tokio::fs::read(&config.cert_path)
+        .await
+        .with_context(|| format!("failed to read TLS certificate {}", config.cert_path))?;
+    let key = tokio::fs::read(&config.key_path)
+        .await
+        .with_context(|| format!("failed to read TLS private key {}", config.key_path))?;
+    Ok((cert, key))
+}
+
+fn invalid_pair(config: &TlsConfig) -> String {
+    format!(
+        "invalid TLS certificate {} or private key {}",
+        config.cert_path, config.key_path
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{routes::build_router, test_support::test_state};
+
+    fn fixture(name: &str) -> String {
+        format!("{}/tests/fixtures/tls/{name}", env!("CARGO_MANIFEST_DIR"))
+    }
+
+    fn fixture_config() -> TlsConfig {
+        TlsConfig {
+            cert_path: fixture("cert.pem"),
+            key_path: fixture("key.pem"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_https_request_with_trusted_fixture_cert() {
+        let tls = ServerTls::load(&fixture_config()).await.unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(async move {
+            tls.serve(listener, build_router(test_state()), async {
+                let _ = stopped.await;
+            })
+            .await
+        });
+
+        let cert = std::fs::read(fixture("cert.pem")).unwrap();
+        let client = reqwest::Client::builder()
+            .add_root_certificate(reqwest::Certificate::from_pem(&cert).unwrap())
+            .build()
+            .unwrap();
+        let response = client
+            .get(format!("https://localhost:{port}/api/_ping"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+
+        stop.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_missing_key_names_the_path() {
+        let config = TlsConfig {
+            key_path: "/nonexistent/janus-key.pem".to_string(),
+            ..fixture_config()
+        };
+        let err = ServerTls::load(&config).await.err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "failed to read TLS private key /nonexistent/janus-key.pem"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_reload_keeps_serving_the_old_cert() {
+        let dir = std::env::temp_dir().join(format!("janus-tls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = TlsConfig {
+            cert_path: dir.join("cert.pem").display().to_string(),
+            key_path: fixture("key.pem"),
+        };
+        std::fs::copy(fixture("cert.pem"), &config.cert_path).unwrap();
+        let tls = ServerTls::load(&config).await.unwrap();
+        let before = tls.rustls.get_inner();
+
+        std::fs::write(&config.cert_path, "not a certificate").unwrap();
+        let err = tls.reload().await.unwrap_err();
+        assert!(err.to_string().starts_with("invalid TLS certificate"));
+        assert!(std::sync::Arc::ptr_eq(&before, &tls.rustls.get_inner()));
+
+        std::fs::copy(fixture("cert.pem"), &config.cert_path).unwrap();
+        tls.reload().await.unwrap();
+        assert!(!std::sync::Arc::ptr_eq(&before, &tls.rustls.get_inner()));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
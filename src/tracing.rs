@@ -35,7 +35,7 @@ fn init_env_filter(override_filter: Option<&String>, level: &LogLevel) -> EnvFil
         .expect("logger initialization failed")
 }
 
-fn init_layer<W2>(
+pub(crate) fn init_layer<W2>(
     make_writer: W2,
     format: &LogFormat,
     ansi: bool,
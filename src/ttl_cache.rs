@@ -0,0 +1,194 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+#[derive(Debug)]
+struct Entry<V> {
+    /// Matches the `order` slot this entry was inserted with
+    seq: u64,
+    at: Instant,
+    value: V,
+}
+
+#[derive(Debug)]
+struct Entries<K, V> {
+    by_key: HashMap<K, Entry<V>>,
+    /// Keys in insertion order, oldest first; inserting a key again leaves its old slot behind
+    /// as stale
+    order: VecDeque<(u64, K)>,
+    next_seq: u64,
+}
+
+/// Values kept for `ttl` after they were inserted, at most `max_entries` of them
+///
+/// Inserting drops expired entries from the oldest end, then the oldest ones while over
+/// `max_entries`, so a burst of distinct keys can't grow it without bound.
+#[derive(Debug)]
+pub struct TtlCache<K, V> {
+    ttl: Duration,
+    max_entries: usize,
+    entries: Arc<Mutex<Entries<K, V>>>,
+}
+
+impl<K, V> Clone for TtlCache<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            ttl: self.ttl,
+            max_entries: self.max_entries,
+            entries: self.entries.clone(),
+        }
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> TtlCache<K, V> {
+    /// A zero `ttl` or `max_entries` keeps nothing
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            ttl,
+            max_entries,
+            entries: Arc::new(Mutex::new(Entries {
+                by_key: HashMap::new(),
+                order: VecDeque::new(),
+                next_seq: 0,
+            })),
+        }
+    }
+
+    /// The value inserted for `key` within the TTL
+    pub fn get(&self, key: &K) -> Option<V> {
+        let entries = self.entries.lock().expect("TTL cache lock poisoned");
+        entries
+            .by_key
+            .get(key)
+            .filter(|entry| entry.at.elapsed() < self.ttl)
+            .map(|entry| entry.value.clone())
+    }
+
+    /// Set `key` to `value`, restarting its TTL
+    pub fn insert(&self, key: K, value: V) {
+        if self.ttl.is_zero() || self.max_entries == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().expect("TTL cache lock poisoned");
+        self.push(&mut entries, key, value);
+    }
+
+    /// Set `key` to `value` unless it already has a value within the TTL; true if it was set
+    pub fn insert_new(&self, key: K, value: V) -> bool {
+        if self.ttl.is_zero() || self.max_entries == 0 {
+            return true;
+        }
+        let mut entries = self.entries.lock().expect("TTL cache lock poisoned");
+        if entries
+            .by_key
+            .get(&key)
+            .is_some_and(|entry| entry.at.elapsed() < self.ttl)
+        {
+            return false;
+        }
+        self.push(&mut entries, key, value);
+        true
+    }
+
+    fn push(&self, entries: &mut Entries<K, V>, key: K, value: V) {
+        let seq = entries.next_seq;
+        entries.next_seq += 1;
+        entries.order.push_back((seq, key.clone()));
+        entries.by_key.insert(
+            key,
+            Entry {
+                seq,
+                at: Instant::now(),
+                value,
+            },
+        );
+
+        let Entries { by_key, order, .. } = entries;
+        while let Some((seq, key)) = order.front() {
+            match by_key.get(key) {
+                Some(entry) if entry.seq == *seq => {
+                    if entry.at.elapsed() < self.ttl && by_key.len() <= self.max_entries {
+                        break;
+                    }
+                    by_key.remove(key);
+                }
+                // Left behind by a later insert of the same key
+                _ => {}
+            }
+            order.pop_front();
+        }
+        // Keys inserted over and over leave stale slots behind the live ones; sweeping them
+        // once they outnumber the entries keeps inserts amortized O(1)
+        if order.len() > by_key.len().saturating_mul(2).max(16) {
+            order.retain(|(seq, key)| by_key.get(key).is_some_and(|entry| entry.seq == *seq));
+        }
+    }
+
+    #[cfg(test)]
+    fn slots(&self) -> usize {
+        self.entries
+            .lock()
+            .expect("TTL cache lock poisoned")
+            .order
+            .len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_values_expire_after_the_ttl() {
+        let cache = TtlCache::new(Duration::from_millis(50), 10);
+        cache.insert("a", 1);
+        assert_eq!(cache.get(&"a"), Some(1));
+        assert!(cache.get(&"b").is_none());
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(cache.get(&"a").is_none());
+    }
+
+    #[test]
+    fn test_oldest_keys_make_room_when_full() {
+        let cache = TtlCache::new(Duration::from_secs(60), 2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        // Inserting a again makes b the oldest
+        cache.insert("a", 3);
+        cache.insert("c", 4);
+
+        assert_eq!(cache.get(&"a"), Some(3));
+        assert!(cache.get(&"b").is_none());
+        assert_eq!(cache.get(&"c"), Some(4));
+    }
+
+    #[test]
+    fn test_insert_new_keeps_the_first_value() {
+        let cache = TtlCache::new(Duration::from_secs(60), 10);
+        assert!(cache.insert_new("a", 1));
+        assert!(!cache.insert_new("a", 2));
+        assert_eq!(cache.get(&"a"), Some(1));
+    }
+
+    #[test]
+    fn test_repeated_key_stays_bounded() {
+        let cache = TtlCache::new(Duration::from_secs(60), 10);
+        for value in 0..1000 {
+            cache.insert("a", value);
+        }
+        assert!(cache.slots() <= 16);
+        assert_eq!(cache.get(&"a"), Some(999));
+    }
+
+    #[test]
+    fn test_zero_ttl_keeps_nothing() {
+        let cache = TtlCache::new(Duration::ZERO, 10);
+        cache.insert("a", 1);
+        assert!(cache.insert_new("a", 1));
+        assert!(cache.get(&"a").is_none());
+    }
+}
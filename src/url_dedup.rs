@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use crate::ttl_cache::TtlCache;
+
+/// Remembers recently refreshed CDN URLs so an object saved again and again within the window
+/// is purged once
+///
+/// Unlike [`crate::event_dedup::EventDedup`], a new ETag doesn't count as new: a save inside the
+/// window stays cached until the CDN's own TTL runs out.
+#[derive(Debug, Clone)]
+pub struct UrlDedup {
+    entries: TtlCache<String, String>,
+}
+
+/// URLs compare equal whatever the case of their scheme and host, or an explicit default port
+fn normalize(url: &str) -> String {
+    reqwest::Url::parse(url).map_or_else(|_| url.to_string(), String::from)
+}
+
+impl UrlDedup {
+    /// A zero `window` disables deduplication; past `max_entries`, the oldest are forgotten first
+    pub fn new(window: Duration, max_entries: usize) -> Self {
+        Self {
+            entries: TtlCache::new(window, max_entries),
+        }
+    }
+
+    /// Task id of a refresh of `url` issued within the window
+    pub fn recent_task(&self, url: &str) -> Option<String> {
+        self.entries.get(&normalize(url))
+    }
+
+    /// Remember that each of `urls` was refreshed as `task_id`
+    pub fn record<'a>(&self, urls: impl IntoIterator<Item = &'a str>, task_id: &str) {
+        for url in urls {
+            self.entries.insert(normalize(url), task_id.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recent_urls_are_remembered_until_the_window_ends() {
+        let dedup = UrlDedup::new(Duration::from_millis(50), 100);
+        dedup.record(["https://static.prts.wiki/a.png"], "42");
+
+        assert_eq!(
+            dedup
+                .recent_task("https://STATIC.prts.wiki:443/a.png")
+                .as_deref(),
+            Some("42")
+        );
+        assert_eq!(dedup.recent_task("https://static.prts.wiki/b.png"), None);
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(dedup.recent_task("https://static.prts.wiki/a.png"), None);
+    }
+
+    #[test]
+    fn test_oldest_urls_make_room_when_full() {
+        let dedup = UrlDedup::new(Duration::from_secs(60), 2);
+        dedup.record(["https://static.prts.wiki/a.png"], "1");
+        dedup.record(["https://static.prts.wiki/b.png"], "2");
+        // Refreshing a again makes b the oldest
+        dedup.record(["https://static.prts.wiki/a.png"], "3");
+        dedup.record(["https://static.prts.wiki/c.png"], "4");
+
+        assert_eq!(
+            dedup
+                .recent_task("https://static.prts.wiki/a.png")
+                .as_deref(),
+            Some("3")
+        );
+        assert_eq!(dedup.recent_task("https://static.prts.wiki/b.png"), None);
+        assert_eq!(
+            dedup
+                .recent_task("https://static.prts.wiki/c.png")
+                .as_deref(),
+            Some("4")
+        );
+    }
+
+    #[test]
+    fn test_zero_window_disables_dedup() {
+        let dedup = UrlDedup::new(Duration::ZERO, 100);
+        dedup.record(["https://static.prts.wiki/a.png"], "42");
+        assert_eq!(dedup.recent_task("https://static.prts.wiki/a.png"), None);
+    }
+}
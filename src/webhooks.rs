@@ -0,0 +1,592 @@
+//! Outgoing notifications when CDN refreshes and Bilibili posts finish
+//!
+//! Events are queued without blocking and delivered by one background dispatcher, so a slow
+//! or failing receiver never delays or fails the request that produced them.
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use bytes::Bytes;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use tokio::sync::Notify;
+use tracing::{debug, warn};
+
+use crate::{
+    aliyun::{RefreshObjectCachesRequest, url_host},
+    config::WebhookConfig,
+    error::AppError,
+    refresh_log::RefreshLogEntry,
+};
+
+/// `sha256=` followed by the hex HMAC-SHA256 of the body under the endpoint's secret
+pub const SIGNATURE_HEADER: &str = "x-janus-signature";
+/// Event type of the delivery, same as the body's `type`
+pub const EVENT_HEADER: &str = "x-janus-event";
+
+pub const CDN_REFRESH_SUCCEEDED: &str = "cdn.refresh.succeeded";
+pub const CDN_REFRESH_FAILED: &str = "cdn.refresh.failed";
+pub const BILIBILI_DYNAMIC_POSTED: &str = "bilibili.dynamic.posted";
+pub const BILIBILI_DYNAMIC_FAILED: &str = "bilibili.dynamic.failed";
+pub const OSS_OBJECT_REFRESHED: &str = "oss.object.refreshed";
+
+/// Every event type, for validating `webhooks.events`
+pub const EVENT_TYPES: &[&str] = &[
+    CDN_REFRESH_SUCCEEDED,
+    CDN_REFRESH_FAILED,
+    BILIBILI_DYNAMIC_POSTED,
+    BILIBILI_DYNAMIC_FAILED,
+    OSS_OBJECT_REFRESHED,
+];
+
+/// Deliveries waiting beyond this are dropped
+const QUEUE_CAPACITY: usize = 1000;
+
+/// JSON body of a delivery
+#[derive(Serialize, Debug, Clone)]
+pub struct WebhookEvent {
+    #[serde(rename = "type")]
+    pub event_type: &'static str,
+    /// `succeeded` or `failed`
+    pub outcome: &'static str,
+    /// RFC 3339
+    pub timestamp: String,
+    /// What triggered a CDN refresh: `manual` or `oss_event`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<&'static str>,
+    /// Bucket of the refreshed OSS object
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bucket: Option<String>,
+    /// Refreshed OSS object, as stored in the bucket
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub object_key: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub object_paths: Vec<String>,
+    /// CDN domains of `object_paths`, for an OSS object
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub domains: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub object_type: Option<String>,
+    /// Aliyun refresh task id
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task_id: Option<String>,
+    /// Queued refresh job id
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub job_id: Option<u64>,
+    /// Bilibili account that posted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+    /// Posted dynamic id, as a string since it exceeds JSON's safe integer range
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dynamic_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl WebhookEvent {
+    fn new(event_type: &'static str, error: Option<&AppError>) -> Self {
+        Self {
+            event_type,
+            outcome: if error.is_some() {
+                "failed"
+            } else {
+                "succeeded"
+            },
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            source: None,
+            bucket: None,
+            object_key: None,
+            object_paths: Vec::new(),
+            domains: Vec::new(),
+            object_type: None,
+            task_id: None,
+            job_id: None,
+            account: None,
+            dynamic_id: None,
+            error: error.map(|err| format!("{err:#}")),
+        }
+    }
+
+    /// A CDN refresh finished with Aliyun task id `outcome`, or failed
+    pub fn cdn_refresh(
+        source: &'static str,
+        request: &RefreshObjectCachesRequest,
+        outcome: Result<&str, &AppError>,
+    ) -> Self {
+        let event_type = match outcome {
+            Ok(_) => CDN_REFRESH_SUCCEEDED,
+            Err(_) => CDN_REFRESH_FAILED,
+        };
+        Self {
+            source: Some(source),
+            object_paths: request.object_path.lines().map(str::to_string).collect(),
+            object_type: request.object_type.clone(),
+            task_id: outcome.ok().map(str::to_string),
+            ..Self::new(event_type, outcome.err())
+        }
+    }
+
+    /// The CDN URLs of an OSS object were refreshed for its event as `task_id`
+    pub fn object_refreshed(
+        bucket: &str,
+        object_key: &str,
+        object_paths: &[String],
+        task_id: &str,
+    ) -> Self {
+        let mut domains = Vec::<String>::new();
+        for url in object_paths {
+            let host = url_host(url).to_ascii_lowercase();
+            if !domains.contains(&host) {
+                domains.push(host);
+            }
+        }
+        Self {
+            source: Some("oss_event"),
+            bucket: Some(bucket.to_string()),
+            object_key: Some(object_key.to_string()),
+            object_paths: object_paths.to_vec(),
+            domains,
+            task_id: Some(task_id.to_string()),
+            ..Self::new(OSS_OBJECT_REFRESHED, None)
+        }
+    }
+
+    /// A dynamic was posted as `account`, or posting failed
+    pub fn dynamic(account: &str, outcome: Result<Option<u64>, &AppError>) -> Self {
+        let event_type = match outcome {
+            Ok(_) => BILIBILI_DYNAMIC_POSTED,
+            Err(_) => BILIBILI_DYNAMIC_FAILED,
+        };
+        Self {
+            account: Some(account.to_string()),
+            dynamic_id: outcome.ok().flatten().map(|id| id.to_string()),
+            ..Self::new(event_type, outcome.err())
+        }
+    }
+
+    /// Aliyun marked an accepted refresh task failed after the fact
+    pub fn refresh_task_failed(source: &'static str, entry: &RefreshLogEntry) -> Self {
+        Self {
+            outcome: "failed",
+            source: Some(source),
+            object_paths: entry.object_paths.clone(),
+            object_type: Some(entry.object_type.clone()),
+            task_id: Some(entry.task_id.clone()),
+            error: Some(
+                entry
+                    .description
+                    .clone()
+                    .unwrap_or_else(|| "Aliyun marked the refresh task failed".to_string()),
+            ),
+            ..Self::new(CDN_REFRESH_FAILED, None)
+        }
+    }
+
+    pub fn with_job(mut self, job_id: u64) -> Self {
+        self.job_id = Some(job_id);
+        self
+    }
+}
+
+struct Delivery {
+    endpoint: usize,
+    event_type: &'static str,
+    body: Bytes,
+}
+
+/// Configured endpoints and the queue of pending deliveries
+#[derive(Clone, Default)]
+pub struct Webhooks {
+    endpoints: Arc<Vec<WebhookConfig>>,
+    queue: Arc<Mutex<VecDeque<Delivery>>>,
+    notify: Arc<Notify>,
+    closed: Arc<AtomicBool>,
+}
+
+impl std::fmt::Debug for Webhooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Webhooks")
+            .field("endpoints", &self.endpoints.len())
+            .finish()
+    }
+}
+
+impl Webhooks {
+    pub fn new(endpoints: Vec<WebhookConfig>) -> Self {
+        Self {
+            endpoints: Arc::new(endpoints),
+            ..Self::default()
+        }
+    }
+
+    /// Queue `event` for every endpoint subscribed to its type, dropping it when the queue
+    /// is full
+    pub fn notify(&self, event: WebhookEvent) {
+        let subscribed = self
+            .endpoints
+            .iter()
+            .enumerate()
+            .filter(|(_, endpoint)| endpoint.subscribes_to(event.event_type))
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        if subscribed.is_empty() {
+            return;
+        }
+        let body = Bytes::from(serde_json::to_vec(&event).expect("webhook event serializes"));
+
+        let mut queue = self.queue.lock().expect("webhook queue lock poisoned");
+        for endpoint in subscribed {
+            if queue.len() >= QUEUE_CAPACITY {
+                warn!(
+                    event_type = event.event_type,
+                    "Webhook queue is full, dropping delivery"
+                );
+                break;
+            }
+            queue.push_back(Delivery {
+                endpoint,
+                event_type: event.event_type,
+                body: body.clone(),
+            });
+        }
+        drop(queue);
+        self.notify.notify_one();
+    }
+
+    /// Have the dispatcher return once every queued delivery is made
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.notify.notify_one();
+    }
+
+    async fn next(&self) -> Option<Delivery> {
+        loop {
+            if let Some(delivery) = self
+                .queue
+                .lock()
+                .expect("webhook queue lock poisoned")
+                .pop_front()
+            {
+                return Some(delivery);
+            }
+            if self.closed.load(Ordering::SeqCst) {
+                return None;
+            }
+            self.notify.notified().await;
+        }
+    }
+}
+
+impl WebhookConfig {
+    /// Whether `event_type` matches `events`, where no entries match everything
+    pub fn subscribes_to(&self, event_type: &str) -> bool {
+        self.events.is_empty()
+            || self
+                .events
+                .iter()
+                .any(|pattern| pattern_matches(pattern, event_type))
+    }
+}
+
+/// Whether `pattern` is `event_type` itself or a `prefix.*` covering it
+pub fn pattern_matches(pattern: &str, event_type: &str) -> bool {
+    pattern == event_type
+        || pattern
+            .strip_suffix('*')
+            .is_some_and(|prefix| event_type.starts_with(prefix))
+}
+
+/// `sha256=<hex>` HMAC of `body`
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC can take key of any size");
+    mac.update(body);
+    let hex = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    format!("sha256={hex}")
+}
+
+/// Deliver queued events one at a time until [`Webhooks::close`] is called and the queue is
+/// empty
+pub async fn run_webhook_dispatcher(webhooks: Webhooks, client: reqwest::Client) {
+    while let Some(delivery) = webhooks.next().await {
+        deliver(&client, &webhooks.endpoints[delivery.endpoint], &delivery).await;
+    }
+}
+
+async fn deliver(client: &reqwest::Client, endpoint: &WebhookConfig, delivery: &Delivery) {
+    let signature = sign(&endpoint.secret, &delivery.body);
+    for attempt in 1..=endpoint.max_attempts.get() {
+        let mut request = client
+            .post(&endpoint.url)
+            .timeout(Duration::from_secs(endpoint.timeout_secs))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, &signature)
+            .header(EVENT_HEADER, delivery.event_type);
+        if let Some(token) = &endpoint.bearer_token {
+            request = request.bearer_auth(token);
+        }
+        let outcome = request
+            .body(delivery.body.clone())
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status);
+        match outcome {
+            Ok(_) => {
+                debug!(url = %endpoint.url, event_type = delivery.event_type, "Webhook delivered");
+                return;
+            }
+            Err(err) => {
+                warn!(
+                    url = %endpoint.url,
+                    event_type = delivery.event_type,
+                    attempt,
+                    error = %err,
+                    "Webhook delivery failed"
+                );
+            }
+        }
+        if attempt < endpoint.max_attempts.get() {
+            tokio::time::sleep(
+                Duration::from_millis(endpoint.retry_backoff_ms)
+                    .saturating_mul(1 << (attempt - 1).min(16)),
+            )
+            .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{header, method, path},
+    };
+
+    use super::*;
+
+    const SECRET: &str = "webhook-secret";
+
+    fn endpoint(server: &MockServer, events: &[&str]) -> WebhookConfig {
+        WebhookConfig {
+            url: format!("{}/hook", server.uri()),
+            secret: SECRET.to_string(),
+            events: events.iter().map(|event| event.to_string()).collect(),
+            max_attempts: NonZeroU32::new(3).unwrap(),
+            retry_backoff_ms: 1,
+            bearer_token: None,
+            timeout_secs: 10,
+        }
+    }
+
+    fn refresh_request() -> RefreshObjectCachesRequest {
+        RefreshObjectCachesRequest {
+            object_path: "https://static.prts.wiki/a.png\nhttps://static.prts.wiki/b.png"
+                .to_string(),
+            object_type: Some("File".to_string()),
+            force: Some(false),
+            product: None,
+        }
+    }
+
+    /// Wait until `server` got `count` requests
+    async fn received(server: &MockServer, count: usize) -> Vec<wiremock::Request> {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let requests = server.received_requests().await.unwrap();
+                if requests.len() >= count {
+                    return requests;
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("webhook should be delivered")
+    }
+
+    #[tokio::test]
+    async fn test_subscribed_event_is_delivered_signed() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/hook"))
+            .and(header(EVENT_HEADER, CDN_REFRESH_FAILED))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let webhooks = Webhooks::new(vec![endpoint(&server, &[CDN_REFRESH_FAILED])]);
+        let dispatcher = tokio::spawn(run_webhook_dispatcher(
+            webhooks.clone(),
+            reqwest::Client::new(),
+        ));
+
+        let request = refresh_request();
+        webhooks.notify(WebhookEvent::cdn_refresh("manual", &request, Ok("42")));
+        let error = AppError::InternalError(anyhow::anyhow!("Aliyun API error (status 503)"));
+        webhooks.notify(WebhookEvent::cdn_refresh("manual", &request, Err(&error)));
+
+        let delivered = received(&server, 1).await;
+        let body: serde_json::Value = serde_json::from_slice(&delivered[0].body).unwrap();
+        assert_eq!(body["type"], "cdn.refresh.failed");
+        assert_eq!(body["outcome"], "failed");
+        assert_eq!(body["source"], "manual");
+        assert_eq!(
+            body["object_paths"],
+            serde_json::json!([
+                "https://static.prts.wiki/a.png",
+                "https://static.prts.wiki/b.png"
+            ])
+        );
+        assert!(body["error"].as_str().unwrap().contains("status 503"));
+        assert_eq!(
+            delivered[0].headers[SIGNATURE_HEADER],
+            sign(SECRET, &delivered[0].body).as_str()
+        );
+
+        dispatcher.abort();
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_closed_dispatcher_delivers_what_is_queued_then_returns() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/hook"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(2)
+            .mount(&server)
+            .await;
+        let webhooks = Webhooks::new(vec![endpoint(&server, &[])]);
+        let request = refresh_request();
+        webhooks.notify(WebhookEvent::cdn_refresh("manual", &request, Ok("1")));
+        webhooks.notify(WebhookEvent::cdn_refresh("manual", &request, Ok("2")));
+        webhooks.close();
+
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            run_webhook_dispatcher(webhooks, reqwest::Client::new()),
+        )
+        .await
+        .expect("dispatcher should return once the queue is empty");
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_failed_delivery_is_retried() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let webhooks = Webhooks::new(vec![endpoint(&server, &["bilibili.*"])]);
+        let dispatcher = tokio::spawn(run_webhook_dispatcher(
+            webhooks.clone(),
+            reqwest::Client::new(),
+        ));
+
+        webhooks.notify(WebhookEvent::dynamic(
+            "default",
+            Ok(Some(1021451253404745734)),
+        ));
+
+        let delivered = received(&server, 2).await;
+        assert_eq!(delivered[0].body, delivered[1].body);
+        let body: serde_json::Value = serde_json::from_slice(&delivered[1].body).unwrap();
+        assert_eq!(body["type"], "bilibili.dynamic.posted");
+        assert_eq!(body["dynamic_id"], "1021451253404745734");
+        assert_eq!(body["account"], "default");
+
+        dispatcher.abort();
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_object_refresh_carries_the_object_and_bearer_token() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("authorization", "Bearer hook-token"))
+            .and(header(EVENT_HEADER, OSS_OBJECT_REFRESHED))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let webhooks = Webhooks::new(vec![WebhookConfig {
+            bearer_token: Some("hook-token".to_string()),
+            ..endpoint(&server, &["oss.*"])
+        }]);
+        let dispatcher = tokio::spawn(run_webhook_dispatcher(
+            webhooks.clone(),
+            reqwest::Client::new(),
+        ));
+
+        let paths = [
+            "https://static.prts.wiki/%E7%AB%8B%E7%BB%98/a.png".to_string(),
+            "https://Media.prts.wiki:443/%E7%AB%8B%E7%BB%98/a.png".to_string(),
+        ];
+        webhooks.notify(WebhookEvent::object_refreshed(
+            "prts-static",
+            "立绘/a.png",
+            &paths,
+            "1,2",
+        ));
+        // Not subscribed
+        webhooks.notify(WebhookEvent::cdn_refresh(
+            "oss_event",
+            &refresh_request(),
+            Ok("3"),
+        ));
+
+        let delivered = received(&server, 1).await;
+        let body: serde_json::Value = serde_json::from_slice(&delivered[0].body).unwrap();
+        assert_eq!(body["type"], "oss.object.refreshed");
+        assert_eq!(body["bucket"], "prts-static");
+        assert_eq!(body["object_key"], "立绘/a.png");
+        assert_eq!(
+            body["domains"],
+            serde_json::json!(["static.prts.wiki", "media.prts.wiki"])
+        );
+        assert_eq!(body["object_paths"], serde_json::json!(paths));
+        assert_eq!(body["task_id"], "1,2");
+        assert!(body["timestamp"].is_string());
+
+        dispatcher.abort();
+        server.verify().await;
+    }
+
+    #[test]
+    fn test_event_filters() {
+        let server_less = |events: &[&str]| WebhookConfig {
+            url: "http://localhost/hook".to_string(),
+            secret: SECRET.to_string(),
+            events: events.iter().map(|event| event.to_string()).collect(),
+            max_attempts: NonZeroU32::new(1).unwrap(),
+            retry_backoff_ms: 0,
+            bearer_token: None,
+            timeout_secs: 10,
+        };
+        assert!(server_less(&[]).subscribes_to(CDN_REFRESH_SUCCEEDED));
+        assert!(server_less(&["cdn.*"]).subscribes_to(CDN_REFRESH_FAILED));
+        assert!(!server_less(&["cdn.*"]).subscribes_to(BILIBILI_DYNAMIC_POSTED));
+        assert!(!server_less(&[CDN_REFRESH_FAILED]).subscribes_to(CDN_REFRESH_SUCCEEDED));
+    }
+}